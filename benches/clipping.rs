@@ -0,0 +1,86 @@
+//! The clipping of the grid triangles with the polygon, on one thread and on
+//! all of them. `cargo bench --bench clipping` prints the time of both on
+//! meshes of growing size, and checks that they find the same areas to the
+//! last bit. RAYON_NUM_THREADS sets the threads of the second run.
+
+use std::time::{Duration, Instant};
+
+use surface_area::compute::{self, Surface};
+use surface_area::point::MercatorPoint;
+use surface_area::triangulation::grid;
+
+// a lattice of rough terrain and a wavy ring within it, crossing many cells
+fn scene(size: isize) -> (Vec<MercatorPoint>, Vec<(isize, isize)>, Vec<MercatorPoint>) {
+    let mut points = Vec::new();
+    let mut indices = Vec::new();
+    for row in 0..size {
+        for col in 0..size {
+            let (x, y) = (30.0 * col as f64, 30.0 * row as f64);
+            points.push(MercatorPoint {
+                x,
+                y,
+                ele: Some(100.0 * (x / 700.0).sin() * (y / 400.0).cos()),
+            });
+            indices.push((col, -row));
+        }
+    }
+    let (center, radius) = (15.0 * size as f64, 13.0 * size as f64);
+    let ring = (0..2000)
+        .map(|k| {
+            let a = k as f64 * std::f64::consts::TAU / 2000.0;
+            let r = radius * (1.0 + 0.05 * (40.0 * a).sin());
+            MercatorPoint {
+                x: center + r * a.cos(),
+                y: center + r * a.sin(),
+                ele: None,
+            }
+        })
+        .collect();
+    (points, indices, ring)
+}
+
+// the best of a few runs
+fn time(threads: usize, f: impl Fn() -> Surface + Sync) -> (Duration, Surface) {
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .unwrap();
+    let mut best = Duration::MAX;
+    let mut surface = pool.install(&f);
+    for _ in 0..3 {
+        let start = Instant::now();
+        surface = pool.install(&f);
+        best = best.min(start.elapsed());
+    }
+    (best, surface)
+}
+
+fn main() {
+    // RAYON_NUM_THREADS or the number of cores
+    let threads = rayon::ThreadPoolBuilder::new()
+        .build()
+        .unwrap()
+        .current_num_threads();
+    println!(
+        "{:>10} {:>12} {:>12} {:>8}",
+        "triangles",
+        "1 thread",
+        format!("{} threads", threads),
+        "speedup"
+    );
+    for size in [100, 300, 600] {
+        let (points, indices, ring) = scene(size);
+        let mesh = grid::triangulate_regular(&points, &indices).unwrap();
+        let (one, a) = time(1, || compute::clip(&ring, &mesh));
+        let (all, b) = time(threads, || compute::clip(&ring, &mesh));
+        assert_eq!(a.projected2d.to_bits(), b.projected2d.to_bits());
+        assert_eq!(a.projected3d.to_bits(), b.projected3d.to_bits());
+        println!(
+            "{:>10} {:>10.1}ms {:>10.1}ms {:>7.1}x",
+            mesh.len(),
+            one.as_secs_f64() * 1e3,
+            all.as_secs_f64() * 1e3,
+            one.as_secs_f64() / all.as_secs_f64()
+        );
+    }
+}
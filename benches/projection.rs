@@ -0,0 +1,68 @@
+//! Projecting the samples of a DEM: in bulk, one by one, and building the
+//! projection for every point. `cargo bench --bench projection` prints the
+//! time of the three on a grid of 1M points, and checks that the first two
+//! agree.
+
+use std::time::{Duration, Instant};
+
+use surface_area::point::WGS84Point;
+use surface_area::polygon::Polygon;
+
+fn point(lon: f64, lat: f64) -> WGS84Point {
+    WGS84Point {
+        lon,
+        lat,
+        ele: None,
+    }
+}
+
+// the best of a few runs
+fn time<T>(mut f: impl FnMut() -> T) -> (Duration, T) {
+    let mut best = Duration::MAX;
+    let mut ret = f();
+    for _ in 0..3 {
+        let start = Instant::now();
+        ret = f();
+        best = best.min(start.elapsed());
+    }
+    (best, ret)
+}
+
+fn main() {
+    // a 1000 × 1000 grid of 3 arc-seconds
+    let step = 1.0 / 1200.0;
+    let grid: Vec<WGS84Point> = (0..1_000_000)
+        .map(|k| {
+            point(
+                7.0 + (k % 1000) as f64 * step,
+                46.0 + (k / 1000) as f64 * step,
+            )
+        })
+        .collect();
+    let ring = vec![point(7.0, 46.0), point(7.8, 46.0), point(7.8, 46.8)];
+    let projector = Polygon::new(ring.clone()).projector();
+    let (bulk, many) = time(|| projector.project_many(&grid).unwrap());
+    let (single, one_by_one) = time(|| {
+        grid.iter()
+            .map(|p| projector.project(p).unwrap())
+            .collect::<Vec<_>>()
+    });
+    assert_eq!(many, one_by_one);
+    // the projection built for every point, on a part of the grid
+    let (rebuilt, _) = time(|| {
+        for p in &grid[..10_000] {
+            Polygon::new(ring.clone()).projector().project(p).unwrap();
+        }
+    });
+    println!("{:>12} {:>10.1}ms", "bulk", bulk.as_secs_f64() * 1e3);
+    println!(
+        "{:>12} {:>10.1}ms",
+        "one by one",
+        single.as_secs_f64() * 1e3
+    );
+    println!(
+        "{:>12} {:>10.1}ms (extrapolated from 10k points)",
+        "rebuilt",
+        100.0 * rebuilt.as_secs_f64() * 1e3
+    );
+}
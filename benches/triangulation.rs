@@ -0,0 +1,63 @@
+//! The two triangulations of a DEM lattice: Delaunay and the cells of the
+//! lattice. `cargo bench --bench triangulation` prints the time of both on
+//! lattices of growing size, and checks that they cover the same flat area.
+
+use std::time::{Duration, Instant};
+
+use surface_area::point::MercatorPoint;
+use surface_area::triangulation::{Mesh, grid};
+
+// a slightly sheared lattice, as the pixels of a DEM in a projection, with
+// a rough terrain on it
+fn lattice(size: isize) -> (Vec<MercatorPoint>, Vec<(isize, isize)>) {
+    let mut points = Vec::new();
+    let mut indices = Vec::new();
+    for row in 0..size {
+        for col in 0..size {
+            let (x, y) = (30.0 * col as f64, -30.0 * row as f64);
+            points.push(MercatorPoint {
+                x: x + 0.5 * row as f64,
+                y: y + 0.25 * col as f64,
+                ele: Some(100.0 * (x / 700.0).sin() * (y / 400.0).cos()),
+            });
+            indices.push((col, row));
+        }
+    }
+    (points, indices)
+}
+
+fn flat(mesh: &Mesh) -> f64 {
+    mesh.iter().map(|t| t.flat().area()).sum()
+}
+
+// the best of a few runs
+fn time(mut f: impl FnMut() -> Mesh) -> (Duration, Mesh) {
+    let mut best = Duration::MAX;
+    let mut mesh = f();
+    for _ in 0..3 {
+        let start = Instant::now();
+        mesh = f();
+        best = best.min(start.elapsed());
+    }
+    (best, mesh)
+}
+
+fn main() {
+    println!(
+        "{:>10} {:>12} {:>12} {:>8}",
+        "samples", "delaunay", "lattice", "speedup"
+    );
+    for size in [100, 300, 600] {
+        let (points, indices) = lattice(size);
+        let (delaunay, a) = time(|| grid::triangulate(&points));
+        let (regular, b) = time(|| grid::triangulate_regular(&points, &indices).unwrap());
+        assert!((flat(&a) - flat(&b)).abs() < 1e-9 * flat(&a));
+        println!(
+            "{:>10} {:>10.1}ms {:>10.1}ms {:>7.1}x",
+            points.len(),
+            delaunay.as_secs_f64() * 1e3,
+            regular.as_secs_f64() * 1e3,
+            delaunay.as_secs_f64() / regular.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,268 @@
+//! A gallery of small synthetic scenarios run through the whole pipeline.
+//!
+//! Every scenario builds its DEM from an elevation function (see
+//! `prelude::Synthetic`), computes the areas of a polygon on it, writes the
+//! SVG, JSON and typst artifacts to `target/examples` and checks the key
+//! numbers against their analytic values. `cargo run --example gallery` runs
+//! them all, `cargo test` runs each as a test.
+
+use std::f64::consts::PI;
+use std::path::Path;
+
+use surface_area::fixtures::{assert_close, local, polygon, square, synthetic};
+use surface_area::options::{ComputeOptions, VoidPolicy};
+use surface_area::pipeline;
+use surface_area::prelude::*;
+use surface_area::typst::{self, Data};
+
+// Runs the pipeline and writes the artifacts.
+fn run(name: &str, ring: &[(f64, f64)], datasets: &[Dataset], options: &ComputeOptions) -> Data {
+    let polygon = polygon(ring);
+    let data = pipeline::compute(name, &polygon, datasets, options).unwrap();
+    write(name, &data);
+    data
+}
+
+// Writes <name>.svg, <name>.json and <name>.typst.
+fn write(name: &str, data: &Data) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("target/examples");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join(format!("{}.svg", name)), &data.svg).unwrap();
+    let json = serde_json::to_string_pretty(data).unwrap();
+    std::fs::write(dir.join(format!("{}.json", name)), json).unwrap();
+    let doc = typst::make_typst_document(&vec![data.clone()]);
+    std::fs::write(dir.join(format!("{}.typst", name)), doc).unwrap();
+}
+
+// A 400 m square on a plane rising 30% to the east: the triangles interpolate
+// the plane exactly, only the projection is left.
+fn inclined_plane() -> Data {
+    let dem = synthetic("plane", (-250.0, -250.0, 250.0, 250.0), |x, _| {
+        Some(500.0 + 0.3 * x)
+    });
+    let mut options = ComputeOptions::default();
+    options.self_check = true;
+    let data = run(
+        "inclined-plane",
+        &square(200.0),
+        std::slice::from_ref(&dem),
+        &options,
+    );
+    assert_close("planar", data.planar2d, 160_000.0, 1e-4);
+    assert_close("geodesic", data.geodesic2d, 160_000.0, 1e-3);
+    assert_close("flat", data.projected2d, data.planar2d, 1e-6);
+    assert_close(
+        "ratio",
+        data.projected3d / data.projected2d,
+        1.09f64.sqrt(),
+        1e-4,
+    );
+    assert!(data.planes.iter().all(|p| (p.slope - 30.0).abs() < 0.01));
+    assert!(data.self_check.as_ref().unwrap().passed());
+    // the check follows the options, the constrained triangulation here
+    options.constrained = true;
+    let constrained = run(
+        "inclined-plane-constrained",
+        &square(200.0),
+        &[dem],
+        &options,
+    );
+    let check = constrained.self_check.as_ref().unwrap();
+    assert!(check.passed(), "{}", check);
+    data
+}
+
+// A cone of radius R and height H in a 600 m square: the flat area outside
+// the base plus the lateral surface pi R sqrt(R² + H²). The triangles cut
+// the apex and the rim, 0.2%.
+fn cone() -> Data {
+    const R: f64 = 200.0;
+    const H: f64 = 100.0;
+    let dem = synthetic("cone", (-350.0, -350.0, 350.0, 350.0), |x, y| {
+        let r = (x * x + y * y).sqrt();
+        Some(400.0 + H * (1.0 - r / R).max(0.0))
+    });
+    let data = run("cone", &square(300.0), &[dem], &ComputeOptions::default());
+    let expected = 600.0 * 600.0 - PI * R * R + PI * R * (R * R + H * H).sqrt();
+    assert_close("surface", data.projected3d, expected, 0.002);
+    // the apex is a sample
+    let highest = data.extremes.highest.as_ref().unwrap();
+    assert_close("apex", highest.elevation, 400.0 + H, 1e-9);
+    data
+}
+
+// A 400 m square with a 200 m square hole, as a single ring through a cut,
+// on a plane rising 20% to the north.
+fn donut() -> Data {
+    let ring = [
+        (-200.0, -200.0),
+        (200.0, -200.0),
+        (200.0, 200.0),
+        (-200.0, 200.0),
+        (-200.0, 0.0),
+        (-100.0, 0.0),
+        (-100.0, 100.0),
+        (100.0, 100.0),
+        (100.0, -100.0),
+        (-100.0, -100.0),
+        (-100.0, 0.0),
+        (-200.0, 0.0),
+    ];
+    let dem = synthetic("donut", (-250.0, -250.0, 250.0, 250.0), |_, y| {
+        Some(300.0 + 0.2 * y)
+    });
+    let mut options = ComputeOptions::default();
+    options.self_check = true;
+    let data = run("donut", &ring, &[dem], &options);
+    assert_close("planar", data.planar2d, 120_000.0, 1e-4);
+    assert_close("geodesic", data.geodesic2d, 120_000.0, 1e-3);
+    assert_close("flat", data.projected2d, 120_000.0, 1e-4);
+    assert_close(
+        "surface",
+        data.projected3d,
+        120_000.0 * 1.04f64.sqrt(),
+        1e-4,
+    );
+    // the cut leaks nothing
+    assert!(data.self_check.as_ref().unwrap().passed());
+    data
+}
+
+// The same plane from two tiles sharing their boundary column, like adjacent
+// SRTM tiles: no gap and no double counting along the seam.
+fn two_tile_seam() -> Data {
+    let plane = |x: f64, y: f64| Some(200.0 + 0.1 * x + 0.25 * y);
+    let west = synthetic("west", (-250.0, -250.0, 0.0, 250.0), plane);
+    let east = synthetic("east", (0.0, -250.0, 250.0, 250.0), plane);
+    let data = run(
+        "two-tile-seam",
+        &square(200.0),
+        &[west, east],
+        &ComputeOptions::default(),
+    );
+    assert_close("flat", data.projected2d, data.planar2d, 1e-6);
+    let ratio = (1.0f64 + 0.1 * 0.1 + 0.25 * 0.25).sqrt();
+    assert_close("ratio", data.projected3d / data.projected2d, ratio, 1e-4);
+    data
+}
+
+// A plane with a 60 m nodata disc in the middle, with the triangles across
+// the void excluded: they cover the disc and up to a cell around it.
+fn nodata_hole() -> Data {
+    const R: f64 = 60.0;
+    let dem = synthetic("hole", (-250.0, -250.0, 250.0, 250.0), |x, y| {
+        (x * x + y * y > R * R).then_some(100.0 + 0.3 * x)
+    });
+    let mut options = ComputeOptions::default();
+    options.void_triangles = VoidPolicy::Exclude;
+    let data = run("nodata-hole", &square(200.0), &[dem], &options);
+    assert!(data.notes.iter().any(|n| n.contains("excluded")));
+    let discarded = data.planar2d - data.projected2d;
+    assert!(
+        discarded > PI * R * R && discarded < PI * (R + 30.0) * (R + 30.0),
+        "discarded {}",
+        discarded
+    );
+    assert_close(
+        "ratio",
+        data.projected3d / data.projected2d,
+        1.09f64.sqrt(),
+        1e-4,
+    );
+    data
+}
+
+// An L-shaped polygon, the square without its north-east quarter, on a
+// plane rising 40% to the south-west.
+fn concave_boundary() -> Data {
+    let ring = [
+        (-200.0, -200.0),
+        (200.0, -200.0),
+        (200.0, 0.0),
+        (0.0, 0.0),
+        (0.0, 200.0),
+        (-200.0, 200.0),
+    ];
+    let gradient = 0.4 / 2f64.sqrt();
+    let dem = synthetic("concave", (-250.0, -250.0, 250.0, 250.0), move |x, y| {
+        Some(800.0 - gradient * (x + y))
+    });
+    let data = run(
+        "concave-boundary",
+        &ring,
+        &[dem],
+        &ComputeOptions::default(),
+    );
+    assert_close("planar", data.planar2d, 120_000.0, 1e-4);
+    assert_close("flat", data.projected2d, 120_000.0, 1e-4);
+    assert_close(
+        "surface",
+        data.projected3d,
+        120_000.0 * 1.16f64.sqrt(),
+        1e-4,
+    );
+    // nothing is sampled in the notch
+    let highest = data.extremes.highest.as_ref().unwrap();
+    let (x, y) = local(highest.lon, highest.lat);
+    assert!(x < 0.0 && y < 0.0, "{} {}", x, y);
+    data
+}
+
+// a scenario and its name
+type Scenario = (&'static str, fn() -> Data);
+
+fn main() {
+    let scenarios: [Scenario; 6] = [
+        ("inclined plane", inclined_plane),
+        ("cone", cone),
+        ("donut", donut),
+        ("two-tile seam", two_tile_seam),
+        ("nodata hole", nodata_hole),
+        ("concave boundary", concave_boundary),
+    ];
+    for (name, scenario) in scenarios {
+        let data = scenario();
+        println!(
+            "{:>16}: {} flat, {} surface, {} planes",
+            name,
+            data.area(data.projected2d),
+            data.area(data.projected3d),
+            data.nplanes
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inclined_plane() {
+        inclined_plane();
+    }
+
+    #[test]
+    fn test_cone() {
+        cone();
+    }
+
+    #[test]
+    fn test_donut() {
+        donut();
+    }
+
+    #[test]
+    fn test_two_tile_seam() {
+        two_tile_seam();
+    }
+
+    #[test]
+    fn test_nodata_hole() {
+        nodata_hole();
+    }
+
+    #[test]
+    fn test_concave_boundary() {
+        concave_boundary();
+    }
+}
@@ -0,0 +1,285 @@
+use std::fmt;
+use std::str::FromStr;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::format::{self, Quantity};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum AreaUnit {
+    M2,
+    Ha,
+    Km2,
+    Acre,
+}
+
+impl AreaUnit {
+    // square meters per unit (the international acre is exactly 4046.8564224 m²)
+    pub fn factor(&self) -> f64 {
+        match self {
+            AreaUnit::M2 => 1.0,
+            AreaUnit::Ha => 10_000.0,
+            AreaUnit::Km2 => 1_000_000.0,
+            AreaUnit::Acre => 4046.8564224,
+        }
+    }
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            AreaUnit::M2 => "m²",
+            AreaUnit::Ha => "ha",
+            AreaUnit::Km2 => "km²",
+            AreaUnit::Acre => "acre",
+        }
+    }
+}
+
+impl FromStr for AreaUnit {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "m2" | "m²" | "sqm" => Ok(AreaUnit::M2),
+            "ha" => Ok(AreaUnit::Ha),
+            "km2" | "km²" => Ok(AreaUnit::Km2),
+            "acre" | "acres" | "ac" => Ok(AreaUnit::Acre),
+            u => Err(format!("unknown area unit: {}", u)),
+        }
+    }
+}
+
+// The unit of all human-readable areas; auto picks one per value.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Units {
+    M2,
+    Ha,
+    Km2,
+    Acre,
+    #[default]
+    Auto,
+}
+
+impl Units {
+    pub fn unit_for(&self, m2: f64) -> AreaUnit {
+        match self {
+            Units::M2 => AreaUnit::M2,
+            Units::Ha => AreaUnit::Ha,
+            Units::Km2 => AreaUnit::Km2,
+            Units::Acre => AreaUnit::Acre,
+            // below a hectare in m², up to 100 km² in hectares
+            Units::Auto if m2.abs() < 1e4 => AreaUnit::M2,
+            Units::Auto if m2.abs() < 1e8 => AreaUnit::Ha,
+            Units::Auto => AreaUnit::Km2,
+        }
+    }
+}
+
+// An area in m², rendered in the selected unit with about four significant
+// digits (at most one decimal for m²).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AreaValue {
+    pub m2: f64,
+}
+
+impl AreaValue {
+    pub fn new(m2: f64) -> Self {
+        AreaValue { m2 }
+    }
+
+    pub fn number(&self, unit: AreaUnit) -> String {
+        let v = self.m2 / unit.factor();
+        // below a square meter the DEM resolves nothing
+        if unit == AreaUnit::M2 && v.abs() < 100.0 {
+            return format!("{:.1}", v);
+        }
+        format::number(v, Quantity::Area)
+    }
+
+    pub fn render(&self, units: Units) -> String {
+        let unit = units.unit_for(self.m2);
+        format!("{} {}", self.number(unit), unit.symbol())
+    }
+}
+
+// An area given by the user, "2ha", "1.5 acre" or "12000" (m²).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "AreaInput", into = "String")]
+pub struct AreaArg {
+    pub m2: f64,
+    pub unit: AreaUnit,
+}
+
+impl AreaArg {
+    pub fn value(&self) -> f64 {
+        self.m2 / self.unit.factor()
+    }
+}
+
+impl FromStr for AreaArg {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let split = s
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-' || c == '+' || c == 'e'))
+            .unwrap_or(s.len());
+        let (number, unit) = s.split_at(split);
+        let value: f64 = number.parse().map_err(|_| format!("invalid area: {}", s))?;
+        let unit: AreaUnit = unit.parse()?;
+        if !(value > 0.0 && value.is_finite()) {
+            return Err(format!("area must be positive: {}", s));
+        }
+        Ok(AreaArg {
+            m2: value * unit.factor(),
+            unit,
+        })
+    }
+}
+
+impl fmt::Display for AreaArg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = AreaValue::new(self.m2).number(self.unit);
+        write!(f, "{} {}", value, self.unit.symbol())
+    }
+}
+
+// manifests may give a plain number of m² or a string with a unit
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AreaInput {
+    Number(f64),
+    Text(String),
+}
+
+impl TryFrom<AreaInput> for AreaArg {
+    type Error = String;
+    fn try_from(input: AreaInput) -> Result<Self, Self::Error> {
+        match input {
+            AreaInput::Number(m2) => format!("{}m2", m2).parse(),
+            AreaInput::Text(s) => s.parse(),
+        }
+    }
+}
+
+impl From<AreaArg> for String {
+    fn from(a: AreaArg) -> String {
+        format!("{}{}", a.value(), a.unit.symbol())
+    }
+}
+
+// The measurement compared with a registered (cadastral) area.
+#[derive(Clone, Debug, Serialize)]
+pub struct ReferenceComparison {
+    pub registered: AreaArg,
+    pub planar: f64,
+    pub surface: f64,
+    // relative deviations from the registered area, in percent
+    pub planar_deviation: f64,
+    pub surface_deviation: f64,
+    pub tolerance: f64,
+    pub within_tolerance: bool,
+}
+
+impl ReferenceComparison {
+    pub fn new(registered: AreaArg, planar: f64, surface: f64, tolerance: f64) -> Self {
+        let deviation = |a: f64| 100.0 * (a / registered.m2 - 1.0);
+        let planar_deviation = deviation(planar);
+        ReferenceComparison {
+            registered,
+            planar,
+            surface,
+            planar_deviation,
+            surface_deviation: deviation(surface),
+            tolerance,
+            within_tolerance: planar_deviation.abs() <= tolerance,
+        }
+    }
+}
+
+impl fmt::Display for ReferenceComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let unit = self.registered.unit;
+        write!(
+            f,
+            "registered {}, measured planar {} {} ({}), 3D surface {} {} ({} vs registered)",
+            self.registered,
+            AreaValue::new(self.planar).number(unit),
+            unit.symbol(),
+            format::signed(self.planar_deviation, Quantity::Percent),
+            AreaValue::new(self.surface).number(unit),
+            unit.symbol(),
+            format::signed(self.surface_deviation, Quantity::Percent)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_area() {
+        assert_eq!("2ha".parse::<AreaArg>().unwrap().m2, 20_000.0);
+        assert_eq!("2 ha".parse::<AreaArg>().unwrap().m2, 20_000.0);
+        assert_eq!("12000".parse::<AreaArg>().unwrap().m2, 12_000.0);
+        assert_eq!("1.5km2".parse::<AreaArg>().unwrap().m2, 1_500_000.0);
+        assert_eq!("350m²".parse::<AreaArg>().unwrap().m2, 350.0);
+        // an acre is not 4000 m², nor 0.4 ha
+        let acre = "1acre".parse::<AreaArg>().unwrap();
+        assert_eq!(acre.m2, 4046.8564224);
+        assert_eq!(acre.unit, AreaUnit::Acre);
+        assert!(("640 acres".parse::<AreaArg>().unwrap().m2 - 2_589_988.110336).abs() < 1e-6);
+        assert!("2 hectares".parse::<AreaArg>().is_err());
+        assert!("-3ha".parse::<AreaArg>().is_err());
+        assert!("ha".parse::<AreaArg>().is_err());
+    }
+
+    #[test]
+    fn test_area_value() {
+        let render = |m2: f64| AreaValue::new(m2).render(Units::Auto);
+        assert_eq!(render(12.34), "12.3 m²");
+        assert_eq!(render(9999.4), "9999 m²");
+        assert_eq!(render(10_000.0), "1.000 ha");
+        assert_eq!(render(3_000_000.0), "300.0 ha");
+        assert_eq!(render(99_999_000.0), "10000 ha");
+        assert_eq!(render(100_000_000.0), "100.0 km²");
+        assert_eq!(
+            AreaValue::new(4046.8564224).render(Units::Acre),
+            "1.000 acre"
+        );
+        assert_eq!(
+            AreaValue::new(20_234.282112).render(Units::Acre),
+            "5.000 acre"
+        );
+        assert_eq!(AreaValue::new(1234.56).render(Units::M2), "1235 m²");
+        assert_eq!(AreaValue::new(0.26).render(Units::M2), "0.3 m²");
+    }
+
+    #[test]
+    fn test_deserialize_area() {
+        let a: AreaArg = serde_json::from_str("\"2ha\"").unwrap();
+        assert_eq!(a.m2, 20_000.0);
+        let a: AreaArg = serde_json::from_str("500").unwrap();
+        assert_eq!(a.m2, 500.0);
+        let text = serde_json::to_string(&"3acre".parse::<AreaArg>().unwrap()).unwrap();
+        assert_eq!(
+            serde_json::from_str::<AreaArg>(&text).unwrap().unit,
+            AreaUnit::Acre
+        );
+    }
+
+    #[test]
+    fn test_comparison() {
+        let registered: AreaArg = "2ha".parse().unwrap();
+        let c = ReferenceComparison::new(registered, 19_820.0, 21_420.0, 5.0);
+        assert!((c.planar_deviation + 0.9).abs() < 1e-9);
+        assert!((c.surface_deviation - 7.1).abs() < 1e-9);
+        assert!(c.within_tolerance);
+        assert_eq!(
+            c.to_string(),
+            "registered 2.000 ha, measured planar 1.982 ha (-0.90%), 3D surface 2.142 ha (+7.10% vs registered)"
+        );
+        assert!(!ReferenceComparison::new(registered, 18_000.0, 19_000.0, 5.0).within_tolerance);
+    }
+}
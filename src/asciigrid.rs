@@ -0,0 +1,252 @@
+use std::path::Path;
+
+use crate::rasterio::{RasterIo, RasterIoError};
+
+// Arc/Info ASCII grids (.asc), read without GDAL. Small tools write headers
+// that GDAL rejects (mixed case keys, tabs, rows wrapped over several lines,
+// a missing NODATA_value): these are accepted. The georeferencing follows
+// the AAIGrid driver of GDAL, so that both read the same samples.
+pub struct AsciiGrid {
+    geo_transform: [f64; 6],
+    size: (usize, usize),
+    nodata: Option<f64>,
+    values: Vec<f64>,
+    // the WKT of the .prj sidecar
+    projection: String,
+}
+
+#[derive(Default)]
+struct Header {
+    ncols: Option<usize>,
+    nrows: Option<usize>,
+    // the lower left corner of the grid, or the center of its lower left cell
+    xll: Option<(f64, bool)>,
+    yll: Option<(f64, bool)>,
+    dx: Option<f64>,
+    dy: Option<f64>,
+    nodata: Option<f64>,
+}
+
+fn error(message: String) -> RasterIoError {
+    RasterIoError(format!("ascii grid: {}", message))
+}
+
+impl AsciiGrid {
+    pub fn open(path: &Path) -> Result<Self, RasterIoError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| error(format!("{}: {}", path.display(), e)))?;
+        let mut grid = Self::parse(&text)?;
+        if let Ok(prj) = std::fs::read_to_string(path.with_extension("prj")) {
+            grid.projection = prj.trim().to_string();
+        }
+        Ok(grid)
+    }
+
+    pub fn parse(text: &str) -> Result<Self, RasterIoError> {
+        let mut tokens = text.split_whitespace().peekable();
+        let mut header = Header::default();
+        // the header ends with the first number
+        while let Some(key) = tokens.next_if(|t| t.parse::<f64>().is_err()) {
+            let value = tokens
+                .next()
+                .ok_or_else(|| error(format!("no value for {}", key)))?;
+            let number = || {
+                value
+                    .parse::<f64>()
+                    .map_err(|_| error(format!("{} is not a number: {}", key, value)))
+            };
+            let count = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|_| error(format!("{} is not a count: {}", key, value)))
+            };
+            match key.to_ascii_lowercase().as_str() {
+                "ncols" => header.ncols = Some(count()?),
+                "nrows" => header.nrows = Some(count()?),
+                "xllcorner" => header.xll = Some((number()?, false)),
+                "yllcorner" => header.yll = Some((number()?, false)),
+                "xllcenter" => header.xll = Some((number()?, true)),
+                "yllcenter" => header.yll = Some((number()?, true)),
+                "cellsize" => {
+                    header.dx = Some(number()?);
+                    header.dy = header.dx;
+                }
+                "dx" => header.dx = Some(number()?),
+                "dy" => header.dy = Some(number()?),
+                "nodata_value" => header.nodata = Some(number()?),
+                _ => log::warn!("ascii grid: unknown header key {}", key),
+            }
+        }
+        let missing = |key: &str| error(format!("no {} in the header", key));
+        let ncols = header.ncols.ok_or_else(|| missing("ncols"))?;
+        let nrows = header.nrows.ok_or_else(|| missing("nrows"))?;
+        let (xll, xcenter) = header.xll.ok_or_else(|| missing("xllcorner"))?;
+        let (yll, ycenter) = header.yll.ok_or_else(|| missing("yllcorner"))?;
+        let dx = header.dx.ok_or_else(|| missing("cellsize"))?;
+        let dy = header.dy.ok_or_else(|| missing("cellsize"))?;
+
+        let mut values = Vec::with_capacity(ncols * nrows);
+        for token in tokens.by_ref().take(ncols * nrows) {
+            let value = token
+                .parse::<f64>()
+                .map_err(|_| error(format!("not a number: {}", token)))?;
+            values.push(value);
+        }
+        if values.len() < ncols * nrows {
+            return Err(error(format!(
+                "{} values for {} × {} cells",
+                values.len(),
+                ncols,
+                nrows
+            )));
+        }
+        if tokens.next().is_some() {
+            log::warn!("ascii grid: values after the last row are ignored");
+        }
+
+        // the upper left corner, as GDAL reports it
+        let west = if xcenter { xll - 0.5 * dx } else { xll };
+        let south = if ycenter { yll - 0.5 * dy } else { yll };
+        Ok(AsciiGrid {
+            geo_transform: [west, dx, 0.0, south + nrows as f64 * dy, 0.0, -dy],
+            size: (ncols, nrows),
+            nodata: header.nodata,
+            values,
+            projection: String::new(),
+        })
+    }
+}
+
+impl RasterIo for AsciiGrid {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        Ok(self.geo_transform)
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        self.size
+    }
+    fn projection(&self) -> String {
+        self.projection.clone()
+    }
+    fn no_data_value(&self, _band: usize) -> Result<Option<f64>, RasterIoError> {
+        Ok(self.nodata)
+    }
+    fn overview_count(&self, _band: usize) -> Result<usize, RasterIoError> {
+        Ok(0)
+    }
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        let inside = band == 1
+            && window.0 >= 0
+            && window.1 >= 0
+            && window.0 as usize + size.0 <= self.size.0
+            && window.1 as usize + size.1 <= self.size.1;
+        if !inside {
+            return Err(error(format!(
+                "window {:?} {:?} of band {} is outside of the grid",
+                window, size, band
+            )));
+        }
+        let (col, row) = (window.0 as usize, window.1 as usize);
+        let mut ret = Vec::with_capacity(size.0 * size.1);
+        for r in row..row + size.1 {
+            let start = r * self.size.0 + col;
+            ret.extend_from_slice(&self.values[start..start + size.0]);
+        }
+        Ok(ret)
+    }
+    fn read_mask(
+        &self,
+        _band: usize,
+        _window: (isize, isize),
+        _size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::{Dataset, SampleOptions};
+    use crate::point::WGS84BoundingBox;
+    use crate::rasterio::GdalRasterIo;
+
+    const CORNER: &str = "ncols 4
+nrows 3
+xllcorner 6.0
+yllcorner 46.0
+cellsize 0.001
+NODATA_value -9999
+500 501 502 503
+510 -9999 512 513
+520 521 522 523
+";
+
+    // the same grid from a sloppier tool: center origin, mixed case, tabs,
+    // rows wrapped, no nodata
+    const CENTER: &str = "NCOLS\t4\nNRows 3\nXLLCENTER 6.0005\nyllcenter\t46.0005\nCellSize 0.001
+500 501
+502 503 510 511 512 513
+520 521 522 523\n";
+
+    #[test]
+    fn test_corner_header() {
+        let grid = AsciiGrid::parse(CORNER).unwrap();
+        assert_eq!(grid.raster_size(), (4, 3));
+        assert_eq!(
+            grid.geo_transform().unwrap(),
+            [6.0, 0.001, 0.0, 46.003, 0.0, -0.001]
+        );
+        assert_eq!(grid.no_data_value(1).unwrap(), Some(-9999.0));
+        assert_eq!(
+            grid.read_window(1, (0, 1), (3, 2)).unwrap(),
+            vec![510.0, -9999.0, 512.0, 520.0, 521.0, 522.0]
+        );
+        assert!(grid.read_window(1, (2, 2), (3, 1)).is_err());
+        assert!(
+            AsciiGrid::parse("ncols 2\nnrows 2\nxllcorner 0\nyllcorner 0\ncellsize 1\n1 2 3")
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_center_header() {
+        let corner = AsciiGrid::parse(CORNER).unwrap();
+        let center = AsciiGrid::parse(CENTER).unwrap();
+        let (a, b) = (
+            corner.geo_transform().unwrap(),
+            center.geo_transform().unwrap(),
+        );
+        for (a, b) in a.iter().zip(&b) {
+            assert!((a - b).abs() < 1e-12, "{} {}", a, b);
+        }
+        assert_eq!(center.no_data_value(1).unwrap(), None);
+        assert_eq!(center.read_window(1, (1, 1), (1, 1)).unwrap(), vec![511.0]);
+    }
+
+    #[test]
+    fn test_same_samples_as_gdal() {
+        let path = std::env::temp_dir().join("surface-area-asciigrid-test.asc");
+        std::fs::write(&path, CORNER).unwrap();
+        let utm = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let points = |io: Box<dyn RasterIo>| {
+            let dataset = Dataset::from_io("test.asc", io, utm).unwrap();
+            let mut bbox: WGS84BoundingBox = dataset.wgsbbox();
+            dataset.snap(&mut bbox);
+            dataset.points_inside(&bbox, &SampleOptions::default())
+        };
+        let native = points(Box::new(AsciiGrid::open(&path).unwrap()));
+        // 12 cells, one nodata
+        assert_eq!(native.len(), 11);
+        assert!(native.iter().all(|p| p.ele != Some(-9999.0)));
+        // GDAL reads the same samples, where it is available
+        if let Ok(gdal) = GdalRasterIo::open(&path) {
+            assert_eq!(points(Box::new(gdal)), native);
+        }
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,441 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::area::{AreaValue, Units};
+use crate::format::{self, Quantity};
+use crate::options::ComputeOptions;
+use crate::typst::Data;
+
+// A manifest is a TOML file: the top-level keys are the default options, each
+// [[job]] has an input path and overrides any subset of them.
+//
+//   min_cells = 20
+//
+//   [[job]]
+//   input = "parcel.kml"
+//   ignore_mask = true
+pub struct Job {
+    pub input: PathBuf,
+    pub options: ComputeOptions,
+}
+
+#[derive(Debug)]
+pub struct ManifestError(pub String);
+
+impl fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "manifest: {}", self.0)
+    }
+}
+
+impl std::error::Error for ManifestError {}
+
+pub fn read_manifest(path: &Path) -> Result<Vec<Job>, ManifestError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| ManifestError(format!("{}: {}", path.display(), e)))?;
+    let base = path.parent().unwrap_or(Path::new("."));
+    parse_manifest(&text, base)
+}
+
+// relative input paths are resolved against base
+pub fn parse_manifest(text: &str, base: &Path) -> Result<Vec<Job>, ManifestError> {
+    let root: Value = toml::from_str(text).map_err(|e| ManifestError(e.to_string()))?;
+    let Value::Object(mut defaults) = root else {
+        return Err(ManifestError("expected a table".to_string()));
+    };
+    let entries = match defaults.remove("job") {
+        Some(Value::Array(entries)) => entries,
+        _ => return Err(ManifestError("no [[job]] entry".to_string())),
+    };
+    let mut jobs = Vec::new();
+    for (i, entry) in entries.into_iter().enumerate() {
+        let Value::Object(entry) = entry else {
+            return Err(ManifestError(format!("job {}: expected a table", i + 1)));
+        };
+        let mut merged: Map<String, Value> = defaults.clone();
+        merged.extend(entry);
+        let input = match merged.remove("input") {
+            Some(Value::String(input)) => base.join(input),
+            _ => return Err(ManifestError(format!("job {}: missing input", i + 1))),
+        };
+        let options = serde_json::from_value(Value::Object(merged))
+            .map_err(|e| ManifestError(format!("job {}: {}", i + 1, e)))?;
+        jobs.push(Job { input, options });
+    }
+    Ok(jobs)
+}
+
+#[derive(Serialize)]
+pub struct SummaryRow {
+    pub input: String,
+    pub name: String,
+    pub status: String,
+    pub error: Option<String>,
+    pub geodesic2d: f64,
+    pub geodesic3d: f64,
+    pub projected2d: f64,
+    pub projected3d: f64,
+    pub nplanes: usize,
+    pub reference: Option<f64>,
+    pub deviation: Option<f64>,
+    pub within_tolerance: Option<bool>,
+    // the index of a part of a multi-part boundary
+    pub part: Option<usize>,
+    // the sums over the parts
+    pub aggregate: bool,
+}
+
+impl SummaryRow {
+    pub fn ok(input: &str, data: &Data) -> Self {
+        SummaryRow {
+            input: input.to_string(),
+            name: data.name.clone(),
+            status: "ok".to_string(),
+            error: None,
+            geodesic2d: data.geodesic2d,
+            geodesic3d: data.geodesic3d,
+            projected2d: data.projected2d,
+            projected3d: data.projected3d,
+            nplanes: data.nplanes,
+            reference: data.reference.as_ref().map(|r| r.registered.m2),
+            deviation: data.reference.as_ref().map(|r| r.planar_deviation),
+            within_tolerance: data.reference.as_ref().map(|r| r.within_tolerance),
+            part: None,
+            aggregate: !data.parts.is_empty(),
+        }
+    }
+
+    // one row per part of a multi-part boundary, then the aggregate
+    pub fn rows(input: &str, data: &Data) -> Vec<Self> {
+        let mut rows: Vec<_> = data
+            .parts
+            .iter()
+            .enumerate()
+            .map(|(k, part)| SummaryRow {
+                part: Some(k + 1),
+                ..SummaryRow::ok(input, part)
+            })
+            .collect();
+        rows.push(SummaryRow::ok(input, data));
+        rows
+    }
+
+    pub fn failed(input: &str, error: &str) -> Self {
+        SummaryRow {
+            input: input.to_string(),
+            name: String::new(),
+            status: "failed".to_string(),
+            error: Some(error.to_string()),
+            geodesic2d: f64::NAN,
+            geodesic3d: f64::NAN,
+            projected2d: f64::NAN,
+            projected3d: f64::NAN,
+            nplanes: 0,
+            reference: None,
+            deviation: None,
+            within_tolerance: None,
+            part: None,
+            aggregate: false,
+        }
+    }
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+// One unit for all rows: with Units::Auto the one of the largest area.
+pub fn summary_csv(rows: &[SummaryRow], units: Units) -> String {
+    let largest = rows
+        .iter()
+        .map(|r| r.geodesic3d)
+        .filter(|a| a.is_finite())
+        .fold(0f64, f64::max);
+    let unit = units.unit_for(largest);
+    let area = |m2: f64| {
+        if m2.is_finite() {
+            AreaValue::new(m2).number(unit)
+        } else {
+            String::new()
+        }
+    };
+    let mut ret = format!(
+        "input,name,status,error,geodesic2d_{u},geodesic3d_{u},projected2d_{u},projected3d_{u},nplanes,reference_{u},deviation,within_tolerance,part,aggregate\n",
+        u = unit.symbol().replace('²', "2")
+    );
+    for row in rows {
+        ret.push_str(&format!(
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{}\n",
+            csv_field(&row.input),
+            csv_field(&row.name),
+            row.status,
+            csv_field(row.error.as_deref().unwrap_or("")),
+            area(row.geodesic2d),
+            area(row.geodesic3d),
+            area(row.projected2d),
+            area(row.projected3d),
+            row.nplanes,
+            row.reference.map(area).unwrap_or_default(),
+            row.deviation
+                .map(|d| format::number(d, Quantity::Percent))
+                .unwrap_or_default(),
+            row.within_tolerance
+                .map(|w| w.to_string())
+                .unwrap_or_default(),
+            row.part.map(|k| k.to_string()).unwrap_or_default(),
+            row.aggregate
+        ));
+    }
+    ret
+}
+
+#[derive(Serialize)]
+struct SummaryJson<'a> {
+    area_unit: &'static str,
+    rows: &'a [SummaryRow],
+}
+
+// the format follows the extension of the path: .json (areas in m²) or csv
+pub fn write_summary(path: &Path, rows: &[SummaryRow], units: Units) -> std::io::Result<()> {
+    let text = if path.extension().is_some_and(|e| e == "json") {
+        let json = SummaryJson {
+            area_unit: "m2",
+            rows,
+        };
+        serde_json::to_string_pretty(&json).unwrap()
+    } else {
+        summary_csv(rows, units)
+    };
+    std::fs::write(path, text)
+}
+
+// Console progress of the runner: completed/total and the job running the longest.
+pub struct Progress {
+    total: usize,
+    done: usize,
+    running: BTreeMap<usize, (String, Instant)>,
+}
+
+impl Progress {
+    pub fn new(total: usize) -> Self {
+        Progress {
+            total,
+            done: 0,
+            running: BTreeMap::new(),
+        }
+    }
+
+    pub fn start(&mut self, job: usize, input: &str, now: Instant) {
+        self.running.insert(job, (input.to_string(), now));
+    }
+
+    pub fn finish(&mut self, job: usize, now: Instant) -> String {
+        self.running.remove(&job);
+        self.done += 1;
+        let mut line = format!("[{}/{}]", self.done, self.total);
+        if let Some((input, start)) = self.running.values().min_by_key(|(_, start)| *start) {
+            line.push_str(&format!(
+                " longest running: {} ({:.0}s)",
+                input,
+                now.duration_since(*start).as_secs_f64()
+            ));
+        }
+        line
+    }
+}
+
+fn panic_message(e: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = e.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = e.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "panic".to_string()
+    }
+}
+
+// Runs f(index, job) on every job with `threads` workers, the results are in manifest
+// order. A panicking job (unreadable input, broken dataset) gives an Err
+// with the panic message and does not take the other jobs down.
+pub fn run_jobs<T: Send>(
+    jobs: &[Job],
+    threads: usize,
+    f: impl Fn(usize, &Job) -> T + Sync,
+    report: impl Fn(&str) + Sync,
+) -> Vec<Result<T, String>> {
+    let next = AtomicUsize::new(0);
+    let progress = Mutex::new(Progress::new(jobs.len()));
+    let results = Mutex::new(Vec::new());
+    std::thread::scope(|scope| {
+        for _ in 0..threads.max(1) {
+            scope.spawn(|| {
+                loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(job) = jobs.get(i) else {
+                        break;
+                    };
+                    let input = job.input.to_string_lossy();
+                    progress.lock().unwrap().start(i, &input, Instant::now());
+                    let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| f(i, job)))
+                        .map_err(|e| panic_message(e.as_ref()));
+                    results.lock().unwrap().push((i, r));
+                    let line = progress.lock().unwrap().finish(i, Instant::now());
+                    report(&line);
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_by_key(|r| r.0);
+    results.into_iter().map(|r| r.1).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"
+# defaults
+min_cells = 20
+ignore_mask = true
+
+[[job]]
+input = "a.kml"
+
+[[job]]
+input = "b.kml"
+ignore_mask = false
+projection = "+proj=utm +zone=32 +datum=WGS84"
+reference_area = "2ha"
+"#;
+
+    #[test]
+    fn test_manifest_defaults_and_overrides() {
+        let jobs = parse_manifest(MANIFEST, Path::new("/data")).unwrap();
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].input, PathBuf::from("/data/a.kml"));
+        assert!(jobs[0].options.ignore_mask);
+        assert_eq!(jobs[0].options.min_cells, 20.0);
+        assert_eq!(jobs[0].options.tile_degrees, 0.5);
+        assert!(!jobs[1].options.ignore_mask);
+        assert_eq!(jobs[1].options.min_cells, 20.0);
+        assert!(jobs[1].options.projection.is_some());
+        assert_eq!(jobs[1].options.reference_area.unwrap().m2, 20_000.0);
+        assert!(jobs[0].options.reference_area.is_none());
+    }
+
+    #[test]
+    fn test_manifest_errors() {
+        let unknown = "[[job]]\ninput = \"a.kml\"\nbuffer = -5\n";
+        let error = parse_manifest(unknown, Path::new(".")).err().unwrap();
+        assert!(error.to_string().contains("job 1"));
+        assert!(error.to_string().contains("buffer"));
+        let missing = "[[job]]\nignore_mask = true\n";
+        assert!(parse_manifest(missing, Path::new(".")).is_err());
+        assert!(parse_manifest("min_cells = 3\n", Path::new(".")).is_err());
+    }
+
+    #[test]
+    fn test_summary_csv() {
+        let csv = summary_csv(
+            &[SummaryRow::failed("x.kml", "manifest: a, \"b\"")],
+            Units::Auto,
+        );
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("x.kml,,failed,\"manifest: a, \"\"b\"\"\","));
+    }
+
+    #[test]
+    fn test_summary_units() {
+        let mut row = SummaryRow::failed("x.kml", "");
+        (row.geodesic2d, row.geodesic3d) = (20_000.0, 21_500.0);
+        let rows = [row, SummaryRow::failed("y.kml", "missing")];
+        let csv = summary_csv(&rows, Units::Auto);
+        assert!(csv.starts_with("input,name,status,error,geodesic2d_ha,geodesic3d_ha,"));
+        assert!(csv.lines().nth(1).unwrap().contains(",2.000,2.150,"));
+        let csv = summary_csv(&rows, Units::M2);
+        assert!(csv.lines().nth(1).unwrap().contains(",20000,21500,"));
+    }
+
+    #[test]
+    fn test_summary_parts() {
+        let part = |k| SummaryRow {
+            part: Some(k),
+            ..SummaryRow::failed("x.geojson", "")
+        };
+        let mut aggregate = SummaryRow::failed("x.geojson", "");
+        aggregate.aggregate = true;
+        let csv = summary_csv(&[part(1), part(2), aggregate], Units::M2);
+        let lines: Vec<_> = csv.lines().collect();
+        assert!(lines[0].ends_with(",within_tolerance,part,aggregate"));
+        assert!(lines[1].ends_with(",1,false"));
+        assert!(lines[2].ends_with(",2,false"));
+        assert!(lines[3].ends_with(",,true"));
+    }
+
+    fn jobs(inputs: &[&str]) -> Vec<Job> {
+        inputs
+            .iter()
+            .map(|input| Job {
+                input: PathBuf::from(input),
+                options: ComputeOptions::default(),
+            })
+            .collect()
+    }
+
+    // a synthetic job: the area of a square with the input's length as side
+    fn square(_: usize, job: &Job) -> f64 {
+        let input = job.input.to_string_lossy();
+        assert!(!input.contains("corrupt"), "corrupt input {}", input);
+        let side = input.len() as f64;
+        let points: Vec<_> = [(0.0, 0.0), (side, 0.0), (side, side), (0.0, side)]
+            .iter()
+            .map(|(x, y)| crate::point::MercatorPoint {
+                x: *x,
+                y: *y,
+                ele: None,
+            })
+            .collect();
+        crate::reference::planar_area(&points).unwrap()
+    }
+
+    #[test]
+    fn test_run_jobs() {
+        let jobs = jobs(&["a.kml", "bb.kml", "corrupt.kml", "dddd.kml", "e.kml"]);
+        let sequential = run_jobs(&jobs, 1, square, |_| {});
+        let lines = Mutex::new(Vec::new());
+        let parallel = run_jobs(&jobs, 4, square, |l| {
+            lines.lock().unwrap().push(l.to_string())
+        });
+        assert_eq!(sequential, parallel);
+        assert_eq!(parallel[1], Ok(36.0));
+        assert!(parallel[2].as_ref().unwrap_err().contains("corrupt.kml"));
+        assert_eq!(parallel.iter().filter(|r| r.is_ok()).count(), 4);
+        let lines = lines.into_inner().unwrap();
+        assert_eq!(lines.len(), 5);
+        assert!(lines.iter().any(|l| l.starts_with("[5/5]")));
+    }
+
+    #[test]
+    fn test_progress() {
+        let t0 = Instant::now();
+        let mut progress = Progress::new(3);
+        progress.start(0, "a.kml", t0);
+        progress.start(1, "b.kml", t0 + std::time::Duration::from_secs(2));
+        let line = progress.finish(1, t0 + std::time::Duration::from_secs(10));
+        assert_eq!(line, "[1/3] longest running: a.kml (10s)");
+        assert_eq!(progress.finish(0, t0), "[2/3]");
+    }
+}
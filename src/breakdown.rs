@@ -0,0 +1,145 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::compute::Plane;
+
+// The areas of the planes in [from, to), the last slope class is open.
+#[derive(Clone, Debug, Serialize)]
+pub struct Bin {
+    pub from: f64,
+    pub to: Option<f64>,
+    pub area2d: f64,
+    pub area3d: f64,
+}
+
+impl Bin {
+    fn new(from: f64, to: Option<f64>) -> Self {
+        Bin {
+            from,
+            to,
+            area2d: 0.0,
+            area3d: 0.0,
+        }
+    }
+
+    fn add(&mut self, area2d: f64, area3d: f64) {
+        self.area2d += area2d;
+        self.area3d += area3d;
+    }
+
+    // "400–500 m", "≥ 100%"
+    pub fn range(&self, unit: &str) -> String {
+        match self.to {
+            Some(to) => format!("{}–{}{}", self.from, to, unit),
+            None => format!("≥ {}{}", self.from, unit),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Breakdown {
+    // the height of the elevation bands, in meters
+    pub band: f64,
+    // the bands with planes, from the lowest
+    pub elevation: Vec<Bin>,
+    pub slope: Vec<Bin>,
+}
+
+impl Breakdown {
+    // the tiles or the parts of a boundary, with the same bands and classes
+    pub fn add(&mut self, other: &Breakdown) {
+        for bin in &other.elevation {
+            match self.elevation.iter_mut().find(|b| b.from == bin.from) {
+                Some(b) => b.add(bin.area2d, bin.area3d),
+                None => self.elevation.push(bin.clone()),
+            }
+        }
+        self.elevation.sort_by(|a, b| a.from.total_cmp(&b.from));
+        for (b, o) in self.slope.iter_mut().zip(&other.slope) {
+            b.add(o.area2d, o.area3d);
+        }
+    }
+}
+
+// The areas of the planes by band of their mean elevation and by class of
+// their slope. A plane counts in full in the band of its mean elevation,
+// even if its vertices span several bands. The slope classes end at the
+// edges (in percent), the last one is open.
+pub fn breakdown<'a>(
+    planes: impl IntoIterator<Item = &'a Plane>,
+    band: f64,
+    slope_edges: &[f64],
+) -> Breakdown {
+    assert!(band > 0.0);
+    let mut edges = slope_edges.to_vec();
+    edges.sort_by(|a, b| a.total_cmp(b));
+    edges.dedup();
+    let mut slope: Vec<Bin> = std::iter::once(0.0)
+        .chain(edges.iter().copied())
+        .zip(edges.iter().map(|e| Some(*e)).chain(std::iter::once(None)))
+        .map(|(from, to)| Bin::new(from, to))
+        .collect();
+    let mut bands: BTreeMap<i64, Bin> = BTreeMap::new();
+    for plane in planes {
+        let k = (plane.mean_elevation() / band).floor() as i64;
+        bands
+            .entry(k)
+            .or_insert_with(|| Bin::new(k as f64 * band, Some((k + 1) as f64 * band)))
+            .add(plane.area2d, plane.area3d);
+        let s = plane.slope();
+        let class = edges.iter().position(|e| s < *e).unwrap_or(edges.len());
+        slope[class].add(plane.area2d, plane.area3d);
+    }
+    Breakdown {
+        band,
+        elevation: bands.into_values().collect(),
+        slope,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::MercatorPoint;
+
+    // a 10 m triangle rising to the north by the gradient, from z0
+    fn plane(x0: f64, z0: f64, gradient: f64) -> Plane {
+        let p = |x: f64, y: f64| MercatorPoint {
+            x: x0 + x,
+            y,
+            ele: Some(z0 + gradient * y),
+        };
+        let points = vec![p(0.0, 0.0), p(10.0, 0.0), p(0.0, 10.0)];
+        Plane::new(points, 50.0, 50.0 * (1.0 + gradient * gradient).sqrt())
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let planes = [
+            plane(0.0, 420.0, 0.05),
+            plane(10.0, 480.0, 0.15),
+            // from 595 to 625, its mean 605
+            plane(20.0, 595.0, 3.0),
+        ];
+        let b = breakdown(&planes, 100.0, &[20.0, 10.0, 100.0]);
+        let ranges: Vec<String> = b.elevation.iter().map(|b| b.range(" m")).collect();
+        assert_eq!(ranges, ["400–500 m", "600–700 m"]);
+        assert_eq!(b.elevation[0].area2d, 100.0);
+        let slopes: Vec<String> = b.slope.iter().map(|b| b.range("%")).collect();
+        assert_eq!(slopes, ["0–10%", "10–20%", "20–100%", "≥ 100%"]);
+        let areas: Vec<f64> = b.slope.iter().map(|b| b.area2d).collect();
+        assert_eq!(areas, [50.0, 50.0, 0.0, 50.0]);
+        let total: f64 = planes.iter().map(|p| p.area3d).sum();
+        let sum = |bins: &[Bin]| bins.iter().map(|b| b.area3d).sum::<f64>();
+        assert!((sum(&b.elevation) - total).abs() < 1e-9);
+        assert!((sum(&b.slope) - total).abs() < 1e-9);
+
+        // the parts of a boundary
+        let mut sum = breakdown(&planes[..1], 100.0, &[10.0, 20.0, 100.0]);
+        sum.add(&breakdown(&planes[1..], 100.0, &[10.0, 20.0, 100.0]));
+        assert_eq!(sum.elevation.len(), 2);
+        assert_eq!(sum.elevation[0].area2d, 100.0);
+        assert_eq!(sum.slope[3].area2d, 50.0);
+    }
+}
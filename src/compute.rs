@@ -0,0 +1,1134 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use geo::{BooleanOps, Contains};
+use serde::Serialize;
+
+use crate::options::VoidPolicy;
+use crate::triangulation::Triangle;
+use crate::{intersection, point::MercatorPoint, polygon, triangulation::Mesh};
+
+// A grid triangle clipped by the polygon.
+#[derive(Clone)]
+pub struct Plane {
+    pub points: Vec<MercatorPoint>,
+    pub area2d: f64,
+    pub area3d: f64,
+    // the normal of the grid triangle the plane was clipped from, used
+    // instead of its own for slivers (see MIN_CONDITIONING)
+    pub inherited: Option<(f64, f64, f64)>,
+}
+
+// Below this conditioning the vertices of a plane are almost on a line: its
+// fitted normal is unstable, it takes the normal of its grid triangle. A
+// sliver 1 cm wide and 50 m long has about 4e-8.
+pub const MIN_CONDITIONING: f64 = 1e-6;
+
+// unit normal (Newell), pointing up
+fn newell(points: &[MercatorPoint]) -> (f64, f64, f64) {
+    let (mut nx, mut ny, mut nz) = (0f64, 0f64, 0f64);
+    for i in 0..points.len() {
+        let p1 = &points[i];
+        let p2 = &points[(i + 1) % points.len()];
+        let (z1, z2) = (p1.ele.unwrap(), p2.ele.unwrap());
+        nx += (p1.y - p2.y) * (z1 + z2);
+        ny += (z1 - z2) * (p1.x + p2.x);
+        nz += (p1.x - p2.x) * (p1.y + p2.y);
+    }
+    let norm = (nx * nx + ny * ny + nz * nz).sqrt();
+    let sign = if nz < 0.0 { -1.0 } else { 1.0 };
+    (sign * nx / norm, sign * ny / norm, sign * nz / norm)
+}
+
+// The eigenvalues of a symmetric 3x3 matrix, largest first (Smith 1961).
+pub(crate) fn eigenvalues(a: [[f64; 3]; 3]) -> [f64; 3] {
+    let off = a[0][1].powi(2) + a[0][2].powi(2) + a[1][2].powi(2);
+    if off == 0.0 {
+        let mut ret = [a[0][0], a[1][1], a[2][2]];
+        ret.sort_by(|x, y| y.total_cmp(x));
+        return ret;
+    }
+    let q = (a[0][0] + a[1][1] + a[2][2]) / 3.0;
+    let p = (((a[0][0] - q).powi(2) + (a[1][1] - q).powi(2) + (a[2][2] - q).powi(2) + 2.0 * off)
+        / 6.0)
+        .sqrt();
+    let b = |i: usize, j: usize| (a[i][j] - if i == j { q } else { 0.0 }) / p;
+    let det = b(0, 0) * (b(1, 1) * b(2, 2) - b(1, 2) * b(2, 1))
+        - b(0, 1) * (b(1, 0) * b(2, 2) - b(1, 2) * b(2, 0))
+        + b(0, 2) * (b(1, 0) * b(2, 1) - b(1, 1) * b(2, 0));
+    let phi = (det / 2.0).clamp(-1.0, 1.0).acos() / 3.0;
+    let first = q + 2.0 * p * phi.cos();
+    let last = q + 2.0 * p * (phi + 2.0 * std::f64::consts::PI / 3.0).cos();
+    [first, 3.0 * q - first - last, last]
+}
+
+impl Plane {
+    pub fn new(points: Vec<MercatorPoint>, area2d: f64, area3d: f64) -> Self {
+        Plane {
+            points,
+            area2d,
+            area3d,
+            inherited: None,
+        }
+    }
+
+    pub fn mean_elevation(&self) -> f64 {
+        self.points.iter().map(|p| p.ele.unwrap()).sum::<f64>() / self.points.len() as f64
+    }
+
+    // unit normal, pointing up: the fitted one or the inherited one
+    pub fn normal(&self) -> (f64, f64, f64) {
+        self.inherited.unwrap_or_else(|| newell(&self.points))
+    }
+
+    // in percent, of the normal
+    pub fn slope(&self) -> f64 {
+        polygon::normal_slope(self.normal())
+    }
+
+    // The ratio of the second to the largest eigenvalue of the covariance of
+    // the vertices: 0 for vertices on a line, about 1 for a compact polygon.
+    pub fn conditioning(&self) -> f64 {
+        let n = self.points.len() as f64;
+        let (cx, cy, cz) = self.centroid();
+        let mut c = [[0f64; 3]; 3];
+        for p in &self.points {
+            let d = [p.x - cx, p.y - cy, p.ele.unwrap() - cz];
+            for i in 0..3 {
+                for j in 0..3 {
+                    c[i][j] += d[i] * d[j] / n;
+                }
+            }
+        }
+        let [first, second, _] = eigenvalues(c);
+        if first <= 0.0 {
+            return 0.0;
+        }
+        second.max(0.0) / first
+    }
+
+    fn centroid(&self) -> (f64, f64, f64) {
+        let n = self.points.len() as f64;
+        let x = self.points.iter().map(|p| p.x).sum::<f64>() / n;
+        let y = self.points.iter().map(|p| p.y).sum::<f64>() / n;
+        (x, y, self.mean_elevation())
+    }
+}
+
+pub struct Surface {
+    pub planes: Vec<Plane>,
+    pub projected2d: f64,
+    pub projected3d: f64,
+    // planes spanning skipped pixels (VoidPolicy::Flag), counted in the totals
+    pub flagged: Vec<Plane>,
+    // flat area of the planes dropped by VoidPolicy::Exclude, and of those
+    // skipped without elevations
+    pub discarded2d: f64,
+    pub paths: ClipPaths,
+}
+
+impl Surface {
+    // Adds the planes and the totals of the surface of the triangles that
+    // follow.
+    fn append(&mut self, other: Surface) {
+        self.planes.extend(other.planes);
+        self.flagged.extend(other.flagged);
+        self.projected2d += other.projected2d;
+        self.projected3d += other.projected3d;
+        self.discarded2d += other.discarded2d;
+        self.paths.add(&other.paths);
+    }
+}
+
+// How the triangles were clipped: taken whole or dropped, because they lie
+// inside or outside of the polygon (their edges may be on the boundary), or
+// intersected with it. Slivers are the intersected ones with the normal of
+// their triangle.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct ClipPaths {
+    pub whole: usize,
+    pub outside: usize,
+    pub intersected: usize,
+    pub slivers: usize,
+    // planes with missing elevations: interpolated along the ring, or left
+    // out when none of the vertices has one
+    pub patched: usize,
+    pub skipped: usize,
+}
+
+impl ClipPaths {
+    pub fn add(&mut self, other: &ClipPaths) {
+        self.whole += other.whole;
+        self.outside += other.outside;
+        self.intersected += other.intersected;
+        self.slivers += other.slivers;
+        self.patched += other.patched;
+        self.skipped += other.skipped;
+    }
+}
+
+impl fmt::Display for ClipPaths {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "triangles: {} whole, {} outside, {} intersected ({} slivers), {} planes patched, {} skipped",
+            self.whole, self.outside, self.intersected, self.slivers, self.patched, self.skipped
+        )
+    }
+}
+
+impl Surface {
+    pub fn ratio(&self) -> f64 {
+        self.projected3d / self.projected2d
+    }
+}
+
+// Clips every triangle of the mesh with the (projected) polygon and sums the
+// flat and 3D areas of the resulting planes. The vertices of the polygon with
+// an elevation keep it, the others are on the triangles.
+pub fn clip(polygon: &Vec<MercatorPoint>, mesh: &Mesh) -> Surface {
+    clip_with_voids(polygon, &[], mesh, &[], VoidPolicy::Keep)
+}
+
+// voids sorted by x
+fn covers_void(triangle: &Triangle, voids: &[MercatorPoint]) -> bool {
+    let v = triangle.as_vector();
+    let xmin = v.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+    let xmax = v.iter().map(|p| p.x).fold(f64::NEG_INFINITY, f64::max);
+    let start = voids.partition_point(|p| p.x < xmin);
+    let cross = |a: &MercatorPoint, b: &MercatorPoint, p: &MercatorPoint| {
+        (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+    };
+    let area = cross(&v[0], &v[1], &v[2]);
+    let eps = 1e-9 * area.abs();
+    voids[start..].iter().take_while(|p| p.x <= xmax).any(|p| {
+        // inside or on an edge
+        let w = [
+            cross(&v[1], &v[2], p),
+            cross(&v[2], &v[0], p),
+            cross(&v[0], &v[1], p),
+        ];
+        w.iter().all(|w| w * area.signum() >= -eps)
+    })
+}
+
+// Whether the triangle lies in the polygon (Some(true)) or outside of it
+// (Some(false)), its edges possibly on the boundary; None if the boundary
+// (any of its rings) passes through the inside of the triangle. Each edge of
+// the boundary is cut to the triangle, and the middle of what remains must
+// not be strictly inside. The centroid then decides.
+fn locate(
+    triangle: &[MercatorPoint],
+    rings: &[&[MercatorPoint]],
+    mask: &geo::Polygon,
+) -> Option<bool> {
+    let cross = |a: &MercatorPoint, b: &MercatorPoint, x: f64, y: f64| {
+        (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+    };
+    let area = cross(&triangle[0], &triangle[1], triangle[2].x, triangle[2].y);
+    if area == 0.0 {
+        return None;
+    }
+    let eps = 1e-9 * area.abs();
+    let xmin = triangle.iter().map(|p| p.x).fold(f64::INFINITY, f64::min);
+    let xmax = triangle
+        .iter()
+        .map(|p| p.x)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let ymin = triangle.iter().map(|p| p.y).fold(f64::INFINITY, f64::min);
+    let ymax = triangle
+        .iter()
+        .map(|p| p.y)
+        .fold(f64::NEG_INFINITY, f64::max);
+    // the edge functions, positive inside
+    let side = |k: usize, x: f64, y: f64| {
+        area.signum() * cross(&triangle[k], &triangle[(k + 1) % 3], x, y)
+    };
+    let edges = rings
+        .iter()
+        .flat_map(|ring| (0..ring.len()).map(|k| (&ring[k], &ring[(k + 1) % ring.len()])));
+    for (p, q) in edges {
+        if p.x.max(q.x) < xmin || p.x.min(q.x) > xmax || p.y.max(q.y) < ymin || p.y.min(q.y) > ymax
+        {
+            continue;
+        }
+        let (mut t0, mut t1) = (0f64, 1f64);
+        for e in 0..3 {
+            let (f0, f1) = (side(e, p.x, p.y), side(e, q.x, q.y));
+            if f0 < 0.0 && f1 < 0.0 {
+                t1 = -1.0;
+                break;
+            }
+            if f0 < 0.0 {
+                t0 = t0.max(f0 / (f0 - f1));
+            } else if f1 < 0.0 {
+                t1 = t1.min(f0 / (f0 - f1));
+            }
+        }
+        if t1 <= t0 {
+            continue;
+        }
+        let t = 0.5 * (t0 + t1);
+        let (x, y) = (p.x + t * (q.x - p.x), p.y + t * (q.y - p.y));
+        if (0..3).all(|e| side(e, x, y) > eps) {
+            return None;
+        }
+    }
+    let x = triangle.iter().map(|p| p.x).sum::<f64>() / 3.0;
+    let y = triangle.iter().map(|p| p.y).sum::<f64>() / 3.0;
+    Some(mask.contains(&geo::Point::new(x, y)))
+}
+
+// Same as clip, but without the holes of the polygon, and the triangles
+// containing the center of a skipped pixel are handled according to the
+// policy.
+pub fn clip_with_voids(
+    polygon: &Vec<MercatorPoint>,
+    holes: &[Vec<MercatorPoint>],
+    mesh: &Mesh,
+    voids: &[MercatorPoint],
+    policy: VoidPolicy,
+) -> Surface {
+    clip_reporting(polygon, holes, mesh, voids, policy, &|_, _| {})
+}
+
+// The planes of a clip and their totals.
+struct Planes<'a> {
+    surface: Surface,
+    // sorted by x
+    voids: &'a [MercatorPoint],
+    policy: VoidPolicy,
+}
+
+impl<'a> Planes<'a> {
+    fn new(voids: &'a [MercatorPoint], policy: VoidPolicy) -> Self {
+        Planes {
+            surface: Surface {
+                planes: Vec::new(),
+                projected2d: 0.0,
+                projected3d: 0.0,
+                flagged: Vec::new(),
+                discarded2d: 0.0,
+                paths: ClipPaths::default(),
+            },
+            voids,
+            policy,
+        }
+    }
+
+    // Adds the plane cut from the triangle of the mesh.
+    fn add(&mut self, mut plane: Vec<MercatorPoint>, gridtriangle: &Triangle) {
+        let surface = &mut self.surface;
+        if plane.is_empty() {
+            return;
+        }
+        // the vertices clipped from a triangle with a missing elevation
+        if polygon::patch_elevations(&mut plane) > 0 {
+            surface.paths.patched += 1;
+        }
+        let a3d = match polygon::calculate_3d_surface_area(&plane) {
+            Ok(a3d) => a3d,
+            Err(e) => {
+                log::debug!("plane skipped: {}", e);
+                surface.paths.skipped += 1;
+                let flat = polygon::flat(&plane);
+                surface.discarded2d += polygon::calculate_3d_surface_area(&flat).unwrap();
+                return;
+            }
+        };
+        // flat, every vertex has an elevation
+        let a2d = polygon::calculate_3d_surface_area(&polygon::flat(&plane)).unwrap();
+        if a2d < 0.001 {
+            log::info!("remove artifact with area {:.4}", a2d);
+            return;
+        }
+        let mut plane = Plane::new(plane, a2d, a3d);
+        // a grid triangle with a missing elevation has no normal, the sliver
+        // keeps its fitted one
+        if plane.conditioning() < MIN_CONDITIONING {
+            plane.inherited = gridtriangle.normal();
+            surface.paths.slivers += 1;
+        }
+        let void = self.policy != VoidPolicy::Keep && covers_void(gridtriangle, self.voids);
+        if void && self.policy == VoidPolicy::Exclude {
+            surface.discarded2d += a2d;
+            return;
+        }
+        // let rat = 100.0 * (a3d / a2d - 1.0);
+        // log::trace!("plane area: {:6.2} {:6.2} +{:3.1}%", a3d, a2d, rat);
+        surface.projected3d += a3d;
+        surface.projected2d += a2d;
+        if void {
+            surface.flagged.push(plane);
+        } else {
+            surface.planes.push(plane);
+        }
+    }
+}
+
+// Triangles clipped by one task, see clip_chunks.
+const CHUNK: usize = 64;
+
+// Clips the triangles of the mesh on the threads of rayon (RAYON_NUM_THREADS,
+// the number of cores by default), calling progress with the triangles done
+// and their total about every percent. The mesh is halved at a chunk boundary
+// until a chunk is left, which sums its own planes, and the halves are added
+// in the order of the mesh: the split only depends on the number of
+// triangles, the planes and the areas do not depend on the number of
+// threads, to the last bit.
+fn clip_chunks(
+    mesh: &Mesh,
+    voids: &[MercatorPoint],
+    policy: VoidPolicy,
+    progress: &(dyn Fn(usize, usize) + Sync),
+    clip: impl Fn(usize, &Triangle, &mut Planes) + Sync,
+) -> Surface {
+    let mut voids = voids.to_vec();
+    voids.sort_by(|a, b| a.x.total_cmp(&b.x));
+    let chunks = Chunks {
+        mesh,
+        voids: &voids,
+        policy,
+        clip,
+        done: AtomicUsize::new(0),
+        reported: Mutex::new(0),
+        every: (mesh.len() / 100).max(1),
+        progress,
+    };
+    progress(0, mesh.len());
+    let surface = chunks.clip(0, mesh.len());
+    progress(mesh.len(), mesh.len());
+    surface
+}
+
+// The state shared by the tasks of clip_chunks.
+struct Chunks<'a, F> {
+    mesh: &'a Mesh,
+    voids: &'a [MercatorPoint],
+    policy: VoidPolicy,
+    clip: F,
+    // triangles done, and the last count given to progress
+    done: AtomicUsize,
+    reported: Mutex<usize>,
+    every: usize,
+    progress: &'a (dyn Fn(usize, usize) + Sync),
+}
+
+impl<F: Fn(usize, &Triangle, &mut Planes) + Sync> Chunks<'_, F> {
+    fn clip(&self, first: usize, end: usize) -> Surface {
+        let count = end.div_ceil(CHUNK) - first / CHUNK;
+        if count > 1 {
+            let middle = first + count / 2 * CHUNK;
+            let (mut left, right) =
+                rayon::join(|| self.clip(first, middle), || self.clip(middle, end));
+            left.append(right);
+            return left;
+        }
+        let mut planes = Planes::new(self.voids, self.policy);
+        for k in first..end {
+            (self.clip)(k, &self.mesh.triangle(k), &mut planes);
+        }
+        let before = self.done.fetch_add(end - first, Ordering::Relaxed);
+        let after = before + end - first;
+        if after / self.every > before / self.every {
+            // the count when the lock is taken, never less than the last one
+            let mut reported = self.reported.lock().unwrap();
+            let done = self.done.load(Ordering::Relaxed);
+            if done > *reported && done < self.mesh.len() {
+                *reported = done;
+                (self.progress)(done, self.mesh.len());
+            }
+        }
+        planes.surface
+    }
+}
+
+// clip_with_voids, calling progress with the triangles done and their total
+// about every percent.
+pub fn clip_reporting(
+    polygon: &Vec<MercatorPoint>,
+    holes: &[Vec<MercatorPoint>],
+    mesh: &Mesh,
+    voids: &[MercatorPoint],
+    policy: VoidPolicy,
+    progress: &(dyn Fn(usize, usize) + Sync),
+) -> Surface {
+    let mask = intersection::to_geo_polygon_with_holes(polygon, holes);
+    let clean = mask.union(&mask);
+    let rings: Vec<&[MercatorPoint]> = std::iter::once(polygon.as_slice())
+        .chain(holes.iter().map(|h| h.as_slice()))
+        .collect();
+    // the boundary vertices with an elevation keep it where the clip puts
+    // them, instead of the one of the triangle (see BoundaryElevation)
+    let recorded: BTreeMap<Key, f64> = rings
+        .iter()
+        .flat_map(|ring| ring.iter())
+        .filter_map(|p| Some((key(p), p.ele?)))
+        .collect();
+    clip_chunks(mesh, voids, policy, progress, |_, gridtriangle, planes| {
+        let vertices = gridtriangle.as_vector();
+        let paths = &mut planes.surface.paths;
+        let plane = match locate(&vertices, &rings, &mask) {
+            Some(true) => {
+                paths.whole += 1;
+                vertices
+            }
+            Some(false) => {
+                paths.outside += 1;
+                return;
+            }
+            None => {
+                paths.intersected += 1;
+                let mut plane = intersection::intersection(&clean, gridtriangle);
+                for p in &mut plane {
+                    if let Some(ele) = recorded.get(&key(p)) {
+                        p.ele = Some(*ele);
+                    }
+                }
+                plane
+            }
+        };
+        planes.add(plane, gridtriangle);
+    })
+}
+
+// clip_reporting for a mesh triangulated with the rings of the polygon as
+// constraints (see grid::triangulate_constrained): no triangle crosses the
+// boundary, each one is taken whole or dropped. The first triangle of each
+// connected part of the mesh is located, the others are on its side of the
+// boundary or across it.
+pub fn clip_constrained(
+    polygon: &[MercatorPoint],
+    holes: &[Vec<MercatorPoint>],
+    mesh: &Mesh,
+    constraints: &[[u32; 2]],
+    voids: &[MercatorPoint],
+    policy: VoidPolicy,
+    progress: &(dyn Fn(usize, usize) + Sync),
+) -> Surface {
+    let mask = intersection::to_geo_polygon_with_holes(polygon, holes);
+    // inside: on the side of the boundary of a located triangle
+    let inside = mesh.sides(constraints, |t| {
+        let centroid = mesh.triangle(t).centroid();
+        mask.contains(&geo::Point::new(centroid.x, centroid.y))
+    });
+    clip_chunks(mesh, voids, policy, progress, |k, triangle, planes| {
+        if !inside[k] {
+            planes.surface.paths.outside += 1;
+            return;
+        }
+        planes.surface.paths.whole += 1;
+        planes.add(triangle.as_vector(), triangle);
+    })
+}
+
+#[non_exhaustive]
+pub struct MergeOptions {
+    // maximal angle between the normals, in degrees
+    pub max_angle: f64,
+    // maximal distance of a centroid to the other plane, in meters
+    pub max_offset: f64,
+}
+
+impl Default for MergeOptions {
+    fn default() -> Self {
+        MergeOptions {
+            max_angle: 0.5,
+            max_offset: 0.1,
+        }
+    }
+}
+
+fn coplanar(a: &Plane, b: &Plane, options: &MergeOptions) -> bool {
+    let (na, nb) = (a.normal(), b.normal());
+    let cos = (na.0 * nb.0 + na.1 * nb.1 + na.2 * nb.2).min(1.0);
+    if cos.acos().to_degrees() > options.max_angle {
+        return false;
+    }
+    let (ca, cb) = (a.centroid(), b.centroid());
+    let offset = na.0 * (cb.0 - ca.0) + na.1 * (cb.1 - ca.1) + na.2 * (cb.2 - ca.2);
+    offset.abs() <= options.max_offset
+}
+
+type Key = (i64, i64);
+
+// the position rounded to the millimeter
+fn key(p: &MercatorPoint) -> Key {
+    ((p.x * 1000.0).round() as i64, (p.y * 1000.0).round() as i64)
+}
+
+// planes sharing an edge, found by their (millimeter-rounded) vertices
+fn adjacency(planes: &[Plane]) -> Vec<Vec<usize>> {
+    let mut edges: BTreeMap<(Key, Key), Vec<usize>> = BTreeMap::new();
+    for (i, plane) in planes.iter().enumerate() {
+        let n = plane.points.len();
+        for k in 0..n {
+            let (a, b) = (key(&plane.points[k]), key(&plane.points[(k + 1) % n]));
+            if a != b {
+                edges.entry((a.min(b), a.max(b))).or_default().push(i);
+            }
+        }
+    }
+    let mut ret = vec![Vec::new(); planes.len()];
+    for members in edges.values() {
+        for &i in members {
+            for &j in members {
+                if i != j && !ret[i].contains(&j) {
+                    ret[i].push(j);
+                }
+            }
+        }
+    }
+    ret
+}
+
+// drops the closing point and the vertices lying on a straight edge
+fn simplify(ring: Vec<MercatorPoint>) -> Vec<MercatorPoint> {
+    let mut ring = ring;
+    if ring.len() > 1 && ring.first() == ring.last() {
+        ring.pop();
+    }
+    let n = ring.len();
+    (0..n)
+        .filter(|&i| {
+            let (a, b, c) = (&ring[(i + n - 1) % n], &ring[i], &ring[(i + 1) % n]);
+            let cross = (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x);
+            let scale = ((c.x - a.x).powi(2) + (c.y - a.y).powi(2)).max(1e-12);
+            cross.abs() / scale > 1e-9
+        })
+        .map(|i| ring[i].clone())
+        .collect()
+}
+
+fn merge_region(planes: &[Plane], region: &[usize]) -> Option<Plane> {
+    let polygons: Vec<geo::Polygon> = region
+        .iter()
+        .map(|&i| intersection::to_geo_polygon(&planes[i].points))
+        .collect();
+    let union = geo::unary_union(&polygons);
+    // holes and disconnected unions cannot be represented by a single ring
+    if union.0.len() != 1 || !union.0[0].interiors().is_empty() {
+        return None;
+    }
+    let seed = &planes[region[0]];
+    let (nx, ny, nz) = seed.normal();
+    let (x0, y0, z0) = seed.centroid();
+    let ring = union.0[0]
+        .exterior()
+        .coords()
+        .map(|c| MercatorPoint {
+            x: c.x,
+            y: c.y,
+            ele: Some(z0 - (nx * (c.x - x0) + ny * (c.y - y0)) / nz),
+        })
+        .collect();
+    let points = simplify(ring);
+    if points.len() < 3 {
+        return None;
+    }
+    Some(Plane::new(
+        points,
+        region.iter().map(|&i| planes[i].area2d).sum(),
+        region.iter().map(|&i| planes[i].area3d).sum(),
+    ))
+}
+
+// Merges adjacent planes whose normals and offsets agree within the
+// tolerances. Each region grows from a seed and is compared to that seed, so
+// that a slowly curving surface does not collapse into one plane. The areas of
+// the merged plane are the sums of its members: the totals do not change.
+pub fn merge_coplanar(surface: Surface, options: &MergeOptions) -> Surface {
+    let planes = surface.planes;
+    let neighbors = adjacency(&planes);
+    let mut visited = vec![false; planes.len()];
+    let mut merged = Vec::new();
+    for seed in 0..planes.len() {
+        if visited[seed] {
+            continue;
+        }
+        visited[seed] = true;
+        let mut region = vec![seed];
+        let mut k = 0;
+        while k < region.len() {
+            for &j in &neighbors[region[k]] {
+                if !visited[j] && coplanar(&planes[seed], &planes[j], options) {
+                    visited[j] = true;
+                    region.push(j);
+                }
+            }
+            k += 1;
+        }
+        if region.len() == 1 {
+            merged.push(planes[seed].clone());
+            continue;
+        }
+        match merge_region(&planes, &region) {
+            Some(plane) => merged.push(plane),
+            None => merged.extend(region.iter().map(|&i| planes[i].clone())),
+        }
+    }
+    log::info!("merged {} planes into {}", planes.len(), merged.len());
+    Surface {
+        planes: merged,
+        projected2d: surface.projected2d,
+        projected3d: surface.projected3d,
+        flagged: surface.flagged,
+        discarded2d: surface.discarded2d,
+        paths: surface.paths,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triangulation;
+
+    fn field(slope: impl Fn(f64, f64) -> f64) -> Vec<MercatorPoint> {
+        let mut ret = Vec::new();
+        for i in 0..20 {
+            for j in 0..20 {
+                let (x, y) = (i as f64 * 30.0, j as f64 * 30.0);
+                ret.push(MercatorPoint {
+                    x,
+                    y,
+                    ele: Some(slope(x, y)),
+                });
+            }
+        }
+        ret
+    }
+
+    fn square() -> Vec<MercatorPoint> {
+        [(15.0, 15.0), (500.0, 15.0), (500.0, 500.0), (15.0, 500.0)]
+            .iter()
+            .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+            .collect()
+    }
+
+    const OPTIONS: MergeOptions = MergeOptions {
+        max_angle: 0.5,
+        max_offset: 0.1,
+    };
+
+    #[test]
+    fn test_grid_aligned_boundary() {
+        let mesh = triangulation::grid::triangulate(&field(|x, y| 0.2 * x + 0.1 * y));
+        let aligned: Vec<MercatorPoint> =
+            [(60.0, 60.0), (420.0, 60.0), (420.0, 420.0), (60.0, 420.0)]
+                .iter()
+                .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+                .collect();
+        let surface = clip(&aligned, &mesh);
+        assert_eq!(surface.paths.intersected, 0);
+        assert_eq!(surface.paths.whole, 2 * 12 * 12);
+        assert_eq!(surface.paths.whole + surface.paths.outside, mesh.len());
+        let a2d = 360.0 * 360.0;
+        let a3d = a2d * (1.0 + 0.2f64.powi(2) + 0.1f64.powi(2)).sqrt();
+        assert!((surface.projected2d - a2d).abs() < 1e-9 * a2d);
+        assert!((surface.projected3d - a3d).abs() < 1e-9 * a3d);
+
+        // a boundary across the grid still goes through the intersection
+        let surface = clip(&square(), &mesh);
+        assert!(surface.paths.intersected > 0);
+        assert!(surface.paths.whole > 0);
+        assert!((surface.projected2d - 485.0 * 485.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_missing_elevation() {
+        let mut samples = field(|x, y| 0.2 * x + 0.1 * y);
+        // inside, then on the boundary
+        for (x, y) in [(150.0, 150.0), (0.0, 30.0)] {
+            samples
+                .iter_mut()
+                .find(|p| p.x == x && p.y == y)
+                .unwrap()
+                .ele = None;
+        }
+        let mesh = triangulation::grid::triangulate(&samples);
+        let surface = clip(&square(), &mesh);
+        assert!(surface.paths.patched > 0);
+        assert!(surface.paths.skipped > 0);
+        // the skipped planes are in the flat area left out
+        let whole = clip(
+            &square(),
+            &triangulation::grid::triangulate(&field(|_, _| 0.0)),
+        );
+        let flat = surface.projected2d + surface.discarded2d;
+        assert!(surface.discarded2d > 0.0);
+        assert!((flat - whole.projected2d).abs() < 1e-6, "{}", flat);
+        let ratio = surface.projected3d / surface.projected2d;
+        assert!((ratio - 1.05f64.sqrt()).abs() < 1e-3, "{}", ratio);
+    }
+
+    #[test]
+    fn test_recorded_boundary_elevations() {
+        let z = |x: f64, y: f64| 0.2 * x + 0.1 * y;
+        let mesh = triangulation::grid::triangulate(&field(z));
+        let interpolated = clip(&square(), &mesh);
+        // on the terrain, as the interpolation gives them
+        let mut recorded = square();
+        for p in &mut recorded {
+            p.ele = Some(z(p.x, p.y));
+        }
+        let same = clip(&recorded, &mesh);
+        assert!((same.projected3d - interpolated.projected3d).abs() < 1e-6);
+        // a corner 5 m above the terrain
+        recorded[0].ele = Some(z(15.0, 15.0) + 5.0);
+        let surface = clip(&recorded, &mesh);
+        let corner = surface
+            .planes
+            .iter()
+            .flat_map(|plane| &plane.points)
+            .find(|p| p.x == 15.0 && p.y == 15.0)
+            .unwrap();
+        assert_eq!(corner.ele, recorded[0].ele);
+        assert!(surface.projected3d > interpolated.projected3d);
+        assert!((surface.projected2d - interpolated.projected2d).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_constrained() {
+        // a detailed boundary, a hole across the grid
+        let circle: Vec<MercatorPoint> = (0..200)
+            .map(|k| {
+                let a = k as f64 * std::f64::consts::PI / 100.0;
+                let r = 230.0 + 10.0 * (7.0 * a).sin();
+                MercatorPoint {
+                    x: 285.0 + r * a.cos(),
+                    y: 285.0 + r * a.sin(),
+                    ele: None,
+                }
+            })
+            .collect();
+        let holes = vec![
+            [
+                (200.5, 200.5),
+                (300.5, 200.5),
+                (300.5, 320.5),
+                (200.5, 320.5),
+            ]
+            .iter()
+            .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+            .collect::<Vec<_>>(),
+        ];
+        let rings = [circle.as_slice(), holes[0].as_slice()];
+        for z in [(|x, y| 0.2 * x + 0.1 * y) as fn(f64, f64) -> f64, |x, y| {
+            30.0 * (x / 80.0).sin() * (y / 60.0).cos()
+        }] {
+            let points = field(z);
+            let clipped = clip_with_voids(
+                &circle,
+                &holes,
+                &triangulation::grid::triangulate(&points),
+                &[],
+                VoidPolicy::Keep,
+            );
+            let (mesh, constraints) =
+                triangulation::grid::triangulate_constrained(&points, &rings, &|_| None);
+            let surface = clip_constrained(
+                &circle,
+                &holes,
+                &mesh,
+                &constraints,
+                &[],
+                VoidPolicy::Keep,
+                &|_, _| {},
+            );
+            assert_eq!(surface.paths.intersected, 0);
+            assert_eq!(surface.paths.whole + surface.paths.outside, mesh.len());
+            assert!((surface.projected2d - clipped.projected2d).abs() < 1e-6 * clipped.projected2d);
+            let error = surface.projected3d / clipped.projected3d - 1.0;
+            assert!(error.abs() < 1e-3, "{}", error);
+        }
+
+        // overhanging the samples: both count the part on them
+        let overhang: Vec<MercatorPoint> = [(300.0, -100.0), (700.0, -100.0), (700.0, 400.0)]
+            .iter()
+            .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+            .collect();
+        let points = field(|x, y| 0.2 * x + 0.1 * y);
+        let clipped = clip(&overhang, &triangulation::grid::triangulate(&points));
+        let (mesh, constraints) =
+            triangulation::grid::triangulate_constrained(&points, &[&overhang], &|_| None);
+        let surface = clip_constrained(
+            &overhang,
+            &[],
+            &mesh,
+            &constraints,
+            &[],
+            VoidPolicy::Keep,
+            &|_, _| {},
+        );
+        assert_eq!(surface.paths.patched, 0);
+        assert!((surface.projected2d - clipped.projected2d).abs() < 1e-6 * clipped.projected2d);
+        assert!((surface.projected3d - clipped.projected3d).abs() < 1e-6 * clipped.projected3d);
+    }
+
+    #[test]
+    fn test_polygon_with_holes() {
+        let ring = |x0: f64, y0: f64, x1: f64, y1: f64| -> Vec<MercatorPoint> {
+            [(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+                .iter()
+                .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+                .collect()
+        };
+        // across the grid, and within a single triangle
+        let holes = [
+            ring(100.5, 120.5, 300.5, 320.5),
+            ring(32.0, 52.0, 34.0, 54.0),
+        ];
+        let a2d = 485.0 * 485.0 - 200.0 * 200.0 - 2.0 * 2.0;
+        let flat = triangulation::grid::triangulate(&field(|_, _| 0.0));
+        let surface = clip_with_voids(&square(), &holes, &flat, &[], VoidPolicy::Keep);
+        assert!(
+            (surface.projected2d - a2d).abs() < 1e-6,
+            "{}",
+            surface.projected2d
+        );
+        assert!((surface.projected3d - a2d).abs() < 1e-6);
+        let planes2d: f64 = surface.planes.iter().map(|p| p.area2d).sum();
+        assert!((planes2d - a2d).abs() < 1e-6);
+
+        let slope = triangulation::grid::triangulate(&field(|x, y| 0.2 * x + 0.1 * y));
+        let surface = clip_with_voids(&square(), &holes, &slope, &[], VoidPolicy::Keep);
+        let a3d = a2d * (1.0 + 0.2f64.powi(2) + 0.1f64.powi(2)).sqrt();
+        assert!((surface.projected2d - a2d).abs() < 1e-6);
+        assert!((surface.projected3d - a3d).abs() < 1e-6 * a3d);
+    }
+
+    #[test]
+    fn test_threads_deterministic() {
+        // a wavy ring on rough terrain: many chunks, many intersected triangles
+        let mut points = Vec::new();
+        for i in 0..70 {
+            for j in 0..70 {
+                let (x, y) = (i as f64 * 30.0, j as f64 * 30.0);
+                points.push(MercatorPoint {
+                    x,
+                    y,
+                    ele: Some(100.0 * (x / 300.0).sin() * (y / 200.0).cos()),
+                });
+            }
+        }
+        let mesh = triangulation::grid::triangulate(&points);
+        let ring: Vec<MercatorPoint> = (0..200)
+            .map(|k| {
+                let a = k as f64 * std::f64::consts::TAU / 200.0;
+                let r = 900.0 + 70.0 * (9.0 * a).sin();
+                MercatorPoint {
+                    x: 1035.0 + r * a.cos(),
+                    y: 1035.0 + r * a.sin(),
+                    ele: None,
+                }
+            })
+            .collect();
+        let bbox = crate::point::MercatorBoundingBox {
+            min: points[0].clone(),
+            max: points.last().unwrap().clone(),
+        };
+        let run = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .build()
+                .unwrap();
+            let surface = pool.install(|| clip(&ring, &mesh));
+            let mut svg = crate::svg::Svg::init(&bbox);
+            svg.set_regions(false);
+            svg.add_layer("slope", true);
+            for plane in &surface.planes {
+                let color = crate::svg::color_for_slope(plane.slope());
+                svg.add_polygon_to_layer("slope", &plane.points, &color);
+            }
+            (surface, svg.render().unwrap())
+        };
+        let (surface, svg) = run(1);
+        assert!(surface.paths.intersected > 300);
+        for threads in [4, 4, 7] {
+            let (other, other_svg) = run(threads);
+            assert_eq!(other.projected2d.to_bits(), surface.projected2d.to_bits());
+            assert_eq!(other.projected3d.to_bits(), surface.projected3d.to_bits());
+            assert_eq!(other.paths, surface.paths);
+            assert!(other_svg == svg, "{} threads", threads);
+        }
+    }
+
+    #[test]
+    fn test_merge_constant_slope() {
+        let mesh = triangulation::grid::triangulate(&field(|x, y| 0.2 * x + 0.1 * y));
+        let surface = clip(&square(), &mesh);
+        let (n, a2d, a3d) = (
+            surface.planes.len(),
+            surface.projected2d,
+            surface.projected3d,
+        );
+        assert!(n > 100);
+        let merged = merge_coplanar(surface, &OPTIONS);
+        assert!(merged.planes.len() <= 2, "{} planes", merged.planes.len());
+        assert_eq!(merged.projected2d, a2d);
+        assert_eq!(merged.projected3d, a3d);
+        let sum3d: f64 = merged.planes.iter().map(|p| p.area3d).sum();
+        assert!((sum3d - a3d).abs() < 1e-6 * a3d);
+        let expected = (0.2f64.powi(2) + 0.1f64.powi(2)).sqrt() * 100.0;
+        for plane in &merged.planes {
+            assert!((polygon::slope(&plane.points) - expected).abs() < 1e-6);
+        }
+    }
+
+    // 30x30 samples of a slope, with a 10x10 void in the middle
+    fn holed() -> (Vec<MercatorPoint>, Vec<MercatorPoint>) {
+        let (mut points, mut voids) = (Vec::new(), Vec::new());
+        for i in 0..30 {
+            for j in 0..30 {
+                let p = MercatorPoint {
+                    x: i as f64 * 30.0,
+                    y: j as f64 * 30.0,
+                    ele: Some(0.2 * i as f64 * 30.0),
+                };
+                if (10..20).contains(&i) && (10..20).contains(&j) {
+                    voids.push(p);
+                } else {
+                    points.push(p);
+                }
+            }
+        }
+        (points, voids)
+    }
+
+    #[test]
+    fn test_void_policies() {
+        let (points, voids) = holed();
+        let mesh = triangulation::grid::triangulate(&points);
+        let square: Vec<MercatorPoint> = [(0.0, 0.0), (870.0, 0.0), (870.0, 870.0), (0.0, 870.0)]
+            .iter()
+            .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+            .collect();
+        let keep = clip_with_voids(&square, &[], &mesh, &voids, VoidPolicy::Keep);
+        assert!((keep.projected2d - 870.0 * 870.0).abs() < 1.0);
+        assert!(keep.flagged.is_empty());
+
+        // the hole spans 11x11 cells between the valid samples around it, the
+        // triangles cutting its corners do not contain a skipped pixel
+        let hole = 330.0 * 330.0;
+        let flag = clip_with_voids(&square, &[], &mesh, &voids, VoidPolicy::Flag);
+        assert_eq!(flag.projected2d, keep.projected2d);
+        assert_eq!(flag.projected3d, keep.projected3d);
+        let flagged2d: f64 = flag.flagged.iter().map(|p| p.area2d).sum();
+        assert!(
+            flagged2d > 300.0 * 300.0 && flagged2d <= hole,
+            "{}",
+            flagged2d
+        );
+
+        let exclude = clip_with_voids(&square, &[], &mesh, &voids, VoidPolicy::Exclude);
+        assert!(exclude.flagged.is_empty());
+        assert!((exclude.discarded2d - flagged2d).abs() < 1e-6);
+        assert!((exclude.projected2d + exclude.discarded2d - keep.projected2d).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_merge_keeps_ridge() {
+        let mesh = triangulation::grid::triangulate(&field(|x, _| (x - 300.0).abs() * 0.3));
+        let surface = clip(&square(), &mesh);
+        let merged = merge_coplanar(surface, &OPTIONS);
+        assert!(merged.planes.len() >= 2);
+        assert!(merged.planes.len() <= 4, "{} planes", merged.planes.len());
+    }
+
+    #[test]
+    fn test_sliver_inherits_normal() {
+        // 1 cm by 50 m, the far side 2 cm higher: a fitted slope across of 200%
+        let point = |x: f64, y: f64, z: f64| MercatorPoint { x, y, ele: Some(z) };
+        let mut sliver = Plane::new(
+            vec![
+                point(0.0, 0.0, 0.0),
+                point(50.0, 0.0, 10.0),
+                point(50.0, 0.01, 10.02),
+                point(0.0, 0.01, 0.02),
+            ],
+            0.5,
+            0.5,
+        );
+        assert!(sliver.conditioning() < MIN_CONDITIONING);
+        assert!(sliver.slope() > 100.0);
+        sliver.inherited = Some(newell(&[
+            point(0.0, 0.0, 0.0),
+            point(50.0, 0.0, 10.0),
+            point(0.0, 30.0, 0.0),
+        ]));
+        assert!((sliver.slope() - 20.0).abs() < 1e-9);
+        let square = Plane::new(
+            vec![
+                point(0.0, 0.0, 0.0),
+                point(30.0, 0.0, 3.0),
+                point(0.0, 30.0, 0.0),
+            ],
+            450.0,
+            450.0,
+        );
+        assert!(square.conditioning() > 0.1);
+
+        // a strip along a row of the grid: every piece is a sliver with the
+        // slope of its triangle
+        let mesh = triangulation::grid::triangulate(&field(|x, y| 0.2 * x + 0.1 * y));
+        let strip: Vec<MercatorPoint> = [
+            (100.0, 200.0),
+            (150.0, 200.0),
+            (150.0, 200.01),
+            (100.0, 200.01),
+        ]
+        .iter()
+        .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+        .collect();
+        let surface = clip(&strip, &mesh);
+        assert!(!surface.planes.is_empty());
+        assert_eq!(surface.paths.slivers, surface.planes.len());
+        for plane in &surface.planes {
+            assert!(plane.inherited.is_some());
+            assert!((plane.slope() - 100.0 * 0.2f64.hypot(0.1)).abs() < 1e-6);
+        }
+        assert!(
+            eigenvalues([[2.0, 1.0, 0.0], [1.0, 2.0, 0.0], [0.0, 0.0, 5.0]])
+                .iter()
+                .zip([5.0, 3.0, 1.0])
+                .all(|(a, b)| (a - b).abs() < 1e-9)
+        );
+    }
+
+    #[test]
+    fn test_sliver_missing_grid_elevation() {
+        // the strip crosses triangles of the row below with a missing vertex,
+        // its recorded elevations and the patched ones complete its pieces
+        let z = |x: f64, y: f64| 0.2 * x + 0.1 * y;
+        let mut samples = field(z);
+        samples
+            .iter_mut()
+            .find(|p| p.x == 120.0 && p.y == 210.0)
+            .unwrap()
+            .ele = None;
+        let mesh = triangulation::grid::triangulate(&samples);
+        let strip: Vec<MercatorPoint> = [
+            (100.0, 200.0),
+            (150.0, 200.0),
+            (150.0, 200.01),
+            (100.0, 200.01),
+        ]
+        .iter()
+        .map(|&(x, y)| MercatorPoint {
+            x,
+            y,
+            ele: Some(z(x, y)),
+        })
+        .collect();
+        let surface = clip(&strip, &mesh);
+        assert!(surface.paths.patched > 0);
+        assert!(!surface.planes.is_empty());
+        // the pieces of the triangles without a normal keep their own
+        assert_eq!(surface.paths.slivers, surface.planes.len());
+        assert!(surface.planes.iter().any(|p| p.inherited.is_none()));
+        let flat = surface.projected2d + surface.discarded2d;
+        assert!((flat - 0.5).abs() < 1e-6, "{}", flat);
+    }
+}
@@ -1,17 +1,44 @@
-use std::path::Path;
+use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use serde::Serialize;
 
 use crate::{
-    mercator::WebMercatorProjection,
+    asciigrid::AsciiGrid,
+    download::DownloadError,
+    format::{self, Quantity},
+    hgt::HgtTile,
+    hgtfile::HgtFile,
+    mercator::{ProjectionError, WebMercatorProjection},
+    morphology,
+    options::{IoBackend, NodataOverride},
     point::{MercatorBoundingBox, MercatorPoint, WGS84BoundingBox, WGS84Point},
     polygon::Polygon,
+    rasterio::{
+        self, AreaOrPoint, Counting, Flattened, GdalRasterIo, IoCounters, IoStats, RasterIo,
+        RasterIoError,
+    },
+    reference::GeometryError,
+    registry::DatasetRegistry,
+    voidfill,
 };
 
-pub struct Raster {
-    upper_left: WGS84Point,
+#[derive(Clone)]
+pub(crate) struct Raster {
+    // the position of the sample of pixel (0, 0), in degrees or in the
+    // coordinates of crs
+    origin: (f64, f64),
     xsize: usize,
     ysize: usize,
     xstep: f64,
     ystep: f64,
+    // the row and column rotation terms of the geotransform, usually 0
+    rotation: (f64, f64),
+    crs: Option<Crs>,
 }
 
 struct RasterBox {
@@ -19,199 +46,899 @@ struct RasterBox {
     max: (isize, isize),
 }
 
+// The CRS of a raster whose geotransform is not in WGS84 degrees (LV95,
+// UTM...), between which and WGS84 its pixel positions are transformed.
+#[derive(Clone)]
+struct Crs {
+    proj4: String,
+    wgs84: proj4rs::proj::Proj,
+    native: proj4rs::proj::Proj,
+}
+
+impl Crs {
+    fn make(proj4: &str) -> Result<Crs, RasterIoError> {
+        use proj4rs::proj::Proj;
+        let parse = |spec: &str| {
+            Proj::from_proj_string(spec).map_err(|e| RasterIoError(format!("{}: {}", spec, e)))
+        };
+        Ok(Crs {
+            proj4: proj4.to_string(),
+            wgs84: parse("+proj=longlat +ellps=WGS84 +datum=WGS84 +no_defs")?,
+            native: parse(proj4)?,
+        })
+    }
+    fn to_native(&self, wgs: &WGS84Point) -> (f64, f64) {
+        let mut p = (wgs.lon.to_radians(), wgs.lat.to_radians());
+        if proj4rs::transform::transform(&self.wgs84, &self.native, &mut p).is_err() {
+            p = (f64::NAN, f64::NAN);
+        }
+        p
+    }
+    fn to_wgs84(&self, x: f64, y: f64) -> WGS84Point {
+        let mut p = (x, y);
+        if proj4rs::transform::transform(&self.native, &self.wgs84, &mut p).is_err() {
+            p = (f64::NAN, f64::NAN);
+        }
+        WGS84Point {
+            lon: p.0.to_degrees(),
+            lat: p.1.to_degrees(),
+            ele: None,
+        }
+    }
+}
+
 impl Raster {
-    pub fn make(dataset: &gdal::Dataset) -> Raster {
-        let geo = dataset.geo_transform().unwrap();
+    pub fn make(dataset: &dyn RasterIo) -> Result<Raster, RasterIoError> {
+        let geo = dataset.geo_transform()?;
         let raster_size = dataset.raster_size();
+        if raster_size.0 == 0 || raster_size.1 == 0 || geo[1] * geo[5] - geo[2] * geo[4] == 0.0 {
+            return Err(RasterIoError(format!(
+                "{}x{} pixels of {:?}: no grid",
+                raster_size.0, raster_size.1, geo
+            )));
+        }
+        let crs = match rasterio::native_proj4(&dataset.projection())? {
+            Some(proj4) => Some(Crs::make(&proj4)?),
+            None => None,
+        };
         // [0] Upper Left Easting (Longitude)
         // [1] Pixel Width
         // [2] Row Rotation (usually 0)
         // [3] Upper Left Northing (Latitude)
         // [4] Column Rotation (usually 0)
         // [5] Pixel Height (usually negative)
-        Raster {
-            upper_left: WGS84Point {
-                lon: geo[0],
-                lat: geo[3],
-                ele: None,
-            },
+        // South-up rasters have a positive height and [3] at the lower left:
+        // the inverse of the whole transform in pixel() works for both.
+        // The values of area rasters are those of the cells, at their centers
+        let offset = match dataset.area_or_point() {
+            AreaOrPoint::Area => 0.5,
+            AreaOrPoint::Point => 0.0,
+        };
+        Ok(Raster {
+            origin: (
+                geo[0] + offset * (geo[1] + geo[2]),
+                geo[3] + offset * (geo[4] + geo[5]),
+            ),
             xsize: raster_size.0,
             ysize: raster_size.1,
             xstep: geo[1],
             ystep: geo[5],
-        }
+            rotation: (geo[2], geo[4]),
+            crs,
+        })
+    }
+
+    // the rows are not along the parallels: projected or rotated
+    fn oblique(&self) -> bool {
+        self.crs.is_some() || self.rotation != (0.0, 0.0)
+    }
+
+    // the inverse of the geotransform, from native coordinates to pixels
+    fn pixel(&self, x: f64, y: f64) -> (f64, f64) {
+        let (dx, dy) = (x - self.origin.0, y - self.origin.1);
+        let (rx, ry) = self.rotation;
+        let det = self.xstep * self.ystep - rx * ry;
+        (
+            (self.ystep * dx - rx * dy) / det,
+            (self.xstep * dy - ry * dx) / det,
+        )
     }
+
     pub fn coordinates(&self, world: &WGS84Point) -> (f64, f64) {
-        let x = (world.lon - self.upper_left.lon) / self.xstep;
-        let y = (world.lat - self.upper_left.lat) / self.ystep;
-        (x, y)
+        let (x, y) = match &self.crs {
+            Some(crs) => crs.to_native(world),
+            None => (world.lon, world.lat),
+        };
+        self.pixel(x, y)
     }
 
     pub fn icoordinates(&self, world: &WGS84Point) -> (isize, isize) {
-        let x = (world.lon - self.upper_left.lon) / self.xstep;
-        let y = (world.lat - self.upper_left.lat) / self.ystep;
+        let (x, y) = self.coordinates(world);
         (x.round() as isize, y.round() as isize)
     }
 
+    #[cfg(test)]
     pub fn wgs84(&self, col: isize, row: isize) -> WGS84Point {
-        let lon = self.upper_left.lon + (col as f64) * self.xstep;
-        let lat = self.upper_left.lat + (row as f64) * self.ystep;
-        WGS84Point {
-            lon,
-            lat,
-            ele: None,
+        self.wgs84_fractional(col as f64, row as f64)
+    }
+
+    pub fn wgs84_fractional(&self, col: f64, row: f64) -> WGS84Point {
+        let x = self.origin.0 + col * self.xstep + row * self.rotation.0;
+        let y = self.origin.1 + col * self.rotation.1 + row * self.ystep;
+        match &self.crs {
+            Some(crs) => crs.to_wgs84(x, y),
+            None => WGS84Point {
+                lon: x,
+                lat: y,
+                ele: None,
+            },
+        }
+    }
+
+    // the box around the positions of the pixels of the rectangle, its edges
+    // followed when they are curved in degrees
+    fn envelope(&self, min: (f64, f64), max: (f64, f64)) -> WGS84BoundingBox {
+        let n = if self.crs.is_some() { 16 } else { 1 };
+        let mut points = Vec::new();
+        for k in 0..=n {
+            let t = k as f64 / n as f64;
+            let (col, row) = (min.0 + t * (max.0 - min.0), min.1 + t * (max.1 - min.1));
+            points.extend([(col, min.1), (col, max.1), (min.0, row), (max.0, row)]);
         }
+        let mut ret = WGS84BoundingBox::from(
+            &self.wgs84_fractional(min.0, min.1),
+            &self.wgs84_fractional(max.0, max.1),
+        );
+        for (col, row) in points {
+            let p = self.wgs84_fractional(col, row);
+            ret.min.lon = ret.min.lon.min(p.lon);
+            ret.min.lat = ret.min.lat.min(p.lat);
+            ret.max.lon = ret.max.lon.max(p.lon);
+            ret.max.lat = ret.max.lat.max(p.lat);
+        }
+        ret
     }
 }
 
 pub struct Dataset {
     pub filename: String,
-    g: gdal::Dataset,
+    io: Rc<dyn RasterIo>,
+    counters: Arc<IoCounters>,
     raster: Raster,
-    projection: String,
+    // the projection of the samples, built once
+    projection: Rc<WebMercatorProjection>,
+    // the nodata pixels filled so far (SampleOptions::fill_radius)
+    filled: Cell<usize>,
 }
 
 impl Dataset {
-    pub fn open(filename: &String, projection: &String) -> Self {
+    pub fn open(
+        filename: &String,
+        projection: &String,
+        backend: IoBackend,
+    ) -> Result<Self, DatasetError> {
         let path = Path::new(filename.as_str());
-        let g = gdal::Dataset::open(path).unwrap();
-        let transform = Raster::make(&g);
-        Dataset {
+        let unreadable = |error| DatasetError::Unreadable {
             filename: filename.clone(),
-            g,
-            raster: transform,
-            projection: projection.clone(),
-        }
-    }
-    pub fn info(&self) {
-        log::info!("dataset: {}", self.filename);
-        log::info!("dataset: {}", self.projection);
-        log::info!("dataset: xsize {}", self.raster.xsize);
-        log::info!("dataset: ysize {}", self.raster.ysize);
-        log::info!("dataset: xstep {:.5}", self.raster.xstep);
-        log::info!("dataset: ystep {:.5}", self.raster.ystep);
-        log::info!("dataset: wgs bbox: {}", self.wgsbbox());
-        log::info!("dataset: mercator bbox: {}", self.mercatorbbox());
-        log::info!("dataset: width: {:.1}", self.mercatorbbox().width());
-        log::info!("dataset: height: {:.1}", self.mercatorbbox().height());
-        log::info!("dataset: area: {:.1}", self.mercatorbbox().area());
-    }
-    pub fn wgsbbox(&self) -> WGS84BoundingBox {
-        let (width, height) = self.g.raster_size();
-        let geo = self.g.geo_transform().unwrap();
-        let ul_lon = geo[0];
-        let ul_lat = geo[3];
-        let pixel_width = geo[1];
-        let pixel_height = geo[5];
-        let p1 = WGS84Point {
-            lon: ul_lon,
-            lat: ul_lat,
-            ele: None,
+            error,
         };
-        let p2 = WGS84Point {
-            lon: p1.lon + (width as f64 * pixel_width),
-            lat: p1.lat + (height as f64 * pixel_height),
-            ele: None,
+        let mapped = || match backend {
+            IoBackend::Gdal => Ok(None),
+            _ if !HgtFile::accepts(path) => Ok(None),
+            IoBackend::Mmap => HgtFile::open(path).map(Some),
+            _ => Ok(HgtFile::open(path)
+                .inspect_err(|e| log::info!("{}, read through GDAL", e))
+                .ok()),
         };
-
-        WGS84BoundingBox {
-            min: WGS84Point {
-                lon: p1.lon.min(p2.lon),
-                lat: p1.lat.min(p2.lat),
-                ele: None,
+        let io: Box<dyn RasterIo> = match path.extension().and_then(|ext| ext.to_str()) {
+            _ if rasterio::is_remote(filename) => {
+                Box::new(GdalRasterIo::open_remote(filename).map_err(unreadable)?)
+            }
+            Some("asc") => Box::new(AsciiGrid::open(path).map_err(unreadable)?),
+            _ => match mapped().map_err(unreadable)? {
+                Some(tile) => Box::new(tile),
+                None => Box::new(GdalRasterIo::open(path).map_err(unreadable)?),
             },
-            max: WGS84Point {
-                lon: p1.lon.max(p2.lon),
-                lat: p1.lat.max(p2.lat),
-                ele: None,
+        };
+        Self::from_io(
+            filename,
+            rasterio::record_if_requested(io, filename),
+            projection,
+        )
+    }
+    pub fn from_io(
+        filename: &str,
+        io: Box<dyn RasterIo>,
+        projection: &str,
+    ) -> Result<Self, DatasetError> {
+        let transform = Raster::make(io.as_ref()).map_err(|error| DatasetError::Unreadable {
+            filename: filename.to_string(),
+            error,
+        })?;
+        let io = Counting::new(io);
+        Ok(Dataset {
+            filename: filename.to_string(),
+            counters: io.counters(),
+            io: Rc::new(io),
+            raster: transform,
+            projection: Rc::new(WebMercatorProjection::make(projection)),
+            filled: Cell::new(0),
+        })
+    }
+    // the same dataset with every valid sample at the given elevation
+    pub fn flattened(&self, elevation: f64) -> Dataset {
+        let io = Flattened::new(self.io.clone(), elevation);
+        Dataset {
+            filename: self.filename.clone(),
+            raster: self.raster.clone(),
+            io: Rc::new(io),
+            counters: self.counters.clone(),
+            projection: self.projection.clone(),
+            filled: Cell::new(0),
+        }
+    }
+    // The same dataset with its samples in another projection, for another
+    // polygon: the file is not opened again, and the reads are counted apart.
+    pub fn reprojected(&self, projection: &str) -> Dataset {
+        let io = Counting::shared(self.io.clone());
+        Dataset {
+            filename: self.filename.clone(),
+            raster: self.raster.clone(),
+            counters: io.counters(),
+            io: Rc::new(io),
+            projection: if self.projection.spec() == projection {
+                self.projection.clone()
+            } else {
+                Rc::new(WebMercatorProjection::make(projection))
             },
+            filled: Cell::new(0),
         }
     }
+    // the reads so far, of this dataset and of its flattened copies
+    pub fn io_stats(&self) -> IoStats {
+        self.counters.stats(&self.filename)
+    }
+    // the nodata pixels filled by interpolation so far
+    pub fn filled(&self) -> usize {
+        self.filled.get()
+    }
+    // The values counted as nodata: the one declared by the band and the
+    // override, the one naming the dataset or else the global one, with
+    // where each comes from.
+    pub fn nodata(
+        &self,
+        overrides: &[NodataOverride],
+    ) -> Result<Vec<(f64, String)>, RasterIoError> {
+        let mut ret = Vec::new();
+        if let Some(n) = self.io.no_data_value(1)? {
+            ret.push((n, "band".to_string()));
+        }
+        let path = Path::new(&self.filename);
+        let named = overrides
+            .iter()
+            .find(|o| o.dataset.as_ref().is_some_and(|d| path.ends_with(d)));
+        let global = overrides.iter().find(|o| o.dataset.is_none());
+        if let Some(o) = named.or(global) {
+            ret.push((o.value, format!("--nodata {}", String::from(o.clone()))));
+        }
+        Ok(ret)
+    }
+    fn unreadable(&self, error: RasterIoError) -> DatasetError {
+        DatasetError::Unreadable {
+            filename: self.filename.clone(),
+            error,
+        }
+    }
+    // what info logs, with the declared nodata value only
+    pub fn info_data(&self) -> Result<DatasetInfo, DatasetError> {
+        let mercator_bbox = self.mercatorbbox()?;
+        Ok(DatasetInfo {
+            filename: self.filename.clone(),
+            projection: self.projection.spec().to_string(),
+            nodata: self.nodata(&[]).map_err(|e| self.unreadable(e))?,
+            crs: self.raster.crs.as_ref().map(|c| c.proj4.clone()),
+            size: (self.raster.xsize, self.raster.ysize),
+            step: (self.raster.xstep, self.raster.ystep),
+            pixel_size: self.pixel_size(),
+            rotation: self.raster.rotation,
+            wgs_bbox: self.wgsbbox(),
+            area: mercator_bbox.area(),
+            mercator_bbox,
+        })
+    }
+    pub fn info(&self, overrides: &[NodataOverride]) {
+        let info = self.info_data().and_then(|info| {
+            let nodata = self.nodata(overrides).map_err(|e| self.unreadable(e))?;
+            Ok(DatasetInfo { nodata, ..info })
+        });
+        match info {
+            Ok(info) => {
+                for line in info.to_string().lines() {
+                    log::info!("{}", line);
+                }
+            }
+            Err(e) => log::warn!("{}", e),
+        }
+    }
+    // The width and height of the pixel at the center of the raster, in
+    // meters: along the ellipsoid, unlike the steps in degrees.
+    pub fn pixel_size(&self) -> (f64, f64) {
+        let (col, row) = (
+            (self.raster.xsize / 2) as f64,
+            (self.raster.ysize / 2) as f64,
+        );
+        let center = self.raster.wgs84_fractional(col, row);
+        (
+            center.distance_to(&self.raster.wgs84_fractional(col + 1.0, row)),
+            center.distance_to(&self.raster.wgs84_fractional(col, row + 1.0)),
+        )
+    }
+    // the side of the square of the area of a pixel, along the ellipsoid:
+    // unlike cell_size, not stretched by the projection
+    pub fn ground_cell_size(&self) -> f64 {
+        let (width, height) = self.pixel_size();
+        (width * height).sqrt()
+    }
+    // the cells of the samples, half a pixel around them
+    pub fn wgsbbox(&self) -> WGS84BoundingBox {
+        let (width, height) = (self.raster.xsize as f64, self.raster.ysize as f64);
+        self.raster
+            .envelope((-0.5, -0.5), (width - 0.5, height - 0.5))
+    }
     fn raster_box(&self, b: &WGS84BoundingBox) -> RasterBox {
-        // Convert the intersection bbox to raster coordinates
-        // For min point (top-left in intersection)
-        let p1 = self.raster.coordinates(&b.min);
-        let p2 = self.raster.coordinates(&b.max);
-        let minpix = (p1.0.min(p2.0), p1.1.min(p2.1));
-        let maxpix = (p1.0.max(p2.0), p1.1.max(p2.1));
+        // Convert the intersection bbox to raster coordinates: its corners,
+        // and a pixel more around a projected or rotated raster, whose rows
+        // are not along the parallels
+        let corner = |lon: f64, lat: f64| {
+            self.raster.coordinates(&WGS84Point {
+                lon,
+                lat,
+                ele: None,
+            })
+        };
+        let mut corners = vec![corner(b.min.lon, b.min.lat), corner(b.max.lon, b.max.lat)];
+        let mut margin = 0;
+        if self.raster.oblique() {
+            corners.push(corner(b.min.lon, b.max.lat));
+            corners.push(corner(b.max.lon, b.min.lat));
+            margin = 1;
+        }
+        let mut minpix = (f64::INFINITY, f64::INFINITY);
+        let mut maxpix = (f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for (col, row) in corners {
+            minpix = (minpix.0.min(col), minpix.1.min(row));
+            maxpix = (maxpix.0.max(col), maxpix.1.max(row));
+        }
         RasterBox {
-            min: (minpix.0.floor() as isize, minpix.1.floor() as isize),
-            max: (maxpix.0.ceil() as isize, maxpix.1.ceil() as isize),
+            min: (
+                minpix.0.floor() as isize - margin,
+                minpix.1.floor() as isize - margin,
+            ),
+            max: (
+                maxpix.0.ceil() as isize + margin,
+                maxpix.1.ceil() as isize + margin,
+            ),
         }
     }
     pub fn snap(&self, b: &mut WGS84BoundingBox) {
         let rb = self.raster_box(b);
-        let p1 = self.raster.wgs84(rb.min.0, rb.min.1);
-        let p2 = self.raster.wgs84(rb.max.0, rb.max.1);
-        let ret = WGS84BoundingBox::from(&p1, &p2);
+        let ret = self.raster.envelope(
+            (rb.min.0 as f64, rb.min.1 as f64),
+            (rb.max.0 as f64, rb.max.1 as f64),
+        );
         b.min = ret.min;
         b.max = ret.max;
     }
-    pub fn mercatorbbox(&self) -> MercatorBoundingBox {
-        let projection = WebMercatorProjection::make(&self.projection);
+    pub fn mercatorbbox(&self) -> Result<MercatorBoundingBox, ProjectionError> {
         let wgs = self.wgsbbox();
-        let min = projection.project(&wgs.min);
-        let max = projection.project(&wgs.max);
-        MercatorBoundingBox { min, max }
+        let project = |p: &WGS84Point| {
+            self.projection
+                .project(p)
+                .map_err(|e| ProjectionError(format!("{}: {}", self.filename, e)))
+        };
+        Ok(MercatorBoundingBox {
+            min: project(&wgs.min)?,
+            max: project(&wgs.max)?,
+        })
+    }
+    // The outline of the sampled area, from the first to the last sample,
+    // projected as segments of at most 0.01° that follow the curved images
+    // of the meridians and parallels.
+    pub fn edges(&self) -> Vec<(MercatorPoint, MercatorPoint)> {
+        let projection = self.projection.clone();
+        let cols = self.raster.xsize.saturating_sub(1) as f64;
+        let rows = self.raster.ysize.saturating_sub(1) as f64;
+        let corners = [(0.0, 0.0), (cols, 0.0), (cols, rows), (0.0, rows)];
+        let mut ret = Vec::new();
+        for k in 0..corners.len() {
+            let (c0, r0) = corners[k];
+            let (c1, r1) = corners[(k + 1) % corners.len()];
+            let (p0, p1) = (
+                self.raster.wgs84_fractional(c0, r0),
+                self.raster.wgs84_fractional(c1, r1),
+            );
+            let degrees = (p1.lon - p0.lon).abs().max((p1.lat - p0.lat).abs());
+            let n = (degrees / 0.01).ceil().max(1.0) as usize;
+            let at = |i: usize| {
+                let t = i as f64 / n as f64;
+                let wgs = self
+                    .raster
+                    .wgs84_fractional(c0 + t * (c1 - c0), r0 + t * (r1 - r0));
+                projection.project(&wgs).ok()
+            };
+            // the segments with an unprojectable end are left out
+            ret.extend((0..n).filter_map(|i| Some((at(i)?, at(i + 1)?))));
+        }
+        ret
+    }
+    pub fn cell_area(&self) -> Result<f64, ProjectionError> {
+        Ok(self.mercatorbbox()?.area() / (self.raster.xsize * self.raster.ysize) as f64)
+    }
+    pub fn cell_size(&self) -> Result<f64, ProjectionError> {
+        Ok(self.cell_area()?.sqrt())
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DatasetError {
+    NoDatasetCoverage {
+        bbox: WGS84BoundingBox,
+        directories: Vec<String>,
+        tiles: Vec<String>,
+    },
+    InvalidGeometry(GeometryError),
+    Unreadable {
+        filename: String,
+        error: RasterIoError,
+    },
+    // the files given explicitly cover only a part of the polygon's box
+    IncompleteCoverage {
+        bbox: WGS84BoundingBox,
+        coverage: f64,
+    },
+    Download(DownloadError),
+}
+
+impl From<GeometryError> for DatasetError {
+    fn from(e: GeometryError) -> Self {
+        DatasetError::InvalidGeometry(e)
+    }
+}
+
+impl From<ProjectionError> for DatasetError {
+    fn from(e: ProjectionError) -> Self {
+        DatasetError::InvalidGeometry(e.into())
     }
 }
 
+impl From<DownloadError> for DatasetError {
+    fn from(e: DownloadError) -> Self {
+        DatasetError::Download(e)
+    }
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DatasetError::NoDatasetCoverage {
+                bbox,
+                directories,
+                tiles,
+            } => write!(
+                f,
+                "no dataset covers {} (searched: {}; needed tiles: {})",
+                bbox,
+                directories.join(", "),
+                tiles.join(", ")
+            ),
+            DatasetError::InvalidGeometry(e) => write!(f, "{}", e),
+            DatasetError::Unreadable { filename, error } => write!(f, "{}: {}", filename, error),
+            DatasetError::IncompleteCoverage { bbox, coverage } => write!(
+                f,
+                "the datasets cover {} of {}",
+                format::with_unit(100.0 * coverage, Quantity::Percent),
+                bbox
+            ),
+            DatasetError::Download(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for DatasetError {}
+
+// Whether the glob pattern matches all of the text: * matches any run of
+// characters (slashes included), ? any one character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let (p, t): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut i, mut j) = (0, 0);
+    // the last star and the text position it was tried at
+    let mut star: Option<(usize, usize)> = None;
+    while j < t.len() {
+        if i < p.len() && (p[i] == '?' || p[i] == t[j]) {
+            i += 1;
+            j += 1;
+        } else if i < p.len() && p[i] == '*' {
+            star = Some((i, j));
+            i += 1;
+        } else if let Some((si, sj)) = star {
+            // the star takes one more character
+            star = Some((si, sj + 1));
+            i = si + 1;
+            j = sj + 1;
+        } else {
+            return false;
+        }
+    }
+    p[i..].iter().all(|c| *c == '*')
+}
+
+// Glob patterns on the candidate paths, to choose between vintages of the
+// same tiles. A candidate must match one of the include patterns (if any)
+// and none of the exclude patterns; among copies of a tile the preferred
+// ones are kept.
+#[derive(Clone, Debug, Default)]
+pub struct CandidateFilter {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub prefer: Vec<String>,
+}
+
+impl CandidateFilter {
+    fn apply(&self, candidates: Vec<String>) -> Vec<String> {
+        candidates
+            .into_iter()
+            .filter(|filename| {
+                if !self.include.is_empty() && !self.include.iter().any(|p| glob_match(p, filename))
+                {
+                    log::info!("discard {} (no --include-pattern matches)", filename);
+                    return false;
+                }
+                if let Some(p) = self.exclude.iter().find(|p| glob_match(p, filename)) {
+                    log::info!("discard {} (--exclude-pattern {})", filename, p);
+                    return false;
+                }
+                true
+            })
+            .collect()
+    }
+
+    fn preferred(&self, filename: &str) -> bool {
+        self.prefer.iter().any(|p| glob_match(p, filename))
+    }
+
+    // the preferred datasets first, the order is kept otherwise
+    fn sort(&self, datasets: &mut [Dataset]) {
+        datasets.sort_by_key(|d| !self.preferred(&d.filename));
+    }
+}
+
+// Paths reaching the same file (symlinks, ./ prefixes) are opened once,
+// with the first of them.
+fn unique_files(candidates: &BTreeSet<String>) -> Vec<String> {
+    let mut seen: BTreeMap<PathBuf, &String> = BTreeMap::new();
+    let mut ret = Vec::new();
+    for filename in candidates {
+        let path = std::fs::canonicalize(filename).unwrap_or_else(|_| PathBuf::from(filename));
+        match seen.get(&path) {
+            Some(first) => log::info!("discard {} (same file as {})", filename, first),
+            None => {
+                seen.insert(path, filename);
+                ret.push(filename.clone());
+            }
+        }
+    }
+    ret
+}
+
+// The fraction of the box within the union of the boxes.
+fn covered_fraction(bbox: &WGS84BoundingBox, boxes: &[WGS84BoundingBox]) -> f64 {
+    let clipped: Vec<WGS84BoundingBox> =
+        boxes.iter().filter_map(|b| bbox.intersection(b)).collect();
+    let edges = |f: &dyn Fn(&WGS84BoundingBox) -> [f64; 2]| {
+        let mut ret: Vec<f64> = clipped.iter().flat_map(f).collect();
+        ret.sort_by(f64::total_cmp);
+        ret.dedup();
+        ret
+    };
+    let lons = edges(&|b| [b.min.lon, b.max.lon]);
+    let lats = edges(&|b| [b.min.lat, b.max.lat]);
+    // the cells between the edges are either in a box or not
+    let mut covered = 0.0;
+    for x in lons.windows(2) {
+        for y in lats.windows(2) {
+            let center = WGS84Point {
+                lon: 0.5 * (x[0] + x[1]),
+                lat: 0.5 * (y[0] + y[1]),
+                ele: None,
+            };
+            if clipped.iter().any(|b| b.contains_point(&center)) {
+                covered += (x[1] - x[0]) * (y[1] - y[0]);
+            }
+        }
+    }
+    let area = (bbox.max.lon - bbox.min.lon) * (bbox.max.lat - bbox.min.lat);
+    if area > 0.0 { covered / area } else { 1.0 }
+}
+
+fn is_geotiff(filename: &str) -> bool {
+    Path::new(filename)
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("tif") || e.eq_ignore_ascii_case("tiff"))
+}
+
+// Distinct files with the same samples are copies of a tile (in two
+// directories, or an .hgt converted to GeoTIFF): their samples would be
+// merged twice. The preferred copy is kept, else a GeoTIFF, else the first.
+fn drop_copies(datasets: Vec<Dataset>, filter: &CandidateFilter) -> Vec<Dataset> {
+    let rank = |d: &Dataset| (filter.preferred(&d.filename), is_geotiff(&d.filename));
+    let mut ret: Vec<Dataset> = Vec::new();
+    for dataset in datasets {
+        match ret.iter_mut().find(|kept| kept.same_grid(&dataset)) {
+            Some(kept) if rank(&dataset) > rank(kept) => {
+                log::info!(
+                    "discard {} (same grid as {})",
+                    kept.filename,
+                    dataset.filename
+                );
+                *kept = dataset;
+            }
+            Some(kept) => log::info!(
+                "discard {} (same grid as {})",
+                dataset.filename,
+                kept.filename
+            ),
+            None => ret.push(dataset),
+        }
+    }
+    ret
+}
+
 impl Dataset {
-    pub fn select(polygon: &Polygon) -> Vec<Dataset> {
-        let candidates = polygon.candidates();
-        for filename in &candidates {
+    pub fn select(
+        polygon: &Polygon,
+        registry: &DatasetRegistry,
+        filter: &CandidateFilter,
+        backend: IoBackend,
+    ) -> Vec<Dataset> {
+        Self::select_parts(&[polygon], registry, filter, backend).remove(0)
+    }
+
+    // The datasets of every polygon (the parts of a boundary), as select
+    // chooses them: a file that several polygons need is opened once.
+    pub fn select_parts(
+        polygons: &[&Polygon],
+        registry: &DatasetRegistry,
+        filter: &CandidateFilter,
+        backend: IoBackend,
+    ) -> Vec<Vec<Dataset>> {
+        let mut opened = BTreeMap::new();
+        polygons
+            .iter()
+            .map(|polygon| {
+                let candidates = registry.candidates(polygon, backend);
+                Self::select_opened(polygon, &candidates, filter, backend, &mut opened)
+            })
+            .collect()
+    }
+
+    pub fn select_candidates(
+        polygon: &Polygon,
+        candidates: &BTreeSet<String>,
+        filter: &CandidateFilter,
+        backend: IoBackend,
+    ) -> Vec<Dataset> {
+        Self::select_opened(polygon, candidates, filter, backend, &mut BTreeMap::new())
+    }
+
+    // select_candidates, the files already opened taken from opened (None
+    // for those that could not be) and the others added to it
+    fn select_opened(
+        polygon: &Polygon,
+        candidates: &BTreeSet<String>,
+        filter: &CandidateFilter,
+        backend: IoBackend,
+        opened: &mut BTreeMap<String, Option<Dataset>>,
+    ) -> Vec<Dataset> {
+        for filename in candidates {
             log::trace!("found candidate: {}", filename);
         }
+        let candidates = filter.apply(unique_files(candidates));
 
-        let polybox = polygon.wgsbbox();
-        // try to find a high res data set (GL1) => then a single one
-        {
-            let mut high_res: Vec<_> = candidates
-                .iter()
-                .map(|filename| Dataset::open(&filename, &polygon.projection()))
-                .collect();
-            high_res
-                .retain(|d| d.wgsbbox().contains_other(&polybox) && d.filename.contains("/GL1/"));
-            if !high_res.is_empty() {
-                high_res.truncate(1);
-                return high_res;
+        // a missing or corrupt tile leaves a hole for check_coverage to report,
+        // it does not end the run
+        let projection = polygon.projection();
+        let selected = candidates.iter().filter_map(|filename| {
+            opened
+                .entry(filename.clone())
+                .or_insert_with(|| {
+                    Dataset::open(filename, &projection, backend)
+                        .inspect_err(|e| log::warn!("skip {}", e))
+                        .ok()
+                })
+                .as_ref()
+                .map(|dataset| dataset.reprojected(&projection))
+        });
+
+        Self::prune(polygon, selected.collect(), filter)
+    }
+
+    // Exactly the given files, without searching: an unreadable one is an
+    // error, and so is a part of the polygon's box that none of them
+    // covers.
+    pub fn select_from(
+        polygon: &Polygon,
+        files: &[String],
+        backend: IoBackend,
+    ) -> Result<Vec<Dataset>, DatasetError> {
+        Ok(Self::select_from_parts(&[polygon], files, backend)?.remove(0))
+    }
+
+    // select_from for every polygon, the files opened once
+    pub fn select_from_parts(
+        polygons: &[&Polygon],
+        files: &[String],
+        backend: IoBackend,
+    ) -> Result<Vec<Vec<Dataset>>, DatasetError> {
+        let Some(first) = polygons.first() else {
+            return Ok(Vec::new());
+        };
+        let mut opened = Vec::new();
+        for filename in unique_files(&files.iter().cloned().collect()) {
+            opened.push(Dataset::open(&filename, &first.projection(), backend)?);
+        }
+        let mut ret = Vec::new();
+        for polygon in polygons {
+            let polybox = polygon.wgsbbox();
+            let boxes: Vec<WGS84BoundingBox> = opened.iter().map(|d| d.wgsbbox()).collect();
+            let coverage = covered_fraction(&polybox, &boxes);
+            if coverage < 1.0 - 1e-9 {
+                return Err(DatasetError::IncompleteCoverage {
+                    bbox: polybox,
+                    coverage,
+                });
             }
+            let projection = polygon.projection();
+            let reprojected = opened.iter().map(|d| d.reprojected(&projection)).collect();
+            ret.push(Self::prune(
+                polygon,
+                reprojected,
+                &CandidateFilter::default(),
+            ));
         }
+        Ok(ret)
+    }
 
-        // fall back: multiple GL3 tiles
-        let mut datasets: Vec<_> = candidates
-            .iter()
-            .map(|file| Dataset::open(file, &polygon.projection()))
+    // Drops the datasets outside of the polygon's box, and those that a finer
+    // one covering the polygon or a copy of the same tile makes redundant.
+    fn prune(polygon: &Polygon, opened: Vec<Dataset>, filter: &CandidateFilter) -> Vec<Dataset> {
+        let polybox = polygon.wgsbbox();
+        let opened: Vec<Dataset> = opened
+            .into_iter()
+            .filter(|dataset| {
+                let intersection = dataset.wgsbbox().intersection(&polybox);
+                if intersection.is_none() {
+                    log::trace!("discard {} (bbox)", dataset.filename);
+                }
+                intersection.is_some()
+            })
+            .collect();
+
+        let mut unprojectable = Unprojectable::default();
+        let opened: Vec<(f64, Dataset)> = opened
+            .into_iter()
+            .filter_map(|dataset| Some((unprojectable.skip(dataset.cell_size())?, dataset)))
             .collect();
-        datasets.retain(|dataset| {
-            let databox = dataset.wgsbbox();
-            let intersection = databox.intersection(&polybox);
-            if intersection.is_none() {
-                log::trace!("discard {} (bbox)", dataset.filename);
+        unprojectable.warn("datasets");
+
+        // a high res data set (GL1) covering the polygon => then a single one
+        let coarsest = opened.iter().map(|(size, _)| *size).fold(0.0, f64::max);
+        let (mut high_res, mut datasets) = (Vec::new(), Vec::new());
+        for (size, dataset) in opened {
+            if dataset.wgsbbox().contains_other(&polybox) && size < 0.9 * coarsest {
+                high_res.push(dataset);
+            } else {
+                datasets.push(dataset);
             }
-            intersection.is_some() && dataset.filename.contains("/GL3/")
-        });
+        }
+        filter.sort(&mut high_res);
+        if !high_res.is_empty() {
+            high_res.truncate(1);
+            return high_res;
+        }
 
-        datasets
+        // fall back: multiple tiles
+        filter.sort(&mut datasets);
+        drop_copies(datasets, filter)
     }
 
-    pub fn points_inside(&self, snapped_box: &WGS84BoundingBox) -> Vec<MercatorPoint> {
+    // The same samples: the same size, aligned and from the same origin,
+    // whether the geotransform gives the corners of the cells or the samples.
+    fn same_grid(&self, other: &Dataset) -> bool {
+        let (col, row) = self
+            .raster
+            .pixel(other.raster.origin.0, other.raster.origin.1);
+        (self.raster.xsize, self.raster.ysize) == (other.raster.xsize, other.raster.ysize)
+            && self.aligned(other)
+            && col.abs() < 1e-6
+            && row.abs() < 1e-6
+    }
+
+    pub fn check_coverage(
+        polygon: &Polygon,
+        datasets: &[Dataset],
+        directories: &[String],
+    ) -> Result<(), DatasetError> {
+        if !datasets.is_empty() {
+            return Ok(());
+        }
+        Err(DatasetError::NoDatasetCoverage {
+            bbox: polygon.wgsbbox(),
+            directories: directories.to_vec(),
+            tiles: polygon.required_tiles().into_iter().collect(),
+        })
+    }
+
+    // The SRTM tiles the polygon needs whose part of its box none of the
+    // datasets covers, by their basename.
+    pub fn missing_tiles(polygon: &Polygon, datasets: &[Dataset]) -> Vec<String> {
+        let polybox = polygon.wgsbbox();
+        let boxes: Vec<WGS84BoundingBox> = datasets.iter().map(|d| d.wgsbbox()).collect();
+        let missing = |tile: &String| {
+            HgtTile::from_name(tile).is_ok_and(|tile| {
+                tile.bbox()
+                    .intersection(&polybox)
+                    .is_some_and(|part| covered_fraction(&part, &boxes) < 1.0 - 1e-9)
+            })
+        };
+        polygon
+            .required_tiles()
+            .into_iter()
+            .filter(missing)
+            .collect()
+    }
+
+    pub fn points_inside(
+        &self,
+        snapped_box: &WGS84BoundingBox,
+        options: &SampleOptions,
+    ) -> Vec<MercatorPoint> {
         let mut ret = Vec::new();
+        self.visit_inside(snapped_box, options, |_, mercator| ret.push(mercator));
+        ret
+    }
 
+    // Calls f with the geographic and projected position of every sample in
+    // the box, without collecting them. Returns the projected centers of the
+    // skipped (masked or nodata) pixels.
+    pub fn visit_inside(
+        &self,
+        snapped_box: &WGS84BoundingBox,
+        options: &SampleOptions,
+        mut f: impl FnMut(&WGS84Point, MercatorPoint),
+    ) -> Vec<MercatorPoint> {
         let dataset_bbox = self.wgsbbox();
         let intersection = dataset_bbox.intersection(snapped_box);
         if intersection.is_none() {
             assert!(false);
-            return ret;
+            return Vec::new();
         }
         let inter = intersection.unwrap();
 
-        // the input box is snapped => we want integer coordinates.
-        let p1 = self.raster.icoordinates(&inter.min);
-        let p2 = self.raster.icoordinates(&inter.max);
-
-        let minpix = (p1.0.min(p2.0), p1.1.min(p2.1));
-        let maxpix = (p1.0.max(p2.0), p1.1.max(p2.1));
+        // the input box is snapped => we want integer coordinates. The box
+        // is not along the pixels of a projected or rotated raster: all of
+        // them around it.
+        let (minpix, maxpix) = if self.raster.oblique() {
+            let rb = self.raster_box(&inter);
+            (rb.min, rb.max)
+        } else {
+            let p1 = self.raster.icoordinates(&inter.min);
+            let p2 = self.raster.icoordinates(&inter.max);
+            (
+                (p1.0.min(p2.0), p1.1.min(p2.1)),
+                (p1.0.max(p2.0), p1.1.max(p2.1)),
+            )
+        };
 
         log::trace!("minpix:{:?}", minpix);
         let col_start = minpix.0;
@@ -221,48 +948,1928 @@ impl Dataset {
         let col_end = maxpix.0;
         let row_end = maxpix.1;
 
-        // Clamp to valid raster bounds
-        assert!(col_start >= 0);
-        assert!(row_start >= 0);
+        // Clamp to valid raster bounds: the box of a polygon on a tile seam
+        // may start a rounding before the first pixel
+        let col_start = col_start.max(0);
+        let row_start = row_start.max(0);
         let col_end = col_end.min((self.raster.xsize - 1) as isize);
         let row_end = row_end.min((self.raster.ysize - 1) as isize);
-        let projection = WebMercatorProjection::make(&self.projection);
         //log::info!("row: {row_start}..{row_end}");
         //log::info!("col: {col_start}..{col_end}");
 
-        // Read the elevation data for the region of interest
-        let rasterband = self.g.rasterband(1).expect("Failed to get rasterband");
-        let window_xsize = (col_end - col_start + 1) as usize;
-        let window_ysize = (row_end - row_start + 1) as usize;
+        // The window is read in blocks of rows, each sharing its last row
+        // with the next one for the interpolation, so that the memory does
+        // not grow with the box. The opening filter and the void filling
+        // need the whole window.
+        let rows = if options.opening_radius.is_some() || options.fill_radius.is_some() {
+            row_end - row_start
+        } else {
+            BLOCK_ROWS
+        };
+        let lattice = Lattice {
+            subdivide: options.subdivide,
+            decimate: options.decimate.max(1) as isize,
+            start: (col_start, row_start),
+            end: (col_end, row_end),
+        };
+        let projection = self.projection.clone();
+        let nodata_values: Vec<f64> = match self.nodata(&options.nodata) {
+            Ok(nodata) => nodata.iter().map(|n| n.0).collect(),
+            Err(e) => {
+                log::warn!("skip {}", self.unreadable(e));
+                return Vec::new();
+            }
+        };
+        let (mut pixels, mut nodata, mut masked, mut filled) = (0, 0, 0, 0);
+        let mut voids = Vec::new();
+        let mut start = row_start;
+        loop {
+            let end = (start + rows.max(1)).min(row_end);
+            let rb = RasterBox {
+                min: (col_start, start),
+                max: (col_end, end),
+            };
+            // an unreadable block: the rest of the dataset is skipped
+            let window = match self.read_window(&rb, options) {
+                Ok(window) => window,
+                Err(e) => {
+                    log::warn!("skip {}", self.unreadable(e));
+                    break;
+                }
+            };
+            // the first row of a block is the last of the previous one,
+            // counted there
+            let shared = if start > row_start { window.xsize } else { 0 };
+            let first_invalid = window.valid[..shared].iter().filter(|v| !**v).count();
+            let first_nodata = window.values[..shared]
+                .iter()
+                .filter(|v| v.is_nan() || nodata_values.contains(v))
+                .count();
+            pixels += window.values.len() - shared;
+            nodata += window.nodata - first_nodata;
+            masked += window.masked() - (first_invalid - first_nodata);
+            filled += window.filled;
+            voids.extend(window.visit(
+                &self.raster,
+                &projection,
+                snapped_box,
+                &lattice,
+                end < row_end,
+                &mut f,
+            ));
+            if end == row_end {
+                break;
+            }
+            start = end;
+        }
+        if filled > 0 {
+            log::info!(
+                "{}: {} nodata cells of {} pixels filled",
+                self.filename,
+                filled,
+                pixels
+            );
+        }
+        if nodata > 0 {
+            log::info!(
+                "{}: {} nodata cells of {} pixels skipped",
+                self.filename,
+                nodata,
+                pixels
+            );
+        }
+        if masked > 0 {
+            log::info!(
+                "{}: {} of {} pixels masked ({:.1}%)",
+                self.filename,
+                masked,
+                pixels,
+                100f64 * masked as f64 / pixels as f64
+            );
+        }
+        voids
+    }
+
+    // The elevations of the pixels whose sample is in the box, read in the
+    // blocks of visit_inside. Nodata and masked pixels are counted apart.
+    pub fn stats_in(&self, bbox: &WGS84BoundingBox, options: &SampleOptions) -> ElevationStats {
+        let mut ret = ElevationStats::default();
+        let Some(inter) = self.wgsbbox().intersection(bbox) else {
+            return ret;
+        };
+        let own = self.raster_box(&inter);
+        let (col_start, row_start) = (own.min.0.max(0), own.min.1.max(0));
+        let col_end = own.max.0.min(self.raster.xsize as isize - 1);
+        let row_end = own.max.1.min(self.raster.ysize as isize - 1);
+        if col_start > col_end || row_start > row_end {
+            return ret;
+        }
+        // Welford's running mean and sum of squared deviations
+        let (mut mean, mut m2) = (0.0, 0.0);
+        let mut start = row_start;
+        while start <= row_end {
+            let end = (start + BLOCK_ROWS - 1).min(row_end);
+            let rb = RasterBox {
+                min: (col_start, start),
+                max: (col_end, end),
+            };
+            let window = match self.read_window(&rb, options) {
+                Ok(window) => window,
+                Err(e) => {
+                    log::warn!("skip {}", self.unreadable(e));
+                    break;
+                }
+            };
+            for row in 0..window.ysize() {
+                for col in 0..window.xsize {
+                    let wgs = self.raster.wgs84_fractional(
+                        (col_start + col as isize) as f64,
+                        (start + row as isize) as f64,
+                    );
+                    if !bbox.contains_point(&wgs) {
+                        continue;
+                    }
+                    let Some(ele) = window.value(col, row) else {
+                        ret.invalid += 1;
+                        continue;
+                    };
+                    ret.valid += 1;
+                    ret.min = ret.min.min(ele);
+                    ret.max = ret.max.max(ele);
+                    let delta = ele - mean;
+                    mean += delta / ret.valid as f64;
+                    m2 += delta * (ele - mean);
+                }
+            }
+            start = end + 1;
+        }
+        if ret.valid > 0 {
+            ret.mean = mean;
+            ret.stddev = (m2 / ret.valid as f64).sqrt();
+        }
+        ret
+    }
 
-        let window = (col_start as isize, row_start as isize);
+    // The (column, row) of a sample of visit_inside in the lattice of the
+    // pixels and their subdivide steps. None off the lattice, and for a
+    // projected or rotated raster, whose samples in a box are not a rectangle
+    // of it.
+    pub fn lattice_index(&self, wgs: &WGS84Point, subdivide: usize) -> Option<(isize, isize)> {
+        if self.raster.oblique() {
+            return None;
+        }
+        let f = subdivide.max(1) as f64;
+        let (col, row) = self.raster.coordinates(wgs);
+        let (col, row) = (col * f, row * f);
+        let on = |v: f64| (v - v.round()).abs() < 1e-6;
+        (on(col) && on(row)).then(|| (col.round() as isize, row.round() as isize))
+    }
+
+    // Whether the pixels of both datasets are at the same positions: the
+    // same CRS, steps and rotation, the origins a whole number of pixels
+    // apart.
+    pub fn aligned(&self, other: &Dataset) -> bool {
+        let (a, b) = (&self.raster, &other.raster);
+        let step = a.xstep.abs().max(a.ystep.abs());
+        let same = |x: f64, y: f64| (x - y).abs() <= 1e-9 * step;
+        let crs = |r: &Raster| r.crs.as_ref().map(|c| c.proj4.clone());
+        if crs(a) != crs(b)
+            || !same(a.xstep, b.xstep)
+            || !same(a.ystep, b.ystep)
+            || !same(a.rotation.0, b.rotation.0)
+            || !same(a.rotation.1, b.rotation.1)
+        {
+            return false;
+        }
+        let (col, row) = a.pixel(b.origin.0, b.origin.1);
+        (col - col.round()).abs() < 1e-6 && (row - row.round()).abs() < 1e-6
+    }
+
+    // Samples the dataset at the pixel positions of the reference grid in the
+    // box (and the subdivide steps between them), interpolated bilinearly, so
+    // that datasets on different grids give one lattice. Returns the
+    // positions that could not be interpolated for skipped pixels.
+    pub fn visit_on_grid(
+        &self,
+        bbox: &WGS84BoundingBox,
+        reference: &Dataset,
+        options: &SampleOptions,
+        mut visitor: impl FnMut(&WGS84Point, MercatorPoint),
+    ) -> Vec<MercatorPoint> {
+        let own = self.raster_box(bbox);
+        let rb = RasterBox {
+            min: (own.min.0.max(0), own.min.1.max(0)),
+            max: (
+                own.max.0.min(self.raster.xsize as isize - 1),
+                own.max.1.min(self.raster.ysize as isize - 1),
+            ),
+        };
+        if rb.min.0 > rb.max.0 || rb.min.1 > rb.max.1 {
+            return Vec::new();
+        }
+        log::info!(
+            "{}: interpolated at the samples of {}",
+            self.filename,
+            reference.filename
+        );
+        let window = match self.read_window(&rb, options) {
+            Ok(window) => window,
+            Err(e) => {
+                log::warn!("skip {}", self.unreadable(e));
+                return Vec::new();
+            }
+        };
+        let projection = self.projection.clone();
+        let (width, height) = ((rb.max.0 - rb.min.0) as f64, (rb.max.1 - rb.min.1) as f64);
+        let grid = reference.raster_box(bbox);
+        let lattice = Lattice {
+            subdivide: options.subdivide,
+            decimate: options.decimate.max(1) as isize,
+            start: grid.min,
+            end: grid.max,
+        };
+        let f = lattice.subdivide.max(1);
+        let mut voids = Vec::new();
+        let mut unprojectable = Unprojectable::default();
+        for j in 0..=(grid.max.1 - grid.min.1) as usize * f {
+            for i in 0..=(grid.max.0 - grid.min.0) as usize * f {
+                let pixel = (grid.min.0 + (i / f) as isize, grid.min.1 + (j / f) as isize);
+                if !lattice.keeps(pixel) {
+                    continue;
+                }
+                let mut wgs = reference.raster.wgs84_fractional(
+                    grid.min.0 as f64 + i as f64 / f as f64,
+                    grid.min.1 as f64 + j as f64 / f as f64,
+                );
+                if !reference.raster.oblique() && !bbox.contains_point(&wgs) {
+                    continue;
+                }
+                // in pixels of the window, the rounding at its edges dropped
+                let (col, row) = self.raster.coordinates(&wgs);
+                let snap = |v: f64, max: f64| match v {
+                    v if v.abs() < 1e-9 => 0.0,
+                    v if (v - max).abs() < 1e-9 => max,
+                    v => v,
+                };
+                let col = snap(col - rb.min.0 as f64, width);
+                let row = snap(row - rb.min.1 as f64, height);
+                if !(0.0..=width).contains(&col) || !(0.0..=height).contains(&row) {
+                    continue;
+                }
+                let (c, r) = (col.floor(), row.floor());
+                match window.bilinear(c as usize, r as usize, col - c, row - r) {
+                    Some(ele) => {
+                        wgs.ele = Some(ele);
+                        if let Some(mercator) = unprojectable.skip(projection.project(&wgs)) {
+                            visitor(&wgs, mercator);
+                        }
+                    }
+                    None => voids.extend(unprojectable.skip(projection.project(&wgs))),
+                }
+            }
+        }
+        unprojectable.warn("samples");
+        voids
+    }
+
+    // the proj4 of the projected samples
+    pub fn projection(&self) -> &str {
+        self.projection.spec()
+    }
+    pub fn projector(&self) -> &WebMercatorProjection {
+        &self.projection
+    }
+
+    // the position is within the samples of the raster, its edges included
+    pub fn covers(&self, point: &WGS84Point) -> bool {
+        let (col, row) = self.raster.coordinates(point);
+        let inside = |v: f64, size: usize| (-1e-9..=(size - 1) as f64 + 1e-9).contains(&v);
+        inside(col, self.raster.xsize) && inside(row, self.raster.ysize)
+    }
+
+    // The elevation at the position, interpolated bilinearly between the four
+    // surrounding pixels. None outside of the raster; with skipped pixels
+    // among the four, the value of the nearest valid one. The pixels are read
+    // with the options of the sampling.
+    pub fn elevation_at(&self, point: &WGS84Point, options: &SampleOptions) -> Option<f64> {
+        let (col, row) = self.raster.coordinates(point);
+        let (xmax, ymax) = (
+            (self.raster.xsize - 1) as f64,
+            (self.raster.ysize - 1) as f64,
+        );
+        let inside = |v: f64, max: f64| (-1e-9..=max + 1e-9).contains(&v);
+        if !inside(col, xmax) || !inside(row, ymax) {
+            return None;
+        }
+        let (col, row) = (col.clamp(0.0, xmax), row.clamp(0.0, ymax));
+        // the upper-left of the four, one pixel in at the right and bottom edges
+        let c = col.floor().min((xmax - 1.0).max(0.0));
+        let r = row.floor().min((ymax - 1.0).max(0.0));
+        let rb = RasterBox {
+            min: (c as isize, r as isize),
+            max: ((c + 1.0).min(xmax) as isize, (r + 1.0).min(ymax) as isize),
+        };
+        // unreadable: as outside, the sampling warned
+        let window = self.read_window(&rb, options).ok()?;
+        let (tx, ty) = (col - c, row - r);
+        if let Some(ele) = window.bilinear(0, 0, tx, ty) {
+            return Some(ele);
+        }
+        let mut neighbors: Vec<(usize, usize)> = (0..window.ysize())
+            .flat_map(|j| (0..window.xsize).map(move |i| (i, j)))
+            .collect();
+        let distance = |&(i, j): &(usize, usize)| (i as f64 - tx).hypot(j as f64 - ty);
+        neighbors.sort_by(|a, b| distance(a).total_cmp(&distance(b)));
+        neighbors.iter().find_map(|&(i, j)| window.value(i, j))
+    }
+
+    fn read_window(
+        &self,
+        rb: &RasterBox,
+        options: &SampleOptions,
+    ) -> Result<Window, RasterIoError> {
+        // Read the elevation data for the region of interest
+        let window_xsize = (rb.max.0 - rb.min.0 + 1) as usize;
+        let window_ysize = (rb.max.1 - rb.min.1 + 1) as usize;
         let window_size = (window_xsize, window_ysize);
 
-        let buffer = rasterband
-            .read_as::<f64>(window, window_size, window_size, None)
-            .expect("Failed to read raster data");
+        let mut values = self.io.read_window(1, rb.min, window_size)?;
+
+        let nodata: Vec<f64> = self.nodata(&options.nodata)?.iter().map(|n| n.0).collect();
+        let mut valid: Vec<bool> = values
+            .iter()
+            .map(|v| !v.is_nan() && !nodata.contains(v))
+            .collect();
+        let missing: Vec<bool> = valid.iter().map(|v| !v).collect();
+        let mut nodata = valid.iter().filter(|v| !**v).count();
+        if !options.ignore_mask {
+            // the mask band marks invalid pixels with 0 (photogrammetric DSMs
+            // often flag interpolated cells this way instead of using nodata).
+            let mask = self.io.read_mask(1, rb.min, window_size)?;
+            if let Some(mask) = mask {
+                for (v, m) in valid.iter_mut().zip(&mask) {
+                    *v = *v && *m != 0;
+                }
+            }
+        }
+
+        let filled = match options.fill_radius {
+            Some(r) => {
+                let radius = morphology::radius_in_pixels(r, self.ground_cell_size());
+                voidfill::fill(&mut values, &mut valid, &missing, window_xsize, radius)
+            }
+            None => 0,
+        };
+        nodata -= filled;
+        self.filled.set(self.filled.get() + filled);
+
+        let values = match options.opening_radius {
+            Some(r) => {
+                let radius = morphology::radius_in_pixels(r, self.ground_cell_size());
+                morphology::opening(&values, &valid, window_xsize, radius)
+            }
+            None => values,
+        };
+
+        Ok(Window {
+            min: rb.min,
+            xsize: window_xsize,
+            values,
+            valid,
+            nodata,
+            filled,
+        })
+    }
+}
 
-        for row in row_start..=row_end {
-            for col in col_start..=col_end {
-                let mut wgs = self.raster.wgs84(col, row);
+// Pixel values read from a rectangular raster window, row-major, with a
+// validity flag per pixel.
+struct Window {
+    min: (isize, isize),
+    xsize: usize,
+    values: Vec<f64>,
+    valid: Vec<bool>,
+    // the pixels invalid for their value (nodata or NaN), before the mask,
+    // and not filled
+    nodata: usize,
+    // the nodata pixels filled by interpolation
+    filled: usize,
+}
+
+impl Window {
+    fn ysize(&self) -> usize {
+        self.values.len() / self.xsize
+    }
+
+    // the pixels invalid for the mask band only
+    fn masked(&self) -> usize {
+        self.valid.iter().filter(|v| !**v).count() - self.nodata
+    }
+
+    #[cfg(test)]
+    fn masked_fraction(&self) -> f64 {
+        if self.valid.is_empty() {
+            return 0f64;
+        }
+        self.masked() as f64 / self.valid.len() as f64
+    }
+
+    fn value(&self, col: usize, row: usize) -> Option<f64> {
+        let index = row * self.xsize + col;
+        match self.valid[index] {
+            true => Some(self.values[index]),
+            false => None,
+        }
+    }
+
+    // Bilinear interpolation at the fractional position (col + tx, row + ty).
+    // Positions on a pixel only need that pixel to be valid.
+    fn bilinear(&self, col: usize, row: usize, tx: f64, ty: f64) -> Option<f64> {
+        let col1 = if tx > 0f64 { col + 1 } else { col };
+        let row1 = if ty > 0f64 { row + 1 } else { row };
+        let v00 = self.value(col, row)?;
+        let v10 = self.value(col1, row)?;
+        let v01 = self.value(col, row1)?;
+        let v11 = self.value(col1, row1)?;
+        Some(
+            (1f64 - tx) * (1f64 - ty) * v00
+                + tx * (1f64 - ty) * v10
+                + (1f64 - tx) * ty * v01
+                + tx * ty * v11,
+        )
+    }
+
+    #[cfg(test)]
+    fn points(
+        &self,
+        raster: &Raster,
+        projection: &WebMercatorProjection,
+        snapped_box: &WGS84BoundingBox,
+        subdivide: usize,
+    ) -> Vec<MercatorPoint> {
+        let mut ret = Vec::new();
+        let lattice = Lattice::subdivided(subdivide);
+        self.visit(raster, projection, snapped_box, &lattice, false, |_, m| {
+            ret.push(m)
+        });
+        ret
+    }
+
+    // Visits one point per valid pixel of the lattice, or with subdivide > 1
+    // a finer lattice with subdivide steps per pixel, bilinearly interpolated.
+    // Returns the projected centers of the invalid pixels. The last row is
+    // left to the next block with skip_last_row.
+    fn visit(
+        &self,
+        raster: &Raster,
+        projection: &WebMercatorProjection,
+        snapped_box: &WGS84BoundingBox,
+        lattice: &Lattice,
+        skip_last_row: bool,
+        mut visitor: impl FnMut(&WGS84Point, MercatorPoint),
+    ) -> Vec<MercatorPoint> {
+        let mut voids = Vec::new();
+        let mut unprojectable = Unprojectable::default();
+        let f = lattice.subdivide.max(1);
+        let nx = (self.xsize - 1) * f + 1;
+        let ny = (self.ysize() - 1) * f + usize::from(!skip_last_row);
+        for j in 0..ny {
+            for i in 0..nx {
+                let (buffer_col, buffer_row) = (i / f, j / f);
+                let pixel = (
+                    self.min.0 + buffer_col as isize,
+                    self.min.1 + buffer_row as isize,
+                );
+                if !lattice.keeps(pixel) {
+                    continue;
+                }
+                let tx = (i % f) as f64 / f as f64;
+                let ty = (j % f) as f64 / f as f64;
+                let col = self.min.0 as f64 + buffer_col as f64 + tx;
+                let row = self.min.1 as f64 + buffer_row as f64 + ty;
+                let ele = match self.bilinear(buffer_col, buffer_row, tx, ty) {
+                    Some(e) => e,
+                    None => {
+                        if i % f == 0 && j % f == 0 && self.value(buffer_col, buffer_row).is_none()
+                        {
+                            let wgs = raster.wgs84_fractional(col, row);
+                            voids.extend(unprojectable.skip(projection.project(&wgs)));
+                        }
+                        continue;
+                    }
+                };
+                let mut wgs = raster.wgs84_fractional(col, row);
                 if !snapped_box.contains_point(&wgs) {
                     log::trace!("bbox:{}", snapped_box);
                     log::trace!("point:{}", wgs);
                 }
-                assert!(snapped_box.contains_point(&wgs));
+                // the window of a projected or rotated raster is the pixels
+                // around the box, so that their triangles cover it
+                assert!(raster.oblique() || snapped_box.contains_point(&wgs));
 
-                // Calculate buffer index
-                let buffer_col = (col - col_start) as usize;
-                let buffer_row = (row - row_start) as usize;
-                let buffer_index = buffer_row * window_xsize + buffer_col;
+                wgs.ele = Some(ele);
+
+                if let Some(mercator) = unprojectable.skip(projection.project(&wgs)) {
+                    visitor(&wgs, mercator);
+                }
+            }
+        }
+        unprojectable.warn("samples");
+        voids
+    }
+}
 
-                wgs.ele = Some(buffer.data()[buffer_index]);
+// The samples or datasets that could not be projected, skipped with a
+// single warning naming the first of them.
+#[derive(Default)]
+struct Unprojectable {
+    count: usize,
+    first: Option<ProjectionError>,
+}
 
-                let mercator = projection.project(&wgs);
-                ret.push(mercator);
+impl Unprojectable {
+    fn skip<T>(&mut self, projected: Result<T, ProjectionError>) -> Option<T> {
+        match projected {
+            Ok(m) => Some(m),
+            Err(e) => {
+                self.count += 1;
+                self.first.get_or_insert(e);
+                None
             }
         }
+    }
+    fn warn(&self, what: &str) {
+        if let Some(e) = &self.first {
+            log::warn!(
+                "skipped {} unprojectable {}, the first: {}",
+                self.count,
+                what,
+                e
+            );
+        }
+    }
+}
 
-        ret
+// The datasets from the finest to the coarsest, with their cell size, the
+// unprojectable ones skipped.
+pub fn by_cell_size(datasets: &[Dataset]) -> Vec<(f64, &Dataset)> {
+    let mut unprojectable = Unprojectable::default();
+    let mut ret: Vec<(f64, &Dataset)> = datasets
+        .iter()
+        .filter_map(|d| Some((unprojectable.skip(d.cell_size())?, d)))
+        .collect();
+    unprojectable.warn("datasets");
+    ret.sort_by(|a, b| a.0.total_cmp(&b.0));
+    ret
+}
+
+// the rows of a block of visit_inside
+const BLOCK_ROWS: isize = 256;
+
+// The pixels sampled in a box: subdivided, or every decimate-th pixel from
+// start on in both directions and those of the last column and row at end,
+// so that the decimated grid still reaches the edges of the box.
+#[derive(Default)]
+struct Lattice {
+    subdivide: usize,
+    decimate: isize,
+    start: (isize, isize),
+    end: (isize, isize),
+}
+
+impl Lattice {
+    #[cfg(test)]
+    fn subdivided(subdivide: usize) -> Self {
+        Lattice {
+            subdivide,
+            ..Default::default()
+        }
+    }
+
+    fn keeps(&self, pixel: (isize, isize)) -> bool {
+        let keep =
+            |v: isize, start: isize, end: isize| v == end || (v - start) % self.decimate == 0;
+        self.decimate <= 1
+            || (keep(pixel.0, self.start.0, self.end.0) && keep(pixel.1, self.start.1, self.end.1))
+    }
+}
+
+// The description of a dataset, see Dataset::info_data. Displayed as the
+// lines of the log.
+#[derive(Clone, Debug, Serialize)]
+pub struct DatasetInfo {
+    pub filename: String,
+    // the proj4 of the projected samples
+    pub projection: String,
+    // the values counted as nodata and where they come from
+    pub nodata: Vec<(f64, String)>,
+    // the proj4 of a projected raster
+    pub crs: Option<String>,
+    // in pixels
+    pub size: (usize, usize),
+    // the pixel size, in degrees or in the units of crs
+    pub step: (f64, f64),
+    // the pixel size in meters, see Dataset::pixel_size
+    pub pixel_size: (f64, f64),
+    pub rotation: (f64, f64),
+    pub wgs_bbox: WGS84BoundingBox,
+    pub mercator_bbox: MercatorBoundingBox,
+    // of the mercator box
+    pub area: f64,
+}
+
+impl fmt::Display for DatasetInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "dataset: {}", self.filename)?;
+        writeln!(f, "dataset: {}", self.projection)?;
+        if self.nodata.is_empty() {
+            writeln!(f, "dataset: no nodata value")?;
+        }
+        for (value, source) in &self.nodata {
+            writeln!(f, "dataset: nodata {} ({})", value, source)?;
+        }
+        if let Some(crs) = &self.crs {
+            writeln!(f, "dataset: crs {}", crs)?;
+        }
+        writeln!(f, "dataset: xsize {}", self.size.0)?;
+        writeln!(f, "dataset: ysize {}", self.size.1)?;
+        writeln!(
+            f,
+            "dataset: pixel size {:.1} m x {:.1} m",
+            self.pixel_size.0, self.pixel_size.1
+        )?;
+        if self.rotation != (0.0, 0.0) {
+            writeln!(f, "dataset: rotation {:?}", self.rotation)?;
+        }
+        writeln!(f, "dataset: wgs bbox: {}", self.wgs_bbox)?;
+        writeln!(f, "dataset: mercator bbox: {}", self.mercator_bbox)?;
+        writeln!(f, "dataset: width: {:.1}", self.mercator_bbox.width())?;
+        writeln!(f, "dataset: height: {:.1}", self.mercator_bbox.height())?;
+        write!(f, "dataset: area: {:.1}", self.area)
+    }
+}
+
+// The elevations of a dataset in a box, see Dataset::stats_in. Without valid
+// pixels, min and max are infinite and the mean and deviation 0.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ElevationStats {
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub stddev: f64,
+    pub valid: usize,
+    // nodata or masked
+    pub invalid: usize,
+}
+
+impl Default for ElevationStats {
+    fn default() -> Self {
+        ElevationStats {
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            mean: 0.0,
+            stddev: 0.0,
+            valid: 0,
+            invalid: 0,
+        }
+    }
+}
+
+impl fmt::Display for ElevationStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.valid == 0 {
+            return write!(f, "no valid cells, {} nodata", self.invalid);
+        }
+        let ele = |v| format::with_unit(v, Quantity::Elevation);
+        write!(
+            f,
+            "min {}, max {}, mean {}, stddev {}, {} valid cells, {} nodata",
+            ele(self.min),
+            ele(self.max),
+            ele(self.mean),
+            ele(self.stddev),
+            self.valid,
+            self.invalid
+        )
+    }
+}
+
+#[derive(Clone, Default, PartialEq)]
+#[non_exhaustive]
+pub struct SampleOptions {
+    pub ignore_mask: bool,
+    // number of bilinear sub-steps per pixel (0 or 1: raw pixels)
+    pub subdivide: usize,
+    // every n-th pixel in both directions (0 or 1: all of them)
+    pub decimate: usize,
+    // morphological opening of the window, in meters (DSM ground filter)
+    pub opening_radius: Option<f64>,
+    // search radius of the nodata filling, in meters (none: not filled)
+    pub fill_radius: Option<f64>,
+    // nodata values besides the declared one
+    pub nodata: Vec<NodataOverride>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn raster() -> Raster {
+        Raster {
+            origin: (6.0, 46.0),
+            xsize: 3601,
+            ysize: 3601,
+            xstep: 1.0 / 3600.0,
+            ystep: -1.0 / 3600.0,
+            rotation: (0.0, 0.0),
+            crs: None,
+        }
+    }
+
+    #[test]
+    fn test_checkerboard_mask() {
+        let raster = raster();
+        let (n, m) = (8, 6);
+        let window = Window {
+            min: (10, 20),
+            xsize: n,
+            values: vec![100f64; n * m],
+            valid: (0..n * m).map(|i| (i % n + i / n) % 2 == 0).collect(),
+            nodata: 0,
+            filled: 0,
+        };
+        let p1 = raster.wgs84(10, 20);
+        let p2 = raster.wgs84(10 + n as isize - 1, 20 + m as isize - 1);
+        let bbox = WGS84BoundingBox::from(&p1, &p2);
+        let projection = WebMercatorProjection::make(&p1.to_utm_proj4());
+        let points = window.points(&raster, &projection, &bbox, 1);
+        assert_eq!(points.len(), n * m / 2);
+        let lattice = Lattice::subdivided(1);
+        let voids = window.visit(&raster, &projection, &bbox, &lattice, false, |_, _| {});
+        assert_eq!(voids.len(), n * m / 2);
+        assert_eq!(window.masked(), n * m / 2);
+        assert!((window.masked_fraction() - 0.5).abs() < 1e-12);
+    }
+
+    // Elevations in band 1, and an alpha band 2 that GDAL gives as the mask
+    // of band 1.
+    struct TwoBands {
+        elevations: rasterio::InMemory,
+        alpha: rasterio::InMemory,
+    }
+
+    impl RasterIo for TwoBands {
+        fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+            self.elevations.geo_transform()
+        }
+        fn raster_size(&self) -> (usize, usize) {
+            self.elevations.raster_size()
+        }
+        fn projection(&self) -> String {
+            self.elevations.projection()
+        }
+        fn no_data_value(&self, _band: usize) -> Result<Option<f64>, RasterIoError> {
+            Ok(None)
+        }
+        fn overview_count(&self, _band: usize) -> Result<usize, RasterIoError> {
+            Ok(0)
+        }
+        fn read_window(
+            &self,
+            band: usize,
+            window: (isize, isize),
+            size: (usize, usize),
+        ) -> Result<Vec<f64>, RasterIoError> {
+            match band {
+                1 => self.elevations.read_window(1, window, size),
+                _ => self.alpha.read_window(1, window, size),
+            }
+        }
+        fn read_mask(
+            &self,
+            band: usize,
+            window: (isize, isize),
+            size: (usize, usize),
+        ) -> Result<Option<Vec<u8>>, RasterIoError> {
+            if band != 1 {
+                return Ok(None);
+            }
+            let alpha = self.alpha.read_window(1, window, size)?;
+            Ok(Some(alpha.iter().map(|a| *a as u8).collect()))
+        }
+        fn area_or_point(&self) -> AreaOrPoint {
+            AreaOrPoint::Point
+        }
+    }
+
+    #[test]
+    fn test_two_bands() {
+        let (n, m) = (8, 6);
+        let grid = |values| rasterio::InMemory::new((6.0, 46.01), 0.001, (n, m), values).unwrap();
+        let io = TwoBands {
+            elevations: grid((0..n * m).map(|i| 1000.0 + i as f64).collect()),
+            alpha: grid(
+                (0..n * m)
+                    .map(|i| if (i % n + i / n) % 2 == 0 { 255.0 } else { 0.0 })
+                    .collect(),
+            ),
+        };
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let dataset = Dataset::from_io("two-bands", Box::new(io), projection).unwrap();
+        let bbox = dataset.wgsbbox();
+        // the elevations of band 1 on the checkerboard of band 2
+        let points = dataset.points_inside(&bbox, &SampleOptions::default());
+        assert_eq!(points.len(), n * m / 2);
+        assert!(points.iter().all(|p| p.ele.unwrap() >= 1000.0));
+        let options = SampleOptions {
+            ignore_mask: true,
+            ..Default::default()
+        };
+        let points = dataset.points_inside(&bbox, &options);
+        assert_eq!(points.len(), n * m);
+        let mut elevations: Vec<f64> = points.iter().map(|p| p.ele.unwrap()).collect();
+        elevations.sort_by(f64::total_cmp);
+        assert_eq!(
+            elevations,
+            (0..n * m).map(|i| 1000.0 + i as f64).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_bilinear_subdivision() {
+        let raster = raster();
+        // 2x2 pixels: a plane z = 10 * col + 100 * row
+        let window = Window {
+            min: (0, 0),
+            xsize: 2,
+            values: vec![0f64, 10f64, 100f64, 110f64],
+            valid: vec![true; 4],
+            nodata: 0,
+            filled: 0,
+        };
+        let p1 = raster.wgs84(0, 0);
+        let p2 = raster.wgs84(1, 1);
+        let bbox = WGS84BoundingBox::from(&p1, &p2);
+        let projection = WebMercatorProjection::make(&p1.to_utm_proj4());
+        assert_eq!(window.points(&raster, &projection, &bbox, 1).len(), 4);
+        let points = window.points(&raster, &projection, &bbox, 4);
+        assert_eq!(points.len(), 25);
+        // row-major: index 7 is (col 2/4, row 1/4)
+        assert!((points[7].ele.unwrap() - 30f64).abs() < 1e-9);
+        assert!((window.bilinear(0, 0, 0.5, 0.5).unwrap() - 55f64).abs() < 1e-9);
+
+        // an invalid corner removes the interpolated points around it
+        let mut holed = window;
+        holed.valid[3] = false;
+        assert_eq!(holed.points(&raster, &projection, &bbox, 1).len(), 3);
+        assert_eq!(holed.points(&raster, &projection, &bbox, 4).len(), 9);
+    }
+
+    #[test]
+    fn test_fixture_wgsbbox() {
+        let bbox = fixtures::dataset().wgsbbox();
+        assert!((bbox.min.lon - 6.0).abs() < 1e-12);
+        assert!((bbox.max.lon - (6.0 + 10.0 / 1200.0)).abs() < 1e-12);
+        assert!((bbox.min.lat - 46.0).abs() < 1e-12);
+        assert!((bbox.max.lat - (46.0 + 10.0 / 1200.0)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_unreadable_window() {
+        // the fixture without its recorded read or nodata value: every read
+        // fails, the dataset is skipped
+        let path = concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/data/fixtures/N46E006-10x10.json"
+        );
+        let calls: Vec<serde_json::Value> =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        for call in ["ReadWindow", "NoDataValue"] {
+            let kept: Vec<_> = calls.iter().filter(|c| c[0].get(call).is_none()).collect();
+            let replay = rasterio::Replay::from_json(&serde_json::to_string(&kept).unwrap());
+            let io = Box::new(replay.unwrap());
+            let dataset = Dataset::from_io("unreadable", io, projection).unwrap();
+            let bbox = dataset.wgsbbox();
+            let options = SampleOptions::default();
+            assert!(dataset.points_inside(&bbox, &options).is_empty());
+            assert_eq!(dataset.stats_in(&bbox, &options).valid, 0);
+            let center = dataset.raster.wgs84_fractional(4.5, 4.5);
+            assert_eq!(dataset.elevation_at(&center, &options), None);
+            let info = dataset.info_data();
+            match call {
+                "NoDataValue" => assert!(matches!(info, Err(DatasetError::Unreadable { .. }))),
+                _ => assert!(info.is_ok()),
+            }
+        }
+    }
+
+    #[test]
+    fn test_info_data() {
+        let info = fixtures::dataset().info_data().unwrap();
+        assert_eq!(info.size, (10, 10));
+        assert!(info.nodata.is_empty());
+        assert!(info.crs.is_none());
+        let text = info.to_string();
+        assert_eq!(text.lines().next(), Some("dataset: N46E006-10x10"));
+        assert!(text.contains("dataset: no nodata value\n"));
+        assert!(!text.contains("rotation"));
+        // 3 arc-seconds at 46° north
+        let (width, height) = info.pixel_size;
+        assert!(
+            (width - 64.5).abs() < 0.1 && (height - 92.6).abs() < 0.1,
+            "{:?}",
+            info.pixel_size
+        );
+        assert!(text.contains("dataset: pixel size 64.5 m x 92.6 m\n"));
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["wgs_bbox"]["min"]["lon"], 6.0);
+        assert_eq!(json["size"], serde_json::json!([10, 10]));
+    }
+
+    #[test]
+    fn test_fixture_snap_and_points_inside() {
+        let dataset = fixtures::dataset();
+        let step = 1.0 / 1200.0;
+        // the samples are at the centers of the cells
+        let mut bbox = WGS84BoundingBox::from(
+            &WGS84Point {
+                lon: 6.0 + 2.8 * step,
+                lat: 46.0 + 3.7 * step,
+                ele: None,
+            },
+            &WGS84Point {
+                lon: 6.0 + 5.1 * step,
+                lat: 46.0 + 6.4 * step,
+                ele: None,
+            },
+        );
+        dataset.snap(&mut bbox);
+        assert!((bbox.min.lon - (6.0 + 2.5 * step)).abs() < 1e-12);
+        assert!((bbox.max.lon - (6.0 + 5.5 * step)).abs() < 1e-12);
+        assert!((bbox.min.lat - (46.0 + 3.5 * step)).abs() < 1e-12);
+        assert!((bbox.max.lat - (46.0 + 6.5 * step)).abs() < 1e-12);
+
+        let points = dataset.points_inside(&bbox, &SampleOptions::default());
+        // cols 2..=5, rows 3..=6 (row 0 is the northern edge)
+        assert_eq!(points.len(), 16);
+        let mut elevations: Vec<f64> = points.iter().map(|p| p.ele.unwrap()).collect();
+        elevations.sort_by(f64::total_cmp);
+        assert_eq!(elevations[0], 132.0);
+        assert_eq!(elevations[15], 165.0);
+    }
+
+    #[test]
+    fn test_nodata_override() {
+        // no NODATA_value: the -9999 and the ocean zeros are read as terrain
+        let grid = crate::asciigrid::AsciiGrid::parse(
+            "ncols 4\nnrows 3\nxllcorner 6.0\nyllcorner 46.0\ncellsize 0.001\n\
+             0 0 512 513\n0 -9999 522 523\n0 531 532 533\n",
+        )
+        .unwrap();
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let dataset = Dataset::from_io("old/N46E006.asc", Box::new(grid), projection).unwrap();
+        let mut bbox = dataset.wgsbbox();
+        dataset.snap(&mut bbox);
+        let sample = |nodata: &[&str]| {
+            let options = SampleOptions {
+                nodata: nodata.iter().map(|n| n.parse().unwrap()).collect(),
+                ..Default::default()
+            };
+            let mut points = 0;
+            let voids = dataset.visit_inside(&bbox, &options, |_, _| points += 1);
+            (points, voids.len())
+        };
+        assert!(dataset.nodata(&[]).unwrap().is_empty());
+        assert_eq!(sample(&[]), (12, 0));
+        assert_eq!(sample(&["-9999"]), (11, 1));
+        // the override naming the dataset wins over the global one
+        assert_eq!(sample(&["-9999", "N46E006.asc=0"]), (8, 4));
+        assert_eq!(sample(&["N47E006.asc=0"]), (12, 0));
+        let rb = RasterBox {
+            min: (0, 0),
+            max: (3, 2),
+        };
+        let options = SampleOptions {
+            nodata: vec!["-9999".parse().unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(dataset.read_window(&rb, &options).unwrap().nodata, 1);
+        let nodata = dataset
+            .nodata(&["old/N46E006.asc=0".parse().unwrap()])
+            .unwrap();
+        assert_eq!(
+            nodata,
+            vec![(0.0, "--nodata old/N46E006.asc=0".to_string())]
+        );
+    }
+
+    // a plane of 11 x 11 samples, the corner in degrees
+    fn plane_grid(name: &str, lon: f64, lat: f64) -> Dataset {
+        let z = |lon: f64, lat: f64| 100_000.0 * (lon - 6.0) + 50_000.0 * (lat - 46.0);
+        let mut text = format!(
+            "ncols 11\nnrows 11\nxllcorner {}\nyllcorner {}\ncellsize 0.001\n",
+            lon, lat
+        );
+        for row in 0..11 {
+            for col in 0..11 {
+                // at the centers of the cells
+                let (x, y) = (
+                    lon + (col as f64 + 0.5) * 0.001,
+                    lat + 0.011 - (row as f64 + 0.5) * 0.001,
+                );
+                text.push_str(&format!("{} ", z(x, y)));
+            }
+            text.push('\n');
+        }
+        let grid = crate::asciigrid::AsciiGrid::parse(&text).unwrap();
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        Dataset::from_io(name, Box::new(grid), projection).unwrap()
+    }
+
+    #[test]
+    fn test_box_past_upper_left() {
+        // the western tile of a seam at 6°E: the box snapped to its eastern
+        // neighbor starts a pixel before it and ends a pixel above it
+        let dataset = plane_grid("seam.asc", 6.0, 46.0);
+        let mut bbox = dataset.wgsbbox();
+        bbox.min.lon -= 0.001;
+        bbox.max.lat += 0.001;
+        let dbbox = dataset.wgsbbox();
+        let mut count = 0;
+        dataset.visit_inside(&bbox, &SampleOptions::default(), |wgs, _| {
+            assert!(bbox.contains_point(wgs) && dbbox.contains_point(wgs));
+            count += 1;
+        });
+        assert_eq!(count, 11 * 11);
+    }
+
+    #[test]
+    fn test_elevation_at() {
+        let dataset = plane_grid("plane.asc", 6.0, 46.0);
+        let z = |lon: f64, lat: f64| 100_000.0 * (lon - 6.0) + 50_000.0 * (lat - 46.0);
+        let bbox = dataset.wgsbbox();
+        let at = |lon: f64, lat: f64| {
+            let point = WGS84Point {
+                lon,
+                lat,
+                ele: None,
+            };
+            dataset.elevation_at(&point, &SampleOptions::default())
+        };
+        let (lon, lat) = (6.00437, 46.00612);
+        assert!((at(lon, lat).unwrap() - z(lon, lat)).abs() < 1e-6);
+        // the corners of the samples, the last row and column included
+        let (first, last) = (dataset.raster.wgs84(0, 0), dataset.raster.wgs84(10, 10));
+        assert!((at(first.lon, first.lat).unwrap() - z(first.lon, first.lat)).abs() < 1e-6);
+        assert!((at(last.lon, last.lat).unwrap() - z(last.lon, last.lat)).abs() < 1e-6);
+        assert_eq!(at(bbox.min.lon - 0.001, lat), None);
+        assert_eq!(at(lon, bbox.max.lat + 0.001), None);
+
+        // a void among the four pixels: the nearest valid one
+        let text = "ncols 3\nnrows 3\nxllcorner 6.0\nyllcorner 46.0\ncellsize 0.01\n\
+                    NODATA_value -9999\n1 -9999 3\n4 5 6\n7 8 9\n";
+        let grid = crate::asciigrid::AsciiGrid::parse(text).unwrap();
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let dataset = Dataset::from_io("void.asc", Box::new(grid), projection).unwrap();
+        let near = |col: f64, row: f64, options: &SampleOptions| {
+            dataset
+                .elevation_at(&dataset.raster.wgs84_fractional(col, row), options)
+                .unwrap()
+        };
+        let options = SampleOptions::default();
+        assert_eq!(near(1.8, 0.1, &options), 3.0);
+        assert_eq!(near(0.4, 0.3, &options), 1.0);
+        assert!((near(1.5, 1.5, &options) - 7.0).abs() < 1e-9);
+        // --nodata 3 voids the upper right pixel too
+        let options = SampleOptions {
+            nodata: vec!["3".parse().unwrap()],
+            ..Default::default()
+        };
+        assert_eq!(near(1.8, 0.1, &options), 6.0);
+    }
+
+    #[test]
+    fn test_blocks() {
+        // three blocks and a few rows, holes across the seams of the blocks
+        let (xsize, ysize) = (40, 2 * BLOCK_ROWS as usize + 7);
+        let step = 1.0 / 1200.0;
+        let io =
+            crate::rasterio::Synthetic::new((6.0, 46.5), step, (xsize, ysize), move |lon, lat| {
+                let (col, row) = (((lon - 6.0) / step).round(), ((46.5 - lat) / step).round());
+                if (col as usize + 3 * row as usize).is_multiple_of(17) {
+                    return None;
+                }
+                Some(500.0 + 0.1 * col + 0.2 * row)
+            });
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let dataset = Dataset::from_io("blocks", Box::new(io), projection).unwrap();
+        let mut bbox = dataset.wgsbbox();
+        dataset.snap(&mut bbox);
+        for subdivide in [1, 2] {
+            let options = SampleOptions {
+                subdivide,
+                ..Default::default()
+            };
+            let mut blocked = Vec::new();
+            let voids = dataset.visit_inside(&bbox, &options, |_, m| blocked.push(m));
+            // the whole window at once
+            let rb = RasterBox {
+                min: (0, 0),
+                max: (xsize as isize - 1, ysize as isize - 1),
+            };
+            let window = dataset.read_window(&rb, &options).unwrap();
+            let mercator = WebMercatorProjection::make(projection);
+            let whole = window.points(&dataset.raster, &mercator, &bbox, subdivide);
+            let whole_voids = window.visit(
+                &dataset.raster,
+                &mercator,
+                &bbox,
+                &Lattice::subdivided(subdivide),
+                false,
+                |_, _| {},
+            );
+            assert_eq!(blocked, whole);
+            assert_eq!(voids, whole_voids);
+            assert!(!voids.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_offset_grids() {
+        // overlapping by 4 pixels, offset by 0.4 pixel in both directions
+        let a = plane_grid("a.asc", 6.0, 46.0);
+        let b = plane_grid("b.asc", 6.0074, 46.0004);
+        assert!(a.aligned(&a) && !a.aligned(&b));
+        assert!(a.aligned(&plane_grid("c.asc", 6.011, 46.0)));
+        let mut bbox = a.wgsbbox();
+        bbox.max = b.wgsbbox().max;
+        let mut points = BTreeSet::new();
+        let voids =
+            crate::pipeline::visit_datasets(&[a, b], &bbox, &SampleOptions::default(), |wgs, m| {
+                let z = 100_000.0 * (wgs.lon - 6.0) + 50_000.0 * (wgs.lat - 46.0);
+                assert!((wgs.ele.unwrap() - z).abs() < 1e-6);
+                points.insert(m);
+            });
+        assert!(voids.is_empty());
+        // one lattice: every sample has its nearest neighbor at the same
+        // distance, the spacing along the parallels
+        let points: Vec<MercatorPoint> = points.into_iter().collect();
+        let nearest: Vec<f64> = points
+            .iter()
+            .map(|p| {
+                points
+                    .iter()
+                    .filter(|q| *q != p)
+                    .map(|q| (q.x - p.x).hypot(q.y - p.y))
+                    .fold(f64::INFINITY, f64::min)
+            })
+            .collect();
+        let min = nearest.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = nearest.iter().cloned().fold(0.0, f64::max);
+        assert!(
+            max / min < 1.01,
+            "nearest neighbors from {} to {} m",
+            min,
+            max
+        );
+        // the 11 x 11 samples of a, and those of b beyond them
+        assert!(points.len() > 11 * 11 + 50, "{}", points.len());
+    }
+
+    #[test]
+    fn test_stats_in() {
+        let io = crate::rasterio::Synthetic::new((6.0, 46.5), 0.01, (10, 10), |lon, lat| {
+            let (col, row) = (((lon - 6.0) / 0.01).round(), ((46.5 - lat) / 0.01).round());
+            (col < 9.0).then_some(col + 10.0 * row)
+        });
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let dataset = Dataset::from_io("stats", Box::new(io), projection).unwrap();
+        let corner = |lon, lat| WGS84Point {
+            lon,
+            lat,
+            ele: None,
+        };
+        // columns 5 to 9, past the eastern edge, the last one nodata
+        let bbox = WGS84BoundingBox::from(&corner(6.045, 46.3), &corner(6.2, 46.6));
+        let stats = dataset.stats_in(&bbox, &SampleOptions::default());
+        assert_eq!((stats.valid, stats.invalid), (40, 10));
+        assert_eq!((stats.min, stats.max), (5.0, 98.0));
+        assert!((stats.mean - 51.5).abs() < 1e-9);
+        assert!((stats.stddev - (1.25f64 + 825.0).sqrt()).abs() < 1e-9);
+        let outside = WGS84BoundingBox::from(&corner(7.0, 46.3), &corner(7.2, 46.6));
+        assert_eq!(
+            dataset.stats_in(&outside, &SampleOptions::default()),
+            ElevationStats::default()
+        );
+    }
+
+    #[test]
+    fn test_decimate() {
+        let step = 0.001;
+        let io = crate::rasterio::Synthetic::new((6.0, 46.02), step, (20, 15), |lon, lat| {
+            Some(1000.0 * (lon + lat))
+        });
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let dataset = Dataset::from_io("decimate", Box::new(io), projection).unwrap();
+        let mut bbox = dataset.wgsbbox();
+        dataset.snap(&mut bbox);
+        let options = SampleOptions {
+            decimate: 4,
+            ..Default::default()
+        };
+        let mut samples = Vec::new();
+        dataset.visit_inside(&bbox, &options, |wgs, _| samples.push(wgs.clone()));
+        // columns 0, 4, 8, 12, 16 and the last one, rows 0, 4, 8, 12 and 14
+        assert_eq!(samples.len(), 6 * 5);
+        let last = dataset.raster.wgs84(19, 14);
+        assert!(
+            samples
+                .iter()
+                .any(|p| (p.lon - last.lon).abs() < 1e-12 && (p.lat - last.lat).abs() < 1e-12)
+        );
+        let full = dataset.points_inside(&bbox, &SampleOptions::default());
+        assert_eq!(full.len(), 20 * 15);
+    }
+
+    #[test]
+    fn test_seam() {
+        // two tiles sharing a column, the second one off by rounding noise
+        let step = 0.001;
+        let z = |lon: f64, lat: f64| Some(100_000.0 * (lon - 6.0) + 50_000.0 * (lat - 46.0));
+        let west = crate::rasterio::Synthetic::new((6.0, 46.01), step, (11, 11), z);
+        let east = crate::rasterio::Synthetic::new((6.01 + 1e-13, 46.01), step, (11, 11), z);
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let west = Dataset::from_io("west", Box::new(west), projection).unwrap();
+        let east = Dataset::from_io("east", Box::new(east), projection).unwrap();
+        assert!(west.aligned(&east));
+        let mut bbox = west.wgsbbox();
+        bbox.max = east.wgsbbox().max;
+        let mut points = BTreeSet::new();
+        crate::pipeline::visit_datasets(&[west, east], &bbox, &SampleOptions::default(), |_, m| {
+            points.insert(m);
+        });
+        // one sample per position: nothing for slivers along the seam
+        assert_eq!(points.len(), 21 * 11);
+        for p in &points {
+            let nearest = points
+                .iter()
+                .filter(|q| *q != p)
+                .map(|q| (q.x - p.x).hypot(q.y - p.y))
+                .fold(f64::INFINITY, f64::min);
+            assert!(nearest > 50.0, "{}", nearest);
+        }
+    }
+
+    #[test]
+    fn test_finer_overlap() {
+        // a 1" tile over the western half of a 3" one, at other elevations
+        let step = 1.0 / 3600.0;
+        let fine =
+            crate::rasterio::Synthetic::new((6.0, 46.02), step, (37, 73), |_, _| Some(1000.0));
+        let coarse = crate::rasterio::Synthetic::new((6.0, 46.02), 3.0 * step, (25, 25), |_, _| {
+            Some(2000.0)
+        });
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let fine = Dataset::from_io("fine", Box::new(fine), projection).unwrap();
+        let coarse = Dataset::from_io("coarse", Box::new(coarse), projection).unwrap();
+        let bbox = coarse.wgsbbox();
+        let datasets = [coarse, fine];
+        let mut points = Vec::new();
+        let voids = crate::pipeline::visit_datasets(
+            &datasets,
+            &bbox,
+            &SampleOptions::default(),
+            |wgs, m| points.push((wgs.clone(), m)),
+        );
+        assert!(voids.is_empty());
+        // one sample per position, the fine ones over the fine tile
+        let positions: BTreeSet<(u64, u64)> = points
+            .iter()
+            .map(|(_, m)| (m.x.to_bits(), m.y.to_bits()))
+            .collect();
+        assert_eq!(positions.len(), points.len());
+        for (wgs, _) in &points {
+            let expected = if datasets[1].covers(wgs) {
+                1000.0
+            } else {
+                2000.0
+            };
+            assert!((wgs.ele.unwrap() - expected).abs() < 1e-6);
+        }
+        let fine = points.iter().filter(|(wgs, _)| wgs.ele == Some(1000.0));
+        assert_eq!(fine.count(), 37 * 73);
+        assert!(points.len() > 37 * 73 + 30 * 73);
+    }
+
+    #[test]
+    fn test_rotated_raster() {
+        let (sin, cos) = 7f64.to_radians().sin_cos();
+        let step = 1.0 / 3600.0;
+        let raster = Raster {
+            xstep: step * cos,
+            ystep: -step * cos,
+            rotation: (step * sin, step * sin),
+            ..raster()
+        };
+        assert!(raster.oblique());
+        for (col, row) in [(0.0, 0.0), (10.0, 0.0), (0.0, 10.0), (1234.5, 17.25)] {
+            let wgs = raster.wgs84_fractional(col, row);
+            let (c, r) = raster.coordinates(&wgs);
+            assert!((c - col).abs() < 1e-6 && (r - row).abs() < 1e-6);
+        }
+        // the columns turn counterclockwise: eastward and a bit north
+        let (p0, p1) = (raster.wgs84(0, 0), raster.wgs84(100, 0));
+        assert!(p1.lon > p0.lon && p1.lat > p0.lat);
+        assert!(((p1.lat - p0.lat) / (p1.lon - p0.lon) - sin / cos).abs() < 1e-9);
+
+        // the samples of a rotated dataset are where the terrain is
+        let z = |lon: f64, lat: f64| Some(500.0 + 30_000.0 * (lon - 6.0) + 70_000.0 * (lat - 46.0));
+        let io = crate::rasterio::Synthetic::new((6.0, 46.02), 0.001, (20, 15), z).rotated(7.0);
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let dataset = Dataset::from_io("rotated", Box::new(io), projection).unwrap();
+        let ul = dataset.raster.wgs84(0, 0);
+        assert!((ul.lon - 6.0).abs() < 1e-12 && (ul.lat - 46.02).abs() < 1e-12);
+        let mut bbox = dataset.wgsbbox();
+        dataset.snap(&mut bbox);
+        let mut count = 0;
+        dataset.visit_inside(&bbox, &SampleOptions::default(), |wgs, _| {
+            assert!((wgs.ele.unwrap() - z(wgs.lon, wgs.lat).unwrap()).abs() < 1e-6);
+            count += 1;
+        });
+        assert_eq!(count, 20 * 15);
+        assert!(dataset.aligned(&dataset));
+    }
+
+    #[test]
+    fn test_projected_raster() {
+        // the same terrain on an SRTM-like grid and on an LV95 one
+        let z = |lon: f64, lat: f64| {
+            1500.0 + 400.0 * ((lon - 7.4) * 150.0).sin() * ((lat - 46.9) * 200.0).cos()
+        };
+        let lv95 = "+proj=somerc +lat_0=46.9524055555556 +lon_0=7.43958333333333 +k_0=1 \
+                    +x_0=2600000 +y_0=1200000 +ellps=bessel \
+                    +towgs84=674.374,15.056,405.346,0,0,0,0 +units=m +no_defs +type=crs";
+        let center = WGS84Point {
+            lon: 7.45,
+            lat: 46.95,
+            ele: None,
+        };
+        let proj4 = center.to_laea_proj4();
+        let step = 1.0 / 1200.0;
+        let srtm =
+            crate::rasterio::Synthetic::new((7.4, 47.0), step, (121, 121), move |lon, lat| {
+                Some(z(lon, lat))
+            });
+        let srtm = Dataset::from_io("srtm", Box::new(srtm), &proj4).unwrap();
+        let swiss = WebMercatorProjection::make(lv95).project(&center).unwrap();
+        let origin = (swiss.x - 3000.0, swiss.y + 3000.0);
+        let swisstopo = crate::rasterio::Synthetic::new(origin, 50.0, (121, 121), move |e, n| {
+            let wgs = WebMercatorProjection::make(lv95).unproject(&MercatorPoint {
+                x: e,
+                y: n,
+                ele: None,
+            });
+            let wgs = wgs.unwrap();
+            Some(z(wgs.lon, wgs.lat))
+        })
+        .projected(lv95);
+        let swisstopo = Dataset::from_io("swisstopo", Box::new(swisstopo), &proj4).unwrap();
+
+        // the pixels are where the geotransform puts them in LV95, to the
+        // precision of the datum shift
+        let ul = swisstopo.raster.wgs84_fractional(0.0, 0.0);
+        let (col, row) = swisstopo.raster.coordinates(&ul);
+        assert!(col.abs() < 1e-3 && row.abs() < 1e-3);
+        let bbox = swisstopo.wgsbbox();
+        assert!(bbox.contains_point(&center));
+        assert!((bbox.max.lat - bbox.min.lat - 6000.0 / 111_000.0).abs() < 0.002);
+        assert!(!srtm.aligned(&swisstopo));
+
+        // a square of 3 km around the center
+        let projection = WebMercatorProjection::make(&proj4);
+        let polygon: Vec<MercatorPoint> = [
+            (-1500.0, -1500.0),
+            (1500.0, -1500.0),
+            (1500.0, 1500.0),
+            (-1500.0, 1500.0),
+        ]
+        .iter()
+        .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+        .collect();
+        let wgs: Vec<WGS84Point> = polygon
+            .iter()
+            .map(|p| projection.unproject(p).unwrap())
+            .collect();
+        let mut bbox = WGS84BoundingBox::from(&wgs[0], &wgs[2]);
+        for p in &wgs {
+            bbox.min.lon = bbox.min.lon.min(p.lon);
+            bbox.min.lat = bbox.min.lat.min(p.lat);
+            bbox.max.lon = bbox.max.lon.max(p.lon);
+            bbox.max.lat = bbox.max.lat.max(p.lat);
+        }
+        let area = |dataset: Dataset| {
+            let mut points = BTreeSet::new();
+            let voids = crate::pipeline::visit_datasets(
+                &[dataset],
+                &bbox,
+                &SampleOptions::default(),
+                |_, m| {
+                    points.insert(m);
+                },
+            );
+            assert!(voids.is_empty());
+            let points: Vec<MercatorPoint> = points.into_iter().collect();
+            let mesh = crate::triangulation::grid::triangulate(&points);
+            let surface = crate::compute::clip(&polygon, &mesh);
+            assert!((surface.projected2d - 3000.0 * 3000.0).abs() < 1.0);
+            surface.projected3d
+        };
+        let (a, b) = (area(srtm), area(swisstopo));
+        assert!(a > 1.1 * 3000.0 * 3000.0);
+        assert!((a - b).abs() < 0.002 * a, "{} {}", a, b);
+    }
+
+    #[test]
+    fn test_south_up() {
+        // the same samples, the rows going south or north
+        let step = 1.0 / 1200.0;
+        let z = move |lon: f64, lat: f64| {
+            let (col, row) = (((lon - 6.0) / step).round(), ((46.5 - lat) / step).round());
+            (col != 7.0 || row != 4.0)
+                .then_some(1000.0 + 3000.0 * (lon - 6.0) - 2000.0 * (lat - 46.5))
+        };
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let north = crate::rasterio::Synthetic::new((6.0, 46.5), step, (30, 20), z);
+        let south = crate::rasterio::Synthetic::new((6.0, 46.5), step, (30, 20), z).south_up();
+        let north = Dataset::from_io("north", Box::new(north), projection).unwrap();
+        let south = Dataset::from_io("south", Box::new(south), projection).unwrap();
+        let (a, b) = (north.wgsbbox(), south.wgsbbox());
+        assert!((a.min.lat - b.min.lat).abs() < 1e-12 && (a.max.lat - b.max.lat).abs() < 1e-12);
+        assert!((a.min.lon - b.min.lon).abs() < 1e-12 && (a.max.lon - b.max.lon).abs() < 1e-12);
+        let lower_left = south.raster.wgs84(0, 0);
+        assert!((lower_left.lat - (46.5 - 19.0 * step)).abs() < 1e-12);
+        assert_eq!(south.raster.icoordinates(&south.raster.wgs84(3, 5)), (3, 5));
+        let (col, row) = south.raster.coordinates(&north.raster.wgs84(3, 5));
+        assert!((col - 3.0).abs() < 1e-9 && (row - 14.0).abs() < 1e-9);
+        assert!(north.aligned(&north) && !north.aligned(&south));
+
+        let corner = |lon, lat| WGS84Point {
+            lon,
+            lat,
+            ele: None,
+        };
+        let mut bbox = WGS84BoundingBox::from(&corner(6.003, 46.488), &corner(6.017, 46.497));
+        let mut snapped = bbox.clone();
+        north.snap(&mut bbox);
+        south.snap(&mut snapped);
+        assert!((bbox.min.lat - snapped.min.lat).abs() < 1e-12);
+        assert!((bbox.max.lat - snapped.max.lat).abs() < 1e-12);
+        let options = SampleOptions::default();
+        let sorted = |dataset: &Dataset| {
+            let mut points = Vec::new();
+            let voids = dataset.visit_inside(&bbox, &options, |_, m| points.push(m));
+            points.sort();
+            (points, voids)
+        };
+        let (points, voids) = sorted(&south);
+        assert_eq!((points.clone(), voids.clone()), sorted(&north));
+        assert_eq!(voids.len(), 1);
+        assert_eq!(points.len(), 19 * 13 - 1);
+        // the same pixels, summed in another order
+        let (x, y) = (
+            south.stats_in(&bbox, &options),
+            north.stats_in(&bbox, &options),
+        );
+        assert_eq!(
+            (x.valid, x.invalid, x.min, x.max),
+            (y.valid, y.invalid, y.min, y.max)
+        );
+        assert!((x.stddev - y.stddev).abs() < 1e-9);
+        let p = corner(6.0101, 46.4937);
+        let (x, y) = (
+            north.elevation_at(&p, &options).unwrap(),
+            south.elevation_at(&p, &options).unwrap(),
+        );
+        assert!((x - y).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_area_or_point() {
+        // the same samples, given by their position or by the corner of
+        // their cell
+        let step = 1.0 / 1200.0;
+        let z = |lon: f64, lat: f64| Some(1000.0 + 3000.0 * (lon - 6.0) - 2000.0 * (lat - 46.5));
+        let proj4 = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let point = crate::rasterio::Synthetic::new((6.0, 46.5), step, (30, 20), z);
+        let area = crate::rasterio::Synthetic::new(
+            (6.0 - step / 2.0, 46.5 + step / 2.0),
+            step,
+            (30, 20),
+            z,
+        )
+        .area();
+        let point = Dataset::from_io("point", Box::new(point), proj4).unwrap();
+        let area = Dataset::from_io("area", Box::new(area), proj4).unwrap();
+        assert!(point.aligned(&area));
+        let (a, b) = (point.wgsbbox(), area.wgsbbox());
+        assert!((a.min.lon - b.min.lon).abs() < 1e-12 && (a.max.lat - b.max.lat).abs() < 1e-12);
+        assert!((a.min.lon - (6.0 - step / 2.0)).abs() < 1e-12);
+        let mut bbox = a;
+        point.snap(&mut bbox);
+        let options = SampleOptions::default();
+        let samples = |dataset: &Dataset| {
+            let mut ret = Vec::new();
+            dataset.visit_inside(&bbox, &options, |wgs, _| ret.push(wgs.clone()));
+            ret
+        };
+        let (a, b) = (samples(&point), samples(&area));
+        assert_eq!(a.len(), 30 * 20);
+        assert_eq!(a.len(), b.len());
+        for (p, q) in a.iter().zip(&b) {
+            assert!((p.lon - q.lon).abs() < 1e-9 && (p.lat - q.lat).abs() < 1e-9);
+            assert!((p.ele.unwrap() - q.ele.unwrap()).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_no_dataset_coverage() {
+        let dir = fixtures::temp_dir("surface-area-empty-dem");
+        let dir = dir.to_str().unwrap().to_string();
+        // somewhere in the Pacific
+        let polygon = fixtures::triangle(-140.2, -10.1, 0.1, -0.1);
+        let candidates =
+            DatasetRegistry::new(vec![PathBuf::from(&dir)]).candidates(&polygon, IoBackend::Auto);
+        assert!(candidates.is_empty());
+        let datasets = Dataset::select_candidates(
+            &polygon,
+            &candidates,
+            &CandidateFilter::default(),
+            IoBackend::Auto,
+        );
+        let error =
+            Dataset::check_coverage(&polygon, &datasets, std::slice::from_ref(&dir)).unwrap_err();
+        let message = error.to_string();
+        assert!(message.contains(&dir));
+        assert!(message.contains("S11W141.hgt"));
+    }
+
+    #[test]
+    fn test_select_from() {
+        let dir = fixtures::temp_dir("surface-area-select-from");
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        std::fs::write(path("west.asc"), fixtures::grid(6.0)).unwrap();
+        std::fs::write(path("east.asc"), fixtures::grid(6.03)).unwrap();
+        std::fs::write(path("far.asc"), fixtures::grid(10.0)).unwrap();
+        let polygon = fixtures::triangle(6.005, 46.005, 0.04, 0.02);
+        let select = |names: &[&str]| {
+            let files: Vec<String> = names.iter().map(|n| path(n)).collect();
+            Dataset::select_from(&polygon, &files, IoBackend::Auto)
+        };
+        let datasets = select(&["west.asc", "east.asc", "far.asc"]).unwrap();
+        let names: Vec<&str> = datasets.iter().map(|d| d.filename.as_str()).collect();
+        assert_eq!(names, [path("east.asc"), path("west.asc")]);
+        match select(&["west.asc", "far.asc"]) {
+            Err(DatasetError::IncompleteCoverage { coverage, .. }) => {
+                assert!((coverage - 0.625).abs() < 1e-9, "{}", coverage)
+            }
+            _ => panic!("the eastern part is not covered"),
+        }
+        assert!(matches!(
+            select(&["west.asc", "east.asc", "missing.asc"]),
+            Err(DatasetError::Unreadable { .. })
+        ));
+    }
+
+    #[test]
+    fn test_antimeridian() {
+        let dir = fixtures::temp_dir("surface-area-antimeridian");
+        // 60 × 40 cells of 0.001°, flat
+        let grid = |lon: f64| {
+            let row = vec!["100"; 60].join(" ");
+            format!(
+                "ncols 60\nnrows 40\nxllcorner {}\nyllcorner 10.0\ncellsize 0.001\n{}\n",
+                lon,
+                vec![row; 40].join("\n")
+            )
+        };
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        std::fs::write(path("east.asc"), grid(179.95)).unwrap();
+        std::fs::write(path("west.asc"), grid(-180.01)).unwrap();
+        // in the box of the world
+        std::fs::write(path("far.asc"), grid(0.0)).unwrap();
+        let wgs = |lon: f64, lat: f64| WGS84Point {
+            lon,
+            lat,
+            ele: None,
+        };
+        let polygon = Polygon::new(vec![
+            wgs(179.97, 10.01),
+            wgs(-179.97, 10.01),
+            wgs(-179.97, 10.03),
+            wgs(179.97, 10.03),
+        ]);
+        assert!(polygon.crosses_antimeridian());
+        let bbox = polygon.wgsbbox();
+        assert!((bbox.min.lon - 179.97).abs() < 1e-9 && (bbox.max.lon - 180.03).abs() < 1e-9);
+        let tiles: Vec<String> = polygon.required_tiles().into_iter().collect();
+        assert_eq!(tiles, ["N10E179.hgt", "N10W180.hgt"]);
+
+        let candidates =
+            DatasetRegistry::new(vec![dir.clone()]).candidates(&polygon, IoBackend::Auto);
+        let datasets = Dataset::select_candidates(
+            &polygon,
+            &candidates,
+            &CandidateFilter::default(),
+            IoBackend::Auto,
+        );
+        let mut names: Vec<&str> = datasets.iter().map(|d| d.filename.as_str()).collect();
+        names.sort();
+        assert_eq!(names, [path("east.asc"), path("west.asc")]);
+        assert!(Dataset::missing_tiles(&polygon, &datasets).is_empty());
+
+        // 0.06° by 0.02°, about 6.6 by 2.2 km
+        let data =
+            crate::pipeline::compute("antimeridian", &polygon, &datasets, &Default::default())
+                .unwrap();
+        let geodesic = polygon.geodesic_area().unwrap();
+        assert!((geodesic / 1e6 - 14.5).abs() < 0.5, "{}", geodesic);
+        // in UTM zone 1, 3° from its meridian: a scale error of 0.19%
+        assert!(
+            (data.planar2d / geodesic - 1.0).abs() < 3e-3,
+            "{} {}",
+            data.planar2d,
+            geodesic
+        );
+        assert!((data.projected3d / data.planar2d - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_duplicate_candidates() {
+        let dir = fixtures::temp_dir("surface-area-duplicates/GL3");
+        std::fs::create_dir_all(dir.join("copies")).unwrap();
+        let grid = &fixtures::grid(6.0);
+        std::fs::write(dir.join("tile.asc"), grid).unwrap();
+        std::fs::write(dir.join("copies/tile.asc"), grid).unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(dir.join("tile.asc"), dir.join("link.asc")).unwrap();
+        let polygon = fixtures::triangle(6.005, 46.005, 0.01, 0.01);
+        let dir = dir.to_str().unwrap().to_string();
+        let mut candidates =
+            DatasetRegistry::new(vec![PathBuf::from(&dir)]).candidates(&polygon, IoBackend::Auto);
+        candidates.insert(format!("{}/./tile.asc", dir));
+        assert!(candidates.len() >= 3, "{:?}", candidates);
+        assert_eq!(unique_files(&candidates).len(), 2);
+        let datasets = Dataset::select_candidates(
+            &polygon,
+            &candidates,
+            &CandidateFilter::default(),
+            IoBackend::Auto,
+        );
+        assert_eq!(datasets.len(), 1);
+    }
+
+    #[test]
+    fn test_tile_aliases() {
+        // the same samples as an .hgt tile, its GeoTIFF conversion (an area
+        // raster) and a copy of the tile
+        let step = 0.01;
+        let z = |lon: f64, lat: f64| Some(1000.0 * (lon - 6.0) + 500.0 * (lat - 46.0));
+        let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let hgt = |name: &str| {
+            let io = crate::rasterio::Synthetic::new((6.0, 46.09), step, (10, 10), z);
+            Dataset::from_io(name, Box::new(io), projection).unwrap()
+        };
+        let tif = || {
+            let corner = (6.0 - 0.5 * step, 46.09 + 0.5 * step);
+            let io = crate::rasterio::Synthetic::new(corner, step, (10, 10), z).area();
+            Dataset::from_io("tif/N46E006.tif", Box::new(io), projection).unwrap()
+        };
+        assert!(tif().same_grid(&hgt("N46E006.hgt")));
+        let polygon = Polygon::new(vec![
+            WGS84Point {
+                lon: 6.02,
+                lat: 46.02,
+                ele: None,
+            },
+            WGS84Point {
+                lon: 6.05,
+                lat: 46.02,
+                ele: None,
+            },
+            WGS84Point {
+                lon: 6.05,
+                lat: 46.05,
+                ele: None,
+            },
+        ]);
+        let kept = |filter: &CandidateFilter| {
+            let opened = vec![hgt("a/N46E006.hgt"), tif(), hgt("b/N46E006.hgt")];
+            Dataset::prune(&polygon, opened, filter)
+                .iter()
+                .map(|d| d.filename.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kept(&CandidateFilter::default()), ["tif/N46E006.tif"]);
+        let filter = CandidateFilter {
+            prefer: vec!["b/*".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(kept(&filter), ["b/N46E006.hgt"]);
+    }
+
+    #[test]
+    fn test_unreadable_candidates() {
+        let dir = fixtures::temp_dir("surface-area-unreadable/GL3");
+        let grid = &fixtures::grid(6.0);
+        std::fs::write(dir.join("tile.asc"), grid).unwrap();
+        // the first bytes of a little-endian TIFF header, then nothing
+        std::fs::write(dir.join("truncated.tif"), b"II*\0\x08\0").unwrap();
+        std::fs::write(dir.join("N46E006.hgt"), [0u8; 7]).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let polygon = fixtures::triangle(6.005, 46.005, 0.01, 0.01);
+        let projection = polygon.projection();
+        for name in ["missing.tif", "truncated.tif", "N46E006.hgt"] {
+            match Dataset::open(&path(name), &projection, IoBackend::Mmap) {
+                Err(DatasetError::Unreadable { filename, .. }) => assert_eq!(filename, path(name)),
+                _ => panic!("{} opened", name),
+            }
+        }
+        let candidates: BTreeSet<String> = ["missing.tif", "truncated.tif", "tile.asc"]
+            .iter()
+            .map(|name| path(name))
+            .collect();
+        let datasets = Dataset::select_candidates(
+            &polygon,
+            &candidates,
+            &CandidateFilter::default(),
+            IoBackend::Auto,
+        );
+        assert_eq!(datasets.len(), 1);
+        assert_eq!(datasets[0].filename, path("tile.asc"));
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*NASADEM*", "/dem/GL3/NASADEM/N46E006.hgt"));
+        assert!(glob_match("*.hgt", "N46E006.hgt"));
+        assert!(glob_match("N4?E006*", "N46E006.hgt"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("*.tif", "N46E006.hgt"));
+        assert!(!glob_match("N46", "N46E006.hgt"));
+        assert!(glob_match("*a*b*c", "xaxxbxbxc"));
+        assert!(!glob_match("*a*b*c", "xaxxcxb"));
+    }
+
+    #[test]
+    fn test_candidate_patterns() {
+        let dir = fixtures::temp_dir("surface-area-vintages/GL3");
+        let grid = &fixtures::grid(6.0);
+        let mut candidates = BTreeSet::new();
+        for vintage in ["nasadem", "srtm2", "srtm3"] {
+            let path = dir.join(format!("N46E006_{}.asc", vintage));
+            std::fs::write(&path, grid).unwrap();
+            candidates.insert(path.to_str().unwrap().to_string());
+        }
+        let polygon = fixtures::triangle(6.005, 46.005, 0.01, 0.01);
+        let patterns = |list: &[&str]| list.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        let selected = |include: &[&str], exclude: &[&str], prefer: &[&str]| {
+            let filter = CandidateFilter {
+                include: patterns(include),
+                exclude: patterns(exclude),
+                prefer: patterns(prefer),
+            };
+            Dataset::select_candidates(&polygon, &candidates, &filter, IoBackend::Auto)
+                .iter()
+                .map(|d| {
+                    let name = Path::new(&d.filename).file_stem().unwrap();
+                    name.to_str()
+                        .unwrap()
+                        .trim_start_matches("N46E006_")
+                        .to_string()
+                })
+                .collect::<Vec<_>>()
+        };
+        // the same grid three times: the first one is kept
+        assert_eq!(selected(&[], &[], &[]), ["nasadem"]);
+        assert_eq!(selected(&[], &[], &["*srtm3*"]), ["srtm3"]);
+        assert_eq!(selected(&[], &["*nasadem*"], &[]), ["srtm2"]);
+        assert_eq!(selected(&["*srtm*"], &[], &["*srtm3*"]), ["srtm3"]);
+        assert_eq!(
+            selected(&["*srtm2*", "*srtm3*"], &["*2.asc"], &[]),
+            ["srtm3"]
+        );
+        assert!(selected(&["*nasadem*"], &["*nasadem*"], &[]).is_empty());
+        // a preference never brings back an excluded vintage
+        assert_eq!(selected(&[], &["*srtm3*"], &["*srtm3*"]), ["nasadem"]);
+    }
+
+    #[test]
+    fn test_ground_cell_size() {
+        // 3" pixels at 60°N: 46 m by 93 m, twice as large in Web Mercator
+        let io = crate::rasterio::Synthetic::new((10.0, 60.5), 1.0 / 1200.0, (121, 121), |_, _| {
+            Some(100.0)
+        });
+        let proj4 = crate::mercator::epsg_proj4(3857).unwrap();
+        let dataset = Dataset::from_io("north", Box::new(io), &proj4).unwrap();
+        let ground = dataset.ground_cell_size();
+        assert!((ground - (46.4f64 * 92.8).sqrt()).abs() < 1.0, "{}", ground);
+        let ratio = dataset.cell_size().unwrap() / ground;
+        assert!((ratio - 2.0).abs() < 0.05, "{}", ratio);
+        assert_eq!(morphology::radius_in_pixels(200.0, ground), 3);
+    }
+
+    #[test]
+    fn test_unprojectable_dataset() {
+        // a broken geotransform, past the pole
+        let io = crate::rasterio::Synthetic::new((10.0, 90.5), 1.0 / 1200.0, (121, 121), |_, _| {
+            Some(100.0)
+        });
+        let proj4 = crate::mercator::epsg_proj4(3857).unwrap();
+        let polar = Dataset::from_io("polar", Box::new(io), &proj4).unwrap();
+        assert!(polar.mercatorbbox().is_err());
+        assert!(polar.cell_size().is_err());
+        let error = polar.info_data().err().unwrap();
+        assert!(error.to_string().contains("polar"), "{}", error);
+        polar.info(&[]);
+
+        let datasets = [polar, fixtures::dataset()];
+        let sorted = by_cell_size(&datasets);
+        assert_eq!(sorted.len(), 1);
+        assert_eq!(sorted[0].1.filename, "N46E006-10x10");
+    }
+
+    #[test]
+    fn test_fill_voids() {
+        // a slope, planar in the projection, with a hole of 120 m around
+        // the center
+        let center = WGS84Point {
+            lon: 7.025,
+            lat: 46.025,
+            ele: None,
+        };
+        let proj4 = center.to_laea_proj4();
+        let projection = WebMercatorProjection::make(&proj4);
+        let (a, b) = (0.3, -0.2);
+        let z = move |lon, lat| {
+            let m = WebMercatorProjection::make(&center.to_laea_proj4())
+                .project(&WGS84Point {
+                    lon,
+                    lat,
+                    ele: None,
+                })
+                .unwrap();
+            (m.x.hypot(m.y) > 120.0).then_some(1000.0 + a * m.x + b * m.y)
+        };
+        let io = crate::rasterio::Synthetic::new((7.0, 46.05), 1.0 / 1200.0, (61, 61), z);
+        let dataset = Dataset::from_io("hole", Box::new(io), &proj4).unwrap();
+        let polygon: Vec<MercatorPoint> = [(-600.0, -600.0), (600.0, -600.0), (600.0, 600.0)]
+            .iter()
+            .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+            .collect();
+        let wgs: Vec<WGS84Point> = [(-700.0, -700.0), (700.0, 700.0)]
+            .iter()
+            .map(|&(x, y)| projection.unproject(&MercatorPoint { x, y, ele: None }))
+            .collect::<Result<_, _>>()
+            .unwrap();
+        let bbox = WGS84BoundingBox::from(&wgs[0], &wgs[1]);
+        let sample = |fill_radius| {
+            let options = SampleOptions {
+                fill_radius,
+                ..Default::default()
+            };
+            let mut points = BTreeSet::new();
+            let voids = crate::pipeline::visit_datasets(
+                std::slice::from_ref(&dataset),
+                &bbox,
+                &options,
+                |_, m| {
+                    points.insert(m);
+                },
+            );
+            (points.into_iter().collect::<Vec<_>>(), voids)
+        };
+        let (holed, voids) = sample(None);
+        assert!(!voids.is_empty());
+        assert_eq!(dataset.filled(), 0);
+
+        let (points, voids) = sample(Some(300.0));
+        assert!(voids.is_empty());
+        assert_eq!(points.len(), holed.len() + dataset.filled());
+        let mesh = crate::triangulation::grid::triangulate(&points);
+        let surface = crate::compute::clip(&polygon, &mesh);
+        let expected = surface.projected2d * (1.0 + a * a + b * b).sqrt();
+        assert!(
+            (surface.projected3d - expected).abs() < 1e-3 * expected,
+            "{} {}",
+            surface.projected3d,
+            expected
+        );
+        // too small a radius leaves the center of the hole
+        let before = dataset.filled();
+        let (_, voids) = sample(Some(50.0));
+        assert!(!voids.is_empty());
+        assert!(dataset.filled() > before);
     }
 }
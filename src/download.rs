@@ -0,0 +1,278 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+use crate::dataset::{CandidateFilter, Dataset};
+use crate::options::IoBackend;
+use crate::polygon::Polygon;
+use crate::rasterio;
+use crate::registry::DatasetRegistry;
+
+// the gzipped 1 arc-second tiles of the AWS terrain tiles
+pub const DEFAULT_URL: &str =
+    "https://s3.amazonaws.com/elevation-tiles-prod/skadi/{lat}/{tile}.hgt.gz";
+
+// the sizes of the 3 and 1 arc-second tiles
+const SIZES: [usize; 2] = [2 * 1201 * 1201, 2 * 3601 * 3601];
+
+// The tiles that could not be had, with the reason.
+#[derive(Debug)]
+pub struct DownloadError(pub Vec<(String, String)>);
+
+impl fmt::Display for DownloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tiles: Vec<String> = self
+            .0
+            .iter()
+            .map(|(tile, reason)| format!("{} ({})", tile, reason))
+            .collect();
+        write!(f, "could not download {}", tiles.join(", "))
+    }
+}
+
+impl std::error::Error for DownloadError {}
+
+// The URL of the tile (N46E006.hgt) from the template: {tile} is its name
+// without the extension, {lat} the latitude part of it (N46).
+pub fn url(template: &str, tile: &str) -> String {
+    let name = tile.strip_suffix(".hgt").unwrap_or(tile);
+    template
+        .replace("{tile}", name)
+        .replace("{lat}", name.get(..3).unwrap_or(name))
+}
+
+// the body of the URL, read from the disk for file://
+fn get(url: &str) -> Result<Vec<u8>, String> {
+    match url.strip_prefix("file://") {
+        Some(path) => std::fs::read(path).map_err(|e| e.to_string()),
+        None => http_get(url),
+    }
+}
+
+#[cfg(feature = "download")]
+fn http_get(url: &str) -> Result<Vec<u8>, String> {
+    let response = ureq::get(url).call().map_err(|e| e.to_string())?;
+    let mut ret = Vec::new();
+    response
+        .into_reader()
+        .take(SIZES[1] as u64 + 1)
+        .read_to_end(&mut ret)
+        .map_err(|e| e.to_string())?;
+    Ok(ret)
+}
+
+#[cfg(not(feature = "download"))]
+fn http_get(_url: &str) -> Result<Vec<u8>, String> {
+    Err("built without the download feature".to_string())
+}
+
+// the tile in the body, gzipped or not, if it has the size of one
+fn tile(body: Vec<u8>) -> Result<Vec<u8>, String> {
+    let bytes = if body.starts_with(&[0x1f, 0x8b]) {
+        let mut ret = Vec::new();
+        GzDecoder::new(body.as_slice())
+            .take(SIZES[1] as u64 + 1)
+            .read_to_end(&mut ret)
+            .map_err(|e| e.to_string())?;
+        ret
+    } else {
+        body
+    };
+    if !SIZES.contains(&bytes.len()) {
+        return Err(format!("{} bytes is not the size of a tile", bytes.len()));
+    }
+    Ok(bytes)
+}
+
+// Stores the tiles into the directory, with their name, and returns their
+// paths. A tile is written aside and renamed, so that a failed or a
+// concurrent download leaves no partial tile; one already there is kept.
+pub fn fetch(
+    template: &str,
+    directory: &Path,
+    tiles: &[String],
+) -> Result<Vec<PathBuf>, DownloadError> {
+    let mut ret = Vec::new();
+    let mut failed = Vec::new();
+    for name in tiles {
+        let path = directory.join(name);
+        if path.exists() {
+            ret.push(path);
+            continue;
+        }
+        let url = url(template, name);
+        log::info!("download {}", url);
+        let partial = directory.join(format!(".{}.{}", name, std::process::id()));
+        let stored = get(&url)
+            .and_then(tile)
+            .and_then(|bytes| {
+                std::fs::create_dir_all(directory)
+                    .and_then(|_| std::fs::write(&partial, bytes))
+                    .and_then(|_| std::fs::rename(&partial, &path))
+                    .map_err(|e| e.to_string())
+            })
+            .map_err(|e| format!("{}: {}", url, e));
+        match stored {
+            Ok(()) => ret.push(path),
+            Err(e) => {
+                std::fs::remove_file(&partial).ok();
+                failed.push((name.clone(), e));
+            }
+        }
+    }
+    if !failed.is_empty() {
+        return Err(DownloadError(failed));
+    }
+    Ok(ret)
+}
+
+// --download: the tiles that the selected datasets miss are downloaded into
+// the first DEM directory, and the datasets selected again.
+pub fn select(
+    polygon: &Polygon,
+    registry: &DatasetRegistry,
+    filter: &CandidateFilter,
+    backend: IoBackend,
+    template: &str,
+) -> Result<Vec<Dataset>, DownloadError> {
+    Ok(select_parts(&[polygon], registry, filter, backend, template)?.remove(0))
+}
+
+// select for every polygon (the parts of a boundary), see
+// Dataset::select_parts
+pub fn select_parts(
+    polygons: &[&Polygon],
+    registry: &DatasetRegistry,
+    filter: &CandidateFilter,
+    backend: IoBackend,
+    template: &str,
+) -> Result<Vec<Vec<Dataset>>, DownloadError> {
+    let datasets = Dataset::select_parts(polygons, registry, filter, backend);
+    let mut missing = BTreeSet::new();
+    for (polygon, datasets) in polygons.iter().zip(&datasets) {
+        missing.extend(Dataset::missing_tiles(polygon, datasets));
+    }
+    let missing: Vec<String> = missing.into_iter().collect();
+    if missing.is_empty() {
+        return Ok(datasets);
+    }
+    let directory = match registry.directories().into_iter().next() {
+        Some(directory) if !rasterio::is_remote(&directory) => PathBuf::from(directory),
+        _ => {
+            let reason = "the first DEM directory is not a local one".to_string();
+            return Err(DownloadError(
+                missing.into_iter().map(|t| (t, reason.clone())).collect(),
+            ));
+        }
+    };
+    log::info!(
+        "{} missing tiles, download into {}",
+        missing.len(),
+        directory.display()
+    );
+    for path in fetch(template, &directory, &missing)? {
+        registry.add(path.to_string_lossy().into_owned());
+    }
+    Ok(Dataset::select_parts(polygons, registry, filter, backend))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::ComputeOptions;
+    use crate::pipeline;
+    use crate::point::WGS84Point;
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+
+    // a 3 arc-second tile rising to the north
+    fn tile_bytes() -> Vec<u8> {
+        let mut ret = Vec::with_capacity(SIZES[0]);
+        for row in 0..1201 {
+            for _ in 0..1201 {
+                ret.extend_from_slice(&(2000 - row as i16).to_be_bytes());
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_url() {
+        assert_eq!(
+            url(DEFAULT_URL, "N46E006.hgt"),
+            "https://s3.amazonaws.com/elevation-tiles-prod/skadi/N46/N46E006.hgt.gz"
+        );
+        assert_eq!(
+            url("file:///mirror/{tile}.hgt", "S01W071.hgt"),
+            "file:///mirror/S01W071.hgt"
+        );
+    }
+
+    #[test]
+    fn test_download() {
+        let dir = std::env::temp_dir().join("surface-area-download");
+        std::fs::remove_dir_all(&dir).ok();
+        let (mirror, dem) = (dir.join("mirror"), dir.join("dem"));
+        std::fs::create_dir_all(mirror.join("N46")).unwrap();
+        std::fs::create_dir_all(&dem).unwrap();
+        let mut gz = GzEncoder::new(Vec::new(), Compression::fast());
+        gz.write_all(&tile_bytes()).unwrap();
+        std::fs::write(mirror.join("N46/N46E006.hgt.gz"), gz.finish().unwrap()).unwrap();
+        std::fs::write(mirror.join("N46/N46E007.hgt.gz"), [0u8; 100]).unwrap();
+        let template = format!("file://{}/{{lat}}/{{tile}}.hgt.gz", mirror.display());
+
+        let polygon = |lon: f64| {
+            Polygon::new(
+                [(0.2, 0.2), (0.3, 0.2), (0.3, 0.3), (0.2, 0.3)]
+                    .iter()
+                    .map(|(x, y)| WGS84Point {
+                        lon: lon + x,
+                        lat: 46.0 + y,
+                        ele: None,
+                    })
+                    .collect(),
+            )
+        };
+        let registry = DatasetRegistry::new(vec![dem.clone()]);
+        let filter = CandidateFilter::default();
+        let datasets = select(
+            &polygon(6.0),
+            &registry,
+            &filter,
+            IoBackend::Mmap,
+            &template,
+        )
+        .unwrap();
+        assert!(dem.join("N46E006.hgt").exists());
+        assert_eq!(datasets.len(), 1);
+        let data = pipeline::compute(
+            "download",
+            &polygon(6.0),
+            &datasets,
+            &ComputeOptions::default(),
+        )
+        .unwrap();
+        assert!(data.geodesic3d > data.geodesic2d);
+
+        // a tile of the wrong size is not stored, nor a missing one
+        let e = select(
+            &polygon(7.0),
+            &registry,
+            &filter,
+            IoBackend::Mmap,
+            &template,
+        )
+        .err()
+        .unwrap();
+        let tiles: Vec<&str> = e.0.iter().map(|(tile, _)| tile.as_str()).collect();
+        assert_eq!(tiles, ["N46E007.hgt"]);
+        assert!(e.to_string().contains("100 bytes"));
+        let e = fetch(&template, &dem, &["N46E008.hgt".to_string()]).unwrap_err();
+        assert_eq!(e.0[0].0, "N46E008.hgt");
+        assert!(!dem.join("N46E007.hgt").exists());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
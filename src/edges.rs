@@ -0,0 +1,155 @@
+use crate::dataset::Dataset;
+use crate::point::MercatorPoint;
+
+// Fraction of the boundary length along dataset edges above which the
+// seam triangles (interpolated from two tiles that do not quite agree)
+// dominate the boundary planes.
+pub const EDGE_FRACTION_WARNING: f64 = 0.2;
+
+pub fn distance_to_segment(p: &MercatorPoint, a: &MercatorPoint, b: &MercatorPoint) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length2 = dx * dx + dy * dy;
+    let t = if length2 > 0.0 {
+        (((p.x - a.x) * dx + (p.y - a.y) * dy) / length2).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let (x, y) = (a.x + t * dx, a.y + t * dy);
+    ((p.x - x).powi(2) + (p.y - y).powi(2)).sqrt()
+}
+
+// The closed ring as pieces no longer than step.
+pub fn densify(ring: &[MercatorPoint], step: f64) -> Vec<(MercatorPoint, MercatorPoint)> {
+    assert!(step > 0.0);
+    let mut ret = Vec::new();
+    for (k, a) in ring.iter().enumerate() {
+        let b = &ring[(k + 1) % ring.len()];
+        let length = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        let n = (length / step).ceil().max(1.0) as usize;
+        let at = |i: usize| {
+            let t = i as f64 / n as f64;
+            MercatorPoint {
+                x: a.x + t * (b.x - a.x),
+                y: a.y + t * (b.y - a.y),
+                ele: None,
+            }
+        };
+        ret.extend((0..n).map(|i| (at(i), at(i + 1))));
+    }
+    ret
+}
+
+// Fraction of the ring length within `distance` of one of the segments,
+// measured on pieces of a tenth of the distance.
+pub fn fraction_near(
+    ring: &[MercatorPoint],
+    segments: &[(MercatorPoint, MercatorPoint)],
+    distance: f64,
+) -> f64 {
+    let mut total = 0.0;
+    let mut near = 0.0;
+    for (a, b) in densify(ring, 0.1 * distance) {
+        let length = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        let middle = MercatorPoint {
+            x: 0.5 * (a.x + b.x),
+            y: 0.5 * (a.y + b.y),
+            ele: None,
+        };
+        total += length;
+        if segments
+            .iter()
+            .any(|(p, q)| distance_to_segment(&middle, p, q) <= distance)
+        {
+            near += length;
+        }
+    }
+    if total > 0.0 { near / total } else { 0.0 }
+}
+
+// Fraction of the boundary within one cell of the edge of any dataset.
+pub fn fraction_on_edges(ring: &[MercatorPoint], datasets: &[Dataset]) -> f64 {
+    if ring.len() < 2 || datasets.is_empty() {
+        return 0.0;
+    }
+    let Some(&(cell_size, _)) = crate::dataset::by_cell_size(datasets).first() else {
+        return 0.0;
+    };
+    let edges: Vec<_> = datasets.iter().flat_map(|d| d.edges()).collect();
+    fraction_near(ring, &edges, cell_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::WGS84Point;
+    use crate::polygon::Polygon;
+    use crate::rasterio::Synthetic;
+
+    fn point(x: f64, y: f64) -> MercatorPoint {
+        MercatorPoint { x, y, ele: None }
+    }
+
+    #[test]
+    fn test_distance_to_segment() {
+        let (a, b) = (point(0.0, 0.0), point(10.0, 0.0));
+        assert_eq!(distance_to_segment(&point(5.0, 3.0), &a, &b), 3.0);
+        assert_eq!(distance_to_segment(&point(13.0, 4.0), &a, &b), 5.0);
+        assert_eq!(distance_to_segment(&point(-3.0, 0.0), &a, &a), 3.0);
+    }
+
+    #[test]
+    fn test_fraction_near() {
+        let square = [
+            point(0.0, 0.0),
+            point(100.0, 0.0),
+            point(100.0, 100.0),
+            point(0.0, 100.0),
+        ];
+        assert_eq!(densify(&square, 30.0).len(), 16);
+        // the south side lies on the edge, 6 m of the west and east sides
+        // are within 5 m of it
+        let edge = [(point(-50.0, 1.0), point(150.0, 1.0))];
+        let fraction = fraction_near(&square, &edge, 5.0);
+        assert!((fraction - 112.0 / 400.0).abs() < 0.005, "{}", fraction);
+        assert_eq!(fraction_near(&square, &edge[..0], 5.0), 0.0);
+    }
+
+    #[test]
+    fn test_polygon_along_tile_edge() {
+        // two tiles of 0.1° meeting at 7.1°, 1/1200° cells
+        let step = 1.0 / 1200.0;
+        let laea = WGS84Point {
+            lon: 7.1,
+            lat: 46.05,
+            ele: None,
+        }
+        .to_laea_proj4();
+        let tile = |lon: f64| {
+            let io = Synthetic::new((lon, 46.1), step, (121, 121), |_, _| Some(500.0));
+            Dataset::from_io("tile", Box::new(io), &laea).unwrap()
+        };
+        let datasets = [tile(7.0), tile(7.1)];
+        let square = |lon: f64| {
+            let corner = |dlon: f64, dlat: f64| WGS84Point {
+                lon: lon + dlon,
+                lat: 46.04 + dlat,
+                ele: None,
+            };
+            let mut polygon = Polygon::new(vec![
+                corner(0.0, 0.0),
+                corner(0.02, 0.0),
+                corner(0.02, 0.02),
+                corner(0.0, 0.02),
+            ]);
+            polygon.proj4 = Some(laea.clone());
+            polygon.mercator().unwrap()
+        };
+        // the west side (2.2 of 7.5 km) runs along the seam, the ends of the
+        // north and south sides are within a cell of it
+        let along = fraction_on_edges(&square(7.1), &datasets);
+        assert!(along > 0.29 && along < 0.33, "{}", along);
+        // inside the east tile, away from the seam
+        let inside = fraction_on_edges(&square(7.14), &datasets);
+        assert_eq!(inside, 0.0);
+    }
+}
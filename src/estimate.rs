@@ -0,0 +1,176 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::compute::Surface;
+use crate::mercator::WebMercatorProjection;
+use crate::reference::{self, GeometryError};
+
+// How the 3D area on the ellipsoid (the headline number) is derived from the
+// surface in the projection. There is no combination of the two weighted by
+// their variances: neither comes with an error estimate to weigh them by.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum EstimateStrategy {
+    // the geodesic area of the polygon times the ratio of the 3D and flat
+    // areas in the projection: the distortion of the projection cancels
+    #[default]
+    Ratio,
+    // the sum over the planes of their own geodesic area times their own
+    // ratio: the distortion is corrected where it is, not on average
+    PerPlane,
+}
+
+// The estimates, the strategy tells which one is the headline. The ratio is
+// cheap and always there, the per-plane sum unprojects every plane and is
+// only computed when it is the headline.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Estimates {
+    pub strategy: EstimateStrategy,
+    pub ratio: f64,
+    pub per_plane: Option<f64>,
+}
+
+impl Estimates {
+    pub fn new(
+        surface: &Surface,
+        geodesic2d: f64,
+        proj4: &str,
+        strategy: EstimateStrategy,
+    ) -> Self {
+        let estimate = |strategy| estimate_geodesic_3d(surface, geodesic2d, proj4, strategy);
+        Estimates {
+            strategy,
+            ratio: estimate(EstimateStrategy::Ratio),
+            per_plane: (strategy == EstimateStrategy::PerPlane)
+                .then(|| estimate(EstimateStrategy::PerPlane)),
+        }
+    }
+
+    pub fn headline(&self) -> f64 {
+        match (self.strategy, self.per_plane) {
+            (EstimateStrategy::PerPlane, Some(per_plane)) => per_plane,
+            _ => self.ratio,
+        }
+    }
+
+    // the estimates of disjoint surfaces
+    pub fn add(&mut self, other: &Estimates) {
+        self.ratio += other.ratio;
+        if let Some(per_plane) = other.per_plane {
+            *self.per_plane.get_or_insert(0.0) += per_plane;
+        }
+    }
+}
+
+// The 3D area of the surface on the ellipsoid. `geodesic2d` is the geodesic
+// area of the polygon the surface was clipped with, `proj4` the projection
+// of its planes. A plane whose geodesic area fails counts with the ratio
+// of the whole surface, with a warning.
+pub fn estimate_geodesic_3d(
+    surface: &Surface,
+    geodesic2d: f64,
+    proj4: &str,
+    strategy: EstimateStrategy,
+) -> f64 {
+    if surface.projected2d <= 0.0 {
+        return 0.0;
+    }
+    let scale = geodesic2d / surface.projected2d;
+    match strategy {
+        EstimateStrategy::Ratio => surface.projected3d * scale,
+        EstimateStrategy::PerPlane => {
+            let projection = WebMercatorProjection::make(proj4);
+            let mut ret = 0.0;
+            let mut failed = Vec::new();
+            for plane in surface.planes.iter().chain(&surface.flagged) {
+                let wgs: Result<Vec<_>, _> = plane
+                    .points
+                    .iter()
+                    .map(|p| projection.unproject(p))
+                    .collect();
+                let area = wgs
+                    .map_err(GeometryError::from)
+                    .and_then(|wgs| reference::geodesic_area(&wgs));
+                match area {
+                    Ok(area) => ret += area * plane.area3d / plane.area2d,
+                    Err(e) => {
+                        ret += plane.area3d * scale;
+                        failed.push(e);
+                    }
+                }
+            }
+            if let Some(first) = failed.first() {
+                log::warn!(
+                    "{} planes without geodesic area, counted with the ratio, the first: {}",
+                    failed.len(),
+                    first
+                );
+            }
+            ret
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::{MercatorPoint, WGS84Point};
+    use crate::{compute, triangulation};
+
+    #[test]
+    fn test_inclined_plane() {
+        let proj4 = WGS84Point {
+            lon: 7.0,
+            lat: 46.0,
+            ele: None,
+        }
+        .to_laea_proj4();
+        let (a, b) = (0.3, -0.2);
+        let mut points = Vec::new();
+        for i in 0..30 {
+            for j in 0..30 {
+                let (x, y) = (i as f64 * 30.0 - 450.0, j as f64 * 30.0 - 450.0);
+                points.push(MercatorPoint {
+                    x,
+                    y,
+                    ele: Some(a * x + b * y),
+                });
+            }
+        }
+        let polygon: Vec<MercatorPoint> = [(-400.0, -380.0), (390.0, -410.0), (350.0, 400.0)]
+            .iter()
+            .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+            .collect();
+        let mut surface = compute::clip(&polygon, &triangulation::grid::triangulate(&points));
+        let projection = WebMercatorProjection::make(&proj4);
+        let wgs: Vec<WGS84Point> = polygon
+            .iter()
+            .map(|p| projection.unproject(p).unwrap())
+            .collect();
+        let geodesic2d = reference::geodesic_area(&wgs).unwrap();
+        let expected = geodesic2d * (1.0 + a * a + b * b).sqrt();
+        let estimates = Estimates::new(&surface, geodesic2d, &proj4, EstimateStrategy::PerPlane);
+        assert!((estimates.ratio - expected).abs() < 1e-6 * expected);
+        let per_plane = estimates.per_plane.unwrap();
+        assert!((per_plane - expected).abs() < 1e-6 * expected);
+        assert_eq!(estimates.headline(), per_plane);
+        // only the headline is computed
+        let estimates = Estimates::new(&surface, geodesic2d, &proj4, EstimateStrategy::Ratio);
+        assert_eq!(estimates.per_plane, None);
+        assert_eq!(estimates.headline(), estimates.ratio);
+
+        // a plane collapsed onto a point has no geodesic area: it counts
+        // with the ratio
+        let plane = &mut surface.planes[0];
+        let first = plane.points[0].clone();
+        plane.points.fill(first);
+        let estimates = Estimates::new(&surface, geodesic2d, &proj4, EstimateStrategy::PerPlane);
+        let per_plane = estimates.per_plane.unwrap();
+        assert!(
+            (per_plane - expected).abs() < 1e-4 * expected,
+            "{}",
+            per_plane
+        );
+    }
+}
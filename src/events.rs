@@ -0,0 +1,65 @@
+use std::fmt;
+use std::sync::Arc;
+
+use crate::pipeline::Phase;
+
+// What compute reports while it runs, for embedding applications: the
+// stages in their order, the progress within the long ones and the warnings.
+#[derive(Clone, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ComputeEvent {
+    // the datasets the polygon is computed on
+    Datasets(Vec<String>),
+    // the samples of `done` of the `total` datasets are read
+    Sampling { done: usize, total: usize },
+    // the mesh is built or reused
+    Triangulated { triangles: usize },
+    // `done` of the `total` triangles are clipped
+    Clipping { done: usize, total: usize },
+    // a stage is over, as in Data::phases
+    Phase(Phase),
+    Warning(String),
+    // a note of the result, as in Data::notes
+    Note(String),
+}
+
+// The callback of ComputeOptions, none by default. The events are only built
+// when one is installed.
+#[derive(Clone, Default)]
+pub struct Events(Option<Arc<dyn Fn(ComputeEvent) + Send + Sync>>);
+
+impl Events {
+    pub fn new(callback: impl Fn(ComputeEvent) + Send + Sync + 'static) -> Self {
+        Events(Some(Arc::new(callback)))
+    }
+
+    pub fn emit(&self, event: impl FnOnce() -> ComputeEvent) {
+        if let Some(callback) = &self.0 {
+            callback(event());
+        }
+    }
+
+    pub fn is_installed(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(_) => write!(f, "Events(installed)"),
+            None => write!(f, "Events(none)"),
+        }
+    }
+}
+
+// the same callback, or none on both sides
+impl PartialEq for Events {
+    fn eq(&self, other: &Self) -> bool {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => Arc::ptr_eq(a, b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
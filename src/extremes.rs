@@ -0,0 +1,122 @@
+use serde::Serialize;
+
+use crate::point::{MercatorPoint, WGS84Point};
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Extreme {
+    pub lon: f64,
+    pub lat: f64,
+    pub elevation: f64,
+    #[serde(skip)]
+    pub mercator: MercatorPoint,
+}
+
+// The lowest and highest samples inside the polygon.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Extremes {
+    pub lowest: Option<Extreme>,
+    pub highest: Option<Extreme>,
+}
+
+// ties are resolved towards the lowest longitude, then the lowest latitude,
+// so that the result does not depend on the sample order
+fn before(a: &Extreme, b: &Extreme) -> bool {
+    (a.lon, a.lat) < (b.lon, b.lat)
+}
+
+impl Extremes {
+    pub fn update(&mut self, wgs: &WGS84Point, mercator: &MercatorPoint) {
+        let Some(elevation) = mercator.ele else {
+            return;
+        };
+        self.add(&Extreme {
+            lon: wgs.lon,
+            lat: wgs.lat,
+            elevation,
+            mercator: mercator.clone(),
+        });
+    }
+
+    fn add(&mut self, e: &Extreme) {
+        let lower = match &self.lowest {
+            None => true,
+            Some(l) => e.elevation < l.elevation || (e.elevation == l.elevation && before(e, l)),
+        };
+        if lower {
+            self.lowest = Some(e.clone());
+        }
+        let higher = match &self.highest {
+            None => true,
+            Some(h) => e.elevation > h.elevation || (e.elevation == h.elevation && before(e, h)),
+        };
+        if higher {
+            self.highest = Some(e.clone());
+        }
+    }
+
+    pub fn merge(&mut self, other: &Extremes) {
+        for e in other.lowest.iter().chain(other.highest.iter()) {
+            self.add(e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // samples of a cone with its apex at (3, 4)
+    fn cone() -> Vec<(WGS84Point, MercatorPoint)> {
+        let mut ret = Vec::new();
+        for i in 0..9 {
+            for j in 0..9 {
+                let (x, y) = (i as f64, j as f64);
+                let ele = 100.0 - 5.0 * ((x - 3.0).powi(2) + (y - 4.0).powi(2)).sqrt();
+                let wgs = WGS84Point {
+                    lon: 6.0 + 0.001 * x,
+                    lat: 46.0 + 0.001 * y,
+                    ele: Some(ele),
+                };
+                let mercator = MercatorPoint {
+                    x: 10.0 * x,
+                    y: 10.0 * y,
+                    ele: Some(ele),
+                };
+                ret.push((wgs, mercator));
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_cone_apex() {
+        let mut extremes = Extremes::default();
+        for (w, m) in cone() {
+            extremes.update(&w, &m);
+        }
+        let highest = extremes.highest.unwrap();
+        assert_eq!(highest.elevation, 100.0);
+        assert_eq!((highest.mercator.x, highest.mercator.y), (30.0, 40.0));
+        // the corners (8, 0) and (8, 8) are equally far, the lower latitude wins
+        let lowest = extremes.lowest.unwrap();
+        assert_eq!((lowest.mercator.x, lowest.mercator.y), (80.0, 0.0));
+    }
+
+    #[test]
+    fn test_order_independent() {
+        let mut forward = Extremes::default();
+        let mut backward = Extremes::default();
+        let samples = cone();
+        for (w, m) in &samples {
+            forward.update(w, m);
+        }
+        for (w, m) in samples.iter().rev() {
+            backward.update(w, m);
+        }
+        let (f, b) = (forward.lowest.unwrap(), backward.lowest.clone().unwrap());
+        assert_eq!((f.lon, f.lat), (b.lon, b.lat));
+        let mut merged = Extremes::default();
+        merged.merge(&backward);
+        assert_eq!(merged.highest.unwrap().elevation, 100.0);
+    }
+}
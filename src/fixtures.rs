@@ -0,0 +1,148 @@
+// Data shared by the tests of several modules and the gallery.
+
+use std::f64::consts::PI;
+use std::path::PathBuf;
+
+use crate::dataset::Dataset;
+use crate::point::WGS84Point;
+use crate::polygon::Polygon;
+use crate::rasterio::{IoStats, Replay, Synthetic};
+
+// The recorded reads of a 10 × 10 window of the N46E006 tile.
+pub fn replay() -> Replay {
+    Replay::load(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/data/fixtures/N46E006-10x10.json"
+    ))
+    .unwrap()
+}
+
+// The replay as a dataset, in UTM zone 32.
+pub fn dataset() -> Dataset {
+    let projection = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+    Dataset::from_io("N46E006-10x10", Box::new(replay()), projection).unwrap()
+}
+
+// An empty directory in the temporary one, created again for each run.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    std::fs::remove_dir_all(&dir).ok();
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+// An ASCII grid of 3 × 3 cells of 0.01°, from its western edge at 46°N.
+pub fn grid(lon: f64) -> String {
+    format!(
+        "ncols 3\nnrows 3\nxllcorner {}\nyllcorner 46.0\ncellsize 0.01\n\
+         1 2 3\n4 5 6\n7 8 9\n",
+        lon
+    )
+}
+
+// The triangle with a right angle at its second vertex, width degrees east
+// and height degrees north of the first one.
+pub fn triangle(lon: f64, lat: f64, width: f64, height: f64) -> Polygon {
+    let wgs = |lon: f64, lat: f64| WGS84Point {
+        lon,
+        lat,
+        ele: None,
+    };
+    Polygon::new(vec![
+        wgs(lon, lat),
+        wgs(lon + width, lat),
+        wgs(lon + width, lat + height),
+    ])
+}
+
+// The synthetic scenarios are laid out in meters around this point, in its
+// equal-area projection.
+const LON: f64 = 7.0;
+const LAT: f64 = 46.0;
+// a power of two, so that the sample positions of adjacent tiles are exact
+pub const STEP: f64 = 1.0 / 8192.0;
+
+// meters per degree of longitude and latitude at LAT on the WGS84 ellipsoid
+pub fn meters_per_degree() -> (f64, f64) {
+    let (a, e2) = (6_378_137.0, 0.006_694_379_990_14);
+    let phi = LAT.to_radians();
+    let w = (1.0 - e2 * phi.sin().powi(2)).sqrt();
+    let n = a / w;
+    let m = a * (1.0 - e2) / w.powi(3);
+    (n * phi.cos() * PI / 180.0, m * PI / 180.0)
+}
+
+pub fn local(lon: f64, lat: f64) -> (f64, f64) {
+    let (mx, my) = meters_per_degree();
+    ((lon - LON) * mx, (lat - LAT) * my)
+}
+
+pub fn wgs(x: f64, y: f64) -> WGS84Point {
+    let (mx, my) = meters_per_degree();
+    WGS84Point {
+        lon: LON + x / mx,
+        lat: LAT + y / my,
+        ele: None,
+    }
+}
+
+pub fn projection() -> String {
+    wgs(0.0, 0.0).to_laea_proj4()
+}
+
+// The samples covering the rectangle (in meters): the position of the upper
+// left one and their number. The edges are snapped outwards to the grid.
+pub fn samples((west, south, east, north): (f64, f64, f64, f64)) -> ((f64, f64), (usize, usize)) {
+    let (mx, my) = meters_per_degree();
+    let c0 = (west / mx / STEP).floor() as i64;
+    let c1 = (east / mx / STEP).ceil() as i64;
+    let r0 = (north / my / STEP).ceil() as i64;
+    let r1 = (south / my / STEP).floor() as i64;
+    let upper_left = (LON + c0 as f64 * STEP, LAT + r0 as f64 * STEP);
+    (upper_left, ((c1 - c0 + 1) as usize, (r0 - r1 + 1) as usize))
+}
+
+// A synthetic DEM covering the rectangle (in meters), with the elevation
+// given in meters too.
+pub fn synthetic(
+    name: &str,
+    rectangle: (f64, f64, f64, f64),
+    elevation: impl Fn(f64, f64) -> Option<f64> + Send + Sync + 'static,
+) -> Dataset {
+    let (upper_left, size) = samples(rectangle);
+    let io = Synthetic::new(upper_left, STEP, size, move |lon, lat| {
+        let (x, y) = local(lon, lat);
+        elevation(x, y)
+    });
+    Dataset::from_io(name, Box::new(io), &projection()).unwrap()
+}
+
+// The ring (in meters) as a polygon in the projection of the synthetic DEMs.
+pub fn polygon(ring: &[(f64, f64)]) -> Polygon {
+    let mut polygon = Polygon::new(ring.iter().map(|(x, y)| wgs(*x, *y)).collect());
+    polygon.proj4 = Some(projection());
+    polygon
+}
+
+pub fn square(half: f64) -> Vec<(f64, f64)> {
+    vec![(-half, -half), (half, -half), (half, half), (-half, half)]
+}
+
+// The pixels read from the datasets so far.
+pub fn pixels(datasets: &[Dataset]) -> u64 {
+    let stats: Vec<_> = datasets.iter().map(|d| d.io_stats()).collect();
+    IoStats::total(&stats).pixels
+}
+
+// relative deviation from the expected value within the tolerance
+pub fn assert_close(what: &str, value: f64, expected: f64, tolerance: f64) {
+    let deviation = (value / expected - 1.0).abs();
+    assert!(
+        deviation <= tolerance,
+        "{}: {} instead of {} (tolerance {}%)",
+        what,
+        value,
+        expected,
+        100.0 * tolerance
+    );
+}
@@ -0,0 +1,495 @@
+use std::fmt;
+use std::path::Path;
+
+use geo::Intersects;
+
+use crate::intersection;
+use crate::mercator::WebMercatorProjection;
+use crate::point::MercatorPoint;
+use crate::provenance::Provenance;
+use crate::triangulation::Mesh;
+
+// relative to the size of the triangle, for the positions on its edges and
+// vertices
+const EPS: f64 = 1e-9;
+
+// Why the line stops.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum End {
+    Boundary,
+    // the edge of the terrain samples
+    MeshBorder,
+    // a pit, or a flat triangle
+    Minimum,
+    // more steps than the mesh has triangles, should not happen
+    Limit,
+}
+
+impl fmt::Display for End {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            End::Boundary => "leaves the polygon",
+            End::MeshBorder => "reaches the edge of the terrain",
+            End::Minimum => "ends in a local minimum",
+            End::Limit => "stops after too many steps",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+// The steepest-descent line over the mesh, with the cumulative horizontal
+// length and drop at every point, in meters.
+pub struct Flowline {
+    pub points: Vec<MercatorPoint>,
+    pub length: Vec<f64>,
+    pub drop: Vec<f64>,
+    pub end: End,
+}
+
+impl Flowline {
+    fn start(p: MercatorPoint) -> Self {
+        Flowline {
+            points: vec![p],
+            length: vec![0.0],
+            drop: vec![0.0],
+            end: End::Limit,
+        }
+    }
+
+    fn push(&mut self, p: MercatorPoint) {
+        let last = self.points.last().unwrap();
+        let step = (p.x - last.x).hypot(p.y - last.y);
+        if step == 0.0 {
+            return;
+        }
+        self.length.push(self.length.last().unwrap() + step);
+        self.drop.push(self.points[0].ele.unwrap() - p.ele.unwrap());
+        self.points.push(p);
+    }
+
+    pub fn total_length(&self) -> f64 {
+        *self.length.last().unwrap()
+    }
+
+    pub fn total_drop(&self) -> f64 {
+        *self.drop.last().unwrap()
+    }
+
+    // A GeoJSON LineString feature in WGS84 (lon, lat, elevation), with its
+    // provenance sidecar.
+    pub fn write_geojson(&self, path: &Path, proj4: &str) -> std::io::Result<()> {
+        let projection = WebMercatorProjection::make(proj4);
+        let coordinates: Vec<_> = self
+            .points
+            .iter()
+            .map(|p| {
+                let w = projection.unproject(p).map_err(std::io::Error::other)?;
+                Ok(vec![w.lon, w.lat, p.ele.unwrap()])
+            })
+            .collect::<std::io::Result<_>>()?;
+        let feature = serde_json::json!({
+            "type": "Feature",
+            "geometry": {"type": "LineString", "coordinates": coordinates},
+            "properties": {
+                "length": self.total_length(),
+                "drop": self.total_drop(),
+                "cumulative_length": self.length,
+                "cumulative_drop": self.drop,
+                "end": self.end.to_string(),
+            },
+        });
+        let json = serde_json::to_string_pretty(&feature).map_err(std::io::Error::other)?;
+        std::fs::write(path, json)?;
+        Provenance::geographic().write_sidecar(path)
+    }
+}
+
+type Xy = (f64, f64);
+
+fn xy(p: &MercatorPoint) -> Xy {
+    (p.x, p.y)
+}
+
+fn sub(a: Xy, b: Xy) -> Xy {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+fn cross(a: Xy, b: Xy) -> f64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+fn norm(a: Xy) -> f64 {
+    a.0.hypot(a.1)
+}
+
+fn ele(mesh: &Mesh, v: usize) -> f64 {
+    mesh.vertices[v].ele.unwrap()
+}
+
+fn pos(mesh: &Mesh, v: usize) -> Xy {
+    xy(&mesh.vertices[v])
+}
+
+// the vertices of the triangle, counterclockwise
+fn ccw(mesh: &Mesh, t: usize) -> [usize; 3] {
+    let [a, b, c] = mesh.triangles[t].map(|v| v as usize);
+    let (pa, pb, pc) = (pos(mesh, a), pos(mesh, b), pos(mesh, c));
+    if cross(sub(pb, pa), sub(pc, pa)) < 0.0 {
+        [a, c, b]
+    } else {
+        [a, b, c]
+    }
+}
+
+// minus the gradient of the plane of the triangle: downhill, as long as the
+// slope
+fn descent(mesh: &Mesh, t: usize) -> Xy {
+    let [a, b, c] = ccw(mesh, t);
+    let (d1, d2) = (
+        sub(pos(mesh, b), pos(mesh, a)),
+        sub(pos(mesh, c), pos(mesh, a)),
+    );
+    let (z1, z2) = (ele(mesh, b) - ele(mesh, a), ele(mesh, c) - ele(mesh, a));
+    let det = cross(d1, d2);
+    let gx = (z1 * d2.1 - z2 * d1.1) / det;
+    let gy = (d1.0 * z2 - d2.0 * z1) / det;
+    (-gx, -gy)
+}
+
+fn longest_edge(mesh: &Mesh, t: usize) -> f64 {
+    let [a, b, c] = ccw(mesh, t);
+    [(a, b), (b, c), (c, a)]
+        .iter()
+        .map(|(u, v)| norm(sub(pos(mesh, *v), pos(mesh, *u))))
+        .fold(0.0, f64::max)
+}
+
+// the other triangle of the edge
+fn across(mesh: &Mesh, t: usize, u: usize, v: usize) -> Option<usize> {
+    mesh.neighbors(t).into_iter().find(|n| {
+        let vertices = mesh.triangles[*n].map(|w| w as usize);
+        vertices.contains(&u) && vertices.contains(&v)
+    })
+}
+
+// whether one of the edges of the vertex has a single triangle
+fn on_border(mesh: &Mesh, v: usize) -> bool {
+    mesh.vertex_star(v).iter().any(|&t| {
+        let vertices = mesh.triangles[t].map(|w| w as usize);
+        vertices
+            .iter()
+            .any(|&w| w != v && across(mesh, t, v, w).is_none())
+    })
+}
+
+enum At {
+    Face(usize),
+    Vertex(usize),
+}
+
+// where the line starts: on a vertex or in a triangle
+fn locate(mesh: &Mesh, p: &MercatorPoint) -> Option<(At, MercatorPoint)> {
+    for t in 0..mesh.len() {
+        let [a, b, c] = ccw(mesh, t);
+        let scale = longest_edge(mesh, t);
+        for v in [a, b, c] {
+            if norm(sub(xy(p), pos(mesh, v))) <= EPS * scale {
+                return Some((At::Vertex(v), mesh.vertices[v].clone()));
+            }
+        }
+        let area = cross(
+            sub(pos(mesh, b), pos(mesh, a)),
+            sub(pos(mesh, c), pos(mesh, a)),
+        );
+        let inside = [(a, b), (b, c), (c, a)].iter().all(|(u, v)| {
+            cross(sub(pos(mesh, *v), pos(mesh, *u)), sub(xy(p), pos(mesh, *u))) >= -EPS * area
+        });
+        if inside {
+            let d = descent(mesh, t);
+            let offset = sub(xy(p), pos(mesh, a));
+            let z = ele(mesh, a) - (d.0 * offset.0 + d.1 * offset.1);
+            let q = MercatorPoint {
+                x: p.x,
+                y: p.y,
+                ele: Some(z),
+            };
+            return Some((At::Face(t), q));
+        }
+    }
+    None
+}
+
+enum Step {
+    Edge(usize),
+    Face(usize),
+}
+
+// The steepest way down from a vertex: along one of its edges, or into one
+// of its triangles when the descent of that triangle points inside it.
+fn steepest(mesh: &Mesh, v: usize) -> Option<Step> {
+    let pv = pos(mesh, v);
+    let mut best: Option<(f64, Step)> = None;
+    let mut consider = |slope: f64, step: Step| {
+        if slope > 0.0 && best.as_ref().is_none_or(|(s, _)| slope > *s) {
+            best = Some((slope, step));
+        }
+    };
+    for &t in mesh.vertex_star(v) {
+        let [a, b, c] = ccw(mesh, t);
+        let (u, w) = match v {
+            v if v == a => (b, c),
+            v if v == b => (c, a),
+            _ => (a, b),
+        };
+        for x in [u, w] {
+            let length = norm(sub(pos(mesh, x), pv));
+            consider((ele(mesh, v) - ele(mesh, x)) / length, Step::Edge(x));
+        }
+        let d = descent(mesh, t);
+        let (eu, ew) = (sub(pos(mesh, u), pv), sub(pos(mesh, w), pv));
+        let n = norm(d);
+        if cross(eu, d) > EPS * norm(eu) * n && cross(d, ew) > EPS * norm(ew) * n {
+            consider(n, Step::Face(t));
+        }
+    }
+    best.map(|(_, step)| step)
+}
+
+// The first crossing of the segment with the boundary, the elevation
+// interpolated along the segment.
+fn crossing(
+    boundary: &[MercatorPoint],
+    p: &MercatorPoint,
+    q: &MercatorPoint,
+) -> Option<MercatorPoint> {
+    let d = sub(xy(q), xy(p));
+    let mut first: Option<f64> = None;
+    for k in 0..boundary.len() {
+        let (a, b) = (xy(&boundary[k]), xy(&boundary[(k + 1) % boundary.len()]));
+        let e = sub(b, a);
+        let den = cross(d, e);
+        if den == 0.0 {
+            continue;
+        }
+        let ap = sub(a, xy(p));
+        let s = cross(ap, e) / den;
+        let r = cross(ap, d) / den;
+        if s > EPS && s <= 1.0 && (0.0..=1.0).contains(&r) && first.is_none_or(|f| s < f) {
+            first = Some(s);
+        }
+    }
+    first.map(|s| {
+        let (zp, zq) = (p.ele.unwrap(), q.ele.unwrap());
+        MercatorPoint {
+            x: p.x + s * d.0,
+            y: p.y + s * d.1,
+            ele: Some(zp + s * (zq - zp)),
+        }
+    })
+}
+
+// Traces the steepest-descent line from the start across the triangles of
+// the mesh: down the gradient within a triangle, into the next one through
+// the exit edge. Where the next triangle drains back (a valley), the line
+// follows the edge down to its lower vertex. It stops at the boundary, at the
+// edge of the mesh or in a local minimum. None if the start is outside of the
+// polygon or of the mesh.
+pub fn trace(mesh: &Mesh, start: &MercatorPoint, boundary: &[MercatorPoint]) -> Option<Flowline> {
+    let mask = intersection::to_geo_polygon(boundary);
+    if !mask.intersects(&geo::Point::new(start.x, start.y)) {
+        return None;
+    }
+    let (mut at, p) = locate(mesh, start)?;
+    let mut line = Flowline::start(p);
+    // true if the segment to q left the polygon
+    let advance = |line: &mut Flowline, q: MercatorPoint| {
+        let p = line.points.last().unwrap().clone();
+        match crossing(boundary, &p, &q) {
+            Some(exit) => {
+                line.push(exit);
+                true
+            }
+            None => {
+                line.push(q);
+                false
+            }
+        }
+    };
+    for _ in 0..4 * mesh.len() + 16 {
+        let t = match at {
+            At::Vertex(v) => match steepest(mesh, v) {
+                None => {
+                    line.end = match on_border(mesh, v) {
+                        true => End::MeshBorder,
+                        false => End::Minimum,
+                    };
+                    return Some(line);
+                }
+                Some(Step::Edge(w)) => {
+                    if advance(&mut line, mesh.vertices[w].clone()) {
+                        line.end = End::Boundary;
+                        return Some(line);
+                    }
+                    at = At::Vertex(w);
+                    continue;
+                }
+                Some(Step::Face(t)) => t,
+            },
+            At::Face(t) => t,
+        };
+        let p = line.points.last().unwrap().clone();
+        let d = descent(mesh, t);
+        let scale = longest_edge(mesh, t);
+        if norm(d) <= EPS {
+            line.end = End::Minimum;
+            return Some(line);
+        }
+        // the edge the ray leaves the triangle through
+        let mut exit: Option<(f64, usize, usize)> = None;
+        let [a, b, c] = ccw(mesh, t);
+        for (u, v) in [(a, b), (b, c), (c, a)] {
+            let e = sub(pos(mesh, v), pos(mesh, u));
+            let den = cross(e, d);
+            if den >= 0.0 {
+                continue;
+            }
+            let s = (cross(e, sub(xy(&p), pos(mesh, u))) / -den).max(0.0);
+            if exit.is_none_or(|(s0, _, _)| s < s0) {
+                exit = Some((s, u, v));
+            }
+        }
+        let (s, u, v) = exit.unwrap();
+        let mut q = MercatorPoint {
+            x: p.x + s * d.0,
+            y: p.y + s * d.1,
+            ele: Some(p.ele.unwrap() - s * (d.0 * d.0 + d.1 * d.1)),
+        };
+        let snapped = [u, v]
+            .into_iter()
+            .find(|w| norm(sub(xy(&q), pos(mesh, *w))) <= 1e-6 * scale);
+        if let Some(w) = snapped {
+            q = mesh.vertices[w].clone();
+        }
+        if advance(&mut line, q.clone()) {
+            line.end = End::Boundary;
+            return Some(line);
+        }
+        if let Some(w) = snapped {
+            at = At::Vertex(w);
+            continue;
+        }
+        let Some(n) = across(mesh, t, u, v) else {
+            line.end = End::MeshBorder;
+            return Some(line);
+        };
+        let e = sub(pos(mesh, v), pos(mesh, u));
+        let dn = descent(mesh, n);
+        if cross(e, dn) < -EPS * norm(e) * norm(dn) {
+            at = At::Face(n);
+            continue;
+        }
+        // both triangles drain to the edge: down along it
+        let w = if ele(mesh, u) < ele(mesh, v) { u } else { v };
+        if ele(mesh, w) >= q.ele.unwrap() {
+            line.end = End::Minimum;
+            return Some(line);
+        }
+        if advance(&mut line, mesh.vertices[w].clone()) {
+            line.end = End::Boundary;
+            return Some(line);
+        }
+        at = At::Vertex(w);
+    }
+    line.end = End::Limit;
+    Some(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triangulation::grid::triangulate;
+
+    fn field(z: impl Fn(f64, f64) -> f64) -> Mesh {
+        let mut points = Vec::new();
+        for i in -30..=30 {
+            for j in -30..=30 {
+                let (x, y) = (i as f64, j as f64);
+                points.push(MercatorPoint {
+                    x,
+                    y,
+                    ele: Some(z(x, y)),
+                });
+            }
+        }
+        triangulate(&points)
+    }
+
+    fn square(half: f64) -> Vec<MercatorPoint> {
+        [(-half, -half), (half, -half), (half, half), (-half, half)]
+            .iter()
+            .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+            .collect()
+    }
+
+    fn point(x: f64, y: f64) -> MercatorPoint {
+        MercatorPoint { x, y, ele: None }
+    }
+
+    #[test]
+    fn test_cone() {
+        let mesh = field(|x, y| 100.0 - x.hypot(y));
+        let line = trace(&mesh, &point(6.3, 4.2), &square(25.0)).unwrap();
+        assert_eq!(line.end, End::Boundary);
+        // radial: away from the apex, along the direction of the start
+        let (ux, uy) = (6.3 / 6.3f64.hypot(4.2), 4.2 / 6.3f64.hypot(4.2));
+        for p in &line.points {
+            let off = (p.x * uy - p.y * ux).abs();
+            assert!(off < 0.5, "{} off the radial line by {}", p, off);
+        }
+        let last = line.points.last().unwrap();
+        assert!((last.x - 25.0).abs() < 1e-9, "{}", last);
+        let radius = last.x.hypot(last.y) - 6.3f64.hypot(4.2);
+        assert!((line.total_length() - radius).abs() < 0.05 * radius);
+        // the slope of the cone is 1
+        assert!((line.total_drop() - line.total_length()).abs() < 0.05 * radius);
+        assert!(line.length.windows(2).all(|w| w[1] > w[0]));
+        assert!(line.drop.windows(2).all(|w| w[1] >= w[0]));
+
+        let path = std::env::temp_dir().join("surface-area-flowline.geojson");
+        let utm = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        line.write_geojson(&path, utm).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let coordinates = json["geometry"]["coordinates"].as_array().unwrap();
+        assert_eq!(coordinates.len(), line.points.len());
+        assert_eq!(json["properties"]["length"], line.total_length());
+        assert_eq!(
+            Provenance::read_sidecar(&path).unwrap(),
+            Provenance::geographic()
+        );
+        std::fs::remove_file(Provenance::sidecar(&path)).ok();
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_valley_and_pit() {
+        // a V-shaped valley along y = 0, falling to -x
+        let mesh = field(|x, y| 2.0 * y.abs() + 0.1 * x);
+        let line = trace(&mesh, &point(10.5, 7.25), &square(40.0)).unwrap();
+        assert_eq!(line.end, End::MeshBorder);
+        let last = line.points.last().unwrap();
+        assert_eq!((last.x, last.y), (-30.0, 0.0));
+        let (start, end) = (&line.points[0], last);
+        assert!((line.total_drop() - (start.ele.unwrap() - end.ele.unwrap())).abs() < 1e-9);
+
+        // a bowl
+        let mesh = field(|x, y| x.hypot(y));
+        let line = trace(&mesh, &point(12.5, -7.5), &square(40.0)).unwrap();
+        assert_eq!(line.end, End::Minimum);
+        let last = line.points.last().unwrap();
+        assert!(last.x.hypot(last.y) < 1.5, "{}", last);
+
+        // outside of the polygon
+        assert!(trace(&mesh, &point(12.5, -7.5), &square(5.0)).is_none());
+    }
+}
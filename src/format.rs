@@ -0,0 +1,118 @@
+// The precision of every printed number, so that a quantity reads the same in
+// the console, the reports, the CSV summary and the figure legends. Rust's
+// formatting ignores the locale: the decimal separator is always '.'.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Quantity {
+    // in the unit it is printed in, 4 significant digits: the DEM does not
+    // resolve more
+    Area,
+    // ratios and relative deviations, in percent
+    Percent,
+    Slope,
+    // meters, the vertical accuracy of the DEMs is a few meters
+    Elevation,
+    // horizontal distances in meters (cell sizes)
+    Length,
+    // degrees of azimuth or altitude
+    Angle,
+    // degrees of latitude or longitude, ~1 m
+    Coordinate,
+    // cosines and other values in [0, 1]
+    Fraction,
+}
+
+impl Quantity {
+    // the defaults until an uncertainty estimate is available
+    fn decimals(self, value: f64) -> usize {
+        match self {
+            Quantity::Area => decimals(value, 4).min(3),
+            Quantity::Percent => 2,
+            Quantity::Slope => 1,
+            Quantity::Elevation => 0,
+            Quantity::Length => 1,
+            Quantity::Angle => 0,
+            Quantity::Coordinate => 5,
+            Quantity::Fraction => 2,
+        }
+    }
+
+    fn suffix(self) -> &'static str {
+        match self {
+            Quantity::Percent | Quantity::Slope => "%",
+            Quantity::Elevation | Quantity::Length => " m",
+            Quantity::Angle | Quantity::Coordinate => "°",
+            Quantity::Area | Quantity::Fraction => "",
+        }
+    }
+}
+
+// decimals that keep `significant` digits of the value
+pub fn decimals(value: f64, significant: usize) -> usize {
+    if value == 0.0 || !value.is_finite() {
+        return 0;
+    }
+    let magnitude = value.abs().log10().floor() as i64;
+    (significant as i64 - 1 - magnitude).max(0) as usize
+}
+
+// decimals down to the first significant digit of the uncertainty
+pub fn decimals_for_uncertainty(sigma: f64) -> usize {
+    if sigma <= 0.0 || !sigma.is_finite() {
+        return 0;
+    }
+    (-sigma.log10().floor()).max(0.0) as usize
+}
+
+pub fn number(value: f64, quantity: Quantity) -> String {
+    format!("{:.*}", quantity.decimals(value), value)
+}
+
+pub fn with_unit(value: f64, quantity: Quantity) -> String {
+    format!("{}{}", number(value, quantity), quantity.suffix())
+}
+
+// with an explicit sign, e.g. "+7.12%"
+pub fn signed(value: f64, quantity: Quantity) -> String {
+    format!(
+        "{:+.*}{}",
+        quantity.decimals(value),
+        value,
+        quantity.suffix()
+    )
+}
+
+// "1234.6 ± 0.3": the value has the digits of its uncertainty
+pub fn uncertain(value: f64, sigma: f64) -> String {
+    let decimals = decimals_for_uncertainty(sigma);
+    format!("{:.*} ± {:.*}", decimals, value, decimals, sigma)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantities() {
+        assert_eq!(number(21_420.04, Quantity::Area), "21420");
+        assert_eq!(number(2.14204, Quantity::Area), "2.142");
+        assert_eq!(number(0.5, Quantity::Area), "0.500");
+        assert_eq!(signed(7.1234, Quantity::Percent), "+7.12%");
+        assert_eq!(signed(-0.94, Quantity::Percent), "-0.94%");
+        assert_eq!(with_unit(12.345, Quantity::Slope), "12.3%");
+        assert_eq!(with_unit(1234.56, Quantity::Elevation), "1235 m");
+        assert_eq!(with_unit(30.87, Quantity::Length), "30.9 m");
+        assert_eq!(with_unit(179.6, Quantity::Angle), "180°");
+        assert_eq!(with_unit(46.123456, Quantity::Coordinate), "46.12346°");
+        assert_eq!(number(0.8666, Quantity::Fraction), "0.87");
+    }
+
+    #[test]
+    fn test_uncertainty() {
+        assert_eq!(decimals(0.0123, 3), 4);
+        assert_eq!(decimals(98765.0, 3), 0);
+        assert_eq!(uncertain(1234.5678, 0.3), "1234.6 ± 0.3");
+        assert_eq!(uncertain(1234.5678, 25.0), "1235 ± 25");
+        assert_eq!(uncertain(1.5, 0.0), "2 ± 0");
+    }
+}
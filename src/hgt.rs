@@ -1,28 +1,170 @@
-use crate::point::WGS84Point;
+use std::collections::BTreeSet;
+use std::fmt;
+
+use geo::{BoundingRect, Coord, Intersects, LineString, Rect};
+
+use crate::point::{self, WGS84BoundingBox, WGS84Point};
+
+// A 1°×1° SRTM tile, by the integer coordinates of its southwest corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HgtTile {
+    pub lon: i32,
+    pub lat: i32,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct TileNameError(pub String);
+
+impl fmt::Display for TileNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: not the name of an SRTM tile", self.0)
+    }
+}
+
+impl std::error::Error for TileNameError {}
+
+impl HgtTile {
+    // the tile the position is in, a position on an integer degree is in
+    // the tile to the north or to the east of it
+    pub fn from_lonlat(lon: f64, lat: f64) -> Self {
+        HgtTile {
+            lon: lon.floor() as i32,
+            lat: lat.floor() as i32,
+        }
+    }
+
+    pub fn from_point(point: &WGS84Point) -> Self {
+        Self::from_lonlat(point.lon, point.lat)
+    }
+
+    // "S24W047.hgt", or without the extension, in either case
+    pub fn from_name(name: &str) -> Result<Self, TileNameError> {
+        let error = || TileNameError(name.to_string());
+        let upper = name.to_ascii_uppercase();
+        let stem = upper.strip_suffix(".HGT").unwrap_or(&upper);
+        if stem.len() != 7 || !stem.is_ascii() {
+            return Err(error());
+        }
+        let (lat, lon) = stem.split_at(3);
+        let degrees = |hemisphere: &str, digits: &str, signs: [char; 2], max: i32| {
+            if !digits.bytes().all(|b| b.is_ascii_digit()) {
+                return None;
+            }
+            let value: i32 = digits.parse().ok().filter(|v| *v <= max)?;
+            match hemisphere.chars().next()? {
+                c if c == signs[0] => Some(value),
+                c if c == signs[1] => Some(-value),
+                _ => None,
+            }
+        };
+        Ok(HgtTile {
+            lon: degrees(&lon[..1], &lon[1..], ['E', 'W'], 180).ok_or_else(error)?,
+            lat: degrees(&lat[..1], &lat[1..], ['N', 'S'], 90).ok_or_else(error)?,
+        })
+    }
+
+    // N/S + 2-digit latitude + E/W + 3-digit longitude + .hgt
+    pub fn filename(&self) -> String {
+        format!(
+            "{}{:02}{}{:03}.hgt",
+            if self.lat >= 0 { 'N' } else { 'S' },
+            self.lat.abs(),
+            if self.lon >= 0 { 'E' } else { 'W' },
+            self.lon.abs()
+        )
+    }
+
+    pub fn bbox(&self) -> WGS84BoundingBox {
+        let corner = |lon: i32, lat: i32| WGS84Point {
+            lon: lon as f64,
+            lat: lat as f64,
+            ele: None,
+        };
+        WGS84BoundingBox::from(
+            &corner(self.lon, self.lat),
+            &corner(self.lon + 1, self.lat + 1),
+        )
+    }
+
+    // as from_point: the north and east edges belong to the next tiles
+    pub fn contains(&self, point: &WGS84Point) -> bool {
+        Self::from_point(point) == *self
+    }
+}
 
 pub fn hgt_basename_lonlat(lon: f64, lat: f64) -> String {
-    // SRTM tiles are named by the coordinates of their southwest corner
-    // Floor the coordinates to get the tile's southwest corner
-    let lat_floor = lat.floor() as i32;
-    let lon_floor = lon.floor() as i32;
-
-    // Determine latitude prefix (N or S)
-    let lat_prefix = if lat_floor >= 0 { 'N' } else { 'S' };
-    let lat_abs = lat_floor.abs();
-
-    // Determine longitude prefix (E or W)
-    let lon_prefix = if lon_floor >= 0 { 'E' } else { 'W' };
-    let lon_abs = lon_floor.abs();
-
-    // Format: N/S + 2-digit latitude + E/W + 3-digit longitude + .hgt
-    format!(
-        "{}{:02}{}{:03}.hgt",
-        lat_prefix, lat_abs, lon_prefix, lon_abs
-    )
+    HgtTile::from_lonlat(lon, lat).filename()
 }
 
 pub fn hgt_basename(point: &WGS84Point) -> String {
-    hgt_basename_lonlat(point.lon, point.lat)
+    HgtTile::from_point(point).filename()
+}
+
+// The tiles sharing an area with the polygon: a polygon only touching a tile
+// along its edge does not need it.
+pub fn tiles(ring: &[WGS84Point]) -> BTreeSet<String> {
+    if ring.len() < 3 {
+        return ring.iter().map(hgt_basename).collect();
+    }
+    // continue east of 180°
+    let across = point::crosses_antimeridian(ring);
+    let coords: Vec<Coord> = ring
+        .iter()
+        .map(|p| Coord {
+            x: p.unwrapped_lon(across),
+            y: p.lat,
+        })
+        .collect();
+    let polygon = geo::Polygon::new(LineString::from(coords), vec![]);
+    let bbox = polygon.bounding_rect().unwrap();
+    // tiles are shrunk a little, so that touching does not count
+    const EPS: f64 = 1e-9;
+    let mut ret = BTreeSet::new();
+    for (lon, lat) in tile_corners(bbox.min().x_y(), bbox.max().x_y()) {
+        let (x, y) = (lon as f64, lat as f64);
+        let tile = Rect::new((x + EPS, y + EPS), (x + 1.0 - EPS, y + 1.0 - EPS));
+        if polygon.intersects(&tile) {
+            let lon = if lon >= 180 { lon - 360 } else { lon };
+            ret.insert(HgtTile { lon, lat }.filename());
+        }
+    }
+    ret
+}
+
+// The southwest corners of the 1°×1° tiles sharing an area with the box,
+// given by its (lon, lat) corners. A box ending on an integer degree does
+// not need the tile beyond, a flat box needs the tile it lies in.
+pub fn tile_corners(min: (f64, f64), max: (f64, f64)) -> Vec<(i32, i32)> {
+    let range = |min: f64, max: f64| {
+        let first = min.floor() as i32;
+        first..=(max.ceil() as i32 - 1).max(first)
+    };
+    let mut ret = Vec::new();
+    for lat in range(min.1, max.1) {
+        for lon in range(min.0, max.0) {
+            ret.push((lon, lat));
+        }
+    }
+    ret
+}
+
+// The basenames of the tiles sharing an area with the box. A box with its
+// min longitude east of its max longitude crosses the antimeridian, so does
+// a box beyond 180°: the tiles are named on both sides.
+pub fn tiles_for_bbox(bbox: &WGS84BoundingBox) -> Vec<String> {
+    let mut max_lon = bbox.max.lon;
+    if max_lon < bbox.min.lon {
+        max_lon += 360.0;
+    }
+    // once around at most
+    max_lon = max_lon.min(bbox.min.lon + 360.0);
+    tile_corners((bbox.min.lon, bbox.min.lat), (max_lon, bbox.max.lat))
+        .into_iter()
+        .map(|(lon, lat)| {
+            let lon = (lon + 180).rem_euclid(360) - 180;
+            HgtTile { lon, lat }.filename()
+        })
+        .collect()
 }
 
 #[cfg(test)]
@@ -77,4 +219,169 @@ mod tests {
             "N18W070.hgt"
         );
     }
+
+    fn ring(points: &[(f64, f64)]) -> Vec<WGS84Point> {
+        points
+            .iter()
+            .map(|(lon, lat)| WGS84Point {
+                lon: *lon,
+                lat: *lat,
+                ele: None,
+            })
+            .collect()
+    }
+
+    fn rect(lon: (f64, f64), lat: (f64, f64)) -> Vec<WGS84Point> {
+        ring(&[
+            (lon.0, lat.0),
+            (lon.1, lat.0),
+            (lon.1, lat.1),
+            (lon.0, lat.1),
+        ])
+    }
+
+    #[test]
+    fn test_tiles() {
+        let cases = [
+            // equator
+            (rect((6.1, 6.4), (-0.2, 0.3)), vec!["N00E006", "S01E006"]),
+            // a southern sliver is in S01, not N00
+            (rect((6.1, 6.4), (-0.3, -0.1)), vec!["S01E006"]),
+            // touching the equator from the north
+            (rect((6.1, 6.4), (0.0, 0.3)), vec!["N00E006"]),
+            // prime meridian
+            (rect((-0.1, 0.4), (46.1, 46.3)), vec!["N46E000", "N46W001"]),
+            // both
+            (
+                rect((-0.1, 0.4), (-0.2, 0.3)),
+                vec!["N00E000", "N00W001", "S01E000", "S01W001"],
+            ),
+            // antimeridian
+            (
+                rect((179.8, -179.9), (10.1, 10.2)),
+                vec!["N10E179", "N10W180"],
+            ),
+            (
+                rect((179.8, -179.9), (-0.2, 0.3)),
+                vec!["N00E179", "N00W180", "S01E179", "S01W180"],
+            ),
+            // tiles without a vertex
+            (
+                rect((6.5, 8.5), (46.5, 46.7)),
+                vec!["N46E006", "N46E007", "N46E008"],
+            ),
+            // a triangle over N45E006 to N46E008, two tiles without a vertex
+            (
+                ring(&[(6.5, 45.5), (8.5, 45.2), (8.7, 46.8)]),
+                vec!["N45E006", "N45E007", "N45E008", "N46E007", "N46E008"],
+            ),
+            // the hypotenuse only touches the corners of (1, 2) and (2, 1)
+            (
+                ring(&[(0.1, 0.1), (2.9, 0.1), (0.1, 2.9)]),
+                vec![
+                    "N00E000", "N00E001", "N00E002", "N01E000", "N01E001", "N02E000",
+                ],
+            ),
+        ];
+        assert_eq!(
+            tile_corners((6.5, 45.2), (8.7, 46.8)),
+            vec![(6, 45), (7, 45), (8, 45), (6, 46), (7, 46), (8, 46)]
+        );
+        for (polygon, expected) in cases {
+            let expected: BTreeSet<String> =
+                expected.iter().map(|t| format!("{}.hgt", t)).collect();
+            assert_eq!(tiles(&polygon), expected);
+        }
+    }
+
+    #[test]
+    fn test_tiles_for_bbox() {
+        let bbox = |min: (f64, f64), max: (f64, f64)| WGS84BoundingBox {
+            min: WGS84Point {
+                lon: min.0,
+                lat: min.1,
+                ele: None,
+            },
+            max: WGS84Point {
+                lon: max.0,
+                lat: max.1,
+                ele: None,
+            },
+        };
+        let cases = [
+            (
+                bbox((-70.2, 18.1), (-69.8, 18.4)),
+                vec!["N18W071", "N18W070"],
+            ),
+            // equator
+            (bbox((6.1, -0.2), (6.4, 0.3)), vec!["S01E006", "N00E006"]),
+            // prime meridian
+            (bbox((-0.1, 46.1), (0.4, 46.3)), vec!["N46W001", "N46E000"]),
+            // on integer degrees, the tiles beyond are not needed
+            (bbox((6.0, 46.0), (7.0, 47.0)), vec!["N46E006"]),
+            (bbox((-71.0, -1.0), (-70.0, 0.0)), vec!["S01W071"]),
+            // flat
+            (bbox((7.0, 46.5), (7.0, 46.5)), vec!["N46E007"]),
+            // antimeridian, from the east or past 180°
+            (
+                bbox((179.8, 10.1), (-179.9, 10.2)),
+                vec!["N10E179", "N10W180"],
+            ),
+            (
+                bbox((179.8, 10.1), (180.1, 10.2)),
+                vec!["N10E179", "N10W180"],
+            ),
+        ];
+        for (bbox, expected) in cases {
+            let expected: Vec<String> = expected.iter().map(|t| format!("{}.hgt", t)).collect();
+            assert_eq!(tiles_for_bbox(&bbox), expected);
+        }
+        // once around, whatever the width
+        for max in [180.0, 900.0] {
+            let tiles = tiles_for_bbox(&bbox((-180.0, 0.5), (max, 0.6)));
+            assert_eq!(tiles.iter().collect::<BTreeSet<_>>().len(), 360);
+            assert_eq!(tiles.len(), 360);
+        }
+    }
+
+    #[test]
+    fn test_tile() {
+        for name in ["N46E006.hgt", "S24W047.hgt", "N18W070.hgt", "S12E130.hgt"] {
+            let tile = HgtTile::from_name(name).unwrap();
+            assert_eq!(tile.filename(), name);
+            assert_eq!(hgt_basename(&tile.bbox().center()), name);
+        }
+        assert_eq!(
+            HgtTile::from_name("s24w047").unwrap(),
+            HgtTile { lon: -47, lat: -24 }
+        );
+        for name in [
+            "",
+            "N46E06.hgt",
+            "N46E006.tif",
+            "X46E006.hgt",
+            "N46N006.hgt",
+            "N4aE006.hgt",
+            "N-6E006.hgt",
+            "N91E006.hgt",
+            "N46E181.hgt",
+            "N46E006_v3.hgt",
+        ] {
+            assert!(HgtTile::from_name(name).is_err(), "{}", name);
+        }
+        let tile = HgtTile::from_lonlat(-46.8, -23.5);
+        assert_eq!(tile, HgtTile { lon: -47, lat: -24 });
+        let bbox = tile.bbox();
+        assert_eq!((bbox.min.lon, bbox.min.lat), (-47.0, -24.0));
+        assert_eq!((bbox.max.lon, bbox.max.lat), (-46.0, -23.0));
+        let point = |lon, lat| WGS84Point {
+            lon,
+            lat,
+            ele: None,
+        };
+        assert!(tile.contains(&point(-47.0, -24.0)));
+        assert!(tile.contains(&point(-46.5, -23.01)));
+        assert!(!tile.contains(&point(-46.0, -23.5)));
+        assert!(!tile.contains(&point(-46.5, -23.0)));
+    }
 }
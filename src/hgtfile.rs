@@ -0,0 +1,228 @@
+use std::fs::File;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::hgt::HgtTile;
+use crate::rasterio::{AreaOrPoint, RasterIo, RasterIoError};
+
+// the nodata value of the SRTM tiles
+const VOID: i16 = -32768;
+
+// the WKT GDAL reports for the SRTMHGT driver
+const WGS84_WKT: &str = r#"GEOGCS["WGS 84",DATUM["WGS_1984",SPHEROID["WGS 84",6378137,298.257223563,AUTHORITY["EPSG","7030"]],AUTHORITY["EPSG","6326"]],PRIMEM["Greenwich",0,AUTHORITY["EPSG","8901"]],UNIT["degree",0.0174532925199433,AUTHORITY["EPSG","9122"]],AXIS["Latitude",NORTH],AXIS["Longitude",EAST],AUTHORITY["EPSG","4326"]]"#;
+
+// SRTM .hgt tiles, memory-mapped: a square of big-endian 16 bit samples,
+// its southwest corner in the file name. Only the rows of a window are
+// touched, and converted as they are read. The georeferencing follows the
+// SRTMHGT driver of GDAL (the samples are at the cell centers, the first
+// one on the northwest corner of the tile).
+pub struct HgtFile {
+    map: Mmap,
+    // samples per row and per column
+    size: usize,
+    southwest: (f64, f64),
+}
+
+fn error(message: String) -> RasterIoError {
+    RasterIoError(format!("hgt: {}", message))
+}
+
+// N46E006.hgt, or a name starting with it
+pub(crate) fn tile(path: &Path) -> Option<HgtTile> {
+    let name = path.file_stem()?.to_str()?;
+    HgtTile::from_name(name.get(..7)?).ok()
+}
+
+// N46E006.hgt: (6, 46)
+pub(crate) fn southwest(path: &Path) -> Option<(f64, f64)> {
+    tile(path).map(|tile| (tile.lon as f64, tile.lat as f64))
+}
+
+impl HgtFile {
+    pub fn open(path: &Path) -> Result<Self, RasterIoError> {
+        let southwest = southwest(path).ok_or_else(|| {
+            error(format!(
+                "{}: the name is not the southwest corner",
+                path.display()
+            ))
+        })?;
+        let file = File::open(path).map_err(|e| error(format!("{}: {}", path.display(), e)))?;
+        // the file is not expected to change while it is mapped
+        let map =
+            unsafe { Mmap::map(&file) }.map_err(|e| error(format!("{}: {}", path.display(), e)))?;
+        let size = ((map.len() / 2) as f64).sqrt().round() as usize;
+        if size < 2 || 2 * size * size != map.len() {
+            return Err(error(format!(
+                "{}: {} bytes is not a square of 16 bit samples",
+                path.display(),
+                map.len()
+            )));
+        }
+        Ok(HgtFile {
+            map,
+            size,
+            southwest,
+        })
+    }
+
+    // whether the file can be mapped as a tile: its name and extension
+    pub fn accepts(path: &Path) -> bool {
+        let hgt = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("hgt"));
+        hgt && southwest(path).is_some()
+    }
+
+    // the sample at the row (from the north) and the column (from the west),
+    // None on a void or outside of the tile
+    pub fn elevation(&self, row: usize, col: usize) -> Option<f64> {
+        if row >= self.size || col >= self.size {
+            return None;
+        }
+        let start = 2 * (row * self.size + col);
+        match i16::from_be_bytes([self.map[start], self.map[start + 1]]) {
+            VOID => None,
+            value => Some(value as f64),
+        }
+    }
+}
+
+impl RasterIo for HgtFile {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        let step = 1.0 / (self.size - 1) as f64;
+        let (lon, lat) = self.southwest;
+        Ok([lon, step, 0.0, lat + 1.0, 0.0, -step])
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        (self.size, self.size)
+    }
+    fn projection(&self) -> String {
+        WGS84_WKT.to_string()
+    }
+    fn no_data_value(&self, _band: usize) -> Result<Option<f64>, RasterIoError> {
+        Ok(Some(VOID as f64))
+    }
+    fn area_or_point(&self) -> AreaOrPoint {
+        AreaOrPoint::Point
+    }
+    fn overview_count(&self, _band: usize) -> Result<usize, RasterIoError> {
+        Ok(0)
+    }
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        let inside = band == 1
+            && window.0 >= 0
+            && window.1 >= 0
+            && window.0 as usize + size.0 <= self.size
+            && window.1 as usize + size.1 <= self.size;
+        if !inside {
+            return Err(error(format!(
+                "window {:?} {:?} of band {} is outside of the tile",
+                window, size, band
+            )));
+        }
+        let (col, row) = (window.0 as usize, window.1 as usize);
+        let mut ret = Vec::with_capacity(size.0 * size.1);
+        for r in row..row + size.1 {
+            let start = 2 * (r * self.size + col);
+            let bytes = &self.map[start..start + 2 * size.0];
+            ret.extend(
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| i16::from_be_bytes([b[0], b[1]]) as f64),
+            );
+        }
+        Ok(ret)
+    }
+    fn read_mask(
+        &self,
+        _band: usize,
+        _window: (isize, isize),
+        _size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        Ok(None)
+    }
+    fn sample_size(&self, _band: usize) -> usize {
+        2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rasterio::GdalRasterIo;
+
+    // a 3 arc-second tile with a void
+    fn write_tile(path: &Path) {
+        let mut bytes = Vec::with_capacity(2 * 1201 * 1201);
+        for row in 0..1201 {
+            for col in 0..1201 {
+                let value: i16 = if (row, col) == (600, 600) {
+                    VOID
+                } else {
+                    (row as i16) - (col as i16) / 2
+                };
+                bytes.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    #[test]
+    fn test_southwest() {
+        assert_eq!(southwest(Path::new("/a/N46E006.hgt")), Some((6.0, 46.0)));
+        assert_eq!(southwest(Path::new("s12w070.hgt")), Some((-70.0, -12.0)));
+        assert_eq!(southwest(Path::new("tile.hgt")), None);
+        assert!(HgtFile::accepts(Path::new("dem/N46E006.HGT")));
+        assert!(!HgtFile::accepts(Path::new("dem/N46E006.tif")));
+    }
+
+    #[test]
+    fn test_mapped_tile() {
+        let dir = std::env::temp_dir().join("surface-area-hgtfile");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("N46E006.hgt");
+        write_tile(&path);
+        let tile = HgtFile::open(&path).unwrap();
+        assert_eq!(tile.raster_size(), (1201, 1201));
+        let step = 1.0 / 1200.0;
+        assert_eq!(
+            tile.geo_transform().unwrap(),
+            [6.0, step, 0.0, 47.0, 0.0, -step]
+        );
+        assert_eq!(
+            tile.read_window(1, (599, 600), (3, 1)).unwrap(),
+            vec![(600 - 299) as f64, VOID as f64, (600 - 300) as f64]
+        );
+        assert!(tile.read_window(1, (1200, 0), (2, 1)).is_err());
+        assert_eq!(tile.elevation(600, 599), Some((600 - 299) as f64));
+        assert_eq!(tile.elevation(600, 600), None);
+        assert_eq!(tile.elevation(0, 1201), None);
+        // GDAL reads the same values, where it is available
+        if let Ok(gdal) = GdalRasterIo::open(&path) {
+            assert_eq!(gdal.geo_transform().unwrap(), tile.geo_transform().unwrap());
+            assert_eq!(gdal.no_data_value(1).unwrap(), Some(VOID as f64));
+            for (window, size) in [((0, 0), (1201, 3)), ((590, 595), (20, 10))] {
+                let native = tile.read_window(1, window, size).unwrap();
+                let gdal = gdal.read_window(1, window, size).unwrap();
+                assert!(
+                    native
+                        .iter()
+                        .zip(&gdal)
+                        .all(|(a, b)| a.to_bits() == b.to_bits())
+                );
+            }
+        }
+        // not a square
+        let short = dir.join("N46E007.hgt");
+        std::fs::write(&short, [0u8; 6]).unwrap();
+        assert!(HgtFile::open(&short).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
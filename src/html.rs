@@ -0,0 +1,342 @@
+use crate::format::{self, Quantity};
+use crate::typst::{Data, PlaneSummary};
+
+const BINS: usize = 20;
+
+// Self-contained HTML report: everything (SVG, script, data) is inline so the
+// file works offline from file://.
+pub fn make_html_document(data: &[Data]) -> String {
+    let mut doc = String::from(HEADER);
+    for (i, item) in data.iter().enumerate() {
+        doc.push_str(&format!("<section id=\"item-{}\">\n", i));
+        doc.push_str(&format!("<h2>{}</h2>\n", escape(&item.name)));
+        doc.push_str(&summary_table(i, item));
+        for note in &item.notes {
+            doc.push_str(&format!("<p class=\"note\">{}</p>\n", escape(note)));
+        }
+
+        let labels = item.labels();
+        doc.push_str("<div class=\"toggles\">\n");
+        for (id, label) in [("slope", labels.slope), ("elevation", labels.elevation)] {
+            if !item.svg.contains(&format!("id=\"layer-{}\"", id)) {
+                continue;
+            }
+            let checked = if id == "slope" { " checked" } else { "" };
+            doc.push_str(&format!(
+                "<label><input type=\"checkbox\" data-figure=\"figure-{}\" data-layer=\"p{}-layer-{}\"{}> {}</label>\n",
+                i, i, id, checked, label
+            ));
+        }
+        doc.push_str("</div>\n");
+        doc.push_str(&format!("<div class=\"figure\" id=\"figure-{}\">\n", i));
+        doc.push_str(&item.svg.replace("id=\"layer-", &format!("id=\"p{}-layer-", i)));
+        doc.push_str("\n</div>\n");
+
+        let slopes: Vec<f64> = item.planes.iter().map(|p| p.slope).collect();
+        let elevations: Vec<f64> = item.planes.iter().map(|p| p.elevation).collect();
+        doc.push_str(&histogram_svg(
+            &format!("histogram-slope-{}", i),
+            labels.slope_axis,
+            &slopes,
+            &item.planes,
+        ));
+        doc.push_str(&histogram_svg(
+            &format!("histogram-elevation-{}", i),
+            labels.elevation_axis,
+            &elevations,
+            &item.planes,
+        ));
+        doc.push_str("</section>\n");
+    }
+    let json = serde_json::to_string(data).unwrap().replace("</", "<\\/");
+    doc.push_str(&format!(
+        "<script type=\"application/json\" id=\"data\">{}</script>\n",
+        json
+    ));
+    doc.push_str(SCRIPT);
+    doc.push_str("</body>\n</html>\n");
+    doc
+}
+
+fn summary_table(i: usize, item: &Data) -> String {
+    let labels = item.labels();
+    let mut s = format!("<table id=\"summary-{}\">\n", i);
+    s.push_str(&format!(
+        "<tr><th>{}</th><th>{}</th><th>{}</th></tr>\n",
+        labels.field, labels.value2d, labels.value3d
+    ));
+    s.push_str(&format!(
+        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+        labels.geodesic,
+        item.area(item.geodesic2d),
+        item.area(item.geodesic3d)
+    ));
+    s.push_str(&format!(
+        "<tr><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+        labels.mercator,
+        item.area(item.planar2d),
+        item.area(item.projected3d)
+    ));
+    s.push_str(&format!(
+        "<tr><td>{}</td><td colspan=\"2\">{}</td></tr>\n",
+        capitalize(labels.ratio),
+        format::signed(
+            100f64 * (item.projected3d / item.projected2d - 1f64),
+            Quantity::Percent
+        )
+    ));
+    s.push_str("</table>\n");
+    s
+}
+
+// Area-weighted histogram: the bins hold the 3D area of the planes.
+pub fn histogram(values: &[f64], weights: &[f64], bins: usize) -> (f64, f64, Vec<f64>) {
+    let finite = values.iter().filter(|v| v.is_finite());
+    let min = finite.clone().fold(f64::INFINITY, |a, b| a.min(*b));
+    let max = finite.fold(f64::NEG_INFINITY, |a, b| a.max(*b));
+    let mut counts = vec![0f64; bins];
+    if min > max {
+        return (0f64, 0f64, counts);
+    }
+    let width = (max - min).max(f64::EPSILON);
+    for (v, w) in values.iter().zip(weights) {
+        if !v.is_finite() {
+            continue;
+        }
+        let k = (((v - min) / width) * bins as f64) as usize;
+        counts[k.min(bins - 1)] += w;
+    }
+    (min, max, counts)
+}
+
+fn histogram_svg(id: &str, label: &str, values: &[f64], planes: &[PlaneSummary]) -> String {
+    let weights: Vec<f64> = planes.iter().map(|p| p.area).collect();
+    let (min, max, counts) = histogram(values, &weights, BINS);
+    let (width, height) = (300f64, 120f64);
+    let top = counts.iter().cloned().fold(0f64, f64::max).max(f64::EPSILON);
+    let bar = width / BINS as f64;
+    let mut s = format!(
+        "<svg id=\"{}\" class=\"histogram\" width=\"{}\" height=\"{}\" xmlns=\"http://www.w3.org/2000/svg\">\n",
+        id,
+        width,
+        height + 20f64
+    );
+    for (k, c) in counts.iter().enumerate() {
+        let h = height * c / top;
+        s.push_str(&format!(
+            "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"steelblue\"/>\n",
+            k as f64 * bar,
+            height - h,
+            bar - 1f64,
+            h
+        ));
+    }
+    s.push_str(&format!(
+        "<text x=\"0\" y=\"{}\" font-size=\"10\">{:.1}</text>\n",
+        height + 15f64,
+        min
+    ));
+    s.push_str(&format!(
+        "<text x=\"{}\" y=\"{}\" font-size=\"10\" text-anchor=\"middle\">{}</text>\n",
+        width / 2f64,
+        height + 15f64,
+        escape(label)
+    ));
+    s.push_str(&format!(
+        "<text x=\"{}\" y=\"{}\" font-size=\"10\" text-anchor=\"end\">{:.1}</text>\n",
+        width,
+        height + 15f64,
+        max
+    ));
+    s.push_str("</svg>\n");
+    s
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) => c.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HEADER: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>surface-area</title>
+<style>
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin: 1em 0; }
+td, th { border: 1px solid #999; padding: 4px 10px; }
+.figure svg { border: 1px solid #ccc; cursor: grab; }
+.histogram { margin-right: 2em; }
+.note { font-style: italic; }
+</style>
+</head>
+<body>
+"#;
+
+// Layer toggles and pan/zoom by rewriting the viewBox of the figures.
+const SCRIPT: &str = r#"<script>
+document.querySelectorAll('.toggles input').forEach(function (input) {
+  input.addEventListener('change', function () {
+    var figure = document.getElementById(input.dataset.figure);
+    figure.querySelectorAll('g').forEach(function (g) {
+      if (g.id === input.dataset.layer) {
+        g.setAttribute('display', input.checked ? 'inline' : 'none');
+      }
+    });
+  });
+});
+document.querySelectorAll('.figure svg').forEach(function (svg) {
+  var vb = svg.getAttribute('viewBox').split(' ').map(Number);
+  var drag = null;
+  function apply() { svg.setAttribute('viewBox', vb.join(' ')); }
+  svg.addEventListener('wheel', function (e) {
+    e.preventDefault();
+    var r = svg.getBoundingClientRect();
+    var mx = vb[0] + vb[2] * (e.clientX - r.left) / r.width;
+    var my = vb[1] + vb[3] * (e.clientY - r.top) / r.height;
+    var f = e.deltaY < 0 ? 0.8 : 1.25;
+    vb = [mx - (mx - vb[0]) * f, my - (my - vb[1]) * f, vb[2] * f, vb[3] * f];
+    apply();
+  });
+  svg.addEventListener('mousedown', function (e) { drag = [e.clientX, e.clientY]; });
+  window.addEventListener('mouseup', function () { drag = null; });
+  window.addEventListener('mousemove', function (e) {
+    if (!drag) { return; }
+    var r = svg.getBoundingClientRect();
+    vb[0] -= (e.clientX - drag[0]) * vb[2] / r.width;
+    vb[1] -= (e.clientY - drag[1]) * vb[3] / r.height;
+    drag = [e.clientX, e.clientY];
+    apply();
+  });
+});
+</script>
+"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::{MercatorBoundingBox, MercatorPoint};
+    use crate::svg::Svg;
+    use quick_xml::events::Event;
+    use quick_xml::Reader;
+
+    fn point(x: f64, y: f64) -> MercatorPoint {
+        MercatorPoint { x, y, ele: None }
+    }
+
+    fn data() -> Data {
+        let mut svg = Svg::init(&MercatorBoundingBox {
+            min: point(0.0, 0.0),
+            max: point(100.0, 100.0),
+        });
+        svg.add_layer("slope", true);
+        svg.add_layer("elevation", false);
+        let square = vec![
+            point(0.0, 0.0),
+            point(100.0, 0.0),
+            point(100.0, 100.0),
+            point(0.0, 100.0),
+        ];
+        svg.add_polygon_to_layer("slope", &square, "gray");
+        svg.add_polygon_to_layer("elevation", &square, "blue");
+        Data {
+            name: "a<b>.kml".to_string(),
+            geodesic2d: 10000.0,
+            planar2d: 10000.0,
+            spherical2d: 10000.0,
+            projected2d: 10000.0,
+            projected3d: 10500.0,
+            discarded2d: 0.0,
+            geodesic3d: 10500.0,
+            estimates: Default::default(),
+            perimeter2d: 0.0,
+            perimeter3d: None,
+            svg: svg.render().unwrap(),
+            nplanes: 2,
+            low_confidence: false,
+            edge_fraction: 0.0,
+            projection: "+proj=utm +zone=32 +datum=WGS84".to_string(),
+            notes: vec![],
+            insolation: None,
+            breakdown: None,
+            extremes: Default::default(),
+            reference: None,
+            self_check: None,
+            io_stats: Vec::new(),
+            phases: Vec::new(),
+            clip_paths: Default::default(),
+            parts: Vec::new(),
+            units: Default::default(),
+            lang: Default::default(),
+            planes: vec![
+                PlaneSummary {
+                    slope: 10.0,
+                    elevation: 400.0,
+                    area: 5000.0,
+                    inherited: false,
+                },
+                PlaneSummary {
+                    slope: 30.0,
+                    elevation: 420.0,
+                    area: 5500.0,
+                    inherited: false,
+                },
+            ],
+        }
+    }
+
+    fn parse_svgs(html: &str) -> usize {
+        let mut count = 0;
+        let mut rest = html;
+        while let Some(start) = rest.find("<svg") {
+            let end = rest[start..].find("</svg>").unwrap() + start + "</svg>".len();
+            let mut reader = Reader::from_str(&rest[start..end]);
+            loop {
+                match reader.read_event() {
+                    Ok(Event::Eof) => break,
+                    Err(e) => panic!("invalid svg: {}", e),
+                    _ => {}
+                }
+            }
+            count += 1;
+            rest = &rest[end..];
+        }
+        count
+    }
+
+    #[test]
+    fn test_html_document() {
+        let html = make_html_document(&[data()]);
+        assert_eq!(parse_svgs(&html), 3);
+        for id in [
+            "summary-0",
+            "figure-0",
+            "p0-layer-slope",
+            "p0-layer-elevation",
+            "histogram-slope-0",
+            "histogram-elevation-0",
+        ] {
+            assert!(html.contains(&format!("id=\"{}\"", id)), "missing {}", id);
+        }
+        assert!(html.contains("a&lt;b&gt;.kml"));
+        assert!(!html.contains("src=\"http"));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let (min, max, counts) = histogram(&[0.0, 1.0, 2.0, 10.0], &[1.0, 1.0, 1.0, 2.0], 5);
+        assert_eq!((min, max), (0.0, 10.0));
+        assert_eq!(counts, vec![2.0, 1.0, 0.0, 0.0, 2.0]);
+    }
+}
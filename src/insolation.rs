@@ -0,0 +1,170 @@
+use serde::Serialize;
+
+use crate::compute::Plane;
+use crate::polygon;
+
+pub const ASPECT_CLASSES: [&str; 9] = ["flat", "N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+
+// azimuth clockwise from north, altitude above the horizon, both in degrees
+#[derive(Clone, Copy, Debug)]
+pub struct SunPosition {
+    pub azimuth: f64,
+    pub altitude: f64,
+}
+
+impl SunPosition {
+    // solar noon on the equinox: the sun is in the meridian, 90° - latitude high
+    pub fn equinox_noon(latitude: f64) -> Self {
+        SunPosition {
+            azimuth: if latitude >= 0.0 { 180.0 } else { 0.0 },
+            altitude: 90.0 - latitude.abs(),
+        }
+    }
+
+    // unit vector towards the sun in (east, north, up)
+    fn direction(&self) -> (f64, f64, f64) {
+        let (az, alt) = (self.azimuth.to_radians(), self.altitude.to_radians());
+        (az.sin() * alt.cos(), az.cos() * alt.cos(), alt.sin())
+    }
+}
+
+// cosine of the incidence angle, 0 for planes facing away from the sun
+pub fn incidence(normal: (f64, f64, f64), sun: &SunPosition) -> f64 {
+    let s = sun.direction();
+    (normal.0 * s.0 + normal.1 * s.1 + normal.2 * s.2).max(0.0)
+}
+
+fn aspect_class(normal: (f64, f64, f64)) -> usize {
+    match polygon::normal_aspect(normal) {
+        None => 0,
+        Some(a) => 1 + (((a + 22.5) / 45.0).floor() as usize) % 8,
+    }
+}
+
+#[derive(Clone, Serialize)]
+pub struct AspectClass {
+    pub name: String,
+    pub area: f64,
+    pub weighted: f64,
+}
+
+#[derive(Clone, Serialize)]
+pub struct InsolationSummary {
+    pub azimuth: f64,
+    pub altitude: f64,
+    pub area: f64,
+    // sum of area × cos(incidence)
+    pub weighted: f64,
+    // area of an equator-facing slope tilted by the latitude receiving the same
+    pub equivalent: f64,
+    pub classes: Vec<AspectClass>,
+}
+
+impl InsolationSummary {
+    // large-polygon mode: tiles are summed, the sun position of the first is kept
+    pub fn add(&mut self, other: &InsolationSummary) {
+        self.area += other.area;
+        self.weighted += other.weighted;
+        self.equivalent += other.equivalent;
+        for (c, o) in self.classes.iter_mut().zip(&other.classes) {
+            c.area += o.area;
+            c.weighted += o.weighted;
+        }
+    }
+}
+
+pub fn summarize(planes: &[Plane], sun: &SunPosition, latitude: f64) -> InsolationSummary {
+    let mut classes: Vec<AspectClass> = ASPECT_CLASSES
+        .iter()
+        .map(|name| AspectClass {
+            name: name.to_string(),
+            area: 0.0,
+            weighted: 0.0,
+        })
+        .collect();
+    for plane in planes {
+        let normal = plane.normal();
+        let class = &mut classes[aspect_class(normal)];
+        class.area += plane.area3d;
+        class.weighted += plane.area3d * incidence(normal, sun);
+    }
+    let tilt = latitude.abs().to_radians();
+    let north = if latitude >= 0.0 { -1.0 } else { 1.0 };
+    let reference = incidence((0.0, north * tilt.sin(), tilt.cos()), sun);
+    let weighted: f64 = classes.iter().map(|c| c.weighted).sum();
+    InsolationSummary {
+        azimuth: sun.azimuth,
+        altitude: sun.altitude,
+        area: classes.iter().map(|c| c.area).sum(),
+        weighted,
+        equivalent: if reference > 1e-6 {
+            weighted / reference
+        } else {
+            0.0
+        },
+        classes,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::MercatorPoint;
+
+    // a triangle descending towards the south with the given gradient
+    fn south_slope(gradient: f64) -> Plane {
+        let point = |x: f64, y: f64| MercatorPoint {
+            x,
+            y,
+            ele: Some(100.0 + gradient * y),
+        };
+        let points = vec![point(0.0, 0.0), point(10.0, 0.0), point(0.0, 10.0)];
+        Plane {
+            points,
+            area2d: 50.0,
+            area3d: 50.0 * (1.0 + gradient * gradient).sqrt(),
+            inherited: None,
+        }
+    }
+
+    #[test]
+    fn test_equinox_noon() {
+        let sun = SunPosition::equinox_noon(46.0);
+        assert_eq!(sun.azimuth, 180.0);
+        assert_eq!(sun.altitude, 44.0);
+        assert_eq!(SunPosition::equinox_noon(-30.0).azimuth, 0.0);
+    }
+
+    #[test]
+    fn test_uniform_slope() {
+        let plane = south_slope(0.2);
+        let theta = 0.2f64.atan();
+        let sun = SunPosition {
+            azimuth: 180.0,
+            altitude: 40.0,
+        };
+        let cos = incidence(plane.normal(), &sun);
+        assert!((cos - (40f64.to_radians() + theta).sin()).abs() < 1e-9);
+        assert!((polygon::normal_aspect(plane.normal()).unwrap() - 180.0).abs() < 1e-9);
+
+        let summary = summarize(std::slice::from_ref(&plane), &sun, 46.0);
+        assert!((summary.weighted - plane.area3d * cos).abs() < 1e-9);
+        let south = summary.classes.iter().find(|c| c.name == "S").unwrap();
+        assert_eq!(south.area, plane.area3d);
+        // sun perpendicular to the 46° reference slope
+        let summary = summarize(
+            std::slice::from_ref(&plane),
+            &SunPosition::equinox_noon(46.0),
+            46.0,
+        );
+        assert!((summary.equivalent - summary.weighted).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_flat_aspect() {
+        let plane = south_slope(0.0);
+        assert!(polygon::normal_aspect(plane.normal()).is_none());
+        let summary = summarize(&[plane], &SunPosition::equinox_noon(0.0), 0.0);
+        assert!((summary.classes[0].weighted - 50.0).abs() < 1e-9);
+    }
+}
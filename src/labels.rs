@@ -0,0 +1,287 @@
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::estimate::EstimateStrategy;
+
+// The language of the reports (typst, HTML) and of the printed summary. The
+// machine formats (JSON, CSV) do not depend on it.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Lang {
+    #[default]
+    En,
+    Fr,
+    De,
+}
+
+// The strings of the reports, one table per language.
+pub struct Labels {
+    pub field: &'static str,
+    pub value2d: &'static str,
+    pub value3d: &'static str,
+    pub geodesic: &'static str,
+    pub mercator: &'static str,
+    pub ratio: &'static str,
+    pub low_confidence: &'static str,
+    pub low_confidence_reason: &'static str,
+    pub highest_point: &'static str,
+    pub lowest_point: &'static str,
+    pub highest: &'static str,
+    pub lowest: &'static str,
+    pub at: &'static str,
+    pub insolation: &'static str,
+    pub sun_at: &'static str,
+    pub azimuth: &'static str,
+    pub altitude: &'static str,
+    pub weighted_area: &'static str,
+    pub south_equivalent: &'static str,
+    pub aspect: &'static str,
+    pub area: &'static str,
+    pub weighted: &'static str,
+    pub mean_cosine: &'static str,
+    pub slope: &'static str,
+    pub elevation: &'static str,
+    pub slope_axis: &'static str,
+    pub elevation_axis: &'static str,
+    pub planar: &'static str,
+    pub sphere: &'static str,
+    pub flat: &'static str,
+    pub surface: &'static str,
+    pub estimate: &'static str,
+    // the names of the EstimateStrategy values
+    pub by_ratio: &'static str,
+    pub per_plane: &'static str,
+    pub tile_edges: &'static str,
+    pub of_boundary: &'static str,
+    pub projection: &'static str,
+    pub part: &'static str,
+    pub total: &'static str,
+    pub slope_class: &'static str,
+    pub perimeter: &'static str,
+    pub on_terrain: &'static str,
+    // how the planes are attributed to the bands and classes of the breakdown,
+    // with planar areas
+    pub breakdown_note: &'static str,
+    // the names of insolation::ASPECT_CLASSES
+    pub aspects: [&'static str; 9],
+    // between the groups of three digits of the integer part
+    pub thousands: &'static str,
+}
+
+const EN: Labels = Labels {
+    field: "Field",
+    value2d: "2D Value",
+    value3d: "3D Value",
+    geodesic: "Geodesic",
+    mercator: "Mercator (UTM)",
+    ratio: "ratio",
+    low_confidence: "Low confidence",
+    low_confidence_reason: "the polygon is smaller than a few DEM cells.",
+    highest_point: "Highest point",
+    lowest_point: "Lowest point",
+    highest: "highest",
+    lowest: "lowest",
+    at: "at",
+    insolation: "Insolation",
+    sun_at: "sun at",
+    azimuth: "azimuth",
+    altitude: "altitude",
+    weighted_area: "weighted area",
+    south_equivalent: "south-facing equivalent",
+    aspect: "Aspect",
+    area: "Area",
+    weighted: "Weighted",
+    mean_cosine: "Mean cosine",
+    slope: "Slope",
+    elevation: "Elevation",
+    slope_axis: "slope (%)",
+    elevation_axis: "elevation (m)",
+    planar: "planar",
+    sphere: "sphere",
+    flat: "flat",
+    surface: "surface",
+    estimate: "estimate",
+    by_ratio: "ratio × geodesic area",
+    per_plane: "geodesic area per plane",
+    tile_edges: "tile edges",
+    of_boundary: "of the boundary",
+    projection: "projection",
+    part: "Part",
+    total: "Total",
+    slope_class: "Slope class",
+    perimeter: "perimeter",
+    on_terrain: "on the terrain",
+    breakdown_note: "Each plane counts in full in the band of its mean elevation and in the class of its slope. The areas by band and class are planar, in the projection, unlike the geodesic total.",
+    aspects: ["flat", "N", "NE", "E", "SE", "S", "SW", "W", "NW"],
+    thousands: "",
+};
+
+const FR: Labels = Labels {
+    field: "Champ",
+    value2d: "Valeur 2D",
+    value3d: "Valeur 3D",
+    geodesic: "Géodésique",
+    mercator: "Mercator (UTM)",
+    ratio: "rapport",
+    low_confidence: "Fiabilité faible",
+    low_confidence_reason: "le polygone est plus petit que quelques cellules du MNT.",
+    highest_point: "Point le plus haut",
+    lowest_point: "Point le plus bas",
+    highest: "max",
+    lowest: "min",
+    at: "à",
+    insolation: "Ensoleillement",
+    sun_at: "soleil à",
+    azimuth: "azimut",
+    altitude: "hauteur",
+    weighted_area: "surface pondérée",
+    south_equivalent: "équivalent plein sud",
+    aspect: "Exposition",
+    area: "Surface",
+    weighted: "Pondérée",
+    mean_cosine: "Cosinus moyen",
+    slope: "Pente",
+    elevation: "Altitude",
+    slope_axis: "pente (%)",
+    elevation_axis: "altitude (m)",
+    planar: "plane",
+    sphere: "sphère",
+    flat: "à plat",
+    surface: "surface",
+    estimate: "estimation",
+    by_ratio: "rapport × surface géodésique",
+    per_plane: "surface géodésique par plan",
+    tile_edges: "bords des tuiles",
+    of_boundary: "du contour",
+    projection: "projection",
+    part: "Partie",
+    total: "Total",
+    slope_class: "Classe de pente",
+    perimeter: "périmètre",
+    on_terrain: "sur le terrain",
+    breakdown_note: "Chaque plan compte en entier dans la tranche de son altitude moyenne et dans la classe de sa pente. Les surfaces par tranche et par classe sont planes, dans la projection, contrairement au total géodésique.",
+    aspects: ["plat", "N", "NE", "E", "SE", "S", "SO", "O", "NO"],
+    thousands: "\u{202f}",
+};
+
+const DE: Labels = Labels {
+    field: "Feld",
+    value2d: "2D-Wert",
+    value3d: "3D-Wert",
+    geodesic: "Geodätisch",
+    mercator: "Mercator (UTM)",
+    ratio: "Verhältnis",
+    low_confidence: "Geringe Zuverlässigkeit",
+    low_confidence_reason: "das Polygon ist kleiner als einige DHM-Zellen.",
+    highest_point: "Höchster Punkt",
+    lowest_point: "Tiefster Punkt",
+    highest: "max",
+    lowest: "min",
+    at: "bei",
+    insolation: "Besonnung",
+    sun_at: "Sonne bei",
+    azimuth: "Azimut",
+    altitude: "Höhe",
+    weighted_area: "gewichtete Fläche",
+    south_equivalent: "Südhang-Äquivalent",
+    aspect: "Exposition",
+    area: "Fläche",
+    weighted: "Gewichtet",
+    mean_cosine: "Mittlerer Kosinus",
+    slope: "Neigung",
+    elevation: "Höhe",
+    slope_axis: "Neigung (%)",
+    elevation_axis: "Höhe (m)",
+    planar: "eben",
+    sphere: "Kugel",
+    flat: "flach",
+    surface: "Oberfläche",
+    estimate: "Schätzung",
+    by_ratio: "Verhältnis × geodätische Fläche",
+    per_plane: "geodätische Fläche je Ebene",
+    tile_edges: "Kachelränder",
+    of_boundary: "der Grenze",
+    projection: "Projektion",
+    part: "Teil",
+    total: "Summe",
+    slope_class: "Neigungsklasse",
+    perimeter: "Umfang",
+    on_terrain: "im Gelände",
+    breakdown_note: "Jede Ebene zählt ganz zum Band ihrer mittleren Höhe und zur Klasse ihrer Neigung. Die Flächen nach Band und Klasse sind eben, in der Projektion, anders als die geodätische Gesamtfläche.",
+    aspects: ["flach", "N", "NO", "O", "SO", "S", "SW", "W", "NW"],
+    thousands: "\u{202f}",
+};
+
+impl Lang {
+    pub fn labels(self) -> &'static Labels {
+        match self {
+            Lang::En => &EN,
+            Lang::Fr => &FR,
+            Lang::De => &DE,
+        }
+    }
+}
+
+impl Labels {
+    // the name of an aspect class in this language, the name itself if unknown
+    pub fn aspect_name<'a>(&self, name: &'a str) -> &'a str {
+        match crate::insolation::ASPECT_CLASSES
+            .iter()
+            .position(|c| *c == name)
+        {
+            Some(k) => self.aspects[k],
+            None => name,
+        }
+    }
+
+    pub fn strategy(&self, strategy: EstimateStrategy) -> &'static str {
+        match strategy {
+            EstimateStrategy::Ratio => self.by_ratio,
+            EstimateStrategy::PerPlane => self.per_plane,
+        }
+    }
+
+    // "21420.5 m²" to "21 420.5 m²": the digits of the leading number are
+    // grouped, the decimal separator stays '.'
+    pub fn group(&self, text: &str) -> String {
+        if self.thousands.is_empty() {
+            return text.to_string();
+        }
+        let start = usize::from(text.starts_with(['-', '+']));
+        let digits = text[start..]
+            .find(|c: char| !c.is_ascii_digit())
+            .map_or(text.len(), |k| k + start);
+        let integer = &text[start..digits];
+        if integer.len() < 4 {
+            return text.to_string();
+        }
+        let mut ret = text[..start].to_string();
+        for (k, c) in integer.chars().enumerate() {
+            if k > 0 && (integer.len() - k).is_multiple_of(3) {
+                ret.push_str(self.thousands);
+            }
+            ret.push(c);
+        }
+        ret.push_str(&text[digits..]);
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group() {
+        let fr = Lang::Fr.labels();
+        assert_eq!(fr.group("21420 m²"), "21\u{202f}420 m²");
+        assert_eq!(fr.group("-1234567.25 ha"), "-1\u{202f}234\u{202f}567.25 ha");
+        assert_eq!(fr.group("999.5 m²"), "999.5 m²");
+        assert_eq!(fr.group("12.34 km²"), "12.34 km²");
+        assert_eq!(Lang::En.labels().group("21420 m²"), "21420 m²");
+        assert_eq!(fr.aspect_name("SW"), "SO");
+        assert_eq!(Lang::De.labels().aspect_name("flat"), "flach");
+        assert_eq!(fr.aspect_name("other"), "other");
+    }
+}
@@ -1,11 +1,45 @@
+pub mod area;
+pub mod asciigrid;
+pub mod batch;
+pub mod breakdown;
+pub mod compute;
 pub mod dataset;
+pub mod download;
+pub mod edges;
+pub mod estimate;
+pub mod events;
+pub mod extremes;
+#[cfg(any(test, feature = "fixtures"))]
+#[doc(hidden)]
+pub mod fixtures;
+pub mod flowline;
+pub mod format;
 pub mod hgt;
+pub mod hgtfile;
+pub mod html;
+pub mod insolation;
+pub mod labels;
 pub mod intersection;
-pub mod mercator;
+pub(crate) mod mercator;
+pub mod meshexport;
+pub mod morphology;
+pub mod options;
 pub mod point;
+pub mod pipeline;
+pub mod pointcloud;
 pub mod polygon;
+pub mod prelude;
+pub mod preview;
+pub mod provenance;
+pub mod ramp;
+pub(crate) mod rasterio;
 pub mod read_polygon;
 pub mod reference;
-pub mod svg;
+pub mod refine;
+pub mod registry;
+pub mod selfcheck;
+pub mod slopemap;
+pub(crate) mod svg;
 pub mod triangulation;
 pub mod typst;
+pub mod voidfill;
@@ -1,115 +1,751 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use std::collections::BTreeSet;
-use std::path::Path;
-use surface_area::dataset::Dataset;
-use surface_area::point::MercatorPoint;
+use std::path::{Path, PathBuf};
+use surface_area::area::{AreaValue, Units};
+use surface_area::batch::{self, SummaryRow};
+use surface_area::dataset::{Dataset, DatasetError};
+use surface_area::events::{ComputeEvent, Events};
+use surface_area::format::{self, Quantity};
+use surface_area::options::ComputeOptions;
+use surface_area::pointcloud::{self, PointWriter};
 use surface_area::polygon::Polygon;
-use surface_area::{intersection, polygon, read_polygon, reference, svg, triangulation, typst};
+use surface_area::prelude::IoStats;
+use surface_area::provenance::Provenance;
+use surface_area::read_polygon::Part;
+use surface_area::registry::DatasetRegistry;
+use surface_area::{download, html, pipeline, read_polygon, reference, typst};
 
-fn process(name: String, input_polygon: &Polygon) -> typst::Data {
+// exit codes: some input failed, some area is outside the reference tolerance
+const EXIT_FAILED: i32 = 1;
+const EXIT_REFERENCE: i32 = 3;
+
+fn process(
+    name: String,
+    input_polygon: &Polygon,
+    options: &ComputeOptions,
+    registry: &DatasetRegistry,
+) -> Result<typst::Data, DatasetError> {
     input_polygon.info();
-    let pbbox = input_polygon.wgsbbox();
-    let mut gridpoints = BTreeSet::new();
-    for dataset in Dataset::select(&input_polygon) {
-        dataset.info();
-        let dbbox = dataset.wgsbbox();
-        if let Some(mut bbox) = pbbox.intersection(&dbbox) {
-            log::trace!("bbox: {}", bbox);
-            dataset.snap(&mut bbox);
-            log::trace!("snap: {}", bbox);
-            let mercpoints = dataset.points_inside(&bbox);
-            for m in &mercpoints {
-                gridpoints.insert(m.clone());
+    let datasets = select(&[input_polygon], options, registry)?.remove(0);
+    compute(&name, input_polygon, &datasets, options)
+}
+
+// The datasets of every polygon, searched in the registry (and downloaded)
+// or the files given: a file is opened once for all the polygons.
+fn select(
+    polygons: &[&Polygon],
+    options: &ComputeOptions,
+    registry: &DatasetRegistry,
+) -> Result<Vec<Vec<Dataset>>, DatasetError> {
+    if !options.dataset.is_empty() {
+        return Dataset::select_from_parts(polygons, &options.dataset, options.io);
+    }
+    let filter = options.candidate_filter();
+    let datasets = if options.download {
+        download::select_parts(
+            polygons,
+            registry,
+            &filter,
+            options.io,
+            &options.download_url,
+        )?
+    } else {
+        Dataset::select_parts(polygons, registry, &filter, options.io)
+    };
+    for (polygon, datasets) in polygons.iter().zip(&datasets) {
+        Dataset::check_coverage(polygon, datasets, &registry.directories())?;
+    }
+    Ok(datasets)
+}
+
+fn compute(
+    name: &str,
+    input_polygon: &Polygon,
+    datasets: &[Dataset],
+    options: &ComputeOptions,
+) -> Result<typst::Data, DatasetError> {
+    if options.stats {
+        print_stats(input_polygon, datasets, options);
+    }
+    pipeline::compute(name, input_polygon, datasets, options)
+}
+
+// --stats: the elevations of the datasets, before the pipeline runs
+fn print_stats(input_polygon: &Polygon, datasets: &[Dataset], options: &ComputeOptions) {
+    let sample = options.sample_options();
+    let bbox = input_polygon.wgsbbox();
+    println!("elevations in {}:", bbox);
+    for dataset in datasets {
+        println!(
+            "  {}: {}",
+            dataset.filename,
+            dataset.stats_in(&bbox, &sample)
+        );
+    }
+}
+
+// Large-polygon mode: every tile of the split polygon runs through the
+// pipeline in its own equal-area projection, the areas are summed.
+fn process_large(
+    name: String,
+    input_polygon: &Polygon,
+    options: &ComputeOptions,
+    registry: &DatasetRegistry,
+) -> Result<typst::Data, DatasetError> {
+    let parts = input_polygon.split(options.tile_degrees);
+    // the tiles would overwrite each other's raster
+    let mut options = options.clone();
+    if options.export_slope_tif.take().is_some() {
+        log::warn!("--export-slope-tif is ignored in large-polygon mode");
+    }
+    if options.export_flowline.take().is_some() {
+        log::warn!("--export-flowline is ignored in large-polygon mode");
+    }
+    if options.export_mesh.take().is_some() {
+        log::warn!("--export-mesh is ignored in large-polygon mode");
+    }
+    let options = &options;
+    log::info!(
+        "large-polygon mode: {} tiles of {}°",
+        parts.len(),
+        options.tile_degrees
+    );
+    let mut results = Vec::new();
+    let mut error = None;
+    for part in &parts {
+        match process(name.clone(), part, options, registry) {
+            Ok(r) => results.push(r),
+            Err(e) => {
+                log::warn!("skip tile: {}", e);
+                error = Some(e);
             }
         }
     }
+    if results.is_empty() {
+        // no tile at all: a polygon without area
+        return Err(error.unwrap_or_else(|| {
+            reference::GeometryError::Invalid {
+                reason: "no tile of the large polygon could be processed".to_string(),
+            }
+            .into()
+        }));
+    }
+    let projections: BTreeSet<String> = parts.iter().map(|p| p.projection()).collect();
+    let notes = vec![format!(
+        "large-polygon mode: {} tiles, {} local equal-area projections",
+        parts.len(),
+        projections.len()
+    )];
+    let geodesic2d = input_polygon.geodesic_area()?;
+    let mut ret = pipeline::sum(
+        results[0].name.clone(),
+        &results,
+        geodesic2d,
+        pipeline::outline(input_polygon)?,
+        notes,
+        options,
+    );
+    // the tiles are bounded by their cuts too
+    ret.perimeter2d = input_polygon.perimeter_2d();
+    ret.perimeter3d = None;
+    Ok(ret)
+}
+
+fn process_polygon(
+    name: String,
+    input_polygon: &Polygon,
+    options: &ComputeOptions,
+    registry: &DatasetRegistry,
+) -> Result<typst::Data, DatasetError> {
+    if options.large_polygon {
+        return process_large(name, input_polygon, options, registry);
+    }
+    let span = input_polygon.wgsbbox().max.lon - input_polygon.wgsbbox().min.lon;
+    if span > 3.0 {
+        log::warn!(
+            "polygon spans {:.1}° of longitude, consider --large-polygon",
+            span
+        );
+    }
+    process(name, input_polygon, options, registry)
+}
+
+// Every part runs through the pipeline on its own, on the datasets opened
+// once for all of them; a failed part fails the boundary: the aggregate
+// would be partial.
+fn process_parts(
+    name: String,
+    parts: &[Part],
+    options: &ComputeOptions,
+    registry: &DatasetRegistry,
+) -> Result<typst::Data, DatasetError> {
+    let polygons: Vec<&Polygon> = parts.iter().map(|part| &part.polygon).collect();
+    let datasets = if options.large_polygon {
+        None
+    } else {
+        Some(select(&polygons, options, registry)?)
+    };
+    let mut results = Vec::new();
+    for (k, part) in parts.iter().enumerate() {
+        log::info!("part {}: {}", part.index, part.name);
+        let result = match &datasets {
+            Some(datasets) => {
+                part.polygon.info();
+                compute(&name, &part.polygon, &datasets[k], options)
+            }
+            None => process_polygon(name.clone(), &part.polygon, options, registry),
+        };
+        results.push(result?);
+    }
+    let name = results[0].name.clone();
+    for (r, part) in results.iter_mut().zip(parts) {
+        r.name = format!("{}: {}", name, part.name);
+    }
+    pipeline::combine_parts(name, parts, results, options)
+}
 
-    log::trace!("gridpoints: {}", gridpoints.len());
+fn print_summary(data: &typst::Data) {
+    for part in &data.parts {
+        println!("part {}", part.name);
+        print_summary(part);
+        println!();
+    }
+    if !data.parts.is_empty() {
+        println!("all {} parts of {}", data.parts.len(), data.name);
+    }
+    let labels = data.labels();
+    println!(
+        "{:>8}: {} (geo crate)",
+        labels.geodesic.to_lowercase(),
+        data.area(data.geodesic2d)
+    );
+    println!(
+        "{:>8}: {} (geo crate)",
+        labels.planar,
+        data.area(data.planar2d)
+    );
+    println!(
+        "{:>8}: {} (authalic sphere)",
+        labels.sphere,
+        data.area(data.spherical2d)
+    );
+    match data.perimeter3d {
+        Some(perimeter3d) => println!(
+            "{:>8}: {} ({} {})",
+            labels.perimeter,
+            format::with_unit(data.perimeter2d, Quantity::Length),
+            format::with_unit(perimeter3d, Quantity::Length),
+            labels.on_terrain
+        ),
+        None => println!(
+            "{:>8}: {}",
+            labels.perimeter,
+            format::with_unit(data.perimeter2d, Quantity::Length)
+        ),
+    }
+    println!();
+    println!("{:>8}: {}", labels.flat, data.area(data.projected2d));
+    println!("{:>8}: {}", labels.surface, data.area(data.projected3d));
+    let ratio = data.projected3d / data.projected2d;
+    println!(
+        "{:>8}: {}",
+        labels.ratio,
+        format::signed((ratio - 1f64) * 100f64, Quantity::Percent)
+    );
+    println!();
+    println!(
+        "{:>8}: {} ({})",
+        labels.estimate,
+        data.area(data.geodesic3d),
+        labels.strategy(data.estimates.strategy)
+    );
+    println!(
+        "{}: {} {}",
+        labels.tile_edges,
+        format::with_unit(100.0 * data.edge_fraction, Quantity::Percent),
+        labels.of_boundary
+    );
+    println!("{}: {}", labels.projection, data.projection);
+    for (label, e) in [
+        (labels.highest, &data.extremes.highest),
+        (labels.lowest, &data.extremes.lowest),
+    ] {
+        if let Some(e) = e {
+            println!(
+                " {:>7}: {} {} lat {}, lon {}",
+                label,
+                labels.group(&format::with_unit(e.elevation, Quantity::Elevation)),
+                labels.at,
+                format::with_unit(e.lat, Quantity::Coordinate),
+                format::with_unit(e.lon, Quantity::Coordinate)
+            );
+        }
+    }
+    if let Some(reference) = &data.reference {
+        println!("reference: {}", reference);
+        if !reference.within_tolerance {
+            println!(
+                "WARNING: planar area outside the {}% tolerance",
+                reference.tolerance
+            );
+        }
+    }
+    if let Some(check) = &data.self_check {
+        println!("self-check: {}", check);
+    }
+    let paths = &data.clip_paths;
+    if paths.patched + paths.skipped > 0 {
+        println!(
+            "elevations: {} planes patched, {} skipped",
+            paths.patched, paths.skipped
+        );
+    }
+    if let Some(insolation) = &data.insolation {
+        println!(
+            "{}: {} ({} {}/{})",
+            labels.insolation.to_lowercase(),
+            data.area(insolation.weighted),
+            labels.sun_at,
+            format::with_unit(insolation.azimuth, Quantity::Angle),
+            format::with_unit(insolation.altitude, Quantity::Angle)
+        );
+        println!(
+            "  {}: {}",
+            labels.south_equivalent,
+            data.area(insolation.equivalent)
+        );
+    }
+    if let Some(breakdown) = &data.breakdown {
+        for (label, unit, bins) in [
+            (labels.elevation, " m", &breakdown.elevation),
+            (labels.slope_class, "%", &breakdown.slope),
+        ] {
+            println!(
+                "{:>12} {:>14} {:>14}",
+                label.to_lowercase(),
+                labels.flat,
+                labels.surface
+            );
+            for bin in bins.iter().filter(|b| b.area2d > 0.0) {
+                println!(
+                    "{:>12} {:>14} {:>14}",
+                    bin.range(unit),
+                    data.area(bin.area2d),
+                    data.area(bin.area3d)
+                );
+            }
+        }
+        println!("    note: {}", labels.breakdown_note);
+    }
+    for note in &data.notes {
+        println!("    note: {}", note);
+    }
+}
 
-    let gridvec: Vec<MercatorPoint> = gridpoints.into_iter().collect();
-    let gridtriangles = triangulation::grid::triangulate(&gridvec);
-    log::trace!("grid triangles: {}", gridtriangles.len());
+// the phases are printed as they end, see events()
+fn print_io_stats(data: &typst::Data) {
+    println!("clipping:");
+    println!("  {}", data.clip_paths);
+    println!("raster reads:");
+    for stats in &data.io_stats {
+        println!("  {}", stats);
+    }
+    println!("  {}", IoStats::total(&data.io_stats));
+}
 
-    let polygon = input_polygon.mercator();
+// The warnings of the computation, and with --timings every phase when it
+// ends, those of the tiles and parts too.
+fn events(timings: bool) -> Events {
+    Events::new(move |event| match event {
+        ComputeEvent::Warning(warning) => println!("WARNING: {}", warning),
+        ComputeEvent::Phase(phase) if timings => println!("phase {}", phase),
+        _ => {}
+    })
+}
 
-    let mut svg = svg::SVG::init(&input_polygon.mercatorbbox());
-    //svg.add_polygon(&input_polygon.mercatorbbox().as_vector(), "gray");
-    svg.add_polygon(&polygon, "gray");
-    let mut planes = Vec::new();
-    let mut projected3d = 0f64;
-    let mut projected2d = 0f64;
-    for (_i, gridtriangle) in gridtriangles.iter().enumerate() {
-        let plane = intersection::intersection(&polygon, &gridtriangle);
-        if plane.is_empty() {
-            continue;
+fn run(
+    path: &str,
+    options: &ComputeOptions,
+    registry: &DatasetRegistry,
+) -> Vec<Result<typst::Data, DatasetError>> {
+    let mut boundaries: Vec<_> = read_polygon::read_boundaries(path)
+        .into_iter()
+        .map(repair)
+        .collect();
+    if let Some(projection) = &options.projection {
+        for part in boundaries.iter_mut().flatten().flatten() {
+            if let Some(proj4) = projection.proj4(&part.polygon) {
+                part.polygon.proj4 = Some(proj4);
+            }
+        }
+    }
+    if let Some(tolerance) = options.simplify {
+        for part in boundaries.iter_mut().flatten().flatten() {
+            let simple = match part.polygon.simplified(tolerance) {
+                Ok(simple) => simple,
+                Err(e) => {
+                    log::warn!("{}: boundary not simplified: {}", part.name, e);
+                    continue;
+                }
+            };
+            let count = |polygon: &Polygon| {
+                polygon.wgs.len() + polygon.holes.iter().map(Vec::len).sum::<usize>()
+            };
+            let (before, after) = (part.polygon.planar_area(), simple.planar_area());
+            if let (Ok(before), Ok(after)) = (before, after) {
+                log::info!(
+                    "{}: boundary simplified ({} m), {} to {} points, planar area {} to {} ({})",
+                    part.name,
+                    tolerance,
+                    count(&part.polygon),
+                    count(&simple),
+                    AreaValue::new(before).render(options.units),
+                    AreaValue::new(after).render(options.units),
+                    format::signed(100.0 * (after / before - 1.0), Quantity::Percent)
+                );
+            }
+            part.polygon = simple;
+        }
+    }
+    if options.smooth_boundary > 0 {
+        for part in boundaries.iter_mut().flatten().flatten() {
+            let smooth = match part
+                .polygon
+                .smooth(options.smooth_boundary, options.smooth_max_shift)
+            {
+                Ok(smooth) => smooth,
+                Err(e) => {
+                    log::warn!("{}: boundary not smoothed: {}", part.name, e);
+                    continue;
+                }
+            };
+            let planar = |polygon: &Polygon| {
+                let ring = polygon.mercator().ok()?;
+                reference::planar_area(&ring).ok()
+            };
+            if let (Some(before), Some(after)) = (planar(&part.polygon), planar(&smooth)) {
+                log::info!(
+                    "{}: boundary smoothed ({} iterations), planar area {} to {} ({})",
+                    part.name,
+                    options.smooth_boundary,
+                    AreaValue::new(before).render(options.units),
+                    AreaValue::new(after).render(options.units),
+                    format::signed(100.0 * (after / before - 1.0), Quantity::Percent)
+                );
+            }
+            part.polygon = smooth;
         }
-        let a2d = polygon::calculate_3d_surface_area(&polygon::flat(&plane));
-        let a3d = polygon::calculate_3d_surface_area(&plane);
-        if a2d < 0.001 {
-            log::info!("remove artifact with area {:.4}", a2d);
+    }
+    boundaries
+        .into_iter()
+        .map(|parts| match parts?.as_slice() {
+            [part] => process_polygon(path.to_string(), &part.polygon, options, registry),
+            parts => process_parts(path.to_string(), parts, options, registry),
+        })
+        .collect()
+}
+
+// The parts without the issues of Polygon::validate, which the clip may
+// not survive: the lobes of a self-intersecting part become parts of their
+// own. A part that cannot be repaired fails its boundary.
+fn repair(parts: Vec<Part>) -> Result<Vec<Part>, DatasetError> {
+    let mut ret = Vec::new();
+    for part in parts {
+        let issues = part.polygon.validate();
+        if issues.is_empty() {
+            ret.push(part);
             continue;
         }
-        // let rat = 100.0 * (a3d / a2d - 1.0);
-        // log::trace!("plane area: {:6.2} {:6.2} +{:3.1}%", a3d, a2d, rat);
-        projected3d += a3d;
-        projected2d += a2d;
-        planes.push(plane.clone());
-        svg.add_polygon(&plane, &svg::color_for_slope(polygon::slope(&plane)));
-    }
-
-    log::trace!("planes: {}", planes.len());
-    let geodesic2d = reference::geodesic_area(&input_polygon.wgs);
-    let planar2d = reference::planar_area(&input_polygon.mercator());
-    println!("geodesic: {:.1} (geo crate)", geodesic2d);
-    println!("  planar: {:.1} (geo crate)", planar2d);
-    println!();
-    println!("    flat: {:.1}", projected2d);
-    println!(" surface: {:.1}", projected3d);
-    let ratio = projected3d / projected2d;
-    println!("   ratio: +{:.1}%", (ratio - 1f64) * 100f64);
-    println!();
-    let geodesic3d = ratio * reference::geodesic_area(&input_polygon.wgs);
-    println!("estimate: {:.1}", geodesic3d);
-
-    //svg.add_polygon(&polygon, "none");
-    //svg.add_triangles(&atoms, true);
-    //svg.add_triangles(&triangulation::polygon::triangulate(&polygon), true);
-    //svg.add_triangles(&gridtriangles, false);
-    std::fs::write("/tmp/triangles.svg", svg.render()).unwrap();
-    typst::Data {
-        name: Path::new(&name)
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string(),
-        geodesic2d,
-        planar2d,
-        projected2d,
-        projected3d,
-        geodesic3d,
-        svg: svg.render(),
-        nplanes: planes.len(),
+        for issue in &issues {
+            log::info!("{}: {}", part.name, issue);
+        }
+        let lobes = match part.polygon.repaired() {
+            Ok(lobes) => lobes,
+            Err(e) => {
+                log::error!("{}: cannot be repaired: {}", part.name, e);
+                return Err(e.into());
+            }
+        };
+        let n = lobes.len();
+        for (k, polygon) in lobes.into_iter().enumerate() {
+            let name = if n == 1 {
+                part.name.clone()
+            } else {
+                format!("{} ({})", part.name, k + 1)
+            };
+            ret.push(Part {
+                index: 0,
+                name,
+                polygon,
+            });
+        }
+    }
+    for (k, part) in ret.iter_mut().enumerate() {
+        part.index = k + 1;
+    }
+    Ok(ret)
+}
+
+// The exit code, or the error writing the output.
+fn run_points(
+    input: &str,
+    out: &str,
+    projected: bool,
+    options: &ComputeOptions,
+    registry: &DatasetRegistry,
+) -> std::io::Result<i32> {
+    let at = |e: std::io::Error| std::io::Error::new(e.kind(), format!("{}: {}", out, e));
+    let file = std::io::BufWriter::new(std::fs::File::create(out).map_err(at)?);
+    let format = pointcloud::Format::from_path(out);
+    let mut writer = PointWriter::new(file, format, projected).map_err(at)?;
+    let sample = options.sample_options();
+    let mut ok = true;
+    let mut projections = BTreeSet::new();
+    for polygon in read_polygon::read_polyline(input) {
+        projections.insert(polygon.projection());
+        let datasets = match select(&[&polygon], options, registry) {
+            Ok(mut datasets) => datasets.remove(0),
+            Err(e) => {
+                println!("  FAILED: {}: {}", input, e);
+                ok = false;
+                continue;
+            }
+        };
+        pointcloud::export(&polygon, &datasets, &sample, &mut writer).map_err(at)?;
+    }
+    println!("{} points written to {}", writer.rows(), out);
+    writer.finish().map_err(at)?;
+    // lon,lat are WGS84, the sidecar tells the projection of x,y
+    if projected && let Some(proj4) = projections.first() {
+        if projections.len() > 1 {
+            log::warn!(
+                "{}: x,y of the polygons are in {} different projections",
+                out,
+                projections.len()
+            );
+        }
+        Provenance::projected(proj4)
+            .write_sidecar(Path::new(out))
+            .map_err(at)?;
+    }
+    Ok(if ok { 0 } else { EXIT_FAILED })
+}
+
+// Runs the jobs on `threads` workers, each writes its figures next to the summary.
+fn run_batch(
+    manifest: &str,
+    threads: usize,
+    summary: &str,
+    units: Units,
+    registry: &DatasetRegistry,
+) -> i32 {
+    let jobs = match batch::read_manifest(Path::new(manifest)) {
+        Ok(jobs) => jobs,
+        Err(e) => {
+            println!("FAILED: {}", e);
+            return EXIT_FAILED;
+        }
+    };
+    let figures = Path::new(summary).parent().unwrap_or(Path::new("."));
+    let results = batch::run_jobs(
+        &jobs,
+        threads,
+        |index, job| {
+            let input = job.input.to_string_lossy().to_string();
+            let results = run(&input, &job.options, registry);
+            let stem = job.input.file_stem().unwrap_or_default().to_string_lossy();
+            for (k, d) in results.iter().flatten().enumerate() {
+                let name = match k {
+                    0 => format!("{:03}-{}.svg", index + 1, stem),
+                    _ => format!("{:03}-{}-{}.svg", index + 1, stem, k),
+                };
+                std::fs::write(figures.join(name), &d.svg).unwrap();
+            }
+            results
+        },
+        |line| println!("{}", line),
+    );
+
+    let mut data = Vec::new();
+    let mut rows = Vec::new();
+    for (job, r) in jobs.iter().zip(results) {
+        let input = job.input.to_string_lossy().to_string();
+        let r = match r {
+            Ok(r) => r,
+            Err(message) => {
+                println!("  FAILED: {}: {}", input, message);
+                rows.push(SummaryRow::failed(&input, &message));
+                continue;
+            }
+        };
+        for result in r {
+            match result {
+                Ok(d) => {
+                    rows.extend(SummaryRow::rows(&input, &d));
+                    data.push(d);
+                }
+                Err(e) => {
+                    println!("  FAILED: {}: {}", input, e);
+                    rows.push(SummaryRow::failed(&input, &e.to_string()));
+                }
+            }
+        }
+    }
+    batch::write_summary(Path::new(summary), &rows, units).unwrap();
+    let doc = typst::make_typst_document(&data);
+    std::fs::write("/tmp/document.typst", &doc).unwrap();
+    let failed = rows.iter().filter(|r| r.error.is_some()).count();
+    println!(
+        "{} of {} results ok, summary in {}",
+        rows.len() - failed,
+        rows.len(),
+        summary
+    );
+    exit_code(
+        failed > 0,
+        rows.iter().any(|r| r.within_tolerance == Some(false)),
+    )
+}
+
+fn exit_code(failed: bool, outside_reference: bool) -> i32 {
+    if failed {
+        EXIT_FAILED
+    } else if outside_reference {
+        EXIT_REFERENCE
+    } else {
+        0
     }
 }
 
 #[derive(Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
     paths: Vec<String>,
+    #[command(flatten)]
+    options: ComputeOptions,
+    /// Directory searched for DEM files (.hgt, .tif, .asc) in any layout, repeatable.
+    /// Also an https:// or s3:// URL, or a *.manifest.toml listing URLs with their box.
+    /// Defaults to $DEM_DIRS, then to dem_dirs in ~/.config/surface-area/config.toml
+    #[arg(long, global = true)]
+    dem_dir: Vec<PathBuf>,
+    /// Write a self-contained interactive HTML report
+    #[arg(long)]
+    html: Option<String>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Write the elevation samples inside the polygons as a point cloud
+    Points {
+        input: String,
+        /// Output file, space separated if the extension is .xyz, CSV otherwise
+        #[arg(long)]
+        out: String,
+        /// Also write the projected x,y
+        #[arg(long)]
+        projected: bool,
+        // the sampling options of the computation (--ignore-mask, --nodata,
+        // --decimate, --fill-voids, --dsm-filter) apply
+        #[command(flatten)]
+        options: Box<ComputeOptions>,
+    },
+    /// Run the jobs of a TOML manifest
+    Batch {
+        manifest: String,
+        /// Number of jobs running in parallel
+        #[arg(long, default_value_t = 1)]
+        jobs: usize,
+        /// Summary of all jobs, JSON if the extension is .json, CSV otherwise
+        #[arg(long, default_value = "summary.csv")]
+        summary: String,
+        /// Unit of the areas in the CSV summary (the JSON summary is in m²)
+        #[arg(long, value_enum, default_value_t = Units::Auto)]
+        units: Units,
+    },
 }
 
 fn main() {
-    env_logger::init();
+    // the warnings (skipped tiles, ignored options) are printed by default
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
     let args = Cli::parse();
+    let registry = match DatasetRegistry::from_sources(&args.dem_dir) {
+        Ok(registry) => registry,
+        // the datasets are given, nothing is searched
+        Err(_) if !args.options.dataset.is_empty() => DatasetRegistry::new(Vec::new()),
+        Err(e) => {
+            println!("FAILED: {}", e);
+            std::process::exit(EXIT_FAILED);
+        }
+    };
+    if let Some(command) = &args.command {
+        let code = match command {
+            Command::Batch {
+                manifest,
+                jobs,
+                summary,
+                units,
+            } => run_batch(manifest, *jobs, summary, *units, &registry),
+            Command::Points {
+                input,
+                out,
+                projected,
+                options,
+            } => match run_points(input, out, *projected, options, &registry) {
+                Ok(code) => code,
+                Err(e) => {
+                    println!("FAILED: {}", e);
+                    EXIT_FAILED
+                }
+            },
+        };
+        std::process::exit(code);
+    }
+    let mut options = args.options.clone();
+    options.events = events(options.timings);
     let mut data: Vec<typst::Data> = Vec::new();
+    let mut failed = Vec::new();
     for path in &args.paths {
-        let input_polygons = read_polygon::read_polyline(&path);
-        let ldata: Vec<_> = input_polygons
-            .iter()
-            .map(|p| process(path.clone(), &p))
-            .collect();
-        data.extend_from_slice(&ldata);
+        for result in run(path, &options, &registry) {
+            match result {
+                Ok(d) => {
+                    print_summary(&d);
+                    if options.timings {
+                        print_io_stats(&d);
+                    }
+                    data.push(d);
+                }
+                Err(e) => {
+                    log::error!("{}: {}", path, e);
+                    println!("  FAILED: {}: {}", path, e);
+                    failed.push(path.clone());
+                }
+            }
+        }
+    }
+    if let Some(d) = data.last() {
+        std::fs::write("/tmp/triangles.svg", &d.svg).unwrap();
     }
     let doc = typst::make_typst_document(&data);
     std::fs::write("/tmp/document.typst", &doc).unwrap();
+    if let Some(path) = &args.html {
+        // the typst document lists the parts itself, the page takes them flat
+        let flat: Vec<typst::Data> = data
+            .iter()
+            .flat_map(|d| d.parts.iter().chain(std::iter::once(d)))
+            .cloned()
+            .collect();
+        std::fs::write(path, html::make_html_document(&flat)).unwrap();
+    }
+    if !failed.is_empty() {
+        println!("{} of {} inputs failed", failed.len(), args.paths.len());
+    }
+    let outside = data
+        .iter()
+        .any(|d| d.reference.as_ref().is_some_and(|r| !r.within_tolerance));
+    let self_check_failed = data
+        .iter()
+        .any(|d| d.self_check.as_ref().is_some_and(|c| !c.passed()));
+    std::process::exit(exit_code(!failed.is_empty() || self_check_failed, outside));
 }
@@ -1,38 +1,306 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+
 use crate::point::{MercatorPoint, WGS84Point};
 
+// beyond it from the central meridian, a point is outside of its UTM zone
+const UTM_HALF_WIDTH: f64 = 3.0;
+
+#[derive(Debug)]
+pub struct ProjectionError(pub String);
+
+impl fmt::Display for ProjectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "projection: {}", self.0)
+    }
+}
+
+impl std::error::Error for ProjectionError {}
+
 pub struct WebMercatorProjection {
     wgs84: proj4rs::proj::Proj,
     dst: proj4rs::proj::Proj,
     dst_spec: String,
+    // the central meridian of a UTM projection
+    meridian: Option<f64>,
+    // a point outside of the area of use was reported
+    warned: AtomicBool,
+}
+
+// the central meridian of "+proj=utm +zone=32 ..."
+fn utm_meridian(spec: &str) -> Option<f64> {
+    if !spec.split_whitespace().any(|w| w == "+proj=utm") {
+        return None;
+    }
+    let zone: f64 = spec
+        .split_whitespace()
+        .find_map(|w| w.strip_prefix("+zone="))?
+        .parse()
+        .ok()?;
+    Some(6.0 * zone - 183.0)
+}
+
+// the proj4 string of the WGS84 UTM zones, of WGS84 and of its
+// pseudo-Mercator
+pub fn epsg_proj4(code: u32) -> Option<String> {
+    let utm = |zone: u32, south: &str| {
+        format!(
+            "+proj=utm +zone={} +datum=WGS84 +units=m +no_defs +type=crs{}",
+            zone, south
+        )
+    };
+    match code {
+        4326 => Some("+proj=longlat +datum=WGS84 +no_defs".to_string()),
+        3857 => Some(
+            "+proj=merc +a=6378137 +b=6378137 +lat_ts=0 +lon_0=0 +x_0=0 +y_0=0 +k=1 \
+             +units=m +nadgrids=@null +no_defs"
+                .to_string(),
+        ),
+        32601..=32660 => Some(utm(code - 32600, "")),
+        32701..=32760 => Some(utm(code - 32700, " +south")),
+        _ => None,
+    }
+}
+
+// the input of the transform, if the point is a position
+fn radians(wgs: &WGS84Point) -> Result<(f64, f64), ProjectionError> {
+    if !wgs.lon.is_finite() || !wgs.lat.is_finite() || wgs.lat.abs() > 90.0 {
+        return Err(ProjectionError(format!("{}: not a position", wgs)));
+    }
+    Ok((wgs.lon.to_radians(), wgs.lat.to_radians()))
 }
 
 impl WebMercatorProjection {
+    // for the proj4 strings made by the crate itself, which are valid
     pub fn make(dst_spec: &str) -> WebMercatorProjection {
-        assert!(!dst_spec.is_empty());
+        Self::from_proj4(dst_spec).unwrap()
+    }
+
+    pub fn from_proj4(dst_spec: &str) -> Result<Self, ProjectionError> {
         use proj4rs::proj::Proj;
-        let dst = Proj::from_proj_string(dst_spec).unwrap();
+        if dst_spec.trim().is_empty() {
+            return Err(ProjectionError("empty proj4 string".to_string()));
+        }
+        let dst = Proj::from_proj_string(dst_spec)
+            .map_err(|e| ProjectionError(format!("{}: {}", dst_spec, e)))?;
         let wgs84_spec = "+proj=longlat +ellps=WGS84 +datum=WGS84 +no_defs";
         let wgs84 = Proj::from_proj_string(wgs84_spec).unwrap();
-        WebMercatorProjection {
-            wgs84: wgs84,
-            dst: dst,
+        Ok(WebMercatorProjection {
+            wgs84,
+            dst,
             dst_spec: dst_spec.to_string(),
+            meridian: utm_meridian(dst_spec),
+            warned: AtomicBool::new(false),
+        })
+    }
+
+    pub fn from_epsg(code: u32) -> Result<Self, ProjectionError> {
+        let spec = epsg_proj4(code).ok_or_else(|| {
+            ProjectionError(format!("EPSG:{} is not known, give its proj4 string", code))
+        })?;
+        Self::from_proj4(&spec)
+    }
+
+    // whether the projection is meant for the point: within the zone of a
+    // UTM projection, anywhere for the others
+    pub fn in_area_of_use(&self, wgs: &WGS84Point) -> bool {
+        match self.meridian {
+            Some(meridian) => {
+                let offset = (wgs.lon - meridian + 180.0).rem_euclid(360.0) - 180.0;
+                offset.abs() <= UTM_HALF_WIDTH + 1e-9
+            }
+            None => true,
         }
     }
-    pub fn project(&self, wgs: &WGS84Point) -> MercatorPoint {
-        if wgs.to_utm_proj4() != self.dst_spec {
+
+    pub fn spec(&self) -> &str {
+        &self.dst_spec
+    }
+
+    // warns once about a point outside of the area of use
+    fn check(&self, wgs: &WGS84Point) {
+        if !self.in_area_of_use(wgs) && !self.warned.swap(true, Ordering::Relaxed) {
             log::warn!(
-                "UTM zone mismatch: [{}] vs [{}]",
-                wgs.to_utm_proj4(),
+                "{} is more than {}° from the central meridian of [{}]",
+                wgs,
+                UTM_HALF_WIDTH,
                 self.dst_spec
             );
         }
-        let mut p = (wgs.lon.to_radians(), wgs.lat.to_radians());
-        proj4rs::transform::transform(&self.wgs84, &self.dst, &mut p).unwrap();
-        MercatorPoint {
+    }
+
+    // fails on a point out of the projection's domain, or not a position
+    pub fn project(&self, wgs: &WGS84Point) -> Result<MercatorPoint, ProjectionError> {
+        let fail = |reason: String| ProjectionError(format!("{}: {}", wgs, reason));
+        let mut p = radians(wgs)?;
+        self.check(wgs);
+        proj4rs::transform::transform(&self.wgs84, &self.dst, &mut p)
+            .map_err(|e| fail(e.to_string()))?;
+        if !p.0.is_finite() || !p.1.is_finite() {
+            return Err(fail(format!("out of [{}]", self.dst_spec)));
+        }
+        Ok(MercatorPoint {
             x: p.0,
             y: p.1,
             ele: wgs.ele,
+        })
+    }
+    // project on all the points, transformed in one buffer; one by one to
+    // tell the point that fails
+    pub fn project_many(&self, wgs: &[WGS84Point]) -> Result<Vec<MercatorPoint>, ProjectionError> {
+        let mut coords: Vec<(f64, f64)> = Vec::with_capacity(wgs.len());
+        for p in wgs {
+            coords.push(radians(p)?);
+            self.check(p);
+        }
+        let transformed =
+            proj4rs::transform::transform(&self.wgs84, &self.dst, coords.as_mut_slice());
+        if transformed.is_err() || coords.iter().any(|p| !p.0.is_finite() || !p.1.is_finite()) {
+            return wgs.iter().map(|p| self.project(p)).collect();
+        }
+        Ok(coords
+            .iter()
+            .zip(wgs)
+            .map(|(p, w)| MercatorPoint {
+                x: p.0,
+                y: p.1,
+                ele: w.ele,
+            })
+            .collect())
+    }
+    // fails on a point out of the projection's domain, or not a position
+    pub fn unproject(&self, m: &MercatorPoint) -> Result<WGS84Point, ProjectionError> {
+        let fail = |reason: String| ProjectionError(format!("{}: {}", m, reason));
+        let mut p = (m.x, m.y);
+        proj4rs::transform::transform(&self.dst, &self.wgs84, &mut p)
+            .map_err(|e| fail(e.to_string()))?;
+        if !p.0.is_finite() || !p.1.is_finite() {
+            return Err(fail(format!("out of [{}]", self.dst_spec)));
+        }
+        Ok(WGS84Point {
+            lon: p.0.to_degrees(),
+            lat: p.1.to_degrees(),
+            ele: m.ele,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(lon: f64, lat: f64) -> WGS84Point {
+        WGS84Point {
+            lon,
+            lat,
+            ele: None,
+        }
+    }
+
+    #[test]
+    fn test_constructors() {
+        assert!(WebMercatorProjection::from_proj4("").is_err());
+        assert!(WebMercatorProjection::from_proj4("+proj=nonsense").is_err());
+        assert!(WebMercatorProjection::from_epsg(1234).is_err());
+        let zone32 = WebMercatorProjection::from_epsg(32632).unwrap();
+        assert_eq!(zone32.dst_spec, point(9.0, 46.0).to_utm_proj4());
+        let p = zone32.project(&point(9.0, 46.0)).unwrap();
+        assert!((p.x - 500000.0).abs() < 1e-6);
+        let south = WebMercatorProjection::from_epsg(32719).unwrap();
+        assert!(south.project(&point(-69.0, -10.0)).unwrap().y > 8000000.0);
+        let pseudo = WebMercatorProjection::from_epsg(3857).unwrap();
+        let back = pseudo
+            .unproject(&pseudo.project(&point(7.5, 46.5)).unwrap())
+            .unwrap();
+        assert!((back.lon - 7.5).abs() < 1e-9 && (back.lat - 46.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_area_of_use() {
+        // zone 19, around -69°
+        let zone19 = WebMercatorProjection::make(&point(-69.0, 18.9).to_utm_proj4());
+        assert!(zone19.in_area_of_use(&point(-69.1, 18.9)));
+        assert!(zone19.in_area_of_use(&point(-72.0, 18.9)));
+        assert!(!zone19.in_area_of_use(&point(-72.5, 18.9)));
+        // any zone is as good as zone 19
+        let zone32 = WebMercatorProjection::make(&point(7.5, 46.5).to_utm_proj4());
+        assert!(zone32.in_area_of_use(&point(7.5, 46.5)));
+        assert!(!zone32.in_area_of_use(&point(-69.1, 18.9)));
+        // across the antimeridian
+        let zone1 = WebMercatorProjection::from_epsg(32601).unwrap();
+        assert!(zone1.in_area_of_use(&point(180.0, 10.0)));
+        assert!(!zone1.in_area_of_use(&point(179.5, 10.0)));
+        let laea = WebMercatorProjection::make(&point(7.5, 46.5).to_laea_proj4());
+        assert!(laea.in_area_of_use(&point(-69.1, 18.9)));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        // zones 1, 19, 32 and 60, both hemispheres
+        for (meridian, lat0) in [(-177.0, 10.0), (-69.0, 18.0), (9.0, -40.0), (177.0, 60.0)] {
+            let projection = WebMercatorProjection::make(&point(meridian, lat0).to_utm_proj4());
+            for i in 0..7 {
+                for j in 0..7 {
+                    let mut wgs = point(meridian - 3.0 + i as f64, lat0 + j as f64);
+                    wgs.ele = Some(100.0 * i as f64);
+                    let back = projection
+                        .unproject(&projection.project(&wgs).unwrap())
+                        .unwrap();
+                    assert!((back.lon - wgs.lon).abs() < 1e-9, "{} {}", wgs, back);
+                    assert!((back.lat - wgs.lat).abs() < 1e-9, "{} {}", wgs, back);
+                    assert_eq!(back.ele, wgs.ele);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_project_many() {
+        // a 100 × 100 grid of 3 arc-seconds, see benches/projection.rs for
+        // the timings
+        let step = 1.0 / 1200.0;
+        let grid: Vec<WGS84Point> = (0..10_000)
+            .map(|k| {
+                point(
+                    7.0 + (k % 100) as f64 * step,
+                    46.0 + (k / 100) as f64 * step,
+                )
+            })
+            .collect();
+        let projection = WebMercatorProjection::make(&grid[0].to_utm_proj4());
+        let many = projection.project_many(&grid).unwrap();
+        let single: Vec<MercatorPoint> = grid
+            .iter()
+            .map(|p| projection.project(p).unwrap())
+            .collect();
+        assert_eq!(many, single);
+    }
+
+    #[test]
+    fn test_unprojectable() {
+        let projection = WebMercatorProjection::make(&point(7.5, 46.5).to_utm_proj4());
+        let pseudo = WebMercatorProjection::from_epsg(3857).unwrap();
+        for bad in [
+            point(7.5, 95.0),
+            point(f64::NAN, 46.5),
+            point(7.5, f64::INFINITY),
+        ] {
+            let e = projection.project(&bad).unwrap_err();
+            assert!(e.to_string().contains(&bad.to_string()), "{}", e);
+            let ring = [point(7.5, 46.5), bad.clone(), point(7.6, 46.6)];
+            let e = projection.project_many(&ring).unwrap_err();
+            assert!(e.to_string().contains(&bad.to_string()), "{}", e);
+        }
+        // the pole is beyond the pseudo-Mercator
+        let ring = [point(7.5, 46.5), point(7.5, 90.0)];
+        let e = pseudo.project_many(&ring).unwrap_err();
+        assert!(e.to_string().contains(&ring[1].to_string()), "{}", e);
+        assert!(pseudo.project_many(&ring[..1]).is_ok());
+        // and back: not a position, or beyond the inverse of the UTM zone
+        for (x, y) in [(f64::NAN, 5e6), (5e5, f64::INFINITY), (1e30, 1e30)] {
+            let bad = MercatorPoint { x, y, ele: None };
+            let e = projection.unproject(&bad).unwrap_err();
+            assert!(e.to_string().contains(&bad.to_string()), "{}", e);
         }
     }
 }
@@ -0,0 +1,292 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use geo::Intersects;
+
+use crate::point::MercatorPoint;
+use crate::provenance::Provenance;
+use crate::triangulation::{Mesh, Triangle};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    // Wavefront, v, vn and f lines
+    Obj,
+    // ASCII Stanford polygon file
+    Ply,
+}
+
+impl Format {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "obj" => Some(Format::Obj),
+            "ply" => Some(Format::Ply),
+            _ => None,
+        }
+    }
+}
+
+// The triangles of the mesh that intersect the polygon, with only the
+// vertices they use.
+pub fn restrict(mesh: &Mesh, mask: &geo::Polygon) -> Mesh {
+    let mut index = vec![None; mesh.vertices.len()];
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    for (k, t) in mesh.triangles.iter().enumerate() {
+        let Triangle(a, b, c) = mesh.triangle(k);
+        let coord = |p: MercatorPoint| geo::Coord { x: p.x, y: p.y };
+        let triangle = geo::Triangle::new(coord(a), coord(b), coord(c));
+        if !mask.intersects(&triangle) {
+            continue;
+        }
+        triangles.push(t.map(|v| {
+            *index[v as usize].get_or_insert_with(|| {
+                vertices.push(mesh.vertices[v as usize].clone());
+                (vertices.len() - 1) as u32
+            })
+        }));
+    }
+    Mesh::new(vertices, triangles)
+}
+
+// The minimum of the box of the vertices, subtracted from their coordinates
+// to keep the numbers small.
+pub fn origin(mesh: &Mesh) -> [f64; 2] {
+    mesh.vertices
+        .iter()
+        .fold([f64::INFINITY, f64::INFINITY], |[x, y], p| {
+            [x.min(p.x), y.min(p.y)]
+        })
+}
+
+// (x, y, ele) of the vertices, the elevation-less ones at 0.
+fn coordinates(mesh: &Mesh, offset: [f64; 2]) -> Vec<[f64; 3]> {
+    let missing = mesh.vertices.iter().filter(|p| p.ele.is_none()).count();
+    if missing > 0 {
+        log::warn!("{} vertices without elevation exported at 0", missing);
+    }
+    mesh.vertices
+        .iter()
+        .map(|p| [p.x - offset[0], p.y - offset[1], p.ele.unwrap_or(0.0)])
+        .collect()
+}
+
+// The faces as they are, with the normals of the vertices if any.
+pub fn write_obj(
+    path: &Path,
+    mesh: &Mesh,
+    offset: [f64; 2],
+    normals: Option<&[(f64, f64, f64)]>,
+) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    for [x, y, z] in coordinates(mesh, offset) {
+        writeln!(out, "v {:.3} {:.3} {:.3}", x, y, z)?;
+    }
+    for (x, y, z) in normals.unwrap_or_default() {
+        writeln!(out, "vn {:.6} {:.6} {:.6}", x, y, z)?;
+    }
+    // the indices of obj start at 1, a vertex and its normal have the same
+    for [a, b, c] in &mesh.triangles {
+        if normals.is_some() {
+            writeln!(out, "f {0}//{0} {1}//{1} {2}//{2}", a + 1, b + 1, c + 1)?;
+        } else {
+            writeln!(out, "f {} {} {}", a + 1, b + 1, c + 1)?;
+        }
+    }
+    out.flush()
+}
+
+// The faces as they are, with the normals of the vertices if any.
+pub fn write_ply(
+    path: &Path,
+    mesh: &Mesh,
+    offset: [f64; 2],
+    normals: Option<&[(f64, f64, f64)]>,
+) -> std::io::Result<()> {
+    let mut out = BufWriter::new(File::create(path)?);
+    writeln!(out, "ply")?;
+    writeln!(out, "format ascii 1.0")?;
+    writeln!(out, "comment surface-area {}", env!("CARGO_PKG_VERSION"))?;
+    writeln!(out, "element vertex {}", mesh.vertices.len())?;
+    for axis in ["x", "y", "z"] {
+        writeln!(out, "property double {}", axis)?;
+    }
+    if normals.is_some() {
+        for axis in ["nx", "ny", "nz"] {
+            writeln!(out, "property double {}", axis)?;
+        }
+    }
+    writeln!(out, "element face {}", mesh.len())?;
+    writeln!(out, "property list uchar int vertex_indices")?;
+    writeln!(out, "end_header")?;
+    for (k, [x, y, z]) in coordinates(mesh, offset).into_iter().enumerate() {
+        match normals {
+            Some(normals) => {
+                let (nx, ny, nz) = normals[k];
+                writeln!(
+                    out,
+                    "{:.3} {:.3} {:.3} {:.6} {:.6} {:.6}",
+                    x, y, z, nx, ny, nz
+                )?
+            }
+            None => writeln!(out, "{:.3} {:.3} {:.3}", x, y, z)?,
+        }
+    }
+    for [a, b, c] in &mesh.triangles {
+        writeln!(out, "3 {} {} {}", a, b, c)?;
+    }
+    out.flush()
+}
+
+// Writes the mesh in the format of the extension of the path, re-centered on
+// the minimum of its box or not, with its provenance sidecar. The faces are
+// turned up (see Mesh::orient_up), the vertices have their smooth normals
+// unless the shading is flat: the viewers then shade each face on its own.
+pub fn write(
+    path: &Path,
+    mesh: &Mesh,
+    proj4: &str,
+    recenter: bool,
+    flat_shading: bool,
+) -> std::io::Result<()> {
+    let offset = if recenter && !mesh.vertices.is_empty() {
+        origin(mesh)
+    } else {
+        [0.0, 0.0]
+    };
+    // with the elevations as written
+    let written: Vec<MercatorPoint> = mesh
+        .vertices
+        .iter()
+        .map(|p| MercatorPoint {
+            ele: Some(p.ele.unwrap_or(0.0)),
+            ..p.clone()
+        })
+        .collect();
+    let mut oriented = Mesh::new(written, mesh.triangles.clone());
+    let reversed = oriented.orient_up();
+    if reversed > 0 {
+        log::debug!("{} faces of the mesh turned up", reversed);
+    }
+    let normals = (!flat_shading).then(|| oriented.vertex_normals());
+    let mesh = Mesh::new(mesh.vertices.clone(), oriented.triangles);
+    match Format::from_path(path) {
+        Some(Format::Obj) => write_obj(path, &mesh, offset, normals.as_deref())?,
+        Some(Format::Ply) => write_ply(path, &mesh, offset, normals.as_deref())?,
+        None => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "unknown mesh format, expected .obj or .ply",
+            ));
+        }
+    }
+    let mut provenance = Provenance::projected(proj4);
+    provenance.offset = offset;
+    provenance.write_sidecar(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64, ele: Option<f64>) -> MercatorPoint {
+        MercatorPoint { x, y, ele }
+    }
+
+    // two triangles of a 10 m square, one corner without elevation
+    fn square() -> Mesh {
+        let vertices = vec![
+            point(1000.0, 2000.0, Some(5.0)),
+            point(1010.0, 2000.0, Some(6.0)),
+            point(1010.0, 2010.0, None),
+            point(1000.0, 2010.0, Some(7.0)),
+        ];
+        Mesh::new(vertices, vec![[0, 1, 2], [0, 2, 3]])
+    }
+
+    #[test]
+    fn test_write_and_parse() {
+        let mesh = square();
+        let dir = std::env::temp_dir();
+
+        let path = dir.join("surface-area-mesh.obj");
+        write(&path, &mesh, "+proj=merc", true, false).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<Vec<&str>> = text.lines().map(|l| l.split(' ').collect()).collect();
+        let count = |kind: &str| lines.iter().filter(|l| l[0] == kind).count();
+        assert_eq!((count("v"), count("vn"), count("f")), (4, 4, 2));
+        let vertices: Vec<_> = lines.iter().filter(|l| l[0] == "v").collect();
+        let faces: Vec<_> = lines.iter().filter(|l| l[0] == "f").collect();
+        assert_eq!(*vertices[0], ["v", "0.000", "0.000", "5.000"]);
+        assert_eq!(*vertices[2], ["v", "10.000", "10.000", "0.000"]);
+        assert_eq!(*faces[1], ["f", "1//1", "3//3", "4//4"]);
+        let provenance = Provenance::read_sidecar(&path).unwrap();
+        assert_eq!(provenance.offset, [1000.0, 2000.0]);
+        std::fs::remove_file(Provenance::sidecar(&path)).ok();
+        std::fs::remove_file(&path).ok();
+
+        let path = dir.join("surface-area-mesh.ply");
+        write(&path, &mesh, "+proj=merc", false, true).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let (header, body) = text.split_once("end_header\n").unwrap();
+        let count = |element: &str| -> usize {
+            let prefix = format!("element {} ", element);
+            let line = header.lines().find(|l| l.starts_with(&prefix)).unwrap();
+            line[prefix.len()..].parse().unwrap()
+        };
+        assert_eq!((count("vertex"), count("face")), (4, 2));
+        let body: Vec<&str> = body.lines().collect();
+        assert_eq!(body.len(), 4 + 2);
+        assert_eq!(body[1], "1010.000 2000.000 6.000");
+        assert_eq!(body[4], "3 0 1 2");
+        std::fs::remove_file(Provenance::sidecar(&path)).ok();
+        std::fs::remove_file(&path).ok();
+
+        let path = dir.join("surface-area-mesh.stl");
+        assert!(write(&path, &mesh, "+proj=merc", false, false).is_err());
+    }
+
+    #[test]
+    fn test_orientation_and_normals() {
+        // the second triangle facing down
+        let mut mesh = square();
+        mesh.triangles[1] = [0, 3, 2];
+        let path = std::env::temp_dir().join("surface-area-normals.ply");
+        write(&path, &mesh, "+proj=merc", true, false).unwrap();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let (header, body) = text.split_once("end_header\n").unwrap();
+        assert!(header.contains("property double nz"));
+        let rows: Vec<Vec<f64>> = body
+            .lines()
+            .map(|l| l.split(' ').map(|v| v.parse().unwrap()).collect())
+            .collect();
+        let (vertices, faces) = rows.split_at(4);
+        for v in vertices {
+            let length = (v[3] * v[3] + v[4] * v[4] + v[5] * v[5]).sqrt();
+            assert!((length - 1.0).abs() < 1e-5, "{:?}", v);
+            assert!(v[5] > 0.0);
+        }
+        for f in faces {
+            let p = |k: usize| &vertices[f[k] as usize];
+            let (u, v) = (
+                [p(2)[0] - p(1)[0], p(2)[1] - p(1)[1]],
+                [p(3)[0] - p(1)[0], p(3)[1] - p(1)[1]],
+            );
+            assert!(u[0] * v[1] - u[1] * v[0] > 0.0, "{:?}", f);
+        }
+        std::fs::remove_file(Provenance::sidecar(&path)).ok();
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn test_restrict() {
+        let mesh = square();
+        // a strip across the lower edge, below the diagonal
+        let mask = geo::Rect::new((1006.0, 1990.0), (1020.0, 2002.0)).to_polygon();
+        let restricted = restrict(&mesh, &mask);
+        assert_eq!(restricted.triangles, [[0, 1, 2]]);
+        assert_eq!(restricted.vertices.len(), 3);
+        assert_eq!(origin(&restricted), [1000.0, 2000.0]);
+    }
+}
@@ -0,0 +1,122 @@
+// Grayscale morphology on row-major rasters with a validity flag per pixel.
+// Invalid pixels are ignored by the filters and keep their value.
+
+// min (erode) or max (dilate) over the (2 radius + 1)² square around every
+// pixel, as two separable passes
+fn filter(
+    values: &[f64],
+    valid: &[bool],
+    xsize: usize,
+    radius: usize,
+    pick: fn(f64, f64) -> f64,
+) -> Vec<f64> {
+    let ysize = values.len() / xsize;
+    let pass = |input: &[f64], horizontal: bool| -> Vec<f64> {
+        let mut output = input.to_vec();
+        for row in 0..ysize {
+            for col in 0..xsize {
+                let k = row * xsize + col;
+                if !valid[k] {
+                    continue;
+                }
+                let (position, size) = if horizontal {
+                    (col, xsize)
+                } else {
+                    (row, ysize)
+                };
+                let lo = position.saturating_sub(radius);
+                let hi = (position + radius).min(size - 1);
+                let mut acc = input[k];
+                for p in lo..=hi {
+                    let n = if horizontal {
+                        row * xsize + p
+                    } else {
+                        p * xsize + col
+                    };
+                    if valid[n] {
+                        acc = pick(acc, input[n]);
+                    }
+                }
+                output[k] = acc;
+            }
+        }
+        output
+    };
+    let rows = pass(values, true);
+    pass(&rows, false)
+}
+
+// Erosion then dilation: removes positive features narrower than the
+// window (trees, buildings) and keeps the wider terrain shapes.
+pub fn opening(values: &[f64], valid: &[bool], xsize: usize, radius: usize) -> Vec<f64> {
+    assert_eq!(values.len(), valid.len());
+    if radius == 0 || xsize == 0 {
+        return values.to_vec();
+    }
+    let eroded = filter(values, valid, xsize, radius, f64::min);
+    filter(&eroded, valid, xsize, radius, f64::max)
+}
+
+// the filter radius in pixels for a radius in meters, at least one pixel
+pub fn radius_in_pixels(meters: f64, cell_size: f64) -> usize {
+    ((meters / cell_size).round() as usize).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 30;
+
+    // flat ground at 100 m with square "trees" of the given width and height
+    fn ground(trees: &[(usize, usize)], width: usize, height: f64) -> Vec<f64> {
+        let mut values = vec![100.0; SIZE * SIZE];
+        for (col, row) in trees {
+            for r in *row..row + width {
+                for c in *col..col + width {
+                    values[r * SIZE + c] += height;
+                }
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn test_opening_removes_trees() {
+        let values = ground(&[(3, 4), (12, 20), (25, 25)], 3, 15.0);
+        let valid = vec![true; values.len()];
+        // a 5 × 5 window is wider than the 3 pixel trees
+        let opened = opening(&values, &valid, SIZE, 2);
+        assert!(opened.iter().all(|v| *v == 100.0));
+        // a 3 × 3 window is not
+        let opened = opening(&values, &valid, SIZE, 1);
+        assert_eq!(opened, values);
+    }
+
+    #[test]
+    fn test_opening_keeps_wide_features() {
+        let values = ground(&[(5, 5)], 12, 20.0);
+        let valid = vec![true; values.len()];
+        assert_eq!(opening(&values, &valid, SIZE, 2), values);
+    }
+
+    #[test]
+    fn test_invalid_pixels() {
+        let mut values = ground(&[(10, 10)], 3, 15.0);
+        let mut valid = vec![true; values.len()];
+        // a void inside a tree keeps its value and does not pull the ground
+        values[11 * SIZE + 11] = -9999.0;
+        valid[11 * SIZE + 11] = false;
+        let opened = opening(&values, &valid, SIZE, 2);
+        assert_eq!(opened[11 * SIZE + 11], -9999.0);
+        assert_eq!(opened[10 * SIZE + 10], 100.0);
+        assert!(opened.iter().all(|v| *v == 100.0 || *v == -9999.0));
+    }
+
+    #[test]
+    fn test_radius_in_pixels() {
+        assert_eq!(radius_in_pixels(10.0, 30.0), 1);
+        assert_eq!(radius_in_pixels(10.0, 1.0), 10);
+        assert_eq!(radius_in_pixels(2.4, 0.5), 5);
+    }
+}
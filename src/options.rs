@@ -0,0 +1,638 @@
+use std::str::FromStr;
+
+use clap::{Args, ValueEnum};
+use serde::{Deserialize, Serialize};
+
+use crate::area::{AreaArg, Units};
+use crate::dataset::{CandidateFilter, SampleOptions};
+use crate::download;
+use crate::estimate::EstimateStrategy;
+use crate::events::Events;
+use crate::labels::Lang;
+use crate::mercator::{self, WebMercatorProjection};
+use crate::polygon::Polygon;
+
+// What to do with triangles spanning skipped (masked or nodata) pixels.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum VoidPolicy {
+    // drop them, their flat area is reported as discarded
+    Exclude,
+    // count them, but carry them separately as low-confidence planes
+    Flag,
+    // count them as any other triangle
+    Keep,
+}
+
+// Where the elevations of the boundary vertices come from.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+#[non_exhaustive]
+pub enum BoundaryElevation {
+    // those of the input file (GPX, GeoJSON, absolute KML altitudes) where
+    // it has them, the DEM for the others
+    FromFile,
+    // interpolated on the DEM
+    FromDem,
+}
+
+// How values are mapped to the color ramps of the figures.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Ramp {
+    Linear,
+    // by rank: every color covers the same share of the planes
+    Quantile,
+}
+
+// Bands of the slope GeoTIFF besides the slope.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum ExtraBand {
+    // downhill direction, clockwise from north in degrees
+    Aspect,
+    // 3D/2D area ratio of the plane
+    Ratio,
+}
+
+// How the SRTM .hgt tiles are read.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum IoBackend {
+    Gdal,
+    // memory-mapped, a tile that cannot be is an error
+    Mmap,
+    // memory-mapped, through GDAL if that fails
+    Auto,
+}
+
+// Ground filter for surface models (DSM), "opening-radius=R" with R in meters.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct DsmFilter {
+    pub opening_radius: f64,
+}
+
+impl FromStr for DsmFilter {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(("opening-radius", r)) = s.trim().split_once('=') else {
+            return Err(format!("{}: expected opening-radius=R", s));
+        };
+        match r.trim().parse::<f64>() {
+            Ok(r) if r > 0.0 => Ok(DsmFilter { opening_radius: r }),
+            _ => Err(format!(
+                "{}: the radius must be a positive number of meters",
+                s
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for DsmFilter {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<DsmFilter> for String {
+    fn from(f: DsmFilter) -> String {
+        format!("opening-radius={}", f.opening_radius)
+    }
+}
+
+// The projection of the planar stage: "utm" (the zone of the polygon's
+// center), "laea" (Lambert azimuthal equal-area on its center), "epsg:CODE"
+// or a proj4 string.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ProjectionChoice {
+    Utm,
+    Laea,
+    Epsg(u32),
+    Proj4(String),
+}
+
+impl ProjectionChoice {
+    // the proj4 string for the polygon, None for its own UTM zone
+    pub fn proj4(&self, polygon: &Polygon) -> Option<String> {
+        match self {
+            ProjectionChoice::Utm => None,
+            ProjectionChoice::Laea => Some(polygon.wgsbbox().center().to_laea_proj4()),
+            ProjectionChoice::Epsg(code) => mercator::epsg_proj4(*code),
+            ProjectionChoice::Proj4(spec) => Some(spec.clone()),
+        }
+    }
+}
+
+impl FromStr for ProjectionChoice {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let lower = s.to_ascii_lowercase();
+        let choice = match lower.as_str() {
+            "utm" => return Ok(ProjectionChoice::Utm),
+            "laea" => return Ok(ProjectionChoice::Laea),
+            _ => match lower.strip_prefix("epsg:") {
+                Some(code) => ProjectionChoice::Epsg(
+                    code.parse()
+                        .map_err(|_| format!("{}: expected epsg:CODE", s))?,
+                ),
+                None => ProjectionChoice::Proj4(s.to_string()),
+            },
+        };
+        let spec = match &choice {
+            ProjectionChoice::Epsg(code) => mercator::epsg_proj4(*code)
+                .ok_or_else(|| format!("{}: unknown code, give its proj4 string", s))?,
+            _ => s.to_string(),
+        };
+        if spec.split_whitespace().any(|w| w == "+proj=longlat") {
+            return Err(format!("{}: not a projection in meters", s));
+        }
+        WebMercatorProjection::from_proj4(&spec).map_err(|e| e.to_string())?;
+        Ok(choice)
+    }
+}
+
+impl TryFrom<String> for ProjectionChoice {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<ProjectionChoice> for String {
+    fn from(p: ProjectionChoice) -> String {
+        match p {
+            ProjectionChoice::Utm => "utm".to_string(),
+            ProjectionChoice::Laea => "laea".to_string(),
+            ProjectionChoice::Epsg(code) => format!("epsg:{}", code),
+            ProjectionChoice::Proj4(spec) => spec,
+        }
+    }
+}
+
+// A nodata value for the datasets, "V" for all of them or "FILE=V" for the
+// dataset of that file name. It adds to the value declared by the band.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NodataOverride {
+    pub dataset: Option<String>,
+    pub value: f64,
+}
+
+impl FromStr for NodataOverride {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (dataset, value) = match s.rsplit_once('=') {
+            Some((dataset, value)) => (Some(dataset.trim().to_string()), value),
+            None => (None, s),
+        };
+        match value.trim().parse::<f64>() {
+            Ok(value) if dataset.as_ref().is_none_or(|d| !d.is_empty()) => {
+                Ok(NodataOverride { dataset, value })
+            }
+            _ => Err(format!("{}: expected a value or FILE=VALUE", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for NodataOverride {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<NodataOverride> for String {
+    fn from(n: NodataOverride) -> String {
+        match n.dataset {
+            Some(dataset) => format!("{}={}", dataset, n.value),
+            None => n.value.to_string(),
+        }
+    }
+}
+
+// Where the steepest-descent line starts: "from=highest", the highest sample
+// of the polygon, or "from=LON,LAT".
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum FlowlineStart {
+    Highest,
+    Point { lon: f64, lat: f64 },
+}
+
+impl FromStr for FlowlineStart {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some(("from", start)) = s.trim().split_once('=') else {
+            return Err(format!("{}: expected from=LON,LAT or from=highest", s));
+        };
+        if start.trim() == "highest" {
+            return Ok(FlowlineStart::Highest);
+        }
+        let point = start
+            .split_once(',')
+            .and_then(|(lon, lat)| Some((lon.trim().parse().ok()?, lat.trim().parse().ok()?)));
+        match point {
+            Some((lon, lat))
+                if (-180.0..=180.0).contains(&lon) && (-90.0..=90.0).contains(&lat) =>
+            {
+                Ok(FlowlineStart::Point { lon, lat })
+            }
+            _ => Err(format!("{}: expected from=LON,LAT in degrees", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for FlowlineStart {
+    type Error = String;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<FlowlineStart> for String {
+    fn from(f: FlowlineStart) -> String {
+        match f {
+            FlowlineStart::Highest => "from=highest".to_string(),
+            FlowlineStart::Point { lon, lat } => format!("from={},{}", lon, lat),
+        }
+    }
+}
+
+// The options of a single computation. They are both command line arguments
+// and manifest entries, so that the two cannot drift apart.
+#[derive(Args, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+#[non_exhaustive]
+pub struct ComputeOptions {
+    /// Sample every pixel, even those flagged invalid by the mask band
+    #[arg(long)]
+    pub ignore_mask: bool,
+    /// Polygons smaller than this many DEM cells are flagged as low-confidence
+    #[arg(long, default_value_t = 10.0)]
+    pub min_cells: f64,
+    /// Warn when the polygon extends beyond its UTM zone by more than this, in degrees
+    #[arg(long, default_value_t = 0.5)]
+    pub zone_margin: f64,
+    /// Split the polygon into tiles with their own equal-area projection
+    #[arg(long)]
+    pub large_polygon: bool,
+    /// Tile size of the large-polygon mode, in degrees
+    #[arg(long, default_value_t = 0.5)]
+    pub tile_degrees: f64,
+    /// Quick estimate from a stratified subset of the DEM samples
+    #[arg(long)]
+    pub preview: bool,
+    /// Quick estimate from every N-th DEM cell in both directions
+    #[arg(long, default_value_t = 1)]
+    pub decimate: usize,
+    /// Merge adjacent planes that lie in the same plane within tolerances
+    #[arg(long)]
+    pub merge_coplanar: bool,
+    /// Maximal angle between merged plane normals, in degrees
+    #[arg(long, default_value_t = 0.5)]
+    pub merge_angle: f64,
+    /// Maximal offset between merged planes, in meters
+    #[arg(long, default_value_t = 0.1)]
+    pub merge_offset: f64,
+    /// Report the insolation-weighted area for a solar position
+    #[arg(long)]
+    pub insolation: bool,
+    /// Solar azimuth in degrees from north (default: equinox solar noon)
+    #[arg(long)]
+    pub sun_azimuth: Option<f64>,
+    /// Solar altitude in degrees (default: equinox solar noon)
+    #[arg(long)]
+    pub sun_altitude: Option<f64>,
+    /// Break the surface area down by elevation band and slope class
+    #[arg(long)]
+    pub breakdown: bool,
+    /// Height of the elevation bands of the breakdown, in meters
+    #[arg(long, default_value_t = 100.0)]
+    pub breakdown_band: f64,
+    /// Upper edges of the slope classes of the breakdown, in percent
+    #[arg(long, value_delimiter = ',', default_values_t = [10.0, 20.0, 30.0, 50.0, 100.0])]
+    pub breakdown_slopes: Vec<f64>,
+    /// Projection of the planar stage: utm (the zone of the polygon, by default), laea
+    /// (equal-area on the polygon), epsg:CODE or a proj4 string
+    #[arg(long)]
+    pub projection: Option<ProjectionChoice>,
+    /// Round the corners of digitized boundaries, in Chaikin iterations
+    #[arg(long, default_value_t = 0)]
+    pub smooth_boundary: usize,
+    /// Largest distance a corner cut moves away from a vertex, in meters
+    #[arg(long, default_value_t = 10.0)]
+    pub smooth_max_shift: f64,
+    /// Drop the boundary vertices within this many meters of the simplified line (Douglas-Peucker)
+    #[arg(long)]
+    pub simplify: Option<f64>,
+    /// Insert the boundary into the triangulation (constrained Delaunay) instead of clipping the triangles
+    #[arg(long)]
+    pub constrained: bool,
+    /// Drop the grid triangles with a smaller angle, in degrees (0: keep them)
+    #[arg(long, default_value_t = 1.0)]
+    pub min_triangle_angle: f64,
+    /// Drop the grid triangles with a smaller flat area, in m² (default: a thousandth of the squared grid spacing)
+    #[arg(long)]
+    pub min_triangle_area: Option<f64>,
+    /// Split the steep grid triangles at their edge midpoints, sampled on the DEM
+    #[arg(long)]
+    pub refine: bool,
+    /// Slope above which the refinement splits a triangle, in percent
+    #[arg(long, default_value_t = 100.0)]
+    pub refine_slope: f64,
+    /// Number of times the refinement splits a triangle at most
+    #[arg(long, default_value_t = 3)]
+    pub refine_depth: usize,
+    /// Triangles spanning masked or nodata pixels
+    #[arg(long, value_enum, default_value_t = VoidPolicy::Flag)]
+    pub void_triangles: VoidPolicy,
+    /// Elevations of the boundary vertices: from the input file where it has them, or the DEM
+    #[arg(long, value_enum, default_value_t = BoundaryElevation::FromDem)]
+    pub boundary_elevation: BoundaryElevation,
+    /// Color ramp of the slope and elevation figures
+    #[arg(long, value_enum, default_value_t = Ramp::Linear)]
+    pub ramp: Ramp,
+    /// Registered area to compare with, e.g. 2ha, 1.5acre, 12000m2
+    #[arg(long)]
+    pub reference_area: Option<AreaArg>,
+    /// Allowed deviation of the planar area from the reference, in percent
+    #[arg(long, default_value_t = 5.0)]
+    pub reference_tolerance: f64,
+    /// Unit of the printed areas
+    #[arg(long, value_enum, default_value_t = Units::Auto)]
+    pub units: Units,
+    /// Language of the reports and of the printed summary
+    #[arg(long, value_enum, default_value_t = Lang::En)]
+    pub lang: Lang,
+    /// How the headline 3D area on the ellipsoid is derived, both are in the JSON
+    #[arg(long, value_enum, default_value_t = EstimateStrategy::Ratio)]
+    pub estimate: EstimateStrategy,
+    /// Write the slope of the planes as a GeoTIFF at the sampling resolution
+    #[arg(long)]
+    pub export_slope_tif: Option<String>,
+    /// Extra bands of the slope GeoTIFF, e.g. aspect,ratio
+    #[arg(long, value_enum, value_delimiter = ',')]
+    pub export_bands: Vec<ExtraBand>,
+    /// Trace the steepest-descent line, from=LON,LAT or from=highest
+    #[arg(long, allow_hyphen_values = true)]
+    pub flowline: Option<FlowlineStart>,
+    /// Write the steepest-descent line as GeoJSON
+    #[arg(long)]
+    pub export_flowline: Option<String>,
+    /// Write the triangulated terrain as a mesh, .obj or .ply
+    #[arg(long)]
+    pub export_mesh: Option<String>,
+    /// Subtract the minimum of the box of the mesh from its coordinates
+    #[arg(long)]
+    pub export_mesh_recenter: bool,
+    /// Write only the triangles of the mesh that intersect the polygon
+    #[arg(long)]
+    pub export_mesh_clip: bool,
+    /// Write the mesh without vertex normals, for the viewers to shade each face flat
+    #[arg(long)]
+    pub flat_shading: bool,
+    /// Suppress trees and buildings of surface models, e.g. opening-radius=10 (meters)
+    #[arg(long)]
+    pub dsm_filter: Option<DsmFilter>,
+    /// Fill nodata cells by inverse distance weighting of the valid ones within this radius (meters)
+    #[arg(long)]
+    pub fill_voids: Option<f64>,
+    /// Check the clipping on flattened terrain: the 3D, flat and planar areas must agree
+    #[arg(long)]
+    pub self_check: bool,
+    /// Print the raster reads of every dataset: windows, pixels, bytes and time
+    #[arg(long)]
+    pub timings: bool,
+    /// Print the elevation statistics of every dataset in the box of the polygon
+    #[arg(long)]
+    pub stats: bool,
+    /// Nodata value besides the declared one, e.g. -9999, or N46E007.tif=0 for one file
+    #[arg(long, allow_hyphen_values = true)]
+    pub nodata: Vec<NodataOverride>,
+    /// Write every plane of the figure as its own polygon instead of one outline per color
+    #[arg(long)]
+    pub svg_polygons: bool,
+    /// DEM file to use instead of searching the DEM directories, repeatable
+    #[arg(long)]
+    pub dataset: Vec<String>,
+    /// Use only the candidate datasets whose path matches the glob, e.g. '*NASADEM*'
+    #[arg(long)]
+    pub include_pattern: Vec<String>,
+    /// Skip the candidate datasets whose path matches the glob
+    #[arg(long)]
+    pub exclude_pattern: Vec<String>,
+    /// Among copies of the same tile, keep the one whose path matches the glob
+    #[arg(long)]
+    pub prefer_pattern: Vec<String>,
+    /// How SRTM .hgt tiles are read: through GDAL or memory-mapped
+    #[arg(long, value_enum, default_value_t = IoBackend::Auto)]
+    pub io: IoBackend,
+    /// Download the SRTM tiles missing from the DEM directories into the first one
+    #[arg(long)]
+    pub download: bool,
+    /// URL of the tiles to download, {tile} is e.g. N46E006 and {lat} N46; file:// for a mirror on disk
+    #[arg(long, default_value = download::DEFAULT_URL)]
+    pub download_url: String,
+    // the progress callback of embedding applications
+    #[arg(skip)]
+    #[serde(skip)]
+    pub events: Events,
+}
+
+// must agree with the clap defaults above
+impl Default for ComputeOptions {
+    fn default() -> Self {
+        ComputeOptions {
+            ignore_mask: false,
+            min_cells: 10.0,
+            zone_margin: 0.5,
+            large_polygon: false,
+            tile_degrees: 0.5,
+            preview: false,
+            decimate: 1,
+            merge_coplanar: false,
+            merge_angle: 0.5,
+            merge_offset: 0.1,
+            insolation: false,
+            sun_azimuth: None,
+            sun_altitude: None,
+            breakdown: false,
+            breakdown_band: 100.0,
+            breakdown_slopes: vec![10.0, 20.0, 30.0, 50.0, 100.0],
+            projection: None,
+            smooth_boundary: 0,
+            smooth_max_shift: 10.0,
+            simplify: None,
+            constrained: false,
+            min_triangle_angle: 1.0,
+            min_triangle_area: None,
+            refine: false,
+            refine_slope: 100.0,
+            refine_depth: 3,
+            void_triangles: VoidPolicy::Flag,
+            boundary_elevation: BoundaryElevation::FromDem,
+            ramp: Ramp::Linear,
+            reference_area: None,
+            reference_tolerance: 5.0,
+            units: Units::Auto,
+            lang: Lang::En,
+            estimate: EstimateStrategy::Ratio,
+            export_slope_tif: None,
+            export_bands: Vec::new(),
+            flowline: None,
+            export_flowline: None,
+            export_mesh: None,
+            export_mesh_recenter: false,
+            export_mesh_clip: false,
+            flat_shading: false,
+            dsm_filter: None,
+            fill_voids: None,
+            self_check: false,
+            timings: false,
+            stats: false,
+            nodata: Vec::new(),
+            svg_polygons: false,
+            dataset: Vec::new(),
+            include_pattern: Vec::new(),
+            exclude_pattern: Vec::new(),
+            prefer_pattern: Vec::new(),
+            io: IoBackend::Auto,
+            download: false,
+            download_url: download::DEFAULT_URL.to_string(),
+            events: Events::default(),
+        }
+    }
+}
+
+impl ComputeOptions {
+    pub fn candidate_filter(&self) -> CandidateFilter {
+        CandidateFilter {
+            include: self.include_pattern.clone(),
+            exclude: self.exclude_pattern.clone(),
+            prefer: self.prefer_pattern.clone(),
+        }
+    }
+
+    // how the datasets are sampled
+    pub fn sample_options(&self) -> SampleOptions {
+        SampleOptions {
+            ignore_mask: self.ignore_mask,
+            opening_radius: self.dsm_filter.map(|f| f.opening_radius),
+            nodata: self.nodata.clone(),
+            decimate: self.decimate,
+            fill_radius: self.fill_voids,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        options: ComputeOptions,
+    }
+
+    #[test]
+    fn test_defaults_agree() {
+        let cli = Cli::parse_from(["surface-area"]);
+        assert_eq!(cli.options, ComputeOptions::default());
+        let parsed: ComputeOptions = serde_json::from_str("{}").unwrap();
+        assert_eq!(parsed, ComputeOptions::default());
+    }
+
+    #[test]
+    fn test_dsm_filter() {
+        let cli = Cli::parse_from(["surface-area", "--dsm-filter", "opening-radius=7.5"]);
+        assert_eq!(cli.options.dsm_filter.unwrap().opening_radius, 7.5);
+        assert!("opening-radius=-1".parse::<DsmFilter>().is_err());
+        assert!("closing-radius=3".parse::<DsmFilter>().is_err());
+        let parsed: ComputeOptions =
+            serde_json::from_str(r#"{"dsm_filter": "opening-radius=3"}"#).unwrap();
+        assert_eq!(parsed.dsm_filter.unwrap().opening_radius, 3.0);
+    }
+
+    #[test]
+    fn test_flowline_start() {
+        let cli = Cli::parse_from(["surface-area", "--flowline", "from=-70.5,-12.25"]);
+        assert_eq!(
+            cli.options.flowline,
+            Some(FlowlineStart::Point {
+                lon: -70.5,
+                lat: -12.25
+            })
+        );
+        assert_eq!("from=highest".parse(), Ok(FlowlineStart::Highest));
+        assert!("from=6.5".parse::<FlowlineStart>().is_err());
+        assert!("from=6.5,95".parse::<FlowlineStart>().is_err());
+        assert!("to=highest".parse::<FlowlineStart>().is_err());
+        let parsed: ComputeOptions =
+            serde_json::from_str(r#"{"flowline": "from=highest"}"#).unwrap();
+        assert_eq!(parsed.flowline, Some(FlowlineStart::Highest));
+    }
+
+    #[test]
+    fn test_nodata() {
+        let cli = Cli::parse_from([
+            "surface-area",
+            "--nodata",
+            "-9999",
+            "--nodata",
+            "old/N46E007.tif=0",
+        ]);
+        let nodata = &cli.options.nodata;
+        assert_eq!(nodata[0].dataset, None);
+        assert_eq!(nodata[0].value, -9999.0);
+        assert_eq!(nodata[1].dataset.as_deref(), Some("old/N46E007.tif"));
+        assert_eq!(nodata[1].value, 0.0);
+        assert!("=3".parse::<NodataOverride>().is_err());
+        assert!("a.tif=sea".parse::<NodataOverride>().is_err());
+        let parsed: ComputeOptions = serde_json::from_str(r#"{"nodata": ["a.tif=-1"]}"#).unwrap();
+        assert_eq!(parsed.nodata[0].dataset.as_deref(), Some("a.tif"));
+    }
+
+    #[test]
+    fn test_projection() {
+        let cli = Cli::parse_from(["surface-area", "--projection", "laea"]);
+        assert_eq!(cli.options.projection, Some(ProjectionChoice::Laea));
+        assert_eq!("EPSG:32632".parse(), Ok(ProjectionChoice::Epsg(32632)));
+        assert_eq!("utm".parse(), Ok(ProjectionChoice::Utm));
+        assert!("epsg:4326".parse::<ProjectionChoice>().is_err());
+        assert!("epsg:1234".parse::<ProjectionChoice>().is_err());
+        assert!("mercator".parse::<ProjectionChoice>().is_err());
+        let parsed: ComputeOptions =
+            serde_json::from_str(r#"{"projection": "+proj=utm +zone=32 +datum=WGS84"}"#).unwrap();
+        let polygon = Polygon::new(
+            [(7.0, 46.0), (7.5, 46.0), (7.5, 46.5), (7.0, 46.5)]
+                .iter()
+                .map(|&(lon, lat)| crate::point::WGS84Point {
+                    lon,
+                    lat,
+                    ele: None,
+                })
+                .collect(),
+        );
+        assert_eq!(
+            parsed.projection.unwrap().proj4(&polygon).as_deref(),
+            Some("+proj=utm +zone=32 +datum=WGS84")
+        );
+        assert_eq!(ProjectionChoice::Utm.proj4(&polygon), None);
+        // equal-area: the planar area is the geodesic one
+        let geodesic = crate::reference::geodesic_area(&polygon.wgs).unwrap();
+        let laea = Polygon::projected(polygon.wgs.clone(), ProjectionChoice::Laea.proj4(&polygon));
+        let planar = crate::reference::planar_area(&laea.mercator().unwrap()).unwrap();
+        assert!((planar - geodesic).abs() < 1e-4 * geodesic);
+    }
+}
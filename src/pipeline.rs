@@ -0,0 +1,1450 @@
+use core::fmt;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::time::Instant;
+
+use geo::{Contains, Coord, InteriorPoint};
+use serde::Serialize;
+
+use crate::area::{AreaValue, ReferenceComparison};
+use crate::breakdown::{self, Breakdown};
+use crate::dataset::{self, Dataset, DatasetError, SampleOptions};
+use crate::estimate::Estimates;
+use crate::events::{ComputeEvent, Events};
+use crate::extremes::Extremes;
+use crate::format::{self, Quantity};
+use crate::options::{BoundaryElevation, ComputeOptions, FlowlineStart, VoidPolicy};
+use crate::point::{MercatorPoint, WGS84BoundingBox, WGS84Point};
+use crate::polygon::Polygon;
+use crate::ramp::ColorScale;
+use crate::rasterio::IoStats;
+use crate::read_polygon::Part;
+use crate::selfcheck::SelfCheck;
+use crate::{
+    compute, edges, flowline, insolation, intersection, meshexport, preview, reference, refine,
+    slopemap, svg, triangulation, typst,
+};
+
+// target spacing of the bilinear subdivision for polygons below the DEM resolution
+pub const SUBDIVISION_TARGET: f64 = 3.0;
+
+// Samples of two datasets closer than this (in meters) are the same sample,
+// at positions that differ by the noise of the projections: the shared edge
+// of two tiles.
+const SEAM_TOLERANCE: f64 = 0.01;
+
+// The time spent in one stage of compute. A cached stage reused the result
+// of an earlier call.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Phase {
+    pub name: String,
+    pub seconds: f64,
+    pub cached: bool,
+}
+
+impl Phase {
+    fn new(name: &str, start: Instant, cached: bool) -> Self {
+        Phase {
+            name: name.to_string(),
+            seconds: start.elapsed().as_secs_f64(),
+            cached,
+        }
+    }
+
+    // the phases of results computed separately, summed by name
+    pub fn merge(phases: &mut Vec<Phase>, other: &Phase) {
+        match phases.iter_mut().find(|p| p.name == other.name) {
+            Some(p) => {
+                p.seconds += other.seconds;
+                p.cached &= other.cached;
+            }
+            None => phases.push(other.clone()),
+        }
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {:.3}s", self.name, self.seconds)?;
+        if self.cached {
+            write!(f, " (cached)")?;
+        }
+        Ok(())
+    }
+}
+
+// What the samples depend on, besides the window.
+#[derive(PartialEq)]
+struct TerrainKey {
+    datasets: Vec<String>,
+    projection: String,
+    sample: SampleOptions,
+}
+
+struct Terrain {
+    key: TerrainKey,
+    window: WGS84BoundingBox,
+    // the samples with their geographic position, for the extremes
+    samples: Vec<(WGS84Point, MercatorPoint)>,
+    // sorted, without duplicates
+    points: Vec<MercatorPoint>,
+    voids: Vec<MercatorPoint>,
+    // the nodata pixels filled while sampling
+    filled: usize,
+    // the triangulation of all the points, built on the first full run, the
+    // thresholds of its degenerate triangles and the triangles they dropped
+    mesh: Option<(Degenerate, triangulation::Mesh, triangulation::Mesh)>,
+}
+
+// The samples and the mesh of the last computation, reused when the next
+// polygon (an edited boundary) lies within the sampled window. The window
+// is the bounding box of the polygon grown by `padding` times its larger
+// side, so that small edits stay inside of it.
+pub struct TerrainCache {
+    terrain: Option<Terrain>,
+    pub padding: f64,
+}
+
+impl TerrainCache {
+    pub fn new(padding: f64) -> Self {
+        TerrainCache {
+            terrain: None,
+            padding,
+        }
+    }
+
+    fn fits(&self, key: &TerrainKey, pbbox: &WGS84BoundingBox) -> bool {
+        match &self.terrain {
+            Some(t) => t.key == *key && t.window.contains_other(pbbox),
+            None => false,
+        }
+    }
+
+    fn window(&self, pbbox: &WGS84BoundingBox) -> WGS84BoundingBox {
+        let margin =
+            self.padding * (pbbox.max.lon - pbbox.min.lon).max(pbbox.max.lat - pbbox.min.lat);
+        let mut ret = pbbox.clone();
+        ret.min.lon -= margin;
+        ret.min.lat -= margin;
+        ret.max.lon += margin;
+        ret.max.lat += margin;
+        ret
+    }
+}
+
+// The whole computation for one polygon on the given datasets: sampling,
+// triangulation, clipping, the reference areas and the figure.
+pub fn compute(
+    name: &str,
+    input_polygon: &Polygon,
+    datasets: &[Dataset],
+    options: &ComputeOptions,
+) -> Result<typst::Data, DatasetError> {
+    compute_cached(
+        name,
+        input_polygon,
+        datasets,
+        options,
+        &mut TerrainCache::new(0.0),
+    )
+}
+
+// compute, with the samples and the mesh taken from the cache when they
+// cover the polygon. The cache is refilled otherwise.
+pub fn compute_cached(
+    name: &str,
+    input_polygon: &Polygon,
+    datasets: &[Dataset],
+    options: &ComputeOptions,
+    cache: &mut TerrainCache,
+) -> Result<typst::Data, DatasetError> {
+    let events = &options.events;
+    events.emit(|| ComputeEvent::Datasets(datasets.iter().map(|d| d.filename.clone()).collect()));
+    let pbbox = input_polygon.wgsbbox();
+    let mut sample = options.sample_options();
+    // the finest dataset
+    let Some(&(cell_size, _)) = dataset::by_cell_size(datasets).first() else {
+        return Err(DatasetError::NoDatasetCoverage {
+            bbox: pbbox,
+            directories: Vec::new(),
+            tiles: input_polygon.required_tiles().into_iter().collect(),
+        });
+    };
+    let polygon_area = input_polygon.planar_area()?;
+    let low_confidence = polygon_area < options.min_cells * cell_size * cell_size;
+    if low_confidence {
+        sample.subdivide = (cell_size / SUBDIVISION_TARGET).ceil() as usize;
+        if sample.decimate > 1 {
+            log::warn!(
+                "--decimate is ignored below {} DEM cells",
+                options.min_cells
+            );
+            sample.decimate = 1;
+        }
+        log::warn!(
+            "polygon area {} is below {} DEM cells of {} × {}",
+            AreaValue::new(polygon_area).render(options.units),
+            options.min_cells,
+            format::with_unit(cell_size, Quantity::Length),
+            format::with_unit(cell_size, Quantity::Length)
+        );
+        events.emit(|| {
+            ComputeEvent::Warning(format!(
+                "polygon ({}) is smaller than {} DEM cells ({}), result is low-confidence, \
+                 cells are subdivided bilinearly {} times (~{})",
+                AreaValue::new(polygon_area).render(options.units),
+                options.min_cells,
+                format::with_unit(cell_size, Quantity::Length),
+                sample.subdivide,
+                format::with_unit(cell_size / sample.subdivide as f64, Quantity::Length)
+            ))
+        });
+    }
+    let (overhang, scale) = input_polygon.zone_overhang();
+    if overhang > options.zone_margin {
+        log::warn!(
+            "polygon extends {:.2}° beyond its UTM zone, scale error {}",
+            overhang,
+            format::with_unit(100.0 * scale, Quantity::Percent)
+        );
+        events.emit(|| {
+            ComputeEvent::Warning(format!(
+                "polygon extends {:.2}° beyond its UTM zone, scale error up to {} \
+                 (consider --large-polygon)",
+                overhang,
+                format::with_unit(100.0 * scale, Quantity::Percent)
+            ))
+        });
+    }
+    if sample.decimate > 1 {
+        log::warn!(
+            "every {}th DEM cell in both directions (--decimate)",
+            sample.decimate
+        );
+        events.emit(|| {
+            ComputeEvent::Warning(format!(
+                "every {}th DEM cell in both directions (--decimate), result is an estimate",
+                sample.decimate
+            ))
+        });
+    }
+    let mut polygon = input_polygon.mercator()?;
+    let mut holes = input_polygon.mercator_holes()?;
+    let mask = intersection::to_geo_polygon_with_holes(&polygon, &holes);
+    let mut phases = Vec::new();
+    let key = TerrainKey {
+        datasets: datasets.iter().map(|d| d.filename.clone()).collect(),
+        projection: input_polygon.projection(),
+        sample: sample.clone(),
+    };
+    let start = Instant::now();
+    let cached = cache.fits(&key, &pbbox);
+    if !cached {
+        let window = cache.window(&pbbox);
+        let mut samples = Vec::new();
+        let mut progress = |done, total| events.emit(|| ComputeEvent::Sampling { done, total });
+        let filled: usize = datasets.iter().map(Dataset::filled).sum();
+        let voids = visit_datasets_reporting(
+            datasets,
+            &window,
+            &sample,
+            |wgs, m| samples.push((wgs.clone(), m)),
+            &mut progress,
+        );
+        // the first sample at a position wins: another elevation there comes
+        // from an overlap that was not recognized as a copy of the tile
+        let mut points: BTreeSet<MercatorPoint> = BTreeSet::new();
+        let mut conflicts = 0;
+        for (_, m) in &samples {
+            if let Some(first) = points.get(m)
+                && first.ele != m.ele
+            {
+                conflicts += 1;
+            }
+            points.insert(m.clone());
+        }
+        if conflicts > 0 {
+            log::warn!(
+                "{} samples at the position of another one with another elevation, dropped",
+                conflicts
+            );
+        }
+        cache.terrain = Some(Terrain {
+            key,
+            window,
+            samples,
+            points: points.into_iter().collect(),
+            voids,
+            filled: datasets.iter().map(Dataset::filled).sum::<usize>() - filled,
+            mesh: None,
+        });
+    }
+    let terrain = cache.terrain.as_mut().unwrap();
+    push_phase(&mut phases, Phase::new("sampling", start, cached), events);
+    let mut extremes = Extremes::default();
+    for (wgs, m) in &terrain.samples {
+        if mask.contains(&Coord::from((m.x, m.y))) {
+            extremes.update(wgs, m);
+        }
+    }
+    let voids = &terrain.voids;
+    let filled = terrain.filled;
+
+    log::trace!("gridpoints: {}", terrain.points.len());
+
+    let mut notes = Vec::new();
+
+    let edge_fraction = edges::fraction_on_edges(&polygon, datasets);
+    log::info!(
+        "{} of the boundary within a cell of a dataset edge",
+        format::with_unit(100.0 * edge_fraction, Quantity::Percent)
+    );
+    if edge_fraction > edges::EDGE_FRACTION_WARNING {
+        notes.push(format!(
+            "{} of the boundary runs along DEM tile edges, where the tiles may disagree; \
+             consider resampling the tiles to a common grid",
+            format::with_unit(100.0 * edge_fraction, Quantity::Percent)
+        ));
+    }
+    if options.boundary_elevation == BoundaryElevation::FromDem {
+        without_elevations(&mut polygon, &mut holes);
+    } else {
+        let recorded = polygon
+            .iter()
+            .chain(holes.iter().flatten())
+            .filter(|p| p.ele.is_some())
+            .count();
+        log::info!(
+            "boundary: {} of {} vertices with an elevation from the file",
+            recorded,
+            polygon.len() + holes.iter().map(|h| h.len()).sum::<usize>()
+        );
+        if recorded == 0 {
+            notes.push(
+                "the boundary has no elevations, they are interpolated on the DEM".to_string(),
+            );
+        }
+    }
+
+    let full_count = terrain.points.len();
+    let start = Instant::now();
+    let rings: Vec<&[MercatorPoint]> = std::iter::once(polygon.as_slice())
+        .chain(holes.iter().map(|h| h.as_slice()))
+        .collect();
+    // the elevation of the DEM between the samples, of the finer datasets
+    // first as when sampling
+    let order: Vec<&Dataset> = dataset::by_cell_size(datasets)
+        .into_iter()
+        .map(|(_, d)| d)
+        .collect();
+    let elevation = |m: &MercatorPoint| {
+        order
+            .iter()
+            .find_map(|d| d.elevation_at(&d.projector().unproject(m).ok()?, &sample))
+    };
+    // the preview subset and the constrained triangulation depend on the
+    // polygon, their mesh is not kept
+    let (owned_mesh, owned_dropped);
+    let mut constraints = None;
+    let (gridtriangles, dropped, sampled) = if options.preview || options.constrained {
+        let gridvec;
+        let points = if options.preview {
+            gridvec =
+                preview::stratified_sample(&terrain.points, &polygon, preview::PREVIEW_BUDGET);
+            log::info!("preview: {} of {} samples", gridvec.len(), full_count);
+            &gridvec
+        } else {
+            &terrain.points
+        };
+        let mut mesh = if options.constrained {
+            let (mesh, edges) =
+                triangulation::grid::triangulate_constrained(points, &rings, &elevation);
+            log::info!("{} boundary edges in the triangulation", edges.len());
+            constraints = Some(edges);
+            mesh
+        } else {
+            triangulation::grid::triangulate(points)
+        };
+        owned_dropped = drop_degenerate(
+            &mut mesh,
+            options,
+            constraints.as_deref().unwrap_or_default(),
+        );
+        owned_mesh = mesh;
+        if !options.preview {
+            push_phase(
+                &mut phases,
+                Phase::new("triangulation", start, false),
+                events,
+            );
+        }
+        (&owned_mesh, &owned_dropped, points.len())
+    } else {
+        let degenerate = Degenerate::new(options);
+        let cached = matches!(&terrain.mesh, Some((d, _, _)) if *d == degenerate);
+        if !cached {
+            let mut mesh = lattice_mesh(datasets, &sample, &terrain.samples, voids)
+                .unwrap_or_else(|| triangulation::grid::triangulate(&terrain.points));
+            let dropped = drop_degenerate(&mut mesh, options, &[]);
+            terrain.mesh = Some((degenerate, mesh, dropped));
+        }
+        push_phase(
+            &mut phases,
+            Phase::new("triangulation", start, cached),
+            events,
+        );
+        let (_, mesh, dropped) = terrain.mesh.as_ref().unwrap();
+        (mesh, dropped, full_count)
+    };
+    let refined_mesh;
+    let gridtriangles = if options.refine && constraints.is_none() {
+        let start = Instant::now();
+        let refine_options = refine::RefineOptions {
+            slope: options.refine_slope,
+            depth: options.refine_depth,
+        };
+        let refined;
+        (refined_mesh, refined) = refine::refine(
+            gridtriangles,
+            &input_polygon.mercatorbbox()?,
+            &refine_options,
+            &elevation,
+        );
+        push_phase(&mut phases, Phase::new("refinement", start, false), events);
+        log::info!(
+            "{} triangles steeper than {} refined, {} triangles",
+            refined,
+            format::with_unit(options.refine_slope, Quantity::Slope),
+            refined_mesh.len()
+        );
+        if refined > 0 {
+            notes.push(format!(
+                "{} steep triangles refined with DEM samples at their edge midpoints",
+                refined
+            ));
+        }
+        &refined_mesh
+    } else {
+        if options.refine {
+            log::warn!("the constrained triangulation is not refined");
+        }
+        gridtriangles
+    };
+    events.emit(|| ComputeEvent::Triangulated {
+        triangles: gridtriangles.len(),
+    });
+    log::trace!("grid triangles: {}", gridtriangles.len());
+    if let Some(path) = &options.export_mesh {
+        let clipped;
+        let mesh = if options.export_mesh_clip {
+            clipped = meshexport::restrict(gridtriangles, &mask);
+            &clipped
+        } else {
+            gridtriangles
+        };
+        let written = meshexport::write(
+            Path::new(path),
+            mesh,
+            &input_polygon.projection(),
+            options.export_mesh_recenter,
+            options.flat_shading,
+        );
+        match written {
+            Ok(()) => log::info!("mesh of {} triangles written to {}", mesh.len(), path),
+            Err(e) => {
+                log::error!("{}: {}", path, e);
+                notes.push(format!("could not write the mesh: {}", e));
+            }
+        }
+    }
+
+    let mut svg = svg::Svg::init(&input_polygon.mercatorbbox()?);
+    svg.set_regions(!options.svg_polygons);
+    //svg.add_polygon(&input_polygon.mercatorbbox().as_vector(), "gray");
+    svg.add_polygon_with_holes(&polygon, &holes, "gray");
+    let clipping = Instant::now();
+    let progress = |done, total| events.emit(|| ComputeEvent::Clipping { done, total });
+    let mut surface = match &constraints {
+        Some(constraints) => compute::clip_constrained(
+            &polygon,
+            &holes,
+            gridtriangles,
+            constraints,
+            voids,
+            options.void_triangles,
+            &progress,
+        ),
+        None => compute::clip_reporting(
+            &polygon,
+            &holes,
+            gridtriangles,
+            voids,
+            options.void_triangles,
+            &progress,
+        ),
+    };
+    push_phase(&mut phases, Phase::new("clipping", clipping, false), events);
+    if let Some(radius) = sample.fill_radius {
+        notes.push(format!(
+            "{} nodata cells filled by interpolation within {}",
+            filled,
+            format::with_unit(radius, Quantity::Length)
+        ));
+    }
+    if surface.discarded2d > 0f64 {
+        notes.push(format!(
+            "{} across DEM voids excluded",
+            AreaValue::new(surface.discarded2d).render(options.units)
+        ));
+    }
+    if !dropped.is_empty() {
+        // the flat area of the degenerate triangles within the polygon
+        let degenerate = compute::clip_with_voids(&polygon, &holes, dropped, &[], VoidPolicy::Keep);
+        let area = degenerate.projected2d + degenerate.discarded2d;
+        surface.discarded2d += area;
+        notes.push(format!(
+            "{} degenerate triangles dropped, {} within the polygon left out",
+            dropped.len(),
+            AreaValue::new(area).render(options.units)
+        ));
+    }
+    if !surface.flagged.is_empty() {
+        let flagged2d: f64 = surface.flagged.iter().map(|p| p.area2d).sum();
+        notes.push(format!(
+            "{} planes ({}) span DEM voids, their surface is interpolated",
+            surface.flagged.len(),
+            AreaValue::new(flagged2d).render(options.units)
+        ));
+    }
+    if let Some(filter) = &options.dsm_filter {
+        // the same pipeline without the filter, for what it removed
+        let mut raw = sample.clone();
+        raw.opening_radius = None;
+        let mut rawpoints = BTreeSet::new();
+        visit_datasets(datasets, &pbbox, &raw, |_, m| {
+            rawpoints.insert(m);
+        });
+        let rawvec: Vec<MercatorPoint> = rawpoints.into_iter().collect();
+        let rawtriangles = triangulation::grid::triangulate(&rawvec);
+        let unfiltered = compute::clip_with_voids(
+            &polygon,
+            &holes,
+            &rawtriangles,
+            voids,
+            options.void_triangles,
+        );
+        notes.push(format!(
+            "DSM filter (opening radius {}) changed the 3D area by {} ({} unfiltered)",
+            format::with_unit(filter.opening_radius, Quantity::Length),
+            format::signed(
+                100.0 * (surface.projected3d / unfiltered.projected3d - 1.0),
+                Quantity::Percent
+            ),
+            AreaValue::new(unfiltered.projected3d).render(options.units)
+        ));
+    }
+    if options.merge_coplanar {
+        let before = surface.planes.len();
+        let merge = compute::MergeOptions {
+            max_angle: options.merge_angle,
+            max_offset: options.merge_offset,
+        };
+        surface = compute::merge_coplanar(surface, &merge);
+        notes.push(format!(
+            "coplanar planes merged: {} into {}",
+            before,
+            surface.planes.len()
+        ));
+    }
+    if sample.decimate > 1 {
+        notes.push(format!(
+            "estimate from every {}th DEM cell in both directions ({}× fewer samples)",
+            sample.decimate,
+            sample.decimate * sample.decimate
+        ));
+    }
+    if options.preview {
+        let elapsed = start.elapsed().as_secs_f64();
+        let estimate = elapsed * full_count as f64 / sampled.max(1) as f64;
+        notes.push(format!(
+            "preview (±{:.0}% expected) from {} of {} samples",
+            preview::expected_error(sampled),
+            sampled,
+            full_count
+        ));
+        notes.push(format!("full run estimated at {:.0}s", estimate));
+    }
+    let summaries: Vec<_> = surface
+        .planes
+        .iter()
+        .map(|plane| typst::PlaneSummary {
+            slope: plane.slope(),
+            elevation: plane.mean_elevation(),
+            area: plane.area3d,
+            inherited: plane.inherited.is_some(),
+        })
+        .collect();
+
+    if let Some(path) = &options.export_slope_tif {
+        let step = cell_size / sample.subdivide.max(1) as f64;
+        let map = slopemap::rasterize(
+            &surface.planes,
+            &input_polygon.mercatorbbox()?,
+            step,
+            &options.export_bands,
+        );
+        match map.write(Path::new(path), &input_polygon.projection()) {
+            Ok(()) => log::info!("slope raster written to {}", path),
+            Err(e) => {
+                log::error!("{}: {}", path, e);
+                notes.push(format!("could not write the slope raster: {}", e));
+            }
+        }
+    }
+
+    let slopes: Vec<f64> = summaries.iter().map(|s| s.slope).collect();
+    let elevations: Vec<f64> = summaries.iter().map(|s| s.elevation).collect();
+    // color_for_slope saturates at 50%
+    let slope_scale = ColorScale::new(&slopes, options.ramp, Some((0.0, 50.0)));
+    let elevation_scale = ColorScale::new(&elevations, options.ramp, None);
+    svg.add_layer("slope", true);
+    svg.add_layer("elevation", false);
+    if !surface.flagged.is_empty() {
+        svg.add_layer("voids", true);
+        for plane in &surface.flagged {
+            svg.add_polygon_to_layer("voids", &plane.points, "magenta");
+        }
+    }
+    for (plane, summary) in surface.planes.iter().zip(&summaries) {
+        let color = svg::color_for_slope(50.0 * slope_scale.fraction(summary.slope));
+        svg.add_polygon_to_layer("slope", &plane.points, &color);
+        let color = svg::color_for_elevation(elevation_scale.fraction(summary.elevation), 0.0, 1.0);
+        svg.add_polygon_to_layer("elevation", &plane.points, &color);
+    }
+    let stops = [0.0, 0.25, 0.5, 0.75, 1.0];
+    let legend: Vec<_> = stops
+        .iter()
+        .map(|t| {
+            let label = format::with_unit(slope_scale.value_at(*t), Quantity::Slope);
+            (svg::color_for_slope(50.0 * t), label)
+        })
+        .collect();
+    svg.add_legend_to_layer("slope", "slope", &legend);
+    let legend: Vec<_> = stops
+        .iter()
+        .map(|t| {
+            let label = format::with_unit(elevation_scale.value_at(*t), Quantity::Elevation);
+            (svg::color_for_elevation(*t, 0.0, 1.0), label)
+        })
+        .collect();
+    svg.add_legend_to_layer("elevation", "elevation", &legend);
+    for (label, e, color) in [
+        ("max", &extremes.highest, "red"),
+        ("min", &extremes.lowest, "blue"),
+    ] {
+        if let Some(e) = e {
+            let text = format!(
+                "{} {}",
+                label,
+                format::with_unit(e.elevation, Quantity::Elevation)
+            );
+            svg.add_marker(&e.mercator, &text, color);
+        }
+    }
+    if let Some(start) = options.flowline {
+        let start = match start {
+            FlowlineStart::Highest => extremes.highest.as_ref().map(|e| e.mercator.clone()),
+            FlowlineStart::Point { lon, lat } => {
+                let wgs = WGS84Point {
+                    lon,
+                    lat,
+                    ele: None,
+                };
+                Some(input_polygon.projector().project(&wgs)?)
+            }
+        };
+        match start.and_then(|p| flowline::trace(gridtriangles, &p, &polygon)) {
+            Some(line) => {
+                notes.push(format!(
+                    "flow line: {} long, {} drop, {}",
+                    format::with_unit(line.total_length(), Quantity::Length),
+                    format::with_unit(line.total_drop(), Quantity::Elevation),
+                    line.end
+                ));
+                svg.add_layer("flowline", true);
+                svg.add_polyline_to_layer("flowline", &line.points, "deepskyblue");
+                if let Some(path) = &options.export_flowline {
+                    match line.write_geojson(Path::new(path), &input_polygon.projection()) {
+                        Ok(()) => log::info!("flow line written to {}", path),
+                        Err(e) => {
+                            log::error!("{}: {}", path, e);
+                            notes.push(format!("could not write the flow line: {}", e));
+                        }
+                    }
+                }
+            }
+            None => notes.push("no flow line: its start is outside of the polygon".to_string()),
+        }
+    }
+    let insolation = if options.insolation {
+        let latitude = pbbox.center().lat;
+        let default = insolation::SunPosition::equinox_noon(latitude);
+        let sun = insolation::SunPosition {
+            azimuth: options.sun_azimuth.unwrap_or(default.azimuth),
+            altitude: options.sun_altitude.unwrap_or(default.altitude),
+        };
+        svg.add_layer("insolation", false);
+        for plane in &surface.planes {
+            let cos = insolation::incidence(plane.normal(), &sun);
+            svg.add_polygon_to_layer("insolation", &plane.points, &svg::color_for_incidence(cos));
+        }
+        Some(insolation::summarize(&surface.planes, &sun, latitude))
+    } else {
+        None
+    };
+    let breakdown = if options.breakdown {
+        Some(breakdown::breakdown(
+            surface.planes.iter().chain(&surface.flagged),
+            options.breakdown_band,
+            &options.breakdown_slopes,
+        ))
+    } else {
+        None
+    };
+
+    log::trace!("planes: {}", surface.planes.len());
+    let geodesic2d = input_polygon.geodesic_area()?;
+    let planar2d = input_polygon.planar_area()?;
+    let laea = Polygon::projected(
+        input_polygon.wgs.clone(),
+        Some(pbbox.center().to_laea_proj4()),
+    )
+    .with_holes(input_polygon.holes.clone());
+    if let Some(d) = reference::inconsistency(geodesic2d, laea.planar_area()?) {
+        log::warn!(
+            "geodesic and equal-area planar areas differ by {}",
+            format::signed(100.0 * d, Quantity::Percent)
+        );
+        notes.push(format!(
+            "geodesic and equal-area planar areas differ by {}, check the polygon",
+            format::signed(100.0 * d, Quantity::Percent)
+        ));
+    }
+    let spherical2d = reference::spherical_area(&input_polygon.wgs)
+        - input_polygon
+            .holes
+            .iter()
+            .map(|hole| reference::spherical_area(hole))
+            .sum::<f64>();
+    if let Some(d) = reference::spherical_deviation(geodesic2d, spherical2d) {
+        log::warn!(
+            "spherical and geodesic areas differ by {}",
+            format::signed(100.0 * d, Quantity::Percent)
+        );
+        notes.push(format!(
+            "spherical and geodesic areas differ by {}, check the winding and closure of the polygon",
+            format::signed(100.0 * d, Quantity::Percent)
+        ));
+    }
+    let self_check = if options.self_check {
+        let check = self_check(input_polygon, datasets, options)?;
+        if !check.passed() {
+            log::error!("self-check: {}", check);
+            notes.push(format!("self-check failed, {}", check));
+        }
+        Some(check)
+    } else {
+        None
+    };
+    let (projected2d, projected3d) = (surface.projected2d, surface.projected3d);
+    let estimates = Estimates::new(
+        &surface,
+        geodesic2d,
+        &input_polygon.projection(),
+        options.estimate,
+    );
+    let geodesic3d = estimates.headline();
+    let planes: Vec<&compute::Plane> = surface.planes.iter().chain(&surface.flagged).collect();
+    let perimeter3d = with_elevations(input_polygon, &rings, &planes).perimeter_3d();
+    for note in &notes {
+        events.emit(|| ComputeEvent::Note(note.clone()));
+    }
+
+    //svg.add_polygon(&polygon, "none");
+    //svg.add_triangles(&atoms, true);
+    //svg.add_triangles(&triangulation::polygon::triangulate(&polygon), true);
+    //svg.add_triangles(gridtriangles.iter(), false);
+    Ok(typst::Data {
+        name: Path::new(name)
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string(),
+        geodesic2d,
+        planar2d,
+        spherical2d,
+        projected2d,
+        projected3d,
+        discarded2d: surface.discarded2d,
+        geodesic3d,
+        estimates,
+        perimeter2d: input_polygon.perimeter_2d(),
+        perimeter3d,
+        svg: svg.render()?,
+        nplanes: surface.planes.len(),
+        low_confidence,
+        edge_fraction,
+        projection: input_polygon.projection(),
+        notes,
+        planes: summaries,
+        insolation,
+        breakdown,
+        extremes,
+        reference: compare(options, geodesic2d, geodesic3d),
+        self_check,
+        io_stats: datasets.iter().map(|d| d.io_stats()).collect(),
+        phases,
+        clip_paths: surface.paths,
+        parts: Vec::new(),
+        units: options.units,
+        lang: options.lang,
+    })
+}
+
+// The computation again, on the datasets flattened: see SelfCheck. The same
+// sampling, triangulation and clipping, without the exports, the extra
+// figures, the events and the boundary elevations of the file, voids
+// included: the planes must cover the polygon.
+pub fn self_check(
+    input_polygon: &Polygon,
+    datasets: &[Dataset],
+    options: &ComputeOptions,
+) -> Result<SelfCheck, DatasetError> {
+    let flat: Vec<Dataset> = datasets.iter().map(|d| d.flattened(0.0)).collect();
+    let options = ComputeOptions {
+        self_check: false,
+        void_triangles: VoidPolicy::Keep,
+        boundary_elevation: BoundaryElevation::FromDem,
+        export_slope_tif: None,
+        export_flowline: None,
+        export_mesh: None,
+        flowline: None,
+        insolation: false,
+        breakdown: false,
+        reference_area: None,
+        events: Events::default(),
+        ..options.clone()
+    };
+    let data = compute_cached(
+        "self-check",
+        input_polygon,
+        &flat,
+        &options,
+        &mut TerrainCache::new(0.0),
+    )?;
+    Ok(SelfCheck::new(
+        data.projected2d,
+        data.projected3d,
+        data.planar2d,
+    ))
+}
+
+// the rings with the elevations of the file dropped: the clip puts the
+// vertices on the mesh
+fn without_elevations(polygon: &mut [MercatorPoint], holes: &mut [Vec<MercatorPoint>]) {
+    for p in polygon.iter_mut().chain(holes.iter_mut().flatten()) {
+        p.ele = None;
+    }
+}
+
+// The polygon with the elevations of its vertices, from the vertices of the
+// clipped planes: the clip keeps the boundary and interpolates on the mesh.
+// A vertex the planes miss (in a void) stays without.
+fn with_elevations(
+    input_polygon: &Polygon,
+    rings: &[&[MercatorPoint]],
+    planes: &[&compute::Plane],
+) -> Polygon {
+    // to the millimeter
+    let key = |p: &MercatorPoint| ((p.x * 1e3).round() as i64, (p.y * 1e3).round() as i64);
+    let elevations: HashMap<(i64, i64), f64> = planes
+        .iter()
+        .flat_map(|plane| &plane.points)
+        .filter_map(|p| Some((key(p), p.ele?)))
+        .collect();
+    let mut sampled = input_polygon.rings().zip(rings).map(|(wgs, mercator)| {
+        wgs.iter()
+            .zip(mercator.iter())
+            .map(|(w, m)| WGS84Point {
+                ele: elevations.get(&key(m)).copied(),
+                ..w.clone()
+            })
+            .collect::<Vec<_>>()
+    });
+    let outline = sampled.next().unwrap_or_default();
+    Polygon::projected(outline, input_polygon.proj4.clone()).with_holes(sampled.collect())
+}
+
+// The figure of the boundary alone, for the tiles of a split polygon.
+pub fn outline(input_polygon: &Polygon) -> Result<String, DatasetError> {
+    let mut svg = svg::Svg::init(&input_polygon.mercatorbbox()?);
+    svg.add_polygon_with_holes(
+        &input_polygon.mercator()?,
+        &input_polygon.mercator_holes()?,
+        "gray",
+    );
+    Ok(svg.render()?)
+}
+
+// The sums over results computed separately: the tiles of a split polygon,
+// or the parts of a multi-part boundary. The geodesic area and the figure
+// are the caller's.
+pub fn sum(
+    name: String,
+    results: &[typst::Data],
+    geodesic2d: f64,
+    svg: String,
+    notes: Vec<String>,
+    options: &ComputeOptions,
+) -> typst::Data {
+    let mut insolation: Option<insolation::InsolationSummary> = None;
+    let mut breakdown: Option<Breakdown> = None;
+    let mut extremes = Extremes::default();
+    let mut self_check: Option<SelfCheck> = None;
+    let mut io_stats = Vec::new();
+    let mut phases = Vec::new();
+    let mut clip_paths = compute::ClipPaths::default();
+    let mut estimates = Estimates {
+        strategy: options.estimate,
+        ..Default::default()
+    };
+    for r in results {
+        match (&mut insolation, &r.insolation) {
+            (Some(total), Some(part)) => total.add(part),
+            (None, Some(part)) => insolation = Some(part.clone()),
+            _ => {}
+        }
+        match (&mut breakdown, &r.breakdown) {
+            (Some(total), Some(part)) => total.add(part),
+            (None, Some(part)) => breakdown = Some(part.clone()),
+            _ => {}
+        }
+        extremes.merge(&r.extremes);
+        match (&mut self_check, &r.self_check) {
+            (Some(total), Some(part)) => total.add(part),
+            (None, Some(part)) => self_check = Some(part.clone()),
+            _ => {}
+        }
+        for stats in &r.io_stats {
+            IoStats::merge(&mut io_stats, stats);
+        }
+        for phase in &r.phases {
+            Phase::merge(&mut phases, phase);
+        }
+        clip_paths.add(&r.clip_paths);
+        estimates.add(&r.estimates);
+    }
+    let geodesic3d = estimates.headline();
+    let projections: BTreeSet<&str> = results.iter().map(|r| r.projection.as_str()).collect();
+    typst::Data {
+        name,
+        geodesic2d,
+        planar2d: results.iter().map(|r| r.planar2d).sum(),
+        spherical2d: results.iter().map(|r| r.spherical2d).sum(),
+        projected2d: results.iter().map(|r| r.projected2d).sum(),
+        projected3d: results.iter().map(|r| r.projected3d).sum(),
+        discarded2d: results.iter().map(|r| r.discarded2d).sum(),
+        geodesic3d,
+        estimates,
+        perimeter2d: results.iter().map(|r| r.perimeter2d).sum(),
+        perimeter3d: results.iter().map(|r| r.perimeter3d).sum(),
+        svg,
+        nplanes: results.iter().map(|r| r.nplanes).sum(),
+        low_confidence: results.iter().any(|r| r.low_confidence),
+        edge_fraction: results.iter().map(|r| r.edge_fraction).fold(0.0, f64::max),
+        projection: match projections.len() {
+            1 => projections.into_iter().next().unwrap().to_string(),
+            n => format!("{} local projections", n),
+        },
+        notes,
+        planes: results.iter().flat_map(|r| r.planes.clone()).collect(),
+        insolation,
+        breakdown,
+        extremes,
+        reference: compare(options, geodesic2d, geodesic3d),
+        self_check,
+        io_stats,
+        phases,
+        clip_paths,
+        parts: Vec::new(),
+        units: options.units,
+        lang: options.lang,
+    }
+}
+
+// The aggregate of the parts of a boundary, computed one by one. The figure
+// outlines the parts, in the projection of the first, with their names.
+pub fn combine_parts(
+    name: String,
+    parts: &[Part],
+    results: Vec<typst::Data>,
+    options: &ComputeOptions,
+) -> Result<typst::Data, DatasetError> {
+    assert_eq!(parts.len(), results.len());
+    let proj4 = parts[0].polygon.projection();
+    let project = |wgs: Vec<WGS84Point>| Polygon::projected(wgs, Some(proj4.clone()));
+    let all = project(parts.iter().flat_map(|p| p.polygon.wgs.clone()).collect());
+    let mut svg = svg::Svg::init(&all.mercatorbbox()?);
+    for part in parts {
+        let part_polygon = project(part.polygon.wgs.clone()).with_holes(part.polygon.holes.clone());
+        let (outline, holes) = (part_polygon.mercator()?, part_polygon.mercator_holes()?);
+        svg.add_polygon_with_holes(&outline, &holes, "gray");
+        let mask = intersection::to_geo_polygon_with_holes(&outline, &holes);
+        if let Some(point) = mask.interior_point() {
+            let at = MercatorPoint {
+                x: point.x(),
+                y: point.y(),
+                ele: None,
+            };
+            svg.add_marker(&at, &format!("{}. {}", part.index, part.name), "white");
+        }
+    }
+    let names: Vec<&str> = parts.iter().map(|p| p.name.as_str()).collect();
+    let notes = vec![format!("{} parts: {}", parts.len(), names.join(", "))];
+    // the parts do not overlap
+    let geodesic2d = results.iter().map(|r| r.geodesic2d).sum();
+    let mut ret = sum(name, &results, geodesic2d, svg.render()?, notes, options);
+    ret.parts = results;
+    Ok(ret)
+}
+
+fn push_phase(phases: &mut Vec<Phase>, phase: Phase, events: &Events) {
+    events.emit(|| ComputeEvent::Phase(phase.clone()));
+    phases.push(phase);
+}
+
+// Visits the samples of every dataset within the bounding box, returns the
+// skipped pixels. The box is snapped to the grid of the finest dataset; the
+// datasets on another grid are interpolated at its samples.
+pub fn visit_datasets(
+    datasets: &[Dataset],
+    pbbox: &WGS84BoundingBox,
+    sample: &SampleOptions,
+    f: impl FnMut(&WGS84Point, MercatorPoint),
+) -> Vec<MercatorPoint> {
+    visit_datasets_reporting(datasets, pbbox, sample, f, &mut |_, _| {})
+}
+
+// visit_datasets, calling progress with the datasets done and their total
+fn visit_datasets_reporting(
+    datasets: &[Dataset],
+    pbbox: &WGS84BoundingBox,
+    sample: &SampleOptions,
+    mut f: impl FnMut(&WGS84Point, MercatorPoint),
+    progress: &mut dyn FnMut(usize, usize),
+) -> Vec<MercatorPoint> {
+    // the finer datasets first: theirs are the samples kept at the seams
+    let order = dataset::by_cell_size(datasets);
+    let Some((_, reference)) = order.first().copied() else {
+        return Vec::new();
+    };
+    let mut pbbox = pbbox.clone();
+    reference.snap(&mut pbbox);
+    let mut voids = Vec::new();
+    let mut visited = (datasets.len() > 1).then(Visited::default);
+    for (k, &(size, dataset)) in order.iter().enumerate() {
+        progress(k, datasets.len());
+        dataset.info(&sample.nodata);
+        // where a finer dataset has samples, the samples of this one would
+        // be a second, conflicting set: the finer ones win
+        let finer: Vec<&Dataset> = order
+            .iter()
+            .filter(|(s, _)| *s < 0.9 * size)
+            .map(|(_, d)| *d)
+            .collect();
+        let covered = |wgs: &WGS84Point| finer.iter().any(|d| d.covers(wgs));
+        let mut visitor = |wgs: &WGS84Point, m: MercatorPoint| {
+            if !covered(wgs) && visited.as_mut().is_none_or(|v| v.insert(&m)) {
+                f(wgs, m);
+            }
+        };
+        let dbbox = dataset.wgsbbox();
+        if let Some(mut bbox) = dbbox.intersection(&pbbox) {
+            log::trace!("bbox: {}", bbox);
+            let own = if dataset.aligned(reference) {
+                dataset.snap(&mut bbox);
+                log::trace!("snap: {}", bbox);
+                dataset.visit_inside(&bbox, sample, &mut visitor)
+            } else {
+                dataset.visit_on_grid(&bbox, reference, sample, &mut visitor)
+            };
+            let projection = dataset.projector();
+            voids.extend(own.into_iter().filter(|v| {
+                finer.is_empty() || !projection.unproject(v).is_ok_and(|w| covered(&w))
+            }));
+        }
+    }
+    progress(datasets.len(), datasets.len());
+    voids
+}
+
+// The thresholds of the degenerate triangles dropped from a mesh, see
+// Mesh::drop_degenerate.
+#[derive(PartialEq)]
+struct Degenerate {
+    min_angle: f64,
+    min_area: Option<f64>,
+}
+
+impl Degenerate {
+    fn new(options: &ComputeOptions) -> Self {
+        Degenerate {
+            min_angle: options.min_triangle_angle,
+            min_area: options.min_triangle_area,
+        }
+    }
+}
+
+// Drops the slivers of the mesh (see Mesh::drop_degenerate), by default
+// those below a thousandth of the square of the grid spacing, and returns
+// them. The triangles on the constraints, the boundary of a constrained
+// mesh, are thin by construction.
+fn drop_degenerate(
+    mesh: &mut triangulation::Mesh,
+    options: &ComputeOptions,
+    constraints: &[[u32; 2]],
+) -> triangulation::Mesh {
+    let min_area = options
+        .min_triangle_area
+        .unwrap_or_else(|| 1e-3 * mesh.spacing().powi(2));
+    let fixed: HashSet<u32> = constraints.iter().flatten().copied().collect();
+    let dropped = mesh.drop_degenerate(min_area, options.min_triangle_angle, &fixed);
+    if !dropped.is_empty() {
+        let area: f64 = dropped.iter().map(|t| t.flat().area()).sum();
+        log::info!(
+            "{} degenerate triangles dropped ({} flat)",
+            dropped.len(),
+            AreaValue::new(area).render(options.units)
+        );
+    }
+    dropped
+}
+
+// The mesh of the samples of a single dataset, two triangles per cell of its
+// lattice. None where Delaunay is needed: several datasets, a decimated
+// lattice, voids (the void policy needs the triangles across them), a
+// projected or rotated raster.
+fn lattice_mesh(
+    datasets: &[Dataset],
+    sample: &SampleOptions,
+    samples: &[(WGS84Point, MercatorPoint)],
+    voids: &[MercatorPoint],
+) -> Option<triangulation::Mesh> {
+    let [dataset] = datasets else {
+        return None;
+    };
+    if sample.decimate > 1 || !voids.is_empty() {
+        return None;
+    }
+    let indices = samples
+        .iter()
+        .map(|(wgs, _)| dataset.lattice_index(wgs, sample.subdivide))
+        .collect::<Option<Vec<_>>>()?;
+    let points: Vec<MercatorPoint> = samples.iter().map(|(_, m)| m.clone()).collect();
+    let mesh = triangulation::grid::triangulate_regular(&points, &indices)?;
+    log::debug!("lattice of {} samples, triangulated by cells", points.len());
+    Some(mesh)
+}
+
+// The positions visited, in cells of SEAM_TOLERANCE.
+#[derive(Default)]
+struct Visited(HashSet<(i64, i64)>);
+
+impl Visited {
+    // false if a sample was visited at the position
+    fn insert(&mut self, m: &MercatorPoint) -> bool {
+        let (i, j) = (
+            (m.x / SEAM_TOLERANCE).floor() as i64,
+            (m.y / SEAM_TOLERANCE).floor() as i64,
+        );
+        let seen = (i - 1..=i + 1).any(|a| (j - 1..=j + 1).any(|b| self.0.contains(&(a, b))));
+        if !seen {
+            self.0.insert((i, j));
+        }
+        !seen
+    }
+}
+
+pub fn compare(options: &ComputeOptions, planar: f64, surface: f64) -> Option<ReferenceComparison> {
+    let registered = options.reference_area?;
+    let comparison =
+        ReferenceComparison::new(registered, planar, surface, options.reference_tolerance);
+    if !comparison.within_tolerance {
+        log::warn!(
+            "planar area deviates {} from the registered area (tolerance {}%)",
+            format::signed(comparison.planar_deviation, Quantity::Percent),
+            options.reference_tolerance
+        );
+    }
+    Some(comparison)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, assert_close, pixels, polygon, square};
+
+    // The 400 m square on the 30% plane, then with its north-east corner
+    // moved out by 20 m: the second run takes the samples and the mesh of the
+    // first from the cache, reads no raster and gives the areas of a fresh
+    // run.
+    #[test]
+    fn test_edited_boundary() {
+        let datasets = [fixtures::synthetic(
+            "edited",
+            (-300.0, -300.0, 300.0, 300.0),
+            |x, _| Some(500.0 + 0.3 * x),
+        )];
+        let options = ComputeOptions::default();
+        let mut cache = TerrainCache::new(0.1);
+        let first = polygon(&square(200.0));
+        let data = compute_cached("edited", &first, &datasets, &options, &mut cache).unwrap();
+        assert!(data.phases.iter().all(|p| !p.cached));
+        let read = pixels(&datasets);
+        assert!(read > 0);
+
+        let mut ring = square(200.0);
+        ring[2] = (220.0, 220.0);
+        let edited = polygon(&ring);
+        let data = compute_cached("edited", &edited, &datasets, &options, &mut cache).unwrap();
+        assert_eq!(pixels(&datasets), read);
+        for name in ["sampling", "triangulation"] {
+            let phase = data.phases.iter().find(|p| p.name == name).unwrap();
+            assert!(phase.cached, "{}", phase);
+        }
+        let fresh = compute("edited", &edited, &datasets, &options).unwrap();
+        assert_close("planar", data.planar2d, fresh.planar2d, 1e-9);
+        assert_close("flat", data.projected2d, fresh.projected2d, 1e-9);
+        assert_close("surface", data.projected3d, fresh.projected3d, 1e-9);
+        assert_eq!(
+            data.extremes.highest.as_ref().map(|e| e.elevation),
+            fresh.extremes.highest.as_ref().map(|e| e.elevation)
+        );
+    }
+
+    // a boundary edited past the cached window samples the terrain again
+    #[test]
+    fn test_edit_leaves_window() {
+        let datasets = [fixtures::synthetic(
+            "window",
+            (-300.0, -300.0, 300.0, 300.0),
+            |x, _| Some(500.0 + 0.3 * x),
+        )];
+        let options = ComputeOptions::default();
+        let mut cache = TerrainCache::new(0.1);
+        let first = polygon(&square(200.0));
+        compute_cached("window", &first, &datasets, &options, &mut cache).unwrap();
+        let read = pixels(&datasets);
+
+        // the window reaches 40 m past the square
+        let mut ring = square(200.0);
+        ring[2] = (200.0, 260.0);
+        ring[3] = (-200.0, 260.0);
+        let edited = polygon(&ring);
+        let data = compute_cached("window", &edited, &datasets, &options, &mut cache).unwrap();
+        assert!(pixels(&datasets) > read);
+        assert!(data.phases.iter().all(|p| !p.cached));
+        assert_close("flat", data.projected2d, 400.0 * 460.0, 1e-4);
+    }
+
+    // Two 300 m squares of a MultiPolygon on a plane rising 30% to the east,
+    // computed part by part: the aggregate is the sum of the parts.
+    #[test]
+    fn test_two_parts() {
+        let datasets = [fixtures::synthetic(
+            "parts",
+            (-450.0, -200.0, 450.0, 200.0),
+            |x, _| Some(500.0 + 0.3 * x),
+        )];
+        let parts: Vec<Part> = [("west", -250.0), ("east", 250.0)]
+            .iter()
+            .enumerate()
+            .map(|(k, (name, dx))| {
+                let ring: Vec<_> = square(150.0).iter().map(|(x, y)| (x + dx, *y)).collect();
+                Part {
+                    index: k + 1,
+                    name: name.to_string(),
+                    polygon: polygon(&ring),
+                }
+            })
+            .collect();
+        let options = ComputeOptions::default();
+        let results = parts
+            .iter()
+            .map(|part| compute(&part.name, &part.polygon, &datasets, &options).unwrap())
+            .collect();
+        let data = combine_parts("two-parts".to_string(), &parts, results, &options).unwrap();
+        assert_eq!(data.parts.len(), 2);
+        for part in &data.parts {
+            assert_close("part", part.planar2d, 90_000.0, 1e-4);
+        }
+        let sum = |area: fn(&typst::Data) -> f64| data.parts.iter().map(area).sum::<f64>();
+        assert_close("geodesic", data.geodesic2d, sum(|d| d.geodesic2d), 1e-12);
+        assert_close("planar", data.planar2d, sum(|d| d.planar2d), 1e-12);
+        assert_close("flat", data.projected2d, sum(|d| d.projected2d), 1e-12);
+        assert_close("surface", data.projected3d, sum(|d| d.projected3d), 1e-12);
+        assert_close("estimate", data.geodesic3d, sum(|d| d.geodesic3d), 1e-12);
+        assert_eq!(
+            data.nplanes,
+            data.parts.iter().map(|d| d.nplanes).sum::<usize>()
+        );
+        assert_close(
+            "ratio",
+            data.projected3d / data.projected2d,
+            1.09f64.sqrt(),
+            1e-4,
+        );
+        let json = serde_json::to_value(&data).unwrap();
+        assert_eq!(json["parts"].as_array().unwrap().len(), 2);
+    }
+
+    // the thresholds of the degenerate triangles belong to the cached mesh,
+    // and apply on the preview and constrained meshes as well
+    #[test]
+    fn test_degenerate_thresholds() {
+        let datasets = [fixtures::synthetic(
+            "degenerate",
+            (-300.0, -300.0, 300.0, 300.0),
+            |x, _| Some(500.0 + 0.3 * x),
+        )];
+        let square = polygon(&square(200.0));
+        let mut options = ComputeOptions::default();
+        let mut cache = TerrainCache::new(0.1);
+        let mut run = |options: &ComputeOptions| {
+            compute_cached("degenerate", &square, &datasets, options, &mut cache).unwrap()
+        };
+        let data = run(&options);
+        assert_close("flat", data.projected2d, 160_000.0, 1e-4);
+        // every triangle is below it
+        options.min_triangle_area = Some(1e9);
+        let data = run(&options);
+        assert_eq!(data.projected2d, 0.0);
+        // their flat area is left out, not lost
+        assert_close("discarded", data.discarded2d, 160_000.0, 1e-4);
+        assert!(data.notes.iter().any(|n| n.contains("degenerate")));
+        let phase = data.phases.iter().find(|p| p.name == "triangulation");
+        assert!(!phase.unwrap().cached);
+        options.preview = true;
+        let data = run(&options);
+        assert_eq!(data.projected2d, 0.0);
+        assert_close("discarded", data.discarded2d, 160_000.0, 1e-4);
+        options.preview = false;
+        options.constrained = true;
+        let data = run(&options);
+        assert_eq!(data.projected2d, 0.0);
+        assert_close("discarded", data.discarded2d, 160_000.0, 1e-4);
+        // the thin triangles that fan out from the long edges of the
+        // boundary are kept
+        options.min_triangle_area = None;
+        assert_close("constrained", run(&options).projected2d, 160_000.0, 1e-6);
+    }
+
+    // a 20 m square on cells of about 10 m: flagged, its cells subdivided;
+    // without a dataset, no coverage
+    #[test]
+    fn test_tiny_polygon() {
+        let dem = || {
+            fixtures::synthetic("tiny", (-50.0, -50.0, 50.0, 50.0), |x, _| {
+                Some(500.0 + 0.3 * x)
+            })
+        };
+        let tiny = polygon(&square(10.0));
+        let mut options = ComputeOptions::default();
+        let data = compute("tiny-polygon", &tiny, &[dem()], &options).unwrap();
+        assert!(data.low_confidence);
+        let triangles = |data: &typst::Data| {
+            data.clip_paths.whole + data.clip_paths.outside + data.clip_paths.intersected
+        };
+        options.min_cells = 0.0;
+        let coarse = compute("tiny", &tiny, &[dem()], &options).unwrap();
+        assert!(!coarse.low_confidence);
+        assert!(triangles(&data) > 4 * triangles(&coarse));
+        assert_close("planar", data.planar2d, 400.0, 1e-4);
+        assert_close("flat", data.projected2d, data.planar2d, 1e-6);
+        assert_close(
+            "ratio",
+            data.projected3d / data.projected2d,
+            1.09f64.sqrt(),
+            1e-4,
+        );
+        assert!(matches!(
+            compute("tiny", &tiny, &[], &ComputeOptions::default()),
+            Err(DatasetError::NoDatasetCoverage { .. })
+        ));
+    }
+
+    #[test]
+    fn test_events() {
+        use std::sync::{Arc, Mutex};
+
+        let dem = fixtures::synthetic("events", (-250.0, -250.0, 250.0, 250.0), |x, _| {
+            Some(500.0 + 0.3 * x)
+        });
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut options = ComputeOptions::default();
+        let collector = events.clone();
+        options.events = Events::new(move |event| collector.lock().unwrap().push(event));
+        let data = compute("events", &polygon(&square(200.0)), &[dem], &options).unwrap();
+        let events = events.lock().unwrap();
+        let mut kinds: Vec<String> = events
+            .iter()
+            .map(|event| match event {
+                ComputeEvent::Datasets(names) => format!("datasets {}", names.join(",")),
+                ComputeEvent::Sampling { .. } => "sampling".to_string(),
+                ComputeEvent::Triangulated { .. } => "triangulated".to_string(),
+                ComputeEvent::Clipping { .. } => "clipping".to_string(),
+                ComputeEvent::Phase(phase) => format!("phase {}", phase.name),
+                ComputeEvent::Warning(_) => "warning".to_string(),
+                ComputeEvent::Note(_) => "note".to_string(),
+            })
+            .collect();
+        kinds.dedup();
+        assert_eq!(
+            kinds,
+            [
+                "datasets events",
+                "sampling",
+                "phase sampling",
+                "phase triangulation",
+                "triangulated",
+                "clipping",
+                "phase clipping",
+            ]
+        );
+        assert!(events.contains(&ComputeEvent::Sampling { done: 1, total: 1 }));
+        assert!(events.contains(&ComputeEvent::Triangulated {
+            triangles: data.clip_paths.whole
+                + data.clip_paths.outside
+                + data.clip_paths.intersected,
+        }));
+        let clipped: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                ComputeEvent::Clipping { done, total } => Some((*done, *total)),
+                _ => None,
+            })
+            .collect();
+        assert!(clipped.len() > 10);
+        assert!(clipped.windows(2).all(|w| w[0].0 < w[1].0));
+        assert_eq!(clipped.last().unwrap().0, clipped.last().unwrap().1);
+        let phases: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                ComputeEvent::Phase(phase) => Some(phase.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(phases, data.phases);
+    }
+}
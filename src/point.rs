@@ -1,7 +1,9 @@
 use core::fmt;
 use std::cmp::Ordering;
 
-#[derive(Clone, Debug)]
+use serde::Serialize;
+
+#[derive(Clone, Debug, Serialize)]
 pub struct WGS84Point {
     pub lon: f64,
     pub lat: f64,
@@ -9,13 +11,10 @@ pub struct WGS84Point {
 }
 
 impl WGS84Point {
-    pub fn in_epsg32619(&self) -> bool {
-        -72.0 <= self.lon && self.lon <= -66.0 && 0.0 <= self.lat && self.lat <= 84.0
-    }
     pub fn to_utm_proj4(&self) -> String {
         // Determine UTM zone from longitude
-        // Zone = floor((lon + 180) / 6) + 1
-        let zone = ((self.lon + 180.0) / 6.0).floor() as i32 + 1;
+        // Zone = floor((lon + 180) / 6) + 1, past 180° in zone 1 again
+        let zone = ((self.lon + 180.0).rem_euclid(360.0) / 6.0).floor() as i32 + 1;
 
         // Determine hemisphere
         let south = if self.lat < 0.0 { " +south" } else { "" };
@@ -25,6 +24,37 @@ impl WGS84Point {
             zone, south
         )
     }
+    pub fn to_laea_proj4(&self) -> String {
+        format!(
+            "+proj=laea +lat_0={} +lon_0={} +datum=WGS84 +units=m +no_defs",
+            self.lat, self.lon
+        )
+    }
+    // the geodesic distance on the WGS84 ellipsoid, in meters
+    pub fn distance_to(&self, other: &WGS84Point) -> f64 {
+        use geo::{Distance, Geodesic, Point};
+        Geodesic.distance(
+            Point::new(self.lon, self.lat),
+            Point::new(other.lon, other.lat),
+        )
+    }
+    // the longitude east of 180° for a ring across the antimeridian, where
+    // the negative longitudes continue the positive ones
+    pub fn unwrapped_lon(&self, across: bool) -> f64 {
+        if across && self.lon < 0.0 {
+            self.lon + 360.0
+        } else {
+            self.lon
+        }
+    }
+}
+
+// Two consecutive vertices of the (closed) ring more than 180° of longitude
+// apart: the edge between them is the short way, across 180°.
+pub fn crosses_antimeridian(ring: &[WGS84Point]) -> bool {
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .any(|(a, b)| (a.lon - b.lon).abs() > 180.0)
 }
 
 impl fmt::Display for WGS84Point {
@@ -41,7 +71,7 @@ impl fmt::Display for WGS84Point {
     }
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, PartialEq, Serialize)]
 pub struct MercatorPoint {
     pub x: f64,
     pub y: f64,
@@ -57,6 +87,14 @@ impl MercatorPoint {
     pub fn x_y(&self) -> (f64, f64) {
         (self.x, self.y)
     }
+    pub fn distance_2d(&self, other: &MercatorPoint) -> f64 {
+        (self.x - other.x).hypot(self.y - other.y)
+    }
+    // with the elevation difference, a missing elevation counts as 0
+    pub fn distance_3d(&self, other: &MercatorPoint) -> f64 {
+        let dz = self.ele.unwrap_or(0.0) - other.ele.unwrap_or(0.0);
+        self.distance_2d(other).hypot(dz)
+    }
 }
 
 impl fmt::Display for MercatorPoint {
@@ -86,7 +124,7 @@ impl Ord for MercatorPoint {
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct WGS84BoundingBox {
     pub min: WGS84Point,
     pub max: WGS84Point,
@@ -113,11 +151,18 @@ impl WGS84BoundingBox {
         };
         Self { min, max }
     }
+    // The boxes are on a circle of longitudes: the other box is moved by a
+    // turn if that makes them meet, the result is in the longitudes of self.
     pub fn intersection(&self, other: &Self) -> Option<Self> {
+        TURNS
+            .iter()
+            .find_map(|turn| self.intersection_at(other, *turn))
+    }
+    fn intersection_at(&self, other: &Self, turn: f64) -> Option<Self> {
         // Calculate the intersection bounds
-        let min_lon = self.min.lon.max(other.min.lon);
+        let min_lon = self.min.lon.max(other.min.lon + turn);
         let min_lat = self.min.lat.max(other.min.lat);
-        let max_lon = self.max.lon.min(other.max.lon);
+        let max_lon = self.max.lon.min(other.max.lon + turn);
         let max_lat = self.max.lat.min(other.max.lat);
 
         // Check if the intersection is valid (boxes actually overlap)
@@ -140,27 +185,35 @@ impl WGS84BoundingBox {
         }
     }
     pub fn contains_point(&self, w: &WGS84Point) -> bool {
-        w.lon >= self.min.lon
-            && w.lon <= self.max.lon
-            && w.lat >= self.min.lat
-            && w.lat <= self.max.lat
+        TURNS.iter().any(|turn| {
+            w.lon + turn >= self.min.lon
+                && w.lon + turn <= self.max.lon
+                && w.lat >= self.min.lat
+                && w.lat <= self.max.lat
+        })
     }
 
     pub fn contains_other(&self, other: &Self) -> bool {
-        self.min.lon <= other.min.lon
-            && self.min.lat <= other.min.lat
-            && self.max.lon >= other.max.lon
-            && self.max.lat >= other.min.lat
+        TURNS.iter().any(|turn| {
+            self.min.lon <= other.min.lon + turn
+                && self.min.lat <= other.min.lat
+                && self.max.lon >= other.max.lon + turn
+                && self.max.lat >= other.max.lat
+        })
     }
 }
 
+// the moves of a longitude that keep the position, the boxes of polygons
+// across the antimeridian continuing past 180°
+const TURNS: [f64; 3] = [0.0, 360.0, -360.0];
+
 impl fmt::Display for WGS84BoundingBox {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "wgsbbox(min: {}, max: {})", self.min, self.max)
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct MercatorBoundingBox {
     pub min: MercatorPoint,
     pub max: MercatorPoint,
@@ -199,3 +252,73 @@ impl fmt::Display for MercatorBoundingBox {
         write!(f, "wgsbbox(min: {}, max: {})", self.min, self.max)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wgs(lon: f64, lat: f64) -> WGS84Point {
+        WGS84Point {
+            lon,
+            lat,
+            ele: None,
+        }
+    }
+
+    fn bbox((west, south): (f64, f64), (east, north): (f64, f64)) -> WGS84BoundingBox {
+        WGS84BoundingBox {
+            min: wgs(west, south),
+            max: wgs(east, north),
+        }
+    }
+
+    #[test]
+    fn test_contains_other() {
+        // a tile east of the antimeridian, in the longitudes past 180°
+        let tile = bbox((179.0, -17.0), (181.0, -16.0));
+        assert!(tile.contains_other(&bbox((-179.5, -16.8), (-179.2, -16.2))));
+        assert!(tile.contains_other(&bbox((179.5, -16.8), (180.5, -16.2))));
+        // north of the tile, then across its northern edge
+        assert!(!tile.contains_other(&bbox((-179.5, -15.8), (-179.2, -15.2))));
+        assert!(!tile.contains_other(&bbox((-179.5, -16.8), (-179.2, -15.5))));
+        assert!(!tile.contains_other(&bbox((179.5, -16.8), (180.5, -15.5))));
+        // across its southern edge
+        assert!(!tile.contains_other(&bbox((179.5, -17.5), (180.5, -16.2))));
+        assert!(!bbox((7.0, 46.0), (7.1, 46.1)).contains_other(&bbox((7.02, 46.02), (7.08, 46.5))));
+    }
+
+    #[test]
+    fn test_distance() {
+        // a degree of latitude at the equator and at the pole, a degree of
+        // longitude at the equator
+        assert!((wgs(7.0, 0.0).distance_to(&wgs(7.0, 1.0)) - 110_574.4).abs() < 0.1);
+        assert!((wgs(7.0, 89.0).distance_to(&wgs(7.0, 90.0)) - 111_693.9).abs() < 0.1);
+        assert!((wgs(0.0, 0.0).distance_to(&wgs(1.0, 0.0)) - 111_319.5).abs() < 0.1);
+        // a 3 arc-second pixel at 70° north is narrow
+        let (w, h) = (
+            wgs(25.0, 70.0).distance_to(&wgs(25.0 + 1.0 / 1200.0, 70.0)),
+            wgs(25.0, 70.0).distance_to(&wgs(25.0, 70.0 + 1.0 / 1200.0)),
+        );
+        assert!(
+            (w / h - 70f64.to_radians().cos()).abs() < 0.01,
+            "{} {}",
+            w,
+            h
+        );
+        assert_eq!(wgs(7.0, 46.0).distance_to(&wgs(7.0, 46.0)), 0.0);
+
+        let a = MercatorPoint {
+            x: 0.0,
+            y: 0.0,
+            ele: Some(100.0),
+        };
+        let b = MercatorPoint {
+            x: 3.0,
+            y: 4.0,
+            ele: Some(112.0),
+        };
+        assert_eq!(a.distance_2d(&b), 5.0);
+        assert_eq!(a.distance_3d(&b), 13.0);
+        assert_eq!(a.distance_3d(&b.flat()), 5.0f64.hypot(100.0));
+    }
+}
@@ -0,0 +1,182 @@
+use std::io::Write;
+
+use geo::Contains;
+
+use crate::dataset::{Dataset, SampleOptions};
+use crate::intersection;
+use crate::pipeline;
+use crate::point::{MercatorPoint, WGS84Point};
+use crate::polygon::Polygon;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Format {
+    // lon,lat,elevation with a header line
+    Csv,
+    // space separated, no header
+    Xyz,
+}
+
+impl Format {
+    pub fn from_path(path: &str) -> Self {
+        if path.ends_with(".xyz") {
+            Format::Xyz
+        } else {
+            Format::Csv
+        }
+    }
+}
+
+// Writes one row per sample as it comes, nothing is kept in memory.
+pub struct PointWriter<W: Write> {
+    out: W,
+    format: Format,
+    // also write the projected x,y
+    projected: bool,
+    rows: usize,
+}
+
+impl<W: Write> PointWriter<W> {
+    pub fn new(mut out: W, format: Format, projected: bool) -> std::io::Result<Self> {
+        if format == Format::Csv {
+            match projected {
+                true => writeln!(out, "lon,lat,elevation,x,y")?,
+                false => writeln!(out, "lon,lat,elevation")?,
+            }
+        }
+        Ok(PointWriter {
+            out,
+            format,
+            projected,
+            rows: 0,
+        })
+    }
+
+    pub fn write(&mut self, wgs: &WGS84Point, mercator: &MercatorPoint) -> std::io::Result<()> {
+        let sep = match self.format {
+            Format::Csv => ",",
+            Format::Xyz => " ",
+        };
+        write!(
+            self.out,
+            "{:.8}{sep}{:.8}{sep}{:.3}",
+            wgs.lon,
+            wgs.lat,
+            mercator.ele.unwrap()
+        )?;
+        if self.projected {
+            write!(self.out, "{sep}{:.3}{sep}{:.3}", mercator.x, mercator.y)?;
+        }
+        writeln!(self.out)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn finish(mut self) -> std::io::Result<W> {
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+// Writes the samples of the datasets that fall inside the polygon, once at
+// the seams and overlaps of the datasets (see pipeline::visit_datasets).
+pub fn export<W: Write>(
+    polygon: &Polygon,
+    datasets: &[Dataset],
+    options: &SampleOptions,
+    writer: &mut PointWriter<W>,
+) -> std::io::Result<()> {
+    let pbbox = polygon.wgsbbox();
+    let (ring, holes) = polygon
+        .mercator()
+        .and_then(|ring| Ok((ring, polygon.mercator_holes()?)))
+        .map_err(std::io::Error::other)?;
+    let mask = intersection::to_geo_polygon_with_holes(&ring, &holes);
+    let mut error = None;
+    pipeline::visit_datasets(datasets, &pbbox, options, |wgs, mercator| {
+        if error.is_some() || !mask.contains(&geo::Coord::from((mercator.x, mercator.y))) {
+            return;
+        }
+        error = writer.write(wgs, &mercator).err();
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_export_rows() {
+        let step = 1.0 / 1200.0;
+        let point = |col: f64, row: f64| WGS84Point {
+            lon: 6.0 + col * step,
+            lat: 46.0 + row * step,
+            ele: None,
+        };
+        // between the pixel centers, half a step from the corners: columns
+        // 2..=5, three rows
+        let mut polygon = Polygon::new(vec![
+            point(2.0, 3.0),
+            point(6.0, 3.0),
+            point(6.0, 6.0),
+            point(2.0, 6.0),
+        ]);
+        polygon.proj4 = Some("+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs".into());
+        let mut writer = PointWriter::new(Vec::new(), Format::Csv, true).unwrap();
+        export(
+            &polygon,
+            &[fixtures::dataset()],
+            &SampleOptions::default(),
+            &mut writer,
+        )
+        .unwrap();
+        assert_eq!(writer.rows(), 12);
+        let text = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 13);
+        assert_eq!(lines[0], "lon,lat,elevation,x,y");
+        assert_eq!(lines[1].split(',').count(), 5);
+    }
+
+    // two tiles sharing their boundary column: its samples are written once
+    #[test]
+    fn test_export_seam() {
+        let plane = |x: f64, y: f64| Some(200.0 + 0.1 * x + 0.25 * y);
+        let datasets = [
+            fixtures::synthetic("west", (-250.0, -250.0, 0.0, 250.0), plane),
+            fixtures::synthetic("east", (0.0, -250.0, 250.0, 250.0), plane),
+        ];
+        let polygon = fixtures::polygon(&fixtures::square(100.0));
+        let mut writer = PointWriter::new(Vec::new(), Format::Xyz, false).unwrap();
+        export(&polygon, &datasets, &SampleOptions::default(), &mut writer).unwrap();
+        let rows = writer.rows();
+        let text = String::from_utf8(writer.finish().unwrap()).unwrap();
+        let positions: std::collections::BTreeSet<(&str, &str)> = text
+            .lines()
+            .map(|line| {
+                let mut fields = line.split(' ');
+                (fields.next().unwrap(), fields.next().unwrap())
+            })
+            .collect();
+        assert_eq!(positions.len(), rows);
+        // the seam column is in the polygon, a column of about 9.4 m
+        let seam = format!("{:.8}", fixtures::wgs(0.0, 0.0).lon);
+        assert!(positions.iter().any(|(lon, _)| **lon == seam));
+        let (mx, my) = fixtures::meters_per_degree();
+        let cells = 200.0 / (mx * fixtures::STEP) * 200.0 / (my * fixtures::STEP);
+        assert!(
+            (rows as f64 / cells - 1.0).abs() < 0.1,
+            "{} {}",
+            rows,
+            cells
+        );
+    }
+}
@@ -1,44 +1,144 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
 
 use crate::{
-    mercator::WebMercatorProjection,
-    point::{MercatorBoundingBox, MercatorPoint, WGS84BoundingBox, WGS84Point},
+    compute, intersection,
+    mercator::{ProjectionError, WebMercatorProjection},
+    point::{self, MercatorBoundingBox, MercatorPoint, WGS84BoundingBox, WGS84Point},
+    reference::{self, GeometryError},
+    triangulation::Triangle,
 };
 
+// The description of a polygon, see Polygon::info_data. Displayed as the
+// lines of the log.
+#[derive(Clone, Debug, Serialize)]
+pub struct PolygonInfo {
+    pub points: usize,
+    pub projection: String,
+    pub wgs_bbox: WGS84BoundingBox,
+    pub mercator_bbox: MercatorBoundingBox,
+    // of the mercator box
+    pub area: f64,
+    // the SRTM tiles it needs, by name (not logged)
+    pub candidates: BTreeSet<String>,
+}
+
+impl fmt::Display for PolygonInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "polygon: len: {}", self.points)?;
+        writeln!(f, "polygon: projection: {}", self.projection)?;
+        writeln!(f, "polygon: wgs bbox: {}", self.wgs_bbox)?;
+        writeln!(f, "polygon: mercator bbox: {}", self.mercator_bbox)?;
+        writeln!(f, "polygon: width: {:.1}", self.mercator_bbox.width())?;
+        writeln!(f, "polygon: height: {:.1}", self.mercator_bbox.height())?;
+        write!(f, "polygon: area: {:.1}", self.area)
+    }
+}
+
+// What Polygon::validate finds wrong with a ring: ring 0 is the outer one,
+// the holes follow.
+#[derive(Clone, Debug)]
+pub enum PolygonIssue {
+    // two edges cross or touch elsewhere than at the vertex they share
+    SelfIntersection { ring: usize, at: WGS84Point },
+    // the vertex repeats the one before it
+    DuplicateVertex { ring: usize, index: usize },
+    // the last vertex is not the first
+    Unclosed { ring: usize },
+    TooFewPoints { ring: usize, distinct: usize },
+}
+
+impl fmt::Display for PolygonIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolygonIssue::SelfIntersection { ring, at } => write!(
+                f,
+                "ring {}: self-intersection at lat {:.6}, lon {:.6}",
+                ring, at.lat, at.lon
+            ),
+            PolygonIssue::DuplicateVertex { ring, index } => {
+                write!(f, "ring {}: vertex {} repeats the one before", ring, index)
+            }
+            PolygonIssue::Unclosed { ring } => write!(f, "ring {}: not closed", ring),
+            PolygonIssue::TooFewPoints { ring, distinct } => write!(
+                f,
+                "ring {}: {} distinct points, at least 3 are needed",
+                ring, distinct
+            ),
+        }
+    }
+}
+
 pub struct Polygon {
     pub wgs: Vec<WGS84Point>,
+    // the interior rings, excluded from the area
+    pub holes: Vec<Vec<WGS84Point>>,
+    // projection override (proj4 string), the UTM zone of the center otherwise
+    pub proj4: Option<String>,
+    // the projection of mercator(), rebuilt when proj4 changes
+    projector: Mutex<Option<Arc<WebMercatorProjection>>>,
 }
 
 impl Polygon {
+    pub fn new(wgs: Vec<WGS84Point>) -> Self {
+        Self::projected(wgs, None)
+    }
+
+    pub fn projected(wgs: Vec<WGS84Point>, proj4: Option<String>) -> Self {
+        Polygon {
+            wgs,
+            holes: Vec::new(),
+            proj4,
+            projector: Mutex::new(None),
+        }
+    }
+
+    pub fn with_holes(mut self, holes: Vec<Vec<WGS84Point>>) -> Self {
+        self.holes = holes;
+        self
+    }
+
+    pub fn info_data(&self) -> Result<PolygonInfo, ProjectionError> {
+        let mercator_bbox = self.mercatorbbox()?;
+        Ok(PolygonInfo {
+            points: self.wgs.len(),
+            projection: self.projection(),
+            wgs_bbox: self.wgsbbox(),
+            area: mercator_bbox.area(),
+            mercator_bbox,
+            candidates: self.required_tiles(),
+        })
+    }
     pub fn info(&self) {
-        log::info!("polygon: len: {}", self.wgs.len());
-        log::info!("polygon: projection: {}", self.projection());
-        log::info!("polygon: wgs bbox: {}", self.wgsbbox());
-        log::info!("polygon: mercator bbox: {}", self.mercatorbbox());
-        log::info!("polygon: width: {:.1}", self.mercatorbbox().width());
-        log::info!("polygon: height: {:.1}", self.mercatorbbox().height());
-        log::info!("polygon: area: {:.1}", self.mercatorbbox().area());
-        let d = self
-            .candidates()
-            .iter()
-            .cloned()
-            .collect::<Vec<String>>()
-            .join(", ");
-        log::info!("polygon: candidates: {}", d);
+        match self.info_data() {
+            Ok(info) => {
+                for line in info.to_string().lines() {
+                    log::info!("{}", line);
+                }
+            }
+            Err(e) => log::warn!("{}", e),
+        }
     }
 
+    // The box of the vertices. Across the antimeridian, the longitudes
+    // continue east of 180°: the box is that of the polygon, not the world.
     pub fn wgsbbox(&self) -> WGS84BoundingBox {
+        let shift = self.crosses_antimeridian();
+        let lon = |p: &WGS84Point| p.unwrapped_lon(shift);
         let (min, max) = self.wgs.iter().fold(
             (self.wgs[0].clone(), self.wgs[0].clone()), // Initialize with the first coordinate
             |(min, max), curr| {
                 (
                     WGS84Point {
-                        lon: min.lon.min(curr.lon),
+                        lon: lon(&min).min(lon(curr)),
                         lat: min.lat.min(curr.lat),
                         ele: None,
                     }, // New min
                     WGS84Point {
-                        lon: max.lon.max(curr.lon),
+                        lon: lon(&max).max(lon(curr)),
                         lat: max.lat.max(curr.lat),
                         ele: None,
                     }, // New
@@ -47,9 +147,12 @@ impl Polygon {
         );
         WGS84BoundingBox { min, max }
     }
+    pub fn crosses_antimeridian(&self) -> bool {
+        point::crosses_antimeridian(&self.wgs)
+    }
 
-    pub fn mercatorbbox(&self) -> MercatorBoundingBox {
-        let mercatorpoints = self.mercator();
+    pub fn mercatorbbox(&self) -> Result<MercatorBoundingBox, ProjectionError> {
+        let mercatorpoints = self.mercator()?;
         let (min, max) = mercatorpoints.iter().fold(
             (mercatorpoints[0].clone(), mercatorpoints[0].clone()), // Initialize with the first coordinate
             |(min, max), curr| {
@@ -67,197 +170,1498 @@ impl Polygon {
                 )
             },
         );
-        MercatorBoundingBox { min, max }
+        Ok(MercatorBoundingBox { min, max })
     }
     pub fn projection(&self) -> String {
+        if let Some(proj4) = &self.proj4 {
+            return proj4.clone();
+        }
+        self.utm_center().to_utm_proj4()
+    }
+    // the center of the box, whose UTM zone is the projection
+    fn utm_center(&self) -> WGS84Point {
         assert!(!self.wgs.is_empty());
-        let wgs0 = self.wgs.first().unwrap().clone();
-        wgs0.to_utm_proj4()
+        self.wgsbbox().center()
     }
-    pub fn mercator(&self) -> Vec<MercatorPoint> {
-        let proj = WebMercatorProjection::make(&self.projection());
-        self.wgs.iter().map(|w| proj.project(&w)).collect()
+    // How far (degrees) the polygon extends beyond the UTM zone it is
+    // projected in, 0 inside of it or with a projection override, and the
+    // scale error of the projection at its farthest vertex.
+    pub fn zone_overhang(&self) -> (f64, f64) {
+        if self.proj4.is_some() {
+            return (0.0, 0.0);
+        }
+        let center = self.utm_center();
+        let zone = ((center.lon + 180.0).rem_euclid(360.0) / 6.0).floor() + 1.0;
+        let meridian = 6.0 * zone - 183.0;
+        let offset = |p: &WGS84Point| ((p.lon - meridian + 180.0).rem_euclid(360.0) - 180.0).abs();
+        let farthest = self
+            .wgs
+            .iter()
+            .max_by(|a, b| offset(a).total_cmp(&offset(b)))
+            .unwrap();
+        // the scale factor of the transverse Mercator projection, to the
+        // second order of the distance to the central meridian
+        let d = offset(farthest).to_radians() * farthest.lat.to_radians().cos();
+        let scale = 0.9996 * (1.0 + d * d / 2.0);
+        ((offset(farthest) - 3.0).max(0.0), scale - 1.0)
     }
-    pub fn candidates(&self) -> BTreeSet<String> {
-        return dataset::candidates(&self);
+    pub fn projector(&self) -> Arc<WebMercatorProjection> {
+        let spec = self.projection();
+        let mut projector = self.projector.lock().unwrap();
+        match projector.as_ref() {
+            Some(p) if p.spec() == spec => p.clone(),
+            _ => projector
+                .insert(Arc::new(WebMercatorProjection::make(&spec)))
+                .clone(),
+        }
+    }
+    pub fn mercator(&self) -> Result<Vec<MercatorPoint>, ProjectionError> {
+        self.projector().project_many(&self.wgs)
+    }
+    pub fn mercator_holes(&self) -> Result<Vec<Vec<MercatorPoint>>, ProjectionError> {
+        let projector = self.projector();
+        self.holes
+            .iter()
+            .map(|hole| projector.project_many(hole))
+            .collect()
+    }
+    // The areas of the outer ring less those of the holes: on the ellipsoid,
+    // and in the projection.
+    pub fn geodesic_area(&self) -> Result<f64, GeometryError> {
+        let mut ret = reference::geodesic_area(&self.wgs)?;
+        for hole in &self.holes {
+            ret -= reference::geodesic_area(hole)?;
+        }
+        Ok(ret)
+    }
+    pub fn planar_area(&self) -> Result<f64, GeometryError> {
+        let mut ret = reference::planar_area(&self.mercator()?)?;
+        for hole in self.mercator_holes()? {
+            ret -= reference::planar_area(&hole)?;
+        }
+        Ok(ret)
+    }
+    // The center of mass of the area, holes excluded, in the projection.
+    pub fn centroid(&self) -> Result<WGS84Point, ProjectionError> {
+        use geo::Centroid;
+        let outline =
+            intersection::to_geo_polygon_with_holes(&self.mercator()?, &self.mercator_holes()?);
+        let center = match outline.centroid() {
+            Some(c) => MercatorPoint {
+                x: c.x(),
+                y: c.y(),
+                ele: None,
+            },
+            None => return Ok(self.wgsbbox().center()),
+        };
+        self.projector().unproject(&center)
+    }
+    // The length of the rings, holes included, along the geodesics between
+    // the vertices, in meters.
+    pub fn perimeter_2d(&self) -> f64 {
+        self.rings()
+            .map(|ring| ring_length(ring, |a, b| a.distance_to(b)))
+            .sum()
+    }
+    // The same with the differences of elevation of the vertices, as a fence
+    // on the terrain, None unless every vertex has an elevation.
+    pub fn perimeter_3d(&self) -> Option<f64> {
+        if self.rings().flatten().any(|p| p.ele.is_none()) {
+            return None;
+        }
+        let length = |a: &WGS84Point, b: &WGS84Point| {
+            a.distance_to(b).hypot(b.ele.unwrap() - a.ele.unwrap())
+        };
+        Some(self.rings().map(|ring| ring_length(ring, length)).sum())
+    }
+    // the outer ring, then the holes
+    pub fn rings(&self) -> impl Iterator<Item = &Vec<WGS84Point>> {
+        std::iter::once(&self.wgs).chain(&self.holes)
+    }
+    // basenames of the SRTM tiles the polygon needs
+    pub fn required_tiles(&self) -> BTreeSet<String> {
+        crate::hgt::tiles(&self.wgs)
     }
-}
 
-pub fn flat(polygon: &Vec<MercatorPoint>) -> Vec<MercatorPoint> {
-    polygon.iter().map(|w| w.flat()).collect()
+    // Splits the polygon along a lon/lat grid with the given cell size (degrees).
+    // Each part gets its own Lambert azimuthal equal-area projection centered on
+    // the part, so that very large polygons are never projected in a single UTM zone.
+    pub fn split(&self, cell: f64) -> Vec<Polygon> {
+        use geo::{BooleanOps, Coord, Rect};
+        assert!(cell > 0f64);
+        let outline = self.to_geo();
+        let bbox = self.wgsbbox();
+        let cols = (bbox.min.lon / cell).floor() as i64..(bbox.max.lon / cell).ceil() as i64;
+        let rows = (bbox.min.lat / cell).floor() as i64..(bbox.max.lat / cell).ceil() as i64;
+        let mut ret = Vec::new();
+        for col in cols {
+            for row in rows.clone() {
+                let rect = Rect::new(
+                    Coord {
+                        x: col as f64 * cell,
+                        y: row as f64 * cell,
+                    },
+                    Coord {
+                        x: (col + 1) as f64 * cell,
+                        y: (row + 1) as f64 * cell,
+                    },
+                );
+                for part in outline.intersection(&rect.to_polygon()) {
+                    let mut polygon = Polygon::from_geo(&part, None);
+                    polygon.proj4 = Some(polygon.wgsbbox().center().to_laea_proj4());
+                    ret.push(polygon);
+                }
+            }
+        }
+        ret
+    }
+
+    // in lon/lat, east of 180° across the antimeridian (see wgsbbox)
+    fn to_geo(&self) -> geo::Polygon {
+        let shift = self.crosses_antimeridian();
+        geo::Polygon::new(
+            line_string(&self.wgs, shift),
+            self.holes
+                .iter()
+                .map(|hole| line_string(hole, shift))
+                .collect(),
+        )
+    }
+    fn from_geo(polygon: &geo::Polygon, proj4: Option<String>) -> Polygon {
+        let wgs = |ring: &geo::LineString| -> Vec<WGS84Point> {
+            ring.coords()
+                .map(|c| WGS84Point {
+                    lon: if c.x > 180.0 { c.x - 360.0 } else { c.x },
+                    lat: c.y,
+                    ele: None,
+                })
+                .collect()
+        };
+        Polygon::projected(wgs(polygon.exterior()), proj4)
+            .with_holes(polygon.interiors().iter().map(wgs).collect())
+    }
+
+    // The issues of every ring, none for a polygon the boolean operations of
+    // the clip can take as is.
+    pub fn validate(&self) -> Vec<PolygonIssue> {
+        self.rings()
+            .enumerate()
+            .flat_map(|(ring, points)| ring_issues(ring, points))
+            .collect()
+    }
+
+    // The polygon without the issues of validate: the repeated vertices are
+    // removed, the rings closed, the holes of less than 3 points dropped and
+    // the self-intersections resolved by the union of the polygon with
+    // itself, whose lobes are returned as separate polygons. An outer ring
+    // of less than 3 points cannot be repaired.
+    pub fn repaired(&self) -> Result<Vec<Polygon>, GeometryError> {
+        use geo::BooleanOps;
+        let issues = self.validate();
+        let mut crossing = false;
+        let mut duplicates = 0;
+        let mut dropped = BTreeSet::new();
+        for issue in &issues {
+            match issue {
+                PolygonIssue::SelfIntersection { .. } => crossing = true,
+                PolygonIssue::DuplicateVertex { .. } => duplicates += 1,
+                PolygonIssue::Unclosed { ring } => log::info!("polygon: ring {} closed", ring),
+                PolygonIssue::TooFewPoints { ring: 0, .. } => {
+                    return Err(GeometryError::Invalid {
+                        reason: issue.to_string(),
+                    });
+                }
+                PolygonIssue::TooFewPoints { ring, .. } => {
+                    log::warn!("polygon: {}, hole dropped", issue);
+                    dropped.insert(*ring);
+                }
+            }
+        }
+        if duplicates > 0 {
+            log::warn!("polygon: {} repeated vertices removed", duplicates);
+        }
+        let holes = self
+            .holes
+            .iter()
+            .enumerate()
+            .filter(|(k, _)| !dropped.contains(&(k + 1)))
+            .map(|(_, hole)| closed(hole))
+            .collect();
+        let polygon = Polygon::projected(closed(&self.wgs), self.proj4.clone()).with_holes(holes);
+        if !crossing {
+            return Ok(vec![polygon]);
+        }
+        let lobes = polygon
+            .to_geo()
+            .union(&geo::MultiPolygon::<f64>::new(vec![]));
+        if lobes.0.is_empty() {
+            return Err(GeometryError::Invalid {
+                reason: "the rings enclose no area".to_string(),
+            });
+        }
+        log::warn!(
+            "polygon: self-intersections resolved into {} parts",
+            lobes.0.len()
+        );
+        Ok(lobes
+            .iter()
+            .map(|lobe| Polygon::from_geo(lobe, self.proj4.clone()))
+            .collect())
+    }
+
+    // The rings with the vertices dropped that are within tolerance (meters)
+    // of the simplified line, see simplified_rings. The rings do not cross
+    // themselves or each other, and no hole leaves the outer ring, unless
+    // they did.
+    pub fn simplified(&self, tolerance: f64) -> Result<Polygon, ProjectionError> {
+        let projected: Vec<Vec<MercatorPoint>> = std::iter::once(self.mercator()?)
+            .chain(self.mercator_holes()?)
+            .collect();
+        let rings: Vec<(&[WGS84Point], &[MercatorPoint])> = self
+            .rings()
+            .zip(&projected)
+            .map(|(ring, projected)| (ring.as_slice(), projected.as_slice()))
+            .collect();
+        let mut simplified = simplified_rings(&rings, tolerance).into_iter();
+        let outer = simplified.next().unwrap();
+        Ok(Polygon::projected(outer, self.proj4.clone()).with_holes(simplified.collect()))
+    }
+
+    // The rings with their corners cut `iterations` times (Chaikin), in the
+    // projection: see chaikin.
+    pub fn smooth(&self, iterations: usize, max_shift: f64) -> Result<Polygon, ProjectionError> {
+        let proj = self.projector();
+        let smooth = |mut ring: Vec<MercatorPoint>| -> Result<Vec<WGS84Point>, ProjectionError> {
+            for _ in 0..iterations {
+                ring = chaikin(&ring, max_shift);
+            }
+            ring.iter().map(|m| proj.unproject(m)).collect()
+        };
+        let holes = self
+            .mercator_holes()?
+            .into_iter()
+            .map(smooth)
+            .collect::<Result<_, _>>()?;
+        Ok(Polygon::projected(smooth(self.mercator()?)?, self.proj4.clone()).with_holes(holes))
+    }
 }
 
-pub fn calculate_3d_surface_area(polygon: &Vec<MercatorPoint>) -> f64 {
-    if polygon.len() < 3 {
+// the sum of the lengths of the edges, the last vertex to the first included
+fn ring_length(ring: &[WGS84Point], length: impl Fn(&WGS84Point, &WGS84Point) -> f64) -> f64 {
+    if ring.len() < 2 {
         return 0.0;
     }
+    ring.iter()
+        .zip(ring.iter().cycle().skip(1))
+        .map(|(a, b)| length(a, b))
+        .sum()
+}
 
-    let mut total_vec_x = 0.0;
-    let mut total_vec_y = 0.0;
-    let mut total_vec_z = 0.0;
+// the negative longitudes shifted by a turn, for a ring across 180°
+fn line_string(ring: &[WGS84Point], shift: bool) -> geo::LineString {
+    ring.iter()
+        .map(|w| geo::Coord {
+            x: w.unwrapped_lon(shift),
+            y: w.lat,
+        })
+        .collect()
+}
 
-    for i in 0..polygon.len() {
-        let p1 = &polygon[i];
-        let p2 = &polygon[(i + 1) % polygon.len()];
+fn same(a: &WGS84Point, b: &WGS84Point) -> bool {
+    a.lon == b.lon && a.lat == b.lat
+}
 
-        // Elevation is treated as Z. We use 0.0 if ele is None.
-        let z1 = p1.ele.unwrap();
-        let z2 = p2.ele.unwrap();
+// the ring without repeated vertices, its first one at the end
+fn closed(ring: &[WGS84Point]) -> Vec<WGS84Point> {
+    let mut ret: Vec<WGS84Point> = Vec::with_capacity(ring.len() + 1);
+    for p in ring {
+        if !ret.last().is_some_and(|last| same(last, p)) {
+            ret.push(p.clone());
+        }
+    }
+    if let (Some(first), Some(last)) = (ret.first(), ret.last())
+        && !same(first, last)
+    {
+        ret.push(first.clone());
+    }
+    ret
+}
 
-        // Cross product components: (p1 x p2)
-        total_vec_x += (p1.y * z2) - (z1 * p2.y);
-        total_vec_y += (z1 * p2.x) - (p1.x * z2);
-        total_vec_z += (p1.x * p2.y) - (p1.y * p2.x);
+fn ring_issues(ring: usize, points: &[WGS84Point]) -> Vec<PolygonIssue> {
+    let mut ret = Vec::new();
+    for index in 1..points.len() {
+        if same(&points[index - 1], &points[index]) {
+            ret.push(PolygonIssue::DuplicateVertex { ring, index });
+        }
+    }
+    if let (Some(first), Some(last)) = (points.first(), points.last())
+        && !same(first, last)
+    {
+        ret.push(PolygonIssue::Unclosed { ring });
     }
+    let mut coords: Vec<(f64, f64)> = points.iter().map(|p| (p.lon, p.lat)).collect();
+    coords.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    coords.dedup();
+    if coords.len() < 3 {
+        ret.push(PolygonIssue::TooFewPoints {
+            ring,
+            distinct: coords.len(),
+        });
+        return ret;
+    }
+    let mut vertices = closed(points);
+    vertices.pop();
+    let mut crossed: Vec<(f64, f64)> = Vec::new();
+    for (_, _, at) in crossings(&vertices) {
+        // the edges through a vertex meet in pairs
+        if !crossed.contains(&(at.lon, at.lat)) {
+            crossed.push((at.lon, at.lat));
+            ret.push(PolygonIssue::SelfIntersection { ring, at });
+        }
+    }
+    ret
+}
 
-    // The magnitude of the sum of cross products
-    let magnitude = (total_vec_x.powi(2) + total_vec_y.powi(2) + total_vec_z.powi(2)).sqrt();
+// The pairs of edges of the (implicitly closed) ring that cross or touch,
+// by a sweep over the edges, and where: edge k goes from vertex k to the
+// next. Consecutive edges share a vertex, that is no crossing unless they
+// overlap.
+fn crossings(vertices: &[WGS84Point]) -> Vec<(usize, usize, WGS84Point)> {
+    use geo::sweep::{Cross, Intersections};
+    use geo::{Coord, Line, LineIntersection};
 
-    magnitude / 2.0
+    #[derive(Clone)]
+    struct Edge {
+        index: usize,
+        line: Line,
+    }
+    impl Cross for Edge {
+        type Scalar = f64;
+        fn line(&self) -> Line {
+            self.line
+        }
+    }
+
+    let n = vertices.len();
+    let across = point::crosses_antimeridian(vertices);
+    let coord = |k: usize| Coord {
+        x: vertices[k % n].unwrapped_lon(across),
+        y: vertices[k % n].lat,
+    };
+    let edges = (0..n).map(|index| Edge {
+        index,
+        line: Line::new(coord(index), coord(index + 1)),
+    });
+    let consecutive = |a: usize, b: usize| {
+        let d = a.abs_diff(b);
+        d == 1 || d == n - 1
+    };
+    let mut ret = Vec::new();
+    for (a, b, intersection) in Intersections::from_iter(edges) {
+        let at = match intersection {
+            LineIntersection::SinglePoint {
+                intersection,
+                is_proper,
+            } => {
+                if !is_proper && consecutive(a.index, b.index) {
+                    continue;
+                }
+                intersection
+            }
+            LineIntersection::Collinear { intersection } => intersection.start,
+        };
+        let at = WGS84Point {
+            lon: if at.x > 180.0 { at.x - 360.0 } else { at.x },
+            lat: at.y,
+            ele: None,
+        };
+        ret.push((a.index.min(b.index), a.index.max(b.index), at));
+    }
+    ret
 }
 
-mod dataset {
-    use super::Polygon;
-    use std::collections::BTreeSet;
-    use std::env;
+// The pairs of edges of two distinct (implicitly closed) rings that cross
+// or touch: edge k goes from vertex k to the next.
+fn crossings_between(a: &[WGS84Point], b: &[WGS84Point]) -> Vec<(usize, usize)> {
+    use geo::sweep::{Cross, Intersections};
+    use geo::{Coord, Line};
 
-    pub fn datasetstring(s: &String) -> String {
-        if s.contains(&"GL1") {
-            "/home/julien/DEM/SRTM/GL1/S2/output_SRTMGL1.tif".to_string()
-        } else if s.contains("HGT") {
-            "/home/julien/DEM/SRTM/GL3/hgt/N18W070.hgt".to_string()
-        } else {
-            String::new()
+    #[derive(Clone)]
+    struct Edge {
+        ring: usize,
+        index: usize,
+        line: Line,
+    }
+    impl Cross for Edge {
+        type Scalar = f64;
+        fn line(&self) -> Line {
+            self.line
         }
     }
 
-    fn datasetsenv() -> Vec<String> {
-        match env::var("DATASETS") {
-            Ok(val) => return val.split(",").map(|s| s.to_string()).collect(),
-            Err(_) => {}
+    let both: Vec<WGS84Point> = a.iter().chain(b).cloned().collect();
+    let across = point::crosses_antimeridian(&both);
+    let edges = [a, b].into_iter().enumerate().flat_map(|(ring, vertices)| {
+        let n = vertices.len();
+        let coord = move |k: usize| Coord {
+            x: vertices[k % n].unwrapped_lon(across),
+            y: vertices[k % n].lat,
+        };
+        (0..n).map(move |index| Edge {
+            ring,
+            index,
+            line: Line::new(coord(index), coord(index + 1)),
+        })
+    });
+    Intersections::from_iter(edges)
+        .filter(|(e, f, _)| e.ring != f.ring)
+        .map(|(e, f, _)| match e.ring {
+            0 => (e.index, f.index),
+            _ => (f.index, e.index),
+        })
+        .collect()
+}
+
+// The vertices of a ring kept by Douglas-Peucker, in the projection, by
+// index in the ring, with the tolerance of the edge they start.
+struct Simplification<'a> {
+    ring: &'a [WGS84Point],
+    projected: &'a [MercatorPoint],
+    // without the repeated first vertex
+    n: usize,
+    closed: bool,
+    kept: BTreeMap<usize, f64>,
+}
+
+impl<'a> Simplification<'a> {
+    // The ring is cut at its first vertex and the one farthest from it, and
+    // each edge of the simplified ring is then split at the vertex it
+    // replaces that is the farthest from it, as long as that is more than
+    // the tolerance. A ring of less than 4 vertices is kept.
+    fn new(ring: &'a [WGS84Point], projected: &'a [MercatorPoint], tolerance: f64) -> Self {
+        let closed = ring.len() > 1 && same(&ring[0], &ring[ring.len() - 1]);
+        let n = if closed { ring.len() - 1 } else { ring.len() };
+        let mut ret = Simplification {
+            ring,
+            projected,
+            n,
+            closed,
+            kept: BTreeMap::new(),
+        };
+        if n < 4 {
+            ret.kept = (0..n).map(|k| (k, tolerance)).collect();
+            return ret;
+        }
+        let first = (1..n)
+            .max_by(|x, y| {
+                let d = |k: usize| projected[k].distance_2d(&projected[0]);
+                d(*x).total_cmp(&d(*y))
+            })
+            .unwrap();
+        ret.split(0, first, tolerance);
+        ret.split(first, n, tolerance);
+        if ret.kept.len() < 3 {
+            // a triangle at least
+            let (a, b) = if first > 1 { (0, first) } else { (first, n) };
+            let (k, _) = ret.farthest(a, b).unwrap();
+            ret.kept.insert(k, tolerance);
         }
-        Vec::new()
+        ret
+    }
+
+    // the vertex of the edge a -> b (b = n is the first vertex) the farthest
+    // from it, and how far
+    fn farthest(&self, a: usize, b: usize) -> Option<(usize, f64)> {
+        let p = self.projected;
+        ((a + 1)..b)
+            .map(|k| (k, segment_distance(&p[k], &p[a], &p[b % self.n])))
+            .max_by(|x, y| x.1.total_cmp(&y.1))
     }
 
-    pub fn candidates(polygon: &Polygon) -> BTreeSet<String> {
-        let ret1: BTreeSet<String> = datasetsenv().iter().map(|s| datasetstring(s)).collect();
-        if !ret1.is_empty() {
-            return ret1;
+    fn split(&mut self, a: usize, b: usize, tolerance: f64) {
+        let mut edges = vec![(a, b)];
+        while let Some((a, b)) = edges.pop() {
+            self.kept.insert(a, tolerance);
+            if let Some((k, d)) = self.farthest(a, b)
+                && d > tolerance
+            {
+                edges.push((a, k));
+                edges.push((k, b));
+            }
         }
+    }
 
-        let mut ret = BTreeSet::new();
+    // the edges of the simplified ring, by the indices of their ends in the
+    // ring (b = n for the first vertex)
+    fn edges(&self) -> Vec<(usize, usize)> {
+        let vertices: Vec<usize> = self.kept.keys().copied().collect();
+        (0..vertices.len())
+            .map(|e| (vertices[e], vertices.get(e + 1).copied().unwrap_or(self.n)))
+            .collect()
+    }
 
-        let hgtdir = "/home/julien/DEM/SRTM/GL3/hgt";
-        let htg: BTreeSet<String> = polygon
-            .wgs
-            .iter()
-            .map(|w| format!("{}/{}", hgtdir, crate::hgt::hgt_basename(w)))
+    // Splits the edge, the e-th of the simplified ring, at its farthest
+    // vertex with half its tolerance. False for an edge of the input ring.
+    fn split_edge(&mut self, e: usize) -> bool {
+        let (a, b) = self.edges()[e];
+        let Some((k, _)) = self.farthest(a, b) else {
+            return false;
+        };
+        let tolerance = self.kept[&a] / 2.0;
+        self.split(a, k, tolerance);
+        self.split(k, b, tolerance);
+        true
+    }
+
+    // The area between the edge, the e-th of the simplified ring, and the
+    // vertices of the ring it replaces, in the projection. None for an edge
+    // of the input ring.
+    fn pocket(&self, e: usize) -> Option<geo::Polygon> {
+        let (a, b) = self.edges()[e];
+        if b - a < 2 {
+            return None;
+        }
+        let ring: geo::LineString = (a..=b)
+            .map(|k| {
+                let p = &self.projected[k % self.n];
+                geo::Coord { x: p.x, y: p.y }
+            })
             .collect();
-        for h in &htg {
-            ret.insert(h.clone());
+        Some(geo::Polygon::new(ring, Vec::new()))
+    }
+
+    fn points(&self) -> Vec<WGS84Point> {
+        self.kept.keys().map(|k| self.ring[*k].clone()).collect()
+    }
+
+    fn result(&self) -> Vec<WGS84Point> {
+        let mut ret = self.points();
+        if self.closed {
+            ret.push(ret[0].clone());
+        }
+        ret
+    }
+}
+
+// The rings of a polygon (the outer one first, then its holes, with their
+// projection) simplified by Douglas-Peucker (see Simplification). The edges
+// of the simplified rings that cross an edge of the same ring or of another
+// one are split again, with half their tolerance, and so are the edges that
+// pass over the first vertex of another ring: a hole that the outer ring or
+// another hole would swallow. Until none does.
+fn simplified_rings(
+    rings: &[(&[WGS84Point], &[MercatorPoint])],
+    tolerance: f64,
+) -> Vec<Vec<WGS84Point>> {
+    use geo::Contains;
+
+    let mut simplifications: Vec<Simplification> = rings
+        .iter()
+        .map(|(ring, projected)| Simplification::new(ring, projected, tolerance))
+        .collect();
+    loop {
+        let points: Vec<Vec<WGS84Point>> = simplifications.iter().map(|s| s.points()).collect();
+        let mut crossed = vec![BTreeSet::new(); rings.len()];
+        for (i, ring) in points.iter().enumerate() {
+            for (a, b, _) in crossings(ring) {
+                crossed[i].insert(a);
+                crossed[i].insert(b);
+            }
+            for (j, other) in points.iter().enumerate().skip(i + 1) {
+                for (a, b) in crossings_between(ring, other) {
+                    crossed[i].insert(a);
+                    crossed[j].insert(b);
+                }
+            }
+        }
+        if crossed.iter().all(|c| c.is_empty()) {
+            // without crossings, a ring is on one side of an edge and of the
+            // vertices it replaces, or it is in between
+            for (i, simplification) in simplifications.iter().enumerate() {
+                for e in 0..simplification.kept.len() {
+                    let Some(pocket) = simplification.pocket(e) else {
+                        continue;
+                    };
+                    let swallowed = rings.iter().enumerate().any(|(j, (_, projected))| {
+                        j != i
+                            && projected
+                                .first()
+                                .is_some_and(|p| pocket.contains(&geo::Point::new(p.x, p.y)))
+                    });
+                    if swallowed {
+                        crossed[i].insert(e);
+                    }
+                }
+            }
+        }
+        let mut split = false;
+        for (simplification, crossed) in simplifications.iter_mut().zip(&crossed) {
+            // from the last edge, the ones before keep their index
+            for e in crossed.iter().rev() {
+                // an edge of the input ring cannot be split, the input
+                // crosses itself
+                split |= simplification.split_edge(*e);
+            }
+        }
+        if !split {
+            break;
+        }
+    }
+    simplifications.iter().map(|s| s.result()).collect()
+}
+
+fn segment_distance(p: &MercatorPoint, a: &MercatorPoint, b: &MercatorPoint) -> f64 {
+    let (dx, dy) = (b.x - a.x, b.y - a.y);
+    let length2 = dx * dx + dy * dy;
+    let t = if length2 == 0.0 {
+        0.0
+    } else {
+        (((p.x - a.x) * dx + (p.y - a.y) * dy) / length2).clamp(0.0, 1.0)
+    };
+    ((p.x - a.x - t * dx).powi(2) + (p.y - a.y - t * dy).powi(2)).sqrt()
+}
+
+// One corner cutting pass: every edge is replaced by its points at 1/4 and
+// 3/4, the cut is at most max_shift away from the vertex it replaces. A ring
+// that repeats its first vertex at the end still does.
+pub fn chaikin(ring: &[MercatorPoint], max_shift: f64) -> Vec<MercatorPoint> {
+    let closed = ring.len() > 1 && ring.first() == ring.last();
+    let ring = if closed {
+        &ring[..ring.len() - 1]
+    } else {
+        ring
+    };
+    if ring.len() < 3 {
+        return ring.to_vec();
+    }
+    let mut ret = Vec::with_capacity(2 * ring.len() + 1);
+    for (k, a) in ring.iter().enumerate() {
+        let b = &ring[(k + 1) % ring.len()];
+        let length = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        if length == 0.0 {
+            continue;
+        }
+        let t = (0.25 * length).min(max_shift) / length;
+        let at = |t: f64| MercatorPoint {
+            x: a.x + t * (b.x - a.x),
+            y: a.y + t * (b.y - a.y),
+            ele: None,
+        };
+        ret.push(at(t));
+        ret.push(at(1.0 - t));
+    }
+    if closed {
+        ret.push(ret[0].clone());
+    }
+    ret
+}
+
+pub fn flat(polygon: &Vec<MercatorPoint>) -> Vec<MercatorPoint> {
+    polygon.iter().map(|w| w.flat()).collect()
+}
+
+#[derive(Debug)]
+pub enum AreaError {
+    MissingElevation { index: usize },
+}
+
+impl fmt::Display for AreaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AreaError::MissingElevation { index } => {
+                write!(f, "vertex {} has no elevation", index)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AreaError {}
+
+// Gives the vertices without an elevation the one interpolated between the
+// vertices with one before and after them, by the distance in the plane,
+// and returns how many. A ring without any elevation is left as is.
+pub fn patch_elevations(polygon: &mut [MercatorPoint]) -> usize {
+    let known: Vec<usize> = (0..polygon.len())
+        .filter(|k| polygon[*k].ele.is_some())
+        .collect();
+    if known.is_empty() || known.len() == polygon.len() {
+        return 0;
+    }
+    let n = polygon.len();
+    let mut patched = 0;
+    for (i, &a) in known.iter().enumerate() {
+        let b = known[(i + 1) % known.len()];
+        // the vertices between a and b, cyclically
+        let gap: Vec<usize> = (1..(b + n - a - 1) % n + 1).map(|d| (a + d) % n).collect();
+        if gap.is_empty() {
+            continue;
         }
+        let mut lengths = Vec::with_capacity(gap.len() + 1);
+        let mut length = 0.0;
+        let mut previous = a;
+        for &k in gap.iter().chain(std::iter::once(&b)) {
+            length += polygon[previous].distance_2d(&polygon[k]);
+            lengths.push(length);
+            previous = k;
+        }
+        let (za, zb) = (polygon[a].ele.unwrap(), polygon[b].ele.unwrap());
+        for (k, along) in gap.iter().zip(&lengths) {
+            let t = if length > 0.0 { along / length } else { 0.0 };
+            polygon[*k].ele = Some(za + t * (zb - za));
+            patched += 1;
+        }
+    }
+    patched
+}
 
-        // Recursively search for .tif files in gl1_dir
-        let gl1_dir = "/home/julien/DEM/SRTM/GL1";
-        for entry in walkdir::WalkDir::new(gl1_dir)
+// The area of the surface through the vertices, each ring triangulated by
+// ear clipping in the XY plane (see ear_clip): unlike a fan, the triangles
+// stay within concave rings, which matters once the vertices are not in one
+// plane, as the vector area of the ring assumes. The rings of the parts and
+// holes that follow the first one (each one closed) count with the sign of
+// their orientation: the holes, turned the other way, are subtracted, which
+// is exact in the plane of their part.
+pub fn calculate_3d_surface_area(polygon: &Vec<MercatorPoint>) -> Result<f64, AreaError> {
+    if let Some(index) = polygon.iter().position(|p| p.ele.is_none()) {
+        return Err(AreaError::MissingElevation { index });
+    }
+    let mut total = 0.0;
+    for ring in split_rings(polygon) {
+        let area: f64 = ear_clip(ring)
             .into_iter()
-            .filter_map(Result::ok)
-        {
-            let path = entry.path();
-            if path.extension().and_then(|ext| ext.to_str()) == Some("tif") {
-                ret.insert(path.to_string_lossy().into_owned());
+            .map(|[a, b, c]| Triangle(ring[a].clone(), ring[b].clone(), ring[c].clone()).area())
+            .sum();
+        total += area.copysign(signed_area(ring));
+    }
+    Ok(total.abs())
+}
+
+// The rings of the vertices, each one without its closing vertex: a ring
+// ends where its first vertex comes back, the last one where the vertices
+// end.
+fn split_rings(polygon: &[MercatorPoint]) -> Vec<&[MercatorPoint]> {
+    let mut ret = Vec::new();
+    let mut start = 0;
+    for k in 1..polygon.len() {
+        if k > start && polygon[k].x == polygon[start].x && polygon[k].y == polygon[start].y {
+            ret.push(&polygon[start..k]);
+            start = k + 1;
+        }
+    }
+    if start < polygon.len() {
+        ret.push(&polygon[start..]);
+    }
+    ret
+}
+
+// twice the area of the ring in the XY plane, positive counterclockwise
+fn signed_area(ring: &[MercatorPoint]) -> f64 {
+    (0..ring.len())
+        .map(|k| {
+            let (p, q) = (&ring[k], &ring[(k + 1) % ring.len()]);
+            p.x * q.y - q.x * p.y
+        })
+        .sum()
+}
+
+// The triangles of a simple ring in the XY plane, as indices into it: an
+// ear, a convex corner without another vertex in its triangle, is cut off
+// until three vertices are left. A ring that crosses itself runs out of
+// ears: its corners are then cut off as they come, without a triangle for
+// those on the line of their neighbors, which would be vertical.
+fn ear_clip(ring: &[MercatorPoint]) -> Vec<[usize; 3]> {
+    let sign = signed_area(ring).signum();
+    let cross = |a: usize, b: usize, c: usize| {
+        let (a, b, c) = (&ring[a], &ring[b], &ring[c]);
+        sign * ((b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x))
+    };
+    let mut remaining: Vec<usize> = (0..ring.len()).collect();
+    let mut ret = Vec::new();
+    let (mut k, mut failed) = (0, 0);
+    while remaining.len() > 3 {
+        let n = remaining.len();
+        k %= n;
+        let (a, b, c) = (
+            remaining[(k + n - 1) % n],
+            remaining[k],
+            remaining[(k + 1) % n],
+        );
+        let turn = cross(a, b, c);
+        let ear = turn > 0.0
+            && !remaining.iter().any(|&v| {
+                let same = |w: usize| ring[v].x == ring[w].x && ring[v].y == ring[w].y;
+                !same(a)
+                    && !same(b)
+                    && !same(c)
+                    && cross(a, b, v) >= 0.0
+                    && cross(b, c, v) >= 0.0
+                    && cross(c, a, v) >= 0.0
+            });
+        if ear || failed > n {
+            if turn != 0.0 {
+                ret.push([a, b, c]);
             }
+            remaining.remove(k);
+            failed = 0;
+        } else {
+            k += 1;
+            failed += 1;
         }
+    }
+    if let [a, b, c] = remaining[..]
+        && cross(a, b, c) != 0.0
+    {
+        ret.push([a, b, c]);
+    }
+    ret
+}
 
-        ret
+// The upward unit normal of the plane fitted through the vertices by total
+// least squares: the direction in which their covariance is the smallest.
+// None for vertices on a line, or as close to one as the slivers of the
+// clip (see compute::MIN_CONDITIONING).
+pub fn fit_plane(polygon: &[MercatorPoint]) -> Option<(f64, f64, f64)> {
+    let n = polygon.len() as f64;
+    let ele = |p: &MercatorPoint| p.ele.expect("All points must have elevation");
+    let cx = polygon.iter().map(|p| p.x).sum::<f64>() / n;
+    let cy = polygon.iter().map(|p| p.y).sum::<f64>() / n;
+    let cz = polygon.iter().map(ele).sum::<f64>() / n;
+    let mut c = [[0f64; 3]; 3];
+    for p in polygon {
+        let d = [p.x - cx, p.y - cy, ele(p) - cz];
+        for i in 0..3 {
+            for j in 0..3 {
+                c[i][j] += d[i] * d[j] / n;
+            }
+        }
+    }
+    let [first, second, last] = compute::eigenvalues(c);
+    if second <= compute::MIN_CONDITIONING * first {
+        return None;
+    }
+    // the eigenvector of the smallest eigenvalue is normal to the rows of
+    // c - last * I: the largest cross product of two of them
+    let row = |i: usize| {
+        let mut r = c[i];
+        r[i] -= last;
+        r
+    };
+    let cross = |a: [f64; 3], b: [f64; 3]| {
+        [
+            a[1] * b[2] - a[2] * b[1],
+            a[2] * b[0] - a[0] * b[2],
+            a[0] * b[1] - a[1] * b[0],
+        ]
+    };
+    let norm = |v: &[f64; 3]| (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    let v = [
+        cross(row(0), row(1)),
+        cross(row(0), row(2)),
+        cross(row(1), row(2)),
+    ]
+    .into_iter()
+    .max_by(|a, b| norm(a).total_cmp(&norm(b)))
+    .unwrap();
+    let length = norm(&v);
+    if length == 0.0 {
+        return None;
+    }
+    let sign = if v[2] < 0.0 { -1.0 } else { 1.0 };
+    Some((
+        sign * v[0] / length,
+        sign * v[1] / length,
+        sign * v[2] / length,
+    ))
+}
+
+// below this slope (1%) a plane has no aspect
+const FLAT: f64 = 0.01;
+
+// In percent, of the plane with the upward unit normal, infinite for a
+// vertical one.
+pub fn normal_slope((nx, ny, nz): (f64, f64, f64)) -> f64 {
+    if nz < 1e-10 {
+        return f64::INFINITY;
+    }
+    // rise / run: the horizontal component over the vertical one
+    100.0 * nx.hypot(ny) / nz
+}
+
+// The direction the plane with the upward unit normal faces (downhill),
+// clockwise from north in degrees. None for a flat plane.
+pub fn normal_aspect((nx, ny, nz): (f64, f64, f64)) -> Option<f64> {
+    if nx.hypot(ny) < FLAT * nz {
+        return None;
     }
+    Some(nx.atan2(ny).to_degrees().rem_euclid(360.0))
 }
 
+// In percent, of the plane fitted through the vertices.
 pub fn slope(polygon: &Vec<MercatorPoint>) -> f64 {
     assert!(
         polygon.len() >= 3,
         "Need at least 3 points to define a plane"
     );
+    match fit_plane(polygon) {
+        Some(normal) => normal_slope(normal),
+        // collinear vertices
+        None => 0.0,
+    }
+}
 
-    for point in polygon {
-        assert!(point.ele.is_some(), "All points must have elevation");
+// The direction the plane fitted through the vertices faces (downslope),
+// see normal_aspect. None for a flat plane or collinear vertices.
+pub fn aspect(polygon: &[MercatorPoint]) -> Option<f64> {
+    normal_aspect(fit_plane(polygon)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn densified_square(lon: f64, lat: f64, size: f64, n: usize) -> Polygon {
+        let corners = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0), (0.0, 0.0)];
+        let mut wgs = Vec::new();
+        for k in 0..4 {
+            let (a, b) = (corners[k], corners[k + 1]);
+            for i in 0..n {
+                let t = i as f64 / n as f64;
+                wgs.push(WGS84Point {
+                    lon: lon + size * (a.0 + t * (b.0 - a.0)),
+                    lat: lat + size * (a.1 + t * (b.1 - a.1)),
+                    ele: None,
+                });
+            }
+        }
+        Polygon::new(wgs)
     }
 
-    // Use the first three non-collinear points to compute the plane's normal vector
-    let p1 = &polygon[0];
-    let p2 = &polygon[1];
-    let p3 = &polygon[2];
+    #[test]
+    fn test_info_data() {
+        let info = densified_square(6.2, 46.2, 0.5, 3).info_data().unwrap();
+        assert_eq!(info.points, 12);
+        assert_eq!(info.candidates.iter().collect::<Vec<_>>(), ["N46E006.hgt"]);
+        assert_eq!(info.to_string().lines().count(), 7);
+    }
 
-    let e1 = p1.ele.unwrap();
-    let e2 = p2.ele.unwrap();
-    let e3 = p3.ele.unwrap();
+    #[test]
+    fn test_unprojectable() {
+        let mut polygon = densified_square(6.2, 46.2, 0.5, 3);
+        polygon.wgs[4].lat = 95.0;
+        let e = polygon.mercator().unwrap_err();
+        assert!(e.to_string().contains(&polygon.wgs[4].to_string()), "{}", e);
+        assert!(polygon.mercatorbbox().is_err());
+        assert!(polygon.info_data().is_err());
+        polygon.wgs[4].lat = f64::NAN;
+        assert!(polygon.smooth(2, 50.0).is_err());
+    }
 
-    // Two edge vectors in 3D
-    let v1 = (p2.x - p1.x, p2.y - p1.y, e2 - e1);
-    let v2 = (p3.x - p1.x, p3.y - p1.y, e3 - e1);
+    #[test]
+    fn test_holes() {
+        let square = |lon: f64, lat: f64, size: f64| densified_square(lon, lat, size, 1).wgs;
+        let polygon =
+            Polygon::new(square(6.2, 46.2, 0.1)).with_holes(vec![square(6.22, 46.22, 0.02)]);
+        let hole = Polygon::new(square(6.22, 46.22, 0.02));
+        let outer = Polygon::new(square(6.2, 46.2, 0.1));
+        let geodesic = outer.geodesic_area().unwrap() - hole.geodesic_area().unwrap();
+        assert!((polygon.geodesic_area().unwrap() - geodesic).abs() < 1e-6 * geodesic);
+        let planar = outer.planar_area().unwrap() - hole.planar_area().unwrap();
+        assert!((polygon.planar_area().unwrap() - planar).abs() < 1e-6 * planar);
+        assert!(polygon.smooth(2, 50.0).unwrap().holes[0].len() > 4);
 
-    // Cross product: v1 × v2 gives the normal vector to the plane
-    let nx = v1.1 * v2.2 - v1.2 * v2.1;
-    let ny = v1.2 * v2.0 - v1.0 * v2.2;
-    let nz = v1.0 * v2.1 - v1.1 * v2.0;
+        // the parts of a split polygon keep the holes they contain
+        let parts = polygon.split(0.05);
+        assert_eq!(parts.iter().map(|p| p.holes.len()).sum::<usize>(), 1);
+        let sum: f64 = parts.iter().map(|p| p.geodesic_area().unwrap()).sum();
+        assert!(
+            (sum - geodesic).abs() < 1e-3 * geodesic,
+            "{} {}",
+            sum,
+            geodesic
+        );
+    }
 
-    // The magnitude of the normal vector
-    let normal_magnitude = (nx * nx + ny * ny + nz * nz).sqrt();
+    fn ring(points: &[(f64, f64)]) -> Polygon {
+        Polygon::new(
+            points
+                .iter()
+                .map(|(lon, lat)| WGS84Point {
+                    lon: *lon,
+                    lat: *lat,
+                    ele: None,
+                })
+                .collect(),
+        )
+    }
 
-    if normal_magnitude < 1e-10 {
-        return 0.0; // Degenerate case (collinear points)
+    // a ring of (x, y, elevation) in meters, in a local equal-area projection
+    fn local(points: &[(f64, f64, Option<f64>)]) -> Polygon {
+        let proj4 = WGS84Point {
+            lon: 6.2,
+            lat: 46.2,
+            ele: None,
+        }
+        .to_laea_proj4();
+        let projector = WebMercatorProjection::make(&proj4);
+        let wgs = points
+            .iter()
+            .map(|&(x, y, ele)| WGS84Point {
+                ele,
+                ..projector
+                    .unproject(&MercatorPoint { x, y, ele: None })
+                    .unwrap()
+            })
+            .collect();
+        Polygon::projected(wgs, Some(proj4))
     }
 
-    // For a horizontal plane, nz should be large and nx, ny should be near zero
-    // The slope is determined by the horizontal component of the normal
-    let horizontal_component = (nx * nx + ny * ny).sqrt();
-    let vertical_component = nz.abs();
+    #[test]
+    fn test_perimeter() {
+        let square = |ele: fn(f64) -> Option<f64>| {
+            local(&[
+                (-50.0, -50.0, ele(-50.0)),
+                (50.0, -50.0, ele(-50.0)),
+                (50.0, 50.0, ele(50.0)),
+                (-50.0, 50.0, ele(50.0)),
+            ])
+        };
+        let flat = square(|_| None);
+        assert!(
+            (flat.perimeter_2d() - 400.0).abs() < 0.01,
+            "{}",
+            flat.perimeter_2d()
+        );
+        assert_eq!(flat.perimeter_3d(), None);
+        // rising by 50% to the north
+        let sloped = square(|y| Some(300.0 + 0.5 * y));
+        let expected = 200.0 + 200.0 * 1.25f64.sqrt();
+        let perimeter = sloped.perimeter_3d().unwrap();
+        assert!((perimeter - expected).abs() < 0.01, "{}", perimeter);
+        assert!((sloped.perimeter_2d() - 400.0).abs() < 0.01);
+        let c = sloped.centroid().unwrap();
+        assert!((c.lon - 6.2).abs() < 1e-9 && (c.lat - 46.2).abs() < 1e-9);
 
-    if vertical_component < 1e-10 {
-        // Normal is horizontal => plane is vertical
-        return f64::INFINITY;
+        // the center of mass of an L, not the mean of its vertices
+        let l = local(&[
+            (0.0, 0.0, None),
+            (200.0, 0.0, None),
+            (200.0, 100.0, None),
+            (100.0, 100.0, None),
+            (100.0, 200.0, None),
+            (0.0, 200.0, None),
+        ]);
+        let c = l.projector().project(&l.centroid().unwrap()).unwrap();
+        assert!(
+            (c.x - 250.0 / 3.0).abs() < 0.01 && (c.y - 250.0 / 3.0).abs() < 0.01,
+            "{}",
+            c
+        );
+        assert!((l.perimeter_2d() - 800.0).abs() < 0.01);
     }
 
-    // Slope = rise / run = horizontal_component / vertical_component
-    // As percentage: slope * 100
-    (horizontal_component / vertical_component) * 100.0
-}
+    #[test]
+    fn test_bowtie() {
+        let bowtie = ring(&[
+            (6.2, 46.2),
+            (6.21, 46.21),
+            (6.21, 46.2),
+            (6.2, 46.21),
+            (6.2, 46.2),
+        ]);
+        let issues = bowtie.validate();
+        assert_eq!(issues.len(), 1, "{:?}", issues);
+        match &issues[0] {
+            PolygonIssue::SelfIntersection { ring: 0, at } => {
+                assert!((at.lon - 6.205).abs() < 1e-9 && (at.lat - 46.205).abs() < 1e-9);
+            }
+            issue => panic!("{}", issue),
+        }
+        let lobes = bowtie.repaired().unwrap();
+        assert_eq!(lobes.len(), 2);
+        assert!(lobes.iter().all(|lobe| lobe.validate().is_empty()));
+        // two quarters of the square
+        let square = ring(&[(6.2, 46.2), (6.21, 46.2), (6.21, 46.21), (6.2, 46.21)]);
+        let half = 0.5 * square.geodesic_area().unwrap();
+        let sum: f64 = lobes.iter().map(|l| l.geodesic_area().unwrap()).sum();
+        assert!((sum - half).abs() < 1e-3 * half, "{} {}", sum, half);
+    }
+
+    #[test]
+    fn test_doubled_vertex() {
+        let doubled = ring(&[
+            (6.2, 46.2),
+            (6.21, 46.2),
+            (6.21, 46.2),
+            (6.21, 46.21),
+            (6.2, 46.21),
+        ]);
+        let issues = doubled.validate();
+        assert_eq!(issues.len(), 2, "{:?}", issues);
+        assert!(matches!(
+            issues[0],
+            PolygonIssue::DuplicateVertex { ring: 0, index: 2 }
+        ));
+        assert!(matches!(issues[1], PolygonIssue::Unclosed { ring: 0 }));
+        let repaired = doubled.repaired().unwrap();
+        assert_eq!(repaired.len(), 1);
+        assert_eq!(repaired[0].wgs.len(), 5);
+        assert!(repaired[0].validate().is_empty());
+
+        // nothing is left once the doubled vertex is removed
+        let flat = ring(&[(6.2, 46.2), (6.21, 46.2), (6.21, 46.2), (6.2, 46.2)]);
+        assert!(matches!(
+            flat.validate().last(),
+            Some(PolygonIssue::TooFewPoints {
+                ring: 0,
+                distinct: 2
+            })
+        ));
+        assert!(flat.repaired().is_err());
+    }
+
+    #[test]
+    fn test_simplified() {
+        // meters from 46.2, 6.2
+        let local = |points: &[(f64, f64)]| -> Vec<(f64, f64)> {
+            points
+                .iter()
+                .map(|(x, y)| (6.2 + x / 77_100.0, 46.2 + y / 111_150.0))
+                .collect()
+        };
+        // a circle of 500 m, traced every meter or so
+        let circle: Vec<(f64, f64)> = (0..3000)
+            .map(|k| {
+                let a = k as f64 * std::f64::consts::TAU / 3000.0;
+                (500.0 * a.cos(), 500.0 * a.sin())
+            })
+            .collect();
+        let traced = ring(&local(&circle));
+        let simple = traced.simplified(1.0).unwrap();
+        assert!(simple.wgs.len() < 100, "{}", simple.wgs.len());
+        assert!(
+            simple
+                .validate()
+                .iter()
+                .all(|i| matches!(i, PolygonIssue::Unclosed { .. }))
+        );
+        let (before, after) = (traced.planar_area().unwrap(), simple.planar_area().unwrap());
+        assert!((after / before - 1.0).abs() < 0.005, "{} {}", before, after);
+
+        // a 20 m wide notch reaching 4 m below an edge that bulges out by
+        // 8 m: the edge straightened by the tolerance of 10 m would cut
+        // through the notch, its bulge is kept
+        let mut notched = vec![(0.0, -50.0), (0.0, 0.0), (500.0, 8.0), (1000.0, 0.0)];
+        notched.extend((0..49).map(|k| (1000.0 - 10.0 * k as f64, -50.0)));
+        notched.extend([(510.0, -50.0), (510.0, 4.0), (490.0, 4.0), (490.0, -50.0)]);
+        notched.push((0.0, -50.0));
+        let notched = ring(&local(&notched));
+        assert!(notched.validate().is_empty());
+        let simple = notched.simplified(10.0).unwrap();
+        assert!(simple.validate().is_empty(), "{:?}", simple.validate());
+        assert!(simple.wgs.len() < 15, "{}", simple.wgs.len());
+        assert!(simple.wgs.iter().any(|p| same(p, &notched.wgs[2])));
+    }
+
+    #[test]
+    fn test_simplified_holes() {
+        use geo::Contains;
+
+        let local = |points: &[(f64, f64)]| -> Vec<WGS84Point> {
+            ring(
+                &points
+                    .iter()
+                    .map(|(x, y)| (6.2 + x / 77_100.0, 46.2 + y / 111_150.0))
+                    .collect::<Vec<_>>(),
+            )
+            .wgs
+        };
+        let rectangle = |x0: f64, y0: f64, x1: f64, y1: f64| {
+            local(&[(x0, y0), (x1, y0), (x1, y1), (x0, y1), (x0, y0)])
+        };
+        // the same bulge of 8 m, with a hole in it that the straightened
+        // edge would leave outside and one that it would cross
+        let mut outer = vec![(0.0, -50.0), (0.0, 0.0), (500.0, 8.0), (1000.0, 0.0)];
+        outer.extend((0..=100).map(|k| (1000.0 - 10.0 * k as f64, -50.0)));
+        let swallowed = rectangle(480.0, 2.0, 520.0, 5.0);
+        let crossed = rectangle(700.0, -3.0, 740.0, 3.0);
+        for holes in [vec![swallowed.clone()], vec![swallowed, crossed]] {
+            let polygon = Polygon::new(local(&outer)).with_holes(holes);
+            assert!(polygon.validate().is_empty());
+            let simple = polygon.simplified(10.0).unwrap();
+            assert!(simple.validate().is_empty(), "{:?}", simple.validate());
+            assert!(simple.wgs.len() < 15, "{}", simple.wgs.len());
+            assert!(simple.wgs.iter().any(|p| same(p, &polygon.wgs[2])));
+            let outer = crate::intersection::to_geo_polygon(&simple.mercator().unwrap());
+            for hole in simple.mercator_holes().unwrap() {
+                assert!(
+                    hole.iter()
+                        .all(|p| outer.contains(&geo::Point::new(p.x, p.y)))
+                );
+            }
+            for hole in &simple.holes {
+                assert!(crossings_between(&simple.wgs, hole).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_large_polygon() {
+        let polygon = densified_square(2.0, 40.0, 5.0, 100);
+        let parts = polygon.split(1.0);
+        assert_eq!(parts.len(), 25);
+        let geodesic = crate::reference::geodesic_area(&polygon.wgs).unwrap();
+        let planar: f64 = parts
+            .iter()
+            .map(|p| crate::reference::planar_area(&p.mercator().unwrap()).unwrap())
+            .sum();
+        let error = (planar - geodesic).abs() / geodesic;
+        assert!(error < 1e-3, "relative error {}", error);
+        // a single UTM projection is way off at this scale
+        let utm = crate::reference::planar_area(&polygon.mercator().unwrap()).unwrap();
+        assert!((utm - geodesic).abs() / geodesic > error);
+    }
+    fn point(x: f64, y: f64) -> MercatorPoint {
+        MercatorPoint { x, y, ele: None }
+    }
+
+    #[test]
+    fn test_chaikin_square() {
+        let square = vec![
+            point(0.0, 0.0),
+            point(100.0, 0.0),
+            point(100.0, 100.0),
+            point(0.0, 100.0),
+        ];
+        let area = |ring: &[MercatorPoint]| crate::reference::planar_area(ring).unwrap();
+        // the first pass cuts 4 corners of (s/4)²/2, the second 8 corners
+        // of 135° with sides s/8 and s√2/16
+        let once = chaikin(&square, f64::INFINITY);
+        assert_eq!(once.len(), 8);
+        assert!((area(&once) - 10_000.0 * 7.0 / 8.0).abs() < 1e-6);
+        let twice = chaikin(&once, f64::INFINITY);
+        assert!((area(&twice) - 10_000.0 * 27.0 / 32.0).abs() < 1e-6);
+        // cuts of 5 m
+        let capped = chaikin(&square, 5.0);
+        assert!((area(&capped) - (10_000.0 - 2.0 * 25.0)).abs() < 1e-6);
+        // the closing vertex is kept
+        let mut closed = square.clone();
+        closed.push(square[0].clone());
+        let smoothed = chaikin(&closed, 5.0);
+        assert_eq!(smoothed.len(), 9);
+        assert_eq!(smoothed.first(), smoothed.last());
+        assert_eq!(&smoothed[..8], &capped[..]);
+    }
+
+    #[test]
+    fn test_smooth_star() {
+        use geo::Validation;
+        let wgs = (0..10)
+            .map(|k| {
+                let angle = k as f64 * std::f64::consts::PI / 5.0;
+                let radius = if k % 2 == 0 { 0.01 } else { 0.004 };
+                WGS84Point {
+                    lon: 7.0 + radius * angle.cos(),
+                    lat: 46.0 + radius * angle.sin(),
+                    ele: None,
+                }
+            })
+            .collect();
+        let star = Polygon::new(wgs);
+        let smooth = star.smooth(3, 50.0).unwrap();
+        assert_eq!(smooth.wgs.len(), 80);
+        let ring = crate::intersection::to_geo_polygon(&smooth.mercator().unwrap());
+        ring.check_validation().unwrap();
+        let before = crate::reference::planar_area(&star.mercator().unwrap()).unwrap();
+        let after = crate::reference::planar_area(&smooth.mercator().unwrap()).unwrap();
+        // the tips lose area, the notches between them gain some
+        assert!((after / before - 1.0).abs() < 0.05, "{} {}", before, after);
+    }
+
+    // the former area: half the norm of the vector area of the ring, right
+    // for vertices in one plane only
+    fn vector_area(polygon: &[MercatorPoint]) -> f64 {
+        let mut v = (0.0, 0.0, 0.0);
+        for (k, p1) in polygon.iter().enumerate() {
+            let p2 = &polygon[(k + 1) % polygon.len()];
+            let (z1, z2) = (p1.ele.unwrap(), p2.ele.unwrap());
+            v.0 += p1.y * z2 - z1 * p2.y;
+            v.1 += z1 * p2.x - p1.x * z2;
+            v.2 += p1.x * p2.y - p1.y * p2.x;
+        }
+        0.5 * (v.0 * v.0 + v.1 * v.1 + v.2 * v.2).sqrt()
+    }
+
+    #[test]
+    fn test_saddle_area() {
+        let p = |x: f64, y: f64, z: f64| MercatorPoint { x, y, ele: Some(z) };
+        let saddle = vec![
+            p(0.0, 0.0, 0.0),
+            p(10.0, 0.0, 10.0),
+            p(10.0, 10.0, 0.0),
+            p(0.0, 10.0, 10.0),
+        ];
+        // either diagonal cuts it into two triangles of 50 * sqrt(3)
+        let expected = 100.0 * 3f64.sqrt();
+        assert!((calculate_3d_surface_area(&saddle).unwrap() - expected).abs() < 1e-9);
+        let mut reversed = saddle.clone();
+        reversed.reverse();
+        assert!((calculate_3d_surface_area(&reversed).unwrap() - expected).abs() < 1e-9);
+        // the vector area is the one of the square below
+        assert!((vector_area(&saddle) - 100.0).abs() < 1e-9);
+        assert!((calculate_3d_surface_area(&flat(&saddle)).unwrap() - 100.0).abs() < 1e-9);
+
+        // a concave ring in a plane, then with a hole following it
+        let plane = |x: f64, y: f64| p(x, y, 0.5 * x);
+        let l = vec![
+            plane(0.0, 0.0),
+            plane(20.0, 0.0),
+            plane(20.0, 10.0),
+            plane(10.0, 10.0),
+            plane(10.0, 20.0),
+            plane(0.0, 20.0),
+            plane(0.0, 0.0),
+        ];
+        let expected = 300.0 * 1.25f64.sqrt();
+        assert!((calculate_3d_surface_area(&l).unwrap() - expected).abs() < 1e-9);
+        assert!((vector_area(&l) - expected).abs() < 1e-9);
+        let mut holed = l.clone();
+        holed.extend([
+            plane(2.0, 2.0),
+            plane(2.0, 4.0),
+            plane(4.0, 4.0),
+            plane(4.0, 2.0),
+            plane(2.0, 2.0),
+        ]);
+        let expected = 296.0 * 1.25f64.sqrt();
+        assert!((calculate_3d_surface_area(&holed).unwrap() - expected).abs() < 1e-9);
+
+        // a dart raised at its reflex corner, from one of its other corners:
+        // its only triangulation cuts it along the diagonal from the reflex
+        // corner, the fan covers the notch and takes it away again
+        let dart = vec![
+            p(20.0, 0.0, 0.0),
+            p(5.0, 5.0, 10.0),
+            p(0.0, 20.0, 0.0),
+            p(0.0, 0.0, 0.0),
+        ];
+        let triangle = |a: usize, b: usize, c: usize| {
+            Triangle(dart[a].clone(), dart[b].clone(), dart[c].clone()).area()
+        };
+        let expected = triangle(3, 0, 1) + triangle(3, 1, 2);
+        let fan = triangle(0, 2, 3) - triangle(0, 1, 2);
+        assert!((fan - expected).abs() > 10.0, "{} {}", fan, expected);
+        assert!((calculate_3d_surface_area(&dart).unwrap() - expected).abs() < 1e-9);
+        // a vertex on the line of its neighbors keeps its elevation
+        let raised = vec![
+            p(0.0, 0.0, 0.0),
+            p(10.0, 0.0, 10.0),
+            p(20.0, 0.0, 0.0),
+            p(20.0, 10.0, 0.0),
+            p(0.0, 10.0, 0.0),
+        ];
+        let area = calculate_3d_surface_area(&raised).unwrap();
+        assert!(area > 200.0 + 10.0, "{}", area);
+    }
+
+    #[test]
+    fn test_missing_elevation() {
+        let p = |x: f64, y: f64, z: Option<f64>| MercatorPoint { x, y, ele: z };
+        let mut ring = vec![
+            p(0.0, 0.0, Some(10.0)),
+            p(10.0, 0.0, None),
+            p(10.0, 10.0, Some(30.0)),
+            p(0.0, 10.0, Some(20.0)),
+        ];
+        assert!(matches!(
+            calculate_3d_surface_area(&ring),
+            Err(AreaError::MissingElevation { index: 1 })
+        ));
+        assert_eq!(patch_elevations(&mut ring), 1);
+        assert_eq!(ring[1].ele, Some(20.0));
+        assert!(calculate_3d_surface_area(&ring).unwrap() > 100.0);
+
+        // across the end of the ring, and a ring without any elevation
+        let mut ring = vec![
+            p(0.0, 0.0, None),
+            p(10.0, 0.0, Some(10.0)),
+            p(10.0, 10.0, Some(30.0)),
+            p(0.0, 10.0, None),
+        ];
+        assert_eq!(patch_elevations(&mut ring), 2);
+        assert!((ring[3].ele.unwrap() - (30.0 - 20.0 / 3.0)).abs() < 1e-9);
+        assert!((ring[0].ele.unwrap() - (30.0 - 40.0 / 3.0)).abs() < 1e-9);
+        let mut ring = flat(&ring);
+        ring.iter_mut().for_each(|p| p.ele = None);
+        assert_eq!(patch_elevations(&mut ring), 0);
+        assert!(calculate_3d_surface_area(&ring).is_err());
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
     #[test]
     fn test_slope() {
+        let p = |x: f64, y: f64, z: f64| MercatorPoint { x, y, ele: Some(z) };
         let p0 = vec![
-            MercatorPoint {
-                x: 0.0,
-                y: 0.0,
-                ele: Some(0.0),
-            },
-            MercatorPoint {
-                x: 100.0,
-                y: 0.0,
-                ele: Some(0.0),
-            },
-            MercatorPoint {
-                x: 100.0,
-                y: 100.0,
-                ele: Some(50.0),
-            },
-            MercatorPoint {
-                x: 0.0,
-                y: 100.0,
-                ele: Some(50.0),
-            },
+            p(0.0, 0.0, 0.0),
+            p(100.0, 0.0, 0.0),
+            p(100.0, 100.0, 50.0),
+            p(0.0, 100.0, 50.0),
         ];
-        let slope_pct = slope(&p0);
-        println!("Slope: {:.2}%", slope_pct);
-        let mut p1 = p0.clone();
-        for p in &mut p1 {
-            p.ele = Some(0.0);
-        }
-        let slope_pct = slope(&p1);
-        println!("Slope: {:.2}%", slope_pct);
-        assert!(false);
+        assert!((slope(&p0) - 50.0).abs() < 1e-9);
+        // rising to the north, facing south
+        assert!((aspect(&p0).unwrap() - 180.0).abs() < 1e-9);
+        let p1 = flat(&p0);
+        assert!(slope(&p1).abs() < 1e-9);
+        assert_eq!(aspect(&p1), None);
+
+        // z = 0.3 x + 0.4 y, 50%, with centimeters of noise and its first
+        // three vertices nearly on a line
+        let plane = |x: f64, y: f64, noise: f64| p(x, y, 0.3 * x + 0.4 * y + noise);
+        let clipped = vec![
+            plane(0.0, 0.0, 0.01),
+            plane(15.0, 0.05, -0.01),
+            plane(30.0, 0.0, 0.01),
+            plane(30.0, 20.0, -0.01),
+            plane(25.0, 30.0, 0.01),
+            plane(10.0, 30.0, -0.01),
+            plane(0.0, 15.0, 0.01),
+        ];
+        assert!((slope(&clipped) - 50.0).abs() < 0.1, "{}", slope(&clipped));
+        let expected = (-0.3f64).atan2(-0.4).to_degrees() + 360.0;
+        assert!((aspect(&clipped).unwrap() - expected).abs() < 0.1);
+
+        // on a line, and in a vertical plane
+        let line: Vec<_> = (0..5)
+            .map(|k| plane(k as f64, 2.0 * k as f64, 0.0))
+            .collect();
+        assert_eq!(slope(&line), 0.0);
+        assert_eq!(aspect(&line), None);
+        let wall = vec![
+            p(0.0, 0.0, 0.0),
+            p(10.0, 0.0, 0.0),
+            p(10.0, 0.0, 10.0),
+            p(0.0, 0.0, 10.0),
+        ];
+        assert_eq!(slope(&wall), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_zone_from_center() {
+        // the first vertex is in zone 31, most of the polygon in zone 32
+        let polygon = densified_square(5.9, 46.0, 2.0, 20);
+        assert_eq!(polygon.projection(), polygon.wgs[20].to_utm_proj4());
+        assert!(polygon.projection().contains("+zone=32"));
+        let (overhang, scale) = polygon.zone_overhang();
+        assert!((overhang - 0.1).abs() < 1e-9, "{}", overhang);
+        assert!(scale > 0.0 && scale < 1e-3, "{}", scale);
+        let mut first = densified_square(5.9, 46.0, 2.0, 20);
+        first.proj4 = Some(first.wgs[0].to_utm_proj4());
+        assert_eq!(first.zone_overhang(), (0.0, 0.0));
+        let geodesic = crate::reference::geodesic_area(&polygon.wgs).unwrap();
+        let error = |p: &Polygon| {
+            let planar = crate::reference::planar_area(&p.mercator().unwrap()).unwrap();
+            (planar - geodesic).abs() / geodesic
+        };
+        // 5° from the central meridian of zone 31, 3.1° from that of zone 32
+        assert!(error(&polygon) < 1e-3, "{}", error(&polygon));
+        assert!(error(&first) > 2.0 * error(&polygon), "{}", error(&first));
     }
 }
@@ -0,0 +1,42 @@
+//! The supported surface of the library, `use surface_area::prelude::*`.
+//!
+//! ```
+//! use surface_area::prelude::*;
+//!
+//! // a recorded 10x10 raster, see rasterio::Replay
+//! let path = concat!(env!("CARGO_MANIFEST_DIR"), "/data/fixtures/N46E006-10x10.json");
+//! let utm = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+//! let replay = Box::new(Replay::load(path).unwrap());
+//! let dataset = Dataset::from_io("N46E006-10x10", replay, utm).unwrap();
+//!
+//! let step = 1.0 / 1200.0;
+//! let corner = |col: f64, row: f64| WGS84Point {
+//!     lon: 6.0 + col * step,
+//!     lat: 46.0 + row * step,
+//!     ele: None,
+//! };
+//! let mut polygon = Polygon::new(vec![
+//!     corner(1.5, 1.5),
+//!     corner(8.5, 1.5),
+//!     corner(8.5, 8.5),
+//!     corner(1.5, 8.5),
+//! ]);
+//! polygon.proj4 = Some(utm.to_string());
+//!
+//! let mut bbox = polygon.wgsbbox();
+//! dataset.snap(&mut bbox);
+//! let points = dataset.points_inside(&bbox, &SampleOptions::default());
+//! let surface = clip(&polygon.mercator().unwrap(), &triangulate(&points));
+//! assert!(surface.projected3d > surface.projected2d);
+//! assert!(surface.ratio() > 1.0);
+//! ```
+
+pub use crate::compute::{MergeOptions, Plane, Surface, clip, clip_with_voids, merge_coplanar};
+pub use crate::dataset::{Dataset, DatasetError, SampleOptions};
+pub use crate::options::{ComputeOptions, VoidPolicy};
+pub use crate::point::{MercatorPoint, WGS84BoundingBox, WGS84Point};
+pub use crate::polygon::Polygon;
+pub use crate::rasterio::{InMemory, IoStats, RasterIo, RasterIoError, Replay, Synthetic};
+pub use crate::read_polygon::read_polyline;
+pub use crate::triangulation::Mesh;
+pub use crate::triangulation::grid::triangulate;
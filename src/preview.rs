@@ -0,0 +1,149 @@
+use std::collections::BTreeMap;
+
+use crate::point::MercatorPoint;
+
+pub const PREVIEW_BUDGET: usize = 5000;
+
+// Relative error (percent) of the 3D/2D ratio by sample count. These are
+// rough guesses, not measured against full-resolution runs.
+const EXPECTED_ERROR: [(usize, f64); 6] = [
+    (500, 15.0),
+    (1000, 10.0),
+    (2000, 6.0),
+    (5000, 3.0),
+    (10000, 2.0),
+    (50000, 1.0),
+];
+
+pub fn expected_error(samples: usize) -> f64 {
+    EXPECTED_ERROR
+        .iter()
+        .find(|(n, _)| samples <= *n)
+        .map(|(_, e)| *e)
+        .unwrap_or(EXPECTED_ERROR.last().unwrap().1)
+}
+
+// Stratified subset of about `budget` grid points: the bbox of the points is
+// cut into square strata, each keeps the point closest to its center.
+// Strata crossed by the polygon boundary also keep the points closest to
+// their four corners, so that the boundary is covered by the triangulation.
+pub fn stratified_sample(
+    points: &[MercatorPoint],
+    polygon: &[MercatorPoint],
+    budget: usize,
+) -> Vec<MercatorPoint> {
+    if points.len() <= budget || points.is_empty() {
+        return points.to_vec();
+    }
+    let (min, max) = points.iter().fold(
+        ((f64::INFINITY, f64::INFINITY), (f64::NEG_INFINITY, f64::NEG_INFINITY)),
+        |(min, max), p| ((min.0.min(p.x), min.1.min(p.y)), (max.0.max(p.x), max.1.max(p.y))),
+    );
+    let (width, height) = ((max.0 - min.0).max(1e-9), (max.1 - min.1).max(1e-9));
+    // boundary strata take 5 points => budget the interior at ~75%
+    let step = (width * height / (0.75 * budget as f64)).sqrt();
+    let stratum = |x: f64, y: f64| -> (i64, i64) {
+        (
+            ((x - min.0) / step).floor() as i64,
+            ((y - min.1) / step).floor() as i64,
+        )
+    };
+
+    let mut boundary = BTreeMap::new();
+    for i in 0..polygon.len() {
+        let (a, b) = (&polygon[i], &polygon[(i + 1) % polygon.len()]);
+        let length = ((b.x - a.x).powi(2) + (b.y - a.y).powi(2)).sqrt();
+        let n = (2f64 * length / step).ceil().max(1f64) as usize;
+        for k in 0..=n {
+            let t = k as f64 / n as f64;
+            boundary.insert(stratum(a.x + t * (b.x - a.x), a.y + t * (b.y - a.y)), ());
+        }
+    }
+
+    // per stratum: best point (distance, index) for each target
+    let mut best: BTreeMap<(i64, i64), Vec<(f64, usize)>> = BTreeMap::new();
+    for (index, p) in points.iter().enumerate() {
+        let s = stratum(p.x, p.y);
+        let (x0, y0) = (min.0 + s.0 as f64 * step, min.1 + s.1 as f64 * step);
+        let mut targets = vec![(x0 + 0.5 * step, y0 + 0.5 * step)];
+        if boundary.contains_key(&s) {
+            targets.extend([(x0, y0), (x0 + step, y0), (x0, y0 + step), (x0 + step, y0 + step)]);
+        }
+        let slots = best
+            .entry(s)
+            .or_insert_with(|| vec![(f64::INFINITY, 0); targets.len()]);
+        for (slot, t) in slots.iter_mut().zip(&targets) {
+            let d = (p.x - t.0).powi(2) + (p.y - t.1).powi(2);
+            if d < slot.0 {
+                *slot = (d, index);
+            }
+        }
+    }
+
+    let mut indices: Vec<usize> = best
+        .values()
+        .flat_map(|slots| slots.iter().map(|s| s.1))
+        .collect();
+    indices.sort();
+    indices.dedup();
+    indices.into_iter().map(|i| points[i].clone()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute;
+    use crate::triangulation::grid::triangulate;
+
+    fn terrain(n: usize, spacing: f64) -> Vec<MercatorPoint> {
+        let mut points = Vec::new();
+        for row in 0..n {
+            for col in 0..n {
+                let (x, y) = (col as f64 * spacing, row as f64 * spacing);
+                points.push(MercatorPoint {
+                    x,
+                    y,
+                    ele: Some(30.0 * (x / 150.0).sin() * (y / 200.0).cos() + 0.2 * x),
+                });
+            }
+        }
+        points
+    }
+
+    fn octagon(cx: f64, cy: f64, r: f64) -> Vec<MercatorPoint> {
+        (0..8)
+            .map(|k| {
+                let a = std::f64::consts::PI * (0.125 + k as f64 / 4.0);
+                MercatorPoint {
+                    x: cx + r * a.cos(),
+                    y: cy + r * a.sin(),
+                    ele: None,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_expected_error() {
+        assert_eq!(expected_error(100), 15.0);
+        assert_eq!(expected_error(5000), 3.0);
+        assert_eq!(expected_error(1_000_000), 1.0);
+    }
+
+    #[test]
+    fn test_preview_agrees_with_full_run() {
+        let points = terrain(100, 10.0);
+        let polygon = octagon(495.0, 495.0, 450.0);
+        let full = compute::clip(&polygon, &triangulate(&points));
+
+        let sample = stratified_sample(&points, &polygon, 1500);
+        assert!(sample.len() < 2000, "{} samples", sample.len());
+        let preview = compute::clip(&polygon, &triangulate(&sample));
+
+        let error = 100.0 * (preview.ratio() / full.ratio() - 1.0).abs();
+        assert!(error < expected_error(sample.len()), "error {:.2}%", error);
+        // the boundary strata keep the clipped area complete
+        let flat = 100.0 * (preview.projected2d / full.projected2d - 1.0).abs();
+        assert!(flat < 1.0, "flat area error {:.2}%", flat);
+    }
+}
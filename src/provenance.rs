@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+// The CRS of the geographic coordinates of every export: always longitude
+// first.
+pub const WGS84: &str = "+proj=longlat +datum=WGS84 +no_defs +type=crs";
+
+// Where the coordinates of an exported artifact come from, written as a
+// JSON sidecar next to it: the CRS as a proj string, the order of the axes
+// and the offset subtracted from the projected coordinates.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Provenance {
+    pub crs: String,
+    // e.g. "x,y" (easting, northing) or "lon,lat"
+    pub axis_order: String,
+    pub offset: [f64; 2],
+    pub software: String,
+}
+
+impl Provenance {
+    // coordinates in the projection, untranslated
+    pub fn projected(proj4: &str) -> Self {
+        Provenance {
+            crs: proj4.to_string(),
+            axis_order: "x,y".to_string(),
+            offset: [0.0, 0.0],
+            software: format!("surface-area {}", env!("CARGO_PKG_VERSION")),
+        }
+    }
+
+    pub fn geographic() -> Self {
+        Provenance {
+            axis_order: "lon,lat".to_string(),
+            ..Self::projected(WGS84)
+        }
+    }
+
+    // slope.tif: slope.tif.json
+    pub fn sidecar(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".json");
+        PathBuf::from(name)
+    }
+
+    pub fn write_sidecar(&self, path: &Path) -> std::io::Result<()> {
+        let json = serde_json::to_string_pretty(self).map_err(std::io::Error::other)?;
+        std::fs::write(Self::sidecar(path), json)
+    }
+
+    pub fn read_sidecar(path: &Path) -> std::io::Result<Self> {
+        let json = std::fs::read_to_string(Self::sidecar(path))?;
+        serde_json::from_str(&json).map_err(std::io::Error::other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sidecar() {
+        let path = std::env::temp_dir().join("surface-area-provenance-test.xyz");
+        assert_eq!(
+            Provenance::sidecar(&path),
+            std::env::temp_dir().join("surface-area-provenance-test.xyz.json")
+        );
+        let utm = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs +type=crs";
+        let mut provenance = Provenance::projected(utm);
+        provenance.offset = [300_000.0, 5_000_000.0];
+        provenance.write_sidecar(&path).unwrap();
+        let text = std::fs::read_to_string(Provenance::sidecar(&path)).unwrap();
+        assert!(text.contains(utm), "{}", text);
+        assert!(
+            text.contains("300000.0") && text.contains("5000000.0"),
+            "{}",
+            text
+        );
+        assert_eq!(Provenance::read_sidecar(&path).unwrap(), provenance);
+        std::fs::remove_file(Provenance::sidecar(&path)).ok();
+
+        let geographic = Provenance::geographic();
+        assert_eq!(geographic.axis_order, "lon,lat");
+        assert_eq!(geographic.offset, [0.0, 0.0]);
+    }
+}
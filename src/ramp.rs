@@ -0,0 +1,112 @@
+use crate::options::Ramp;
+
+// p in [0, 1], linear interpolation between the closest ranks
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    assert!(!sorted.is_empty());
+    let position = p.clamp(0f64, 1f64) * (sorted.len() - 1) as f64;
+    let (i, t) = (position.floor() as usize, position.fract());
+    if i + 1 >= sorted.len() {
+        return sorted[sorted.len() - 1];
+    }
+    (1f64 - t) * sorted[i] + t * sorted[i + 1]
+}
+
+// fraction in [0, 1] of the values below v, equal values share their mean rank
+pub fn rank(sorted: &[f64], v: f64) -> f64 {
+    if sorted.len() < 2 {
+        return 0.5;
+    }
+    let lo = sorted.partition_point(|x| *x < v);
+    let hi = sorted.partition_point(|x| *x <= v);
+    let r = if hi > lo {
+        0.5 * (lo + hi - 1) as f64
+    } else {
+        lo as f64 - 0.5
+    };
+    (r / (sorted.len() - 1) as f64).clamp(0f64, 1f64)
+}
+
+// Maps values to [0, 1] for the color ramps, either linearly over a range or
+// by rank (histogram equalization).
+pub struct ColorScale {
+    ramp: Ramp,
+    sorted: Vec<f64>,
+    min: f64,
+    max: f64,
+}
+
+impl ColorScale {
+    // range: the linear range, the extent of the values if None
+    pub fn new(values: &[f64], ramp: Ramp, range: Option<(f64, f64)>) -> Self {
+        let mut sorted: Vec<f64> = values.iter().copied().filter(|v| v.is_finite()).collect();
+        sorted.sort_by(f64::total_cmp);
+        let (min, max) = match (range, sorted.first(), sorted.last()) {
+            (Some(r), _, _) => r,
+            (None, Some(min), Some(max)) => (*min, *max),
+            _ => (0f64, 1f64),
+        };
+        ColorScale {
+            ramp,
+            sorted,
+            min,
+            max,
+        }
+    }
+
+    pub fn fraction(&self, v: f64) -> f64 {
+        match self.ramp {
+            Ramp::Quantile if !self.sorted.is_empty() => rank(&self.sorted, v),
+            _ if self.max > self.min => ((v - self.min) / (self.max - self.min)).clamp(0f64, 1f64),
+            _ => 0.5,
+        }
+    }
+
+    // the value shown at the color stop t
+    pub fn value_at(&self, t: f64) -> f64 {
+        match self.ramp {
+            Ramp::Quantile if !self.sorted.is_empty() => percentile(&self.sorted, t),
+            _ => self.min + t * (self.max - self.min),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let sorted = [1.0, 2.0, 3.0, 4.0, 5.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 0.25), 2.0);
+        assert_eq!(percentile(&sorted, 0.5), 3.0);
+        assert_eq!(percentile(&sorted, 1.0), 5.0);
+        assert!((percentile(&sorted, 0.1) - 1.4).abs() < 1e-12);
+        assert_eq!(percentile(&[7.0], 0.3), 7.0);
+    }
+
+    #[test]
+    fn test_rank() {
+        let sorted = [1.0, 2.0, 2.0, 2.0, 5.0];
+        assert_eq!(rank(&sorted, 1.0), 0.0);
+        assert_eq!(rank(&sorted, 2.0), 0.5);
+        assert_eq!(rank(&sorted, 5.0), 1.0);
+        assert_eq!(rank(&sorted, 3.0), 0.875);
+    }
+
+    #[test]
+    fn test_gully() {
+        // most of the parcel between 400 and 420 m, a gully at 300 m
+        let mut values: Vec<f64> = (0..95).map(|i| 400.0 + 20.0 * i as f64 / 94.0).collect();
+        values.extend([300.0; 5]);
+        let linear = ColorScale::new(&values, Ramp::Linear, None);
+        let quantile = ColorScale::new(&values, Ramp::Quantile, None);
+        // linearly, the parcel only uses the upper sixth of the ramp
+        assert!(linear.fraction(400.0) > 0.8);
+        assert!(quantile.fraction(400.0) < 0.1);
+        assert!((quantile.fraction(410.0) - 0.52).abs() < 0.02);
+        assert_eq!(quantile.value_at(0.0), 300.0);
+        assert_eq!(quantile.value_at(1.0), 420.0);
+        assert!((linear.value_at(0.5) - 360.0).abs() < 1e-9);
+    }
+}
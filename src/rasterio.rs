@@ -0,0 +1,1205 @@
+use std::fmt;
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+// The handful of raster calls the pipeline needs. Dataset only talks to GDAL
+// through this trait, so that tests can replay recorded calls without GDAL.
+pub trait RasterIo {
+    // the corner of pixel (0, 0) for area rasters, its sample for point ones
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError>;
+    fn raster_size(&self) -> (usize, usize);
+    fn projection(&self) -> String;
+    fn no_data_value(&self, band: usize) -> Result<Option<f64>, RasterIoError>;
+    fn overview_count(&self, band: usize) -> Result<usize, RasterIoError>;
+    // row-major pixel values of the window
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError>;
+    // mask band values (0 = invalid), None when all pixels are valid
+    fn read_mask(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError>;
+    // bytes per pixel in the source, for the IO statistics
+    fn sample_size(&self, _band: usize) -> usize {
+        std::mem::size_of::<f64>()
+    }
+    fn area_or_point(&self) -> AreaOrPoint {
+        AreaOrPoint::Area
+    }
+}
+
+// The AREA_OR_POINT metadata: whether a value is that of its cell, centered
+// half a pixel from the corner of the pixel, or a sample at the position of
+// the pixel (SRTM). GDAL assumes Area when the item is missing.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub enum AreaOrPoint {
+    #[default]
+    Area,
+    Point,
+}
+
+#[derive(Debug, Clone)]
+pub struct RasterIoError(pub String);
+
+impl fmt::Display for RasterIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "raster io: {}", self.0)
+    }
+}
+
+impl std::error::Error for RasterIoError {}
+
+#[cfg(feature = "gdal")]
+impl From<gdal::errors::GdalError> for RasterIoError {
+    fn from(e: gdal::errors::GdalError) -> Self {
+        RasterIoError(e.to_string())
+    }
+}
+
+#[cfg(feature = "gdal")]
+pub struct GdalRasterIo {
+    g: gdal::Dataset,
+}
+
+#[cfg(feature = "gdal")]
+impl GdalRasterIo {
+    pub fn open(path: &Path) -> Result<Self, RasterIoError> {
+        Ok(GdalRasterIo {
+            g: gdal::Dataset::open(path)?,
+        })
+    }
+
+    // an URL, through the virtual file systems of GDAL
+    pub fn open_remote(url: &str) -> Result<Self, RasterIoError> {
+        static INIT: std::sync::Once = std::sync::Once::new();
+        INIT.call_once(configure_remote);
+        Self::open(Path::new(&gdal_path(url)))
+    }
+}
+
+// https:// and s3:// datasets, read through /vsicurl/ and /vsis3/
+pub fn is_remote(filename: &str) -> bool {
+    ["http://", "https://", "s3://"]
+        .iter()
+        .any(|scheme| filename.starts_with(scheme))
+}
+
+#[cfg(any(feature = "gdal", test))]
+pub fn gdal_path(filename: &str) -> String {
+    if let Some(key) = filename.strip_prefix("s3://") {
+        format!("/vsis3/{}", key)
+    } else if is_remote(filename) {
+        format!("/vsicurl/{}", filename)
+    } else {
+        filename.to_string()
+    }
+}
+
+// Without the gdal feature, the files only GDAL reads cannot be opened.
+#[cfg(not(feature = "gdal"))]
+pub struct GdalRasterIo(std::convert::Infallible);
+
+#[cfg(not(feature = "gdal"))]
+impl GdalRasterIo {
+    pub fn open(path: &Path) -> Result<Self, RasterIoError> {
+        Err(RasterIoError(format!(
+            "{}: built without the gdal feature",
+            path.display()
+        )))
+    }
+
+    pub fn open_remote(url: &str) -> Result<Self, RasterIoError> {
+        Self::open(Path::new(url))
+    }
+}
+
+#[cfg(not(feature = "gdal"))]
+impl RasterIo for GdalRasterIo {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        match self.0 {}
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        match self.0 {}
+    }
+    fn projection(&self) -> String {
+        match self.0 {}
+    }
+    fn no_data_value(&self, _band: usize) -> Result<Option<f64>, RasterIoError> {
+        match self.0 {}
+    }
+    fn overview_count(&self, _band: usize) -> Result<usize, RasterIoError> {
+        match self.0 {}
+    }
+    fn read_window(
+        &self,
+        _band: usize,
+        _window: (isize, isize),
+        _size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        match self.0 {}
+    }
+    fn read_mask(
+        &self,
+        _band: usize,
+        _window: (isize, isize),
+        _size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        match self.0 {}
+    }
+}
+
+// Only the blocks of a COG that the windows need are fetched. GDAL would
+// list the remote directory on open, and logs the byte ranges it requests
+// as debug messages: at the trace level, they go to the log.
+#[cfg(feature = "gdal")]
+fn configure_remote() {
+    let set = |key: &str, value: &str| {
+        if std::env::var_os(key).is_none() {
+            gdal::config::set_config_option(key, value)
+                .unwrap_or_else(|e| log::warn!("{}: {}", key, e));
+        }
+    };
+    set("GDAL_DISABLE_READDIR_ON_OPEN", "EMPTY_DIR");
+    if log::log_enabled!(log::Level::Trace) {
+        set("CPL_DEBUG", "ON");
+        gdal::config::set_error_handler(|class, _, message| match class {
+            gdal::errors::CplErrType::Debug => log::trace!("gdal: {}", message),
+            gdal::errors::CplErrType::Warning => log::warn!("gdal: {}", message),
+            _ => log::error!("gdal: {}", message),
+        });
+    }
+}
+
+#[cfg(feature = "gdal")]
+impl RasterIo for GdalRasterIo {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        let mut geo = self.g.geo_transform()?;
+        // GDAL shifts the geotransform of point rasters to the corner of the
+        // pixels too: back to the samples
+        if self.area_or_point() == AreaOrPoint::Point {
+            geo[0] += 0.5 * geo[1];
+            geo[3] += 0.5 * geo[5];
+        }
+        Ok(geo)
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        self.g.raster_size()
+    }
+    fn projection(&self) -> String {
+        self.g.projection()
+    }
+    fn no_data_value(&self, band: usize) -> Result<Option<f64>, RasterIoError> {
+        Ok(self.g.rasterband(band)?.no_data_value())
+    }
+    fn overview_count(&self, band: usize) -> Result<usize, RasterIoError> {
+        Ok(self.g.rasterband(band)?.overview_count()? as usize)
+    }
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        let rasterband = self.g.rasterband(band)?;
+        let buffer = rasterband.read_as::<f64>(window, size, size, None)?;
+        Ok(buffer.into_shape_and_vec().1)
+    }
+    fn read_mask(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        let rasterband = self.g.rasterband(band)?;
+        if rasterband.mask_flags()?.is_all_valid() {
+            return Ok(None);
+        }
+        let mask = rasterband
+            .open_mask_band()?
+            .read_as::<u8>(window, size, size, None)?;
+        Ok(Some(mask.into_shape_and_vec().1))
+    }
+    fn sample_size(&self, band: usize) -> usize {
+        match self.g.rasterband(band) {
+            Ok(rasterband) => rasterband.band_type().bytes() as usize,
+            Err(_) => std::mem::size_of::<f64>(),
+        }
+    }
+    fn area_or_point(&self) -> AreaOrPoint {
+        use gdal::Metadata;
+        match self.g.metadata_item("AREA_OR_POINT", "") {
+            Some(value) if value.eq_ignore_ascii_case("point") => AreaOrPoint::Point,
+            _ => AreaOrPoint::Area,
+        }
+    }
+}
+
+// The proj4 definition of the CRS of a raster, None when its coordinates are
+// geographic, taken as WGS84 degrees (also without a CRS). GDAL gives WKT, the
+// synthetic rasters proj4.
+pub fn native_proj4(projection: &str) -> Result<Option<String>, RasterIoError> {
+    let projection = projection.trim();
+    if projection.is_empty() {
+        return Ok(None);
+    }
+    if projection.starts_with('+') {
+        let geographic = ["+proj=longlat", "+proj=latlong"]
+            .iter()
+            .any(|p| projection.contains(p));
+        return Ok((!geographic).then(|| projection.to_string()));
+    }
+    wkt_proj4(projection)
+}
+
+#[cfg(feature = "gdal")]
+fn wkt_proj4(wkt: &str) -> Result<Option<String>, RasterIoError> {
+    let srs = gdal::spatial_ref::SpatialRef::from_wkt(wkt)?;
+    if srs.is_geographic() {
+        return Ok(None);
+    }
+    Ok(Some(srs.to_proj4()?))
+}
+
+// without GDAL, only the geographic WKT (that of the .hgt tiles) is known
+#[cfg(not(feature = "gdal"))]
+fn wkt_proj4(wkt: &str) -> Result<Option<String>, RasterIoError> {
+    if wkt.starts_with("GEOGCS[") || wkt.starts_with("GEOGCRS[") {
+        return Ok(None);
+    }
+    Err(RasterIoError(format!(
+        "{}: projected WKT needs the gdal feature",
+        wkt
+    )))
+}
+
+// Writes row-major float bands of the same size as a GeoTIFF. The geotransform
+// is in the coordinates of `proj4`.
+#[cfg(feature = "gdal")]
+pub fn write_geotiff(
+    path: &Path,
+    size: (usize, usize),
+    geo_transform: [f64; 6],
+    proj4: &str,
+    nodata: f64,
+    bands: &[(&str, &[f64])],
+) -> Result<(), RasterIoError> {
+    use gdal::Metadata;
+    let driver = gdal::DriverManager::get_driver_by_name("GTiff")?;
+    let mut dataset = driver.create_with_band_type::<f64, _>(path, size.0, size.1, bands.len())?;
+    dataset.set_geo_transform(&geo_transform)?;
+    dataset.set_spatial_ref(&gdal::spatial_ref::SpatialRef::from_proj4(proj4)?)?;
+    for (i, (name, values)) in bands.iter().enumerate() {
+        let mut band = dataset.rasterband(i + 1)?;
+        band.set_no_data_value(Some(nodata))?;
+        band.set_description(name)?;
+        let mut buffer = gdal::raster::Buffer::new(size, values.to_vec());
+        band.write((0, 0), size, &mut buffer)?;
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "gdal"))]
+pub fn write_geotiff(
+    path: &Path,
+    _size: (usize, usize),
+    _geo_transform: [f64; 6],
+    _proj4: &str,
+    _nodata: f64,
+    _bands: &[(&str, &[f64])],
+) -> Result<(), RasterIoError> {
+    Err(RasterIoError(format!(
+        "{}: GeoTIFF export needs the gdal feature",
+        path.display()
+    )))
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum Call {
+    GeoTransform,
+    RasterSize,
+    Projection,
+    NoDataValue {
+        band: usize,
+    },
+    OverviewCount {
+        band: usize,
+    },
+    ReadWindow {
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    },
+    ReadMask {
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    },
+    AreaOrPoint,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum Response {
+    GeoTransform([f64; 6]),
+    RasterSize((usize, usize)),
+    Projection(String),
+    NoDataValue(Option<f64>),
+    OverviewCount(usize),
+    Values(Vec<f64>),
+    Mask(Option<Vec<u8>>),
+    AreaOrPoint(AreaOrPoint),
+    Error(String),
+}
+
+fn response<T>(r: &Result<T, RasterIoError>, ok: impl Fn(&T) -> Response) -> Response {
+    match r {
+        Ok(v) => ok(v),
+        Err(e) => Response::Error(e.0.clone()),
+    }
+}
+
+// Forwards to a backend and keeps every call/response pair. The fixture is
+// written to `path` when the recorder is dropped.
+pub struct Recorder {
+    backend: Box<dyn RasterIo>,
+    path: String,
+    entries: Mutex<Vec<(Call, Response)>>,
+}
+
+impl Recorder {
+    pub fn new(backend: Box<dyn RasterIo>, path: &str) -> Self {
+        Recorder {
+            backend,
+            path: path.to_string(),
+            entries: Mutex::new(Vec::new()),
+        }
+    }
+    fn record(&self, call: Call, response: Response) {
+        self.entries.lock().unwrap().push((call, response));
+    }
+    pub fn save(&self) -> std::io::Result<()> {
+        let entries = self.entries.lock().unwrap();
+        let json = serde_json::to_string_pretty(&*entries).unwrap();
+        std::fs::write(&self.path, json)
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        match self.save() {
+            Ok(()) => log::info!("recorded raster calls to {}", self.path),
+            Err(e) => log::warn!("could not write {}: {}", self.path, e),
+        }
+    }
+}
+
+impl RasterIo for Recorder {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        let r = self.backend.geo_transform();
+        self.record(Call::GeoTransform, response(&r, |v| Response::GeoTransform(*v)));
+        r
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        let r = self.backend.raster_size();
+        self.record(Call::RasterSize, Response::RasterSize(r));
+        r
+    }
+    fn projection(&self) -> String {
+        let r = self.backend.projection();
+        self.record(Call::Projection, Response::Projection(r.clone()));
+        r
+    }
+    fn no_data_value(&self, band: usize) -> Result<Option<f64>, RasterIoError> {
+        let r = self.backend.no_data_value(band);
+        let call = Call::NoDataValue { band };
+        self.record(call, response(&r, |v| Response::NoDataValue(*v)));
+        r
+    }
+    fn overview_count(&self, band: usize) -> Result<usize, RasterIoError> {
+        let r = self.backend.overview_count(band);
+        let call = Call::OverviewCount { band };
+        self.record(call, response(&r, |v| Response::OverviewCount(*v)));
+        r
+    }
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        let r = self.backend.read_window(band, window, size);
+        let call = Call::ReadWindow { band, window, size };
+        self.record(call, response(&r, |v| Response::Values(v.clone())));
+        r
+    }
+    fn read_mask(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        let r = self.backend.read_mask(band, window, size);
+        let call = Call::ReadMask { band, window, size };
+        self.record(call, response(&r, |v| Response::Mask(v.clone())));
+        r
+    }
+    fn sample_size(&self, band: usize) -> usize {
+        self.backend.sample_size(band)
+    }
+    fn area_or_point(&self) -> AreaOrPoint {
+        let r = self.backend.area_or_point();
+        self.record(Call::AreaOrPoint, Response::AreaOrPoint(r));
+        r
+    }
+}
+
+// Answers calls from a recorded fixture. Window reads may be served from any
+// recorded read of the same band that covers the requested window.
+pub struct Replay {
+    entries: Vec<(Call, Response)>,
+}
+
+impl Replay {
+    pub fn load(path: &str) -> Result<Self, RasterIoError> {
+        let json = std::fs::read_to_string(path).map_err(|e| RasterIoError(e.to_string()))?;
+        Self::from_json(&json)
+    }
+    pub fn from_json(json: &str) -> Result<Self, RasterIoError> {
+        let entries = serde_json::from_str(json).map_err(|e| RasterIoError(e.to_string()))?;
+        Ok(Replay { entries })
+    }
+    fn find(&self, call: &Call) -> Option<&Response> {
+        self.entries.iter().find(|(c, _)| c == call).map(|(_, r)| r)
+    }
+    fn missing(call: &Call) -> RasterIoError {
+        RasterIoError(format!("no recorded response for {:?}", call))
+    }
+    // Finds a recorded read covering the window and cuts the window out of it.
+    fn covering<T: Clone>(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+        mask: bool,
+        values: impl Fn(&Response) -> Option<Vec<T>>,
+    ) -> Option<Result<Vec<T>, RasterIoError>> {
+        for (call, response) in &self.entries {
+            let (b, w, s) = match (call, mask) {
+                (Call::ReadWindow { band, window, size }, false) => (band, window, size),
+                (Call::ReadMask { band, window, size }, true) => (band, window, size),
+                _ => continue,
+            };
+            let inside = *b == band
+                && w.0 <= window.0
+                && w.1 <= window.1
+                && window.0 + size.0 as isize <= w.0 + s.0 as isize
+                && window.1 + size.1 as isize <= w.1 + s.1 as isize;
+            if !inside {
+                continue;
+            }
+            if let Response::Error(e) = response {
+                return Some(Err(RasterIoError(e.clone())));
+            }
+            let data = values(response)?;
+            let mut ret = Vec::with_capacity(size.0 * size.1);
+            for row in 0..size.1 {
+                let r = (window.1 - w.1) as usize + row;
+                let c = (window.0 - w.0) as usize;
+                let start = r * s.0 + c;
+                ret.extend_from_slice(&data[start..start + size.0]);
+            }
+            return Some(Ok(ret));
+        }
+        None
+    }
+}
+
+impl RasterIo for Replay {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        match self.find(&Call::GeoTransform) {
+            Some(Response::GeoTransform(v)) => Ok(*v),
+            Some(Response::Error(e)) => Err(RasterIoError(e.clone())),
+            _ => Err(Self::missing(&Call::GeoTransform)),
+        }
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        match self.find(&Call::RasterSize) {
+            Some(Response::RasterSize(v)) => *v,
+            _ => panic!("{}", Self::missing(&Call::RasterSize)),
+        }
+    }
+    fn projection(&self) -> String {
+        match self.find(&Call::Projection) {
+            Some(Response::Projection(v)) => v.clone(),
+            _ => panic!("incomplete replay: {}", Self::missing(&Call::Projection)),
+        }
+    }
+    fn no_data_value(&self, band: usize) -> Result<Option<f64>, RasterIoError> {
+        let call = Call::NoDataValue { band };
+        match self.find(&call) {
+            Some(Response::NoDataValue(v)) => Ok(*v),
+            Some(Response::Error(e)) => Err(RasterIoError(e.clone())),
+            _ => Err(Self::missing(&call)),
+        }
+    }
+    fn overview_count(&self, band: usize) -> Result<usize, RasterIoError> {
+        let call = Call::OverviewCount { band };
+        match self.find(&call) {
+            Some(Response::OverviewCount(v)) => Ok(*v),
+            Some(Response::Error(e)) => Err(RasterIoError(e.clone())),
+            _ => Err(Self::missing(&call)),
+        }
+    }
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        let values = |r: &Response| match r {
+            Response::Values(v) => Some(v.clone()),
+            _ => None,
+        };
+        self.covering(band, window, size, false, values)
+            .unwrap_or_else(|| Err(Self::missing(&Call::ReadWindow { band, window, size })))
+    }
+    fn read_mask(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        // a recorded "all valid" answer holds for every window of the band
+        let all_valid = self.entries.iter().any(|(c, r)| {
+            matches!(c, Call::ReadMask { band: b, .. } if *b == band)
+                && matches!(r, Response::Mask(None))
+        });
+        if all_valid {
+            return Ok(None);
+        }
+        let values = |r: &Response| match r {
+            Response::Mask(Some(v)) => Some(v.clone()),
+            _ => None,
+        };
+        match self.covering(band, window, size, true, values) {
+            Some(r) => r.map(Some),
+            None => Err(Self::missing(&Call::ReadMask { band, window, size })),
+        }
+    }
+    // the fixtures recorded before the call was: GDAL's default
+    fn area_or_point(&self) -> AreaOrPoint {
+        match self.find(&Call::AreaOrPoint) {
+            Some(Response::AreaOrPoint(v)) => *v,
+            _ => AreaOrPoint::Area,
+        }
+    }
+}
+
+// A raster computed from an elevation function of (lon, lat), for tests and
+// examples without DEM files. The function returns None for nodata.
+pub struct Synthetic {
+    geo_transform: [f64; 6],
+    size: (usize, usize),
+    elevation: Box<dyn Fn(f64, f64) -> Option<f64> + Send + Sync>,
+    projection: String,
+    area_or_point: AreaOrPoint,
+}
+
+impl Synthetic {
+    pub const NODATA: f64 = -32768.0;
+
+    // pixel (0, 0) is sampled at upper_left = (lon, lat), the rows go south
+    pub fn new(
+        upper_left: (f64, f64),
+        step: f64,
+        size: (usize, usize),
+        elevation: impl Fn(f64, f64) -> Option<f64> + Send + Sync + 'static,
+    ) -> Self {
+        assert!(step > 0.0);
+        Synthetic {
+            geo_transform: [upper_left.0, step, 0.0, upper_left.1, 0.0, -step],
+            size,
+            elevation: Box::new(elevation),
+            projection: String::new(),
+            area_or_point: AreaOrPoint::Point,
+        }
+    }
+
+    // an area raster: upper_left is the corner of pixel (0, 0), sampled half
+    // a pixel further
+    pub fn area(mut self) -> Self {
+        self.area_or_point = AreaOrPoint::Area;
+        self
+    }
+
+    // the columns turned by `degrees` counterclockwise around upper_left,
+    // the rows with them
+    pub fn rotated(mut self, degrees: f64) -> Self {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let g = &mut self.geo_transform;
+        let step = g[1].hypot(g[4]);
+        (g[1], g[2], g[4], g[5]) = (step * cos, step * sin, step * sin, -step * cos);
+        self
+    }
+
+    // the same samples with the rows going north: pixel (0, 0) is the lower
+    // left one, the pixel height positive (after area, if any)
+    pub fn south_up(mut self) -> Self {
+        let rows = match self.area_or_point {
+            AreaOrPoint::Area => self.size.1,
+            AreaOrPoint::Point => self.size.1 - 1,
+        };
+        let g = &mut self.geo_transform;
+        g[3] += rows as f64 * g[5];
+        g[5] = -g[5];
+        self
+    }
+
+    // the geotransform and the arguments of the elevation function in the
+    // coordinates of `proj4` instead of degrees
+    pub fn projected(mut self, proj4: &str) -> Self {
+        self.projection = proj4.to_string();
+        self
+    }
+}
+
+impl RasterIo for Synthetic {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        Ok(self.geo_transform)
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        self.size
+    }
+    fn projection(&self) -> String {
+        self.projection.clone()
+    }
+    fn no_data_value(&self, _band: usize) -> Result<Option<f64>, RasterIoError> {
+        Ok(Some(Self::NODATA))
+    }
+    fn overview_count(&self, _band: usize) -> Result<usize, RasterIoError> {
+        Ok(0)
+    }
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        let inside = band == 1
+            && window.0 >= 0
+            && window.1 >= 0
+            && window.0 as usize + size.0 <= self.size.0
+            && window.1 as usize + size.1 <= self.size.1;
+        if !inside {
+            return Err(RasterIoError(format!(
+                "window {:?} {:?} of band {} is outside of the raster",
+                window, size, band
+            )));
+        }
+        let g = &self.geo_transform;
+        let offset = match self.area_or_point {
+            AreaOrPoint::Area => 0.5,
+            AreaOrPoint::Point => 0.0,
+        };
+        let mut ret = Vec::with_capacity(size.0 * size.1);
+        for row in 0..size.1 {
+            for col in 0..size.0 {
+                let c = (window.0 as usize + col) as f64 + offset;
+                let r = (window.1 as usize + row) as f64 + offset;
+                let (lon, lat) = (g[0] + c * g[1] + r * g[2], g[3] + c * g[4] + r * g[5]);
+                ret.push((self.elevation)(lon, lat).unwrap_or(Self::NODATA));
+            }
+        }
+        Ok(ret)
+    }
+    fn read_mask(
+        &self,
+        _band: usize,
+        _window: (isize, isize),
+        _size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        Ok(None)
+    }
+    fn area_or_point(&self) -> AreaOrPoint {
+        self.area_or_point
+    }
+}
+
+// A raster of given values, row-major from pixel (0, 0) sampled at
+// upper_left, the rows going south: a DEM for tests and embedding
+// applications without files. NaN values are nodata.
+pub struct InMemory {
+    geo_transform: [f64; 6],
+    size: (usize, usize),
+    values: Vec<f64>,
+}
+
+impl InMemory {
+    pub fn new(
+        upper_left: (f64, f64),
+        step: f64,
+        size: (usize, usize),
+        values: Vec<f64>,
+    ) -> Result<Self, RasterIoError> {
+        if !step.is_finite() || step <= 0.0 || values.len() != size.0 * size.1 {
+            return Err(RasterIoError(format!(
+                "{} values for {}x{} pixels of {}",
+                values.len(),
+                size.0,
+                size.1,
+                step
+            )));
+        }
+        Ok(InMemory {
+            geo_transform: [upper_left.0, step, 0.0, upper_left.1, 0.0, -step],
+            size,
+            values,
+        })
+    }
+}
+
+impl RasterIo for InMemory {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        Ok(self.geo_transform)
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        self.size
+    }
+    fn projection(&self) -> String {
+        String::new()
+    }
+    fn no_data_value(&self, _band: usize) -> Result<Option<f64>, RasterIoError> {
+        Ok(None)
+    }
+    fn overview_count(&self, _band: usize) -> Result<usize, RasterIoError> {
+        Ok(0)
+    }
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        let inside = band == 1
+            && window.0 >= 0
+            && window.1 >= 0
+            && window.0 as usize + size.0 <= self.size.0
+            && window.1 as usize + size.1 <= self.size.1;
+        if !inside {
+            return Err(RasterIoError(format!(
+                "window {:?} {:?} of band {} is outside of the raster",
+                window, size, band
+            )));
+        }
+        let (col, row) = (window.0 as usize, window.1 as usize);
+        let mut ret = Vec::with_capacity(size.0 * size.1);
+        for r in row..row + size.1 {
+            let start = r * self.size.0 + col;
+            ret.extend_from_slice(&self.values[start..start + size.0]);
+        }
+        Ok(ret)
+    }
+    fn read_mask(
+        &self,
+        _band: usize,
+        _window: (isize, isize),
+        _size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        Ok(None)
+    }
+    fn area_or_point(&self) -> AreaOrPoint {
+        AreaOrPoint::Point
+    }
+}
+
+// The backend with every valid sample at the same elevation, nodata kept.
+pub struct Flattened {
+    backend: Rc<dyn RasterIo>,
+    elevation: f64,
+}
+
+impl Flattened {
+    pub fn new(backend: Rc<dyn RasterIo>, elevation: f64) -> Self {
+        Flattened { backend, elevation }
+    }
+}
+
+impl RasterIo for Flattened {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        self.backend.geo_transform()
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        self.backend.raster_size()
+    }
+    fn projection(&self) -> String {
+        self.backend.projection()
+    }
+    fn no_data_value(&self, band: usize) -> Result<Option<f64>, RasterIoError> {
+        self.backend.no_data_value(band)
+    }
+    fn overview_count(&self, band: usize) -> Result<usize, RasterIoError> {
+        self.backend.overview_count(band)
+    }
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        let nodata = self.backend.no_data_value(band)?;
+        let mut values = self.backend.read_window(band, window, size)?;
+        for v in &mut values {
+            if Some(*v) != nodata && !v.is_nan() {
+                *v = self.elevation;
+            }
+        }
+        Ok(values)
+    }
+    fn read_mask(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        self.backend.read_mask(band, window, size)
+    }
+    fn sample_size(&self, band: usize) -> usize {
+        self.backend.sample_size(band)
+    }
+    fn area_or_point(&self) -> AreaOrPoint {
+        self.backend.area_or_point()
+    }
+}
+
+// What was read from a dataset: the windows (values and masks), the pixel
+// values, the bytes in the source data type and the time spent reading.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct IoStats {
+    pub dataset: String,
+    pub windows: u64,
+    pub pixels: u64,
+    pub bytes: u64,
+    pub seconds: f64,
+}
+
+impl IoStats {
+    pub fn add(&mut self, other: &IoStats) {
+        self.windows += other.windows;
+        self.pixels += other.pixels;
+        self.bytes += other.bytes;
+        self.seconds += other.seconds;
+    }
+
+    // adds to the entry of the same dataset
+    pub fn merge(stats: &mut Vec<IoStats>, other: &IoStats) {
+        match stats.iter_mut().find(|s| s.dataset == other.dataset) {
+            Some(s) => s.add(other),
+            None => stats.push(other.clone()),
+        }
+    }
+
+    pub fn total(stats: &[IoStats]) -> IoStats {
+        let mut ret = IoStats {
+            dataset: "total".to_string(),
+            ..Default::default()
+        };
+        for s in stats {
+            ret.add(s);
+        }
+        ret
+    }
+}
+
+impl fmt::Display for IoStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} windows, {} pixels, {:.1} MB, {:.3} s",
+            self.dataset,
+            self.windows,
+            self.pixels,
+            self.bytes as f64 / 1e6,
+            self.seconds
+        )
+    }
+}
+
+// Relaxed atomics: the counters are only summed, the datasets may be read
+// from several threads.
+#[derive(Debug, Default)]
+pub struct IoCounters {
+    windows: AtomicU64,
+    pixels: AtomicU64,
+    bytes: AtomicU64,
+    nanos: AtomicU64,
+}
+
+impl IoCounters {
+    fn add(&self, pixels: usize, bytes: usize, elapsed: Duration) {
+        self.windows.fetch_add(1, Ordering::Relaxed);
+        self.pixels.fetch_add(pixels as u64, Ordering::Relaxed);
+        self.bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.nanos
+            .fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self, dataset: &str) -> IoStats {
+        IoStats {
+            dataset: dataset.to_string(),
+            windows: self.windows.load(Ordering::Relaxed),
+            pixels: self.pixels.load(Ordering::Relaxed),
+            bytes: self.bytes.load(Ordering::Relaxed),
+            seconds: self.nanos.load(Ordering::Relaxed) as f64 * 1e-9,
+        }
+    }
+}
+
+// Forwards to a backend and counts its window reads.
+pub struct Counting {
+    backend: Rc<dyn RasterIo>,
+    counters: Arc<IoCounters>,
+}
+
+impl Counting {
+    pub fn new(backend: Box<dyn RasterIo>) -> Self {
+        Self::shared(Rc::from(backend))
+    }
+    // counts the reads through this one only, not those of the other users
+    // of the backend
+    pub fn shared(backend: Rc<dyn RasterIo>) -> Self {
+        Counting {
+            backend,
+            counters: Arc::new(IoCounters::default()),
+        }
+    }
+    pub fn counters(&self) -> Arc<IoCounters> {
+        self.counters.clone()
+    }
+}
+
+impl RasterIo for Counting {
+    fn geo_transform(&self) -> Result<[f64; 6], RasterIoError> {
+        self.backend.geo_transform()
+    }
+    fn raster_size(&self) -> (usize, usize) {
+        self.backend.raster_size()
+    }
+    fn projection(&self) -> String {
+        self.backend.projection()
+    }
+    fn no_data_value(&self, band: usize) -> Result<Option<f64>, RasterIoError> {
+        self.backend.no_data_value(band)
+    }
+    fn overview_count(&self, band: usize) -> Result<usize, RasterIoError> {
+        self.backend.overview_count(band)
+    }
+    fn read_window(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Vec<f64>, RasterIoError> {
+        let start = Instant::now();
+        let r = self.backend.read_window(band, window, size);
+        let pixels = r.as_ref().map_or(0, |v| v.len());
+        let bytes = pixels * self.backend.sample_size(band);
+        self.counters.add(pixels, bytes, start.elapsed());
+        r
+    }
+    fn read_mask(
+        &self,
+        band: usize,
+        window: (isize, isize),
+        size: (usize, usize),
+    ) -> Result<Option<Vec<u8>>, RasterIoError> {
+        let start = Instant::now();
+        let r = self.backend.read_mask(band, window, size);
+        let bytes = match &r {
+            Ok(Some(mask)) => mask.len(),
+            _ => 0,
+        };
+        self.counters.add(0, bytes, start.elapsed());
+        r
+    }
+    fn sample_size(&self, band: usize) -> usize {
+        self.backend.sample_size(band)
+    }
+    fn area_or_point(&self) -> AreaOrPoint {
+        self.backend.area_or_point()
+    }
+}
+
+// Local development only: with SURFACE_AREA_RECORD=<dir>, the raster calls
+// of every opened dataset are recorded to <dir>/<basename>.json.
+#[cfg(debug_assertions)]
+pub fn record_if_requested(backend: Box<dyn RasterIo>, filename: &str) -> Box<dyn RasterIo> {
+    match std::env::var("SURFACE_AREA_RECORD") {
+        Ok(dir) => {
+            let basename = Path::new(filename).file_name().unwrap().to_string_lossy();
+            let path = format!("{}/{}.json", dir, basename);
+            log::info!("recording raster calls of {} to {}", filename, path);
+            Box::new(Recorder::new(backend, &path))
+        }
+        Err(_) => backend,
+    }
+}
+
+#[cfg(not(debug_assertions))]
+pub fn record_if_requested(backend: Box<dyn RasterIo>, _filename: &str) -> Box<dyn RasterIo> {
+    backend
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dataset::Dataset;
+    use crate::fixtures;
+    use crate::options::ComputeOptions;
+
+    #[test]
+    fn test_gdal_path() {
+        assert_eq!(
+            gdal_path("https://example.com/dem.tif"),
+            "/vsicurl/https://example.com/dem.tif"
+        );
+        assert_eq!(gdal_path("s3://bucket/dem.tif"), "/vsis3/bucket/dem.tif");
+        assert_eq!(gdal_path("/data/dem.tif"), "/data/dem.tif");
+        assert!(!is_remote("/data/s3://dem.tif"));
+    }
+
+    #[test]
+    fn test_replay_window() {
+        let replay = fixtures::replay();
+        assert_eq!(replay.raster_size(), (10, 10));
+        assert_eq!(replay.no_data_value(1).unwrap(), None);
+        let values = replay.read_window(1, (2, 3), (3, 2)).unwrap();
+        assert_eq!(values, vec![132.0, 133.0, 134.0, 142.0, 143.0, 144.0]);
+        assert!(replay.read_mask(1, (2, 3), (3, 2)).unwrap().is_none());
+        assert!(replay.read_window(1, (8, 8), (3, 3)).is_err());
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let path = std::env::temp_dir().join("surface-area-record-test.json");
+        let path = path.to_str().unwrap().to_string();
+        {
+            let recorder = Recorder::new(Box::new(fixtures::replay()), &path);
+            recorder.geo_transform().unwrap();
+            recorder.raster_size();
+            recorder.read_window(1, (0, 0), (2, 2)).unwrap();
+        }
+        let replay = Replay::load(&path).unwrap();
+        assert_eq!(
+            replay.geo_transform().unwrap(),
+            fixtures::replay().geo_transform().unwrap()
+        );
+        assert_eq!(
+            replay.read_window(1, (1, 1), (1, 1)).unwrap(),
+            vec![111.0]
+        );
+        // the projection was not recorded
+        assert!(std::panic::catch_unwind(|| replay.projection()).is_err());
+    }
+
+    #[test]
+    fn test_synthetic() {
+        let synthetic = Synthetic::new((6.0, 46.5), 0.25, (4, 3), |lon, lat| {
+            (lon < 6.5 || lat < 46.5).then_some(100.0 * (lon - 6.0) + 10.0 * (46.5 - lat))
+        });
+        assert_eq!(synthetic.geo_transform().unwrap()[5], -0.25);
+        let values = synthetic.read_window(1, (1, 1), (3, 2)).unwrap();
+        assert_eq!(values, vec![27.5, 52.5, 77.5, 30.0, 55.0, 80.0]);
+        let values = synthetic.read_window(1, (2, 0), (2, 1)).unwrap();
+        assert_eq!(values, vec![Synthetic::NODATA, Synthetic::NODATA]);
+        assert!(synthetic.read_window(1, (2, 2), (3, 1)).is_err());
+    }
+
+    #[test]
+    fn test_in_memory() {
+        let values = (0..12).map(|v| v as f64).collect();
+        let grid = InMemory::new((6.0, 46.5), 0.25, (4, 3), values).unwrap();
+        assert_eq!(grid.geo_transform().unwrap()[5], -0.25);
+        let values = grid.read_window(1, (1, 1), (3, 2)).unwrap();
+        assert_eq!(values, vec![5.0, 6.0, 7.0, 9.0, 10.0, 11.0]);
+        assert!(grid.read_window(1, (2, 2), (3, 1)).is_err());
+        assert!(InMemory::new((6.0, 46.5), 0.25, (4, 3), vec![0.0; 11]).is_err());
+    }
+
+    // A ridge along the meridian of the center, its flanks falling 25% on
+    // both sides. The crest is a column of samples: the triangles follow both
+    // flanks exactly.
+    #[test]
+    fn test_in_memory_ridge() {
+        use crate::fixtures::{STEP, assert_close};
+
+        let (upper_left, size) = fixtures::samples((-250.0, -250.0, 250.0, 250.0));
+        let mut values = Vec::with_capacity(size.0 * size.1);
+        for row in 0..size.1 {
+            for col in 0..size.0 {
+                let lon = upper_left.0 + col as f64 * STEP;
+                let (x, _) = fixtures::local(lon, upper_left.1 - row as f64 * STEP);
+                values.push(350.0 - 0.25 * x.abs());
+            }
+        }
+        let io = InMemory::new(upper_left, STEP, size, values).unwrap();
+        let dem = Dataset::from_io("ridge", Box::new(io), &fixtures::projection()).unwrap();
+        let polygon = fixtures::polygon(&fixtures::square(200.0));
+        let options = ComputeOptions::default();
+        let data = crate::pipeline::compute("ridge", &polygon, &[dem], &options).unwrap();
+        assert_close("flat", data.projected2d, 160_000.0, 1e-4);
+        assert_close(
+            "surface",
+            data.projected3d,
+            160_000.0 * 1.0625f64.sqrt(),
+            1e-4,
+        );
+        let highest = data.extremes.highest.as_ref().unwrap();
+        assert_close("crest", highest.elevation, 350.0, 1e-9);
+    }
+
+    #[test]
+    fn test_counting() {
+        let synthetic = Synthetic::new((6.0, 46.5), 0.25, (10, 8), |_, _| Some(1.0));
+        let counting = Counting::new(Box::new(synthetic));
+        let counters = counting.counters();
+        counting.read_window(1, (2, 3), (4, 5)).unwrap();
+        counting.read_window(1, (0, 0), (10, 1)).unwrap();
+        assert_eq!(counting.read_mask(1, (0, 0), (10, 8)).unwrap(), None);
+        // a failed read counts the window, no pixels
+        assert!(counting.read_window(1, (8, 0), (3, 1)).is_err());
+        let stats = counters.stats("synthetic");
+        assert_eq!(stats.windows, 4);
+        assert_eq!(stats.pixels, 30);
+        assert_eq!(stats.bytes, 30 * 8);
+        let mut all = vec![stats.clone()];
+        IoStats::merge(&mut all, &stats);
+        assert_eq!(all.len(), 1);
+        assert_eq!(IoStats::total(&all).pixels, 60);
+
+        let counters = IoCounters::default();
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..1000 {
+                        counters.add(3, 6, Duration::from_nanos(1));
+                    }
+                });
+            }
+        });
+        let stats = counters.stats("threads");
+        assert_eq!(
+            (stats.windows, stats.pixels, stats.bytes),
+            (4000, 12000, 24000)
+        );
+    }
+}
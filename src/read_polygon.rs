@@ -1,51 +1,84 @@
 use crate::point::WGS84Point;
 use crate::polygon::Polygon;
-use kml::types::Geometry;
+use kml::types::{AltitudeMode, Geometry, LinearRing};
 use kml::Kml;
 use std::fs::File;
 use std::io::Read;
 
 mod lockml {
     use super::*;
-    fn find_first_line_string(kml: &Kml) -> Option<geo::Polygon> {
+
+    // the polygons of the placemarks, with their names, in the order
+    // of the document
+    fn placemark_polygons(kml: &Kml, ret: &mut Vec<(kml::types::Polygon, Option<String>)>) {
         match kml {
-            Kml::KmlDocument(doc) => doc.elements.iter().find_map(find_first_line_string),
-            Kml::Document { elements, .. } => elements.iter().find_map(find_first_line_string),
-            Kml::Folder(z) => z.elements.iter().find_map(find_first_line_string),
+            Kml::KmlDocument(doc) => doc.elements.iter().for_each(|e| placemark_polygons(e, ret)),
+            Kml::Document { elements, .. } => {
+                elements.iter().for_each(|e| placemark_polygons(e, ret))
+            }
+            Kml::Folder(z) => z.elements.iter().for_each(|e| placemark_polygons(e, ret)),
             Kml::Placemark(p) => {
-                if let Some(Geometry::Polygon(ls)) = &p.geometry {
-                    // Convert kml::types::LineString to geo::LineString
-                    // This requires the 'geo-types' feature (enabled by default in kml crate)
-                    Some(geo::Polygon::from(ls.clone()))
-                } else {
-                    None
+                if let Some(geometry) = &p.geometry {
+                    geometry_polygons(geometry, &p.name, ret);
                 }
             }
-            _ => None,
+            _ => {}
         }
     }
 
-    pub fn read(content: &str) -> Vec<Polygon> {
+    fn geometry_polygons(
+        geometry: &Geometry,
+        name: &Option<String>,
+        ret: &mut Vec<(kml::types::Polygon, Option<String>)>,
+    ) {
+        match geometry {
+            Geometry::Polygon(polygon) => ret.push((polygon.clone(), name.clone())),
+            Geometry::MultiGeometry(multi) => multi
+                .geometries
+                .iter()
+                .for_each(|g| geometry_polygons(g, name, ret)),
+            _ => {}
+        }
+    }
+
+    // One boundary, the polygons of the file are its parts: named after
+    // their placemarks.
+    pub fn read(content: &str) -> Vec<Vec<Part>> {
         // 2. Parse KML string
         let kml: Kml = content.parse().unwrap();
 
-        // 3. Extract LineString from the KML structure
+        // 3. Extract the polygons from the KML structure
         // KML can be complex (folders, multiple placemarks),
-        // so we need a recursive helper or a find-first logic.
-        let geo_geometry = find_first_line_string(&kml)
-            .ok_or("No LineString found in the KML file")
-            .unwrap();
-        let wgs: Vec<_> = geo_geometry
-            .exterior()
-            .0
+        // so we need a recursive helper.
+        let mut found = Vec::new();
+        placemark_polygons(&kml, &mut found);
+        assert!(!found.is_empty(), "No Polygon found in the KML file");
+        // the altitudes are elevations only when absolute, KML ignores them
+        // on the ground and they are heights above it otherwise
+        let wgs = |ring: &LinearRing, mode: AltitudeMode| -> Vec<WGS84Point> {
+            ring.coords
+                .iter()
+                .map(|p| WGS84Point {
+                    lon: p.x,
+                    lat: p.y,
+                    ele: p.z.filter(|_| mode == AltitudeMode::Absolute),
+                })
+                .collect()
+        };
+        let names: Vec<String> = found
+            .iter()
+            .map(|(_, name)| name.clone().unwrap_or_default())
+            .collect();
+        let polygons = found
             .iter()
-            .map(|p| WGS84Point {
-                lon: p.x,
-                lat: p.y,
-                ele: None,
+            .map(|(polygon, _)| {
+                let mode = polygon.altitude_mode;
+                // the inner boundaries are the holes
+                let holes = polygon.inner.iter().map(|ring| wgs(ring, mode)).collect();
+                Polygon::new(wgs(&polygon.outer, mode)).with_holes(holes)
             })
             .collect();
-        vec![Polygon { wgs }]
+        vec![parts(polygons, &names)]
     }
 }
 
@@ -58,7 +91,8 @@ mod locgpx {
         // Parse the GPX content
         let gpx: Gpx = gpx::read(Cursor::new(content)).expect("Failed to parse GPX content");
 
-        // Extract track segments and convert them into polygons
+        // Extract track segments and convert them into polygons, GPX has no
+        // holes
         gpx.tracks
             .iter()
             .flat_map(|track| {
@@ -69,10 +103,10 @@ mod locgpx {
                         .map(|point| WGS84Point {
                             lon: point.point().x(),
                             lat: point.point().y(),
-                            ele: None,
+                            ele: point.elevation,
                         })
                         .collect();
-                    Polygon { wgs }
+                    Polygon::new(wgs)
                 })
             })
             .collect()
@@ -81,81 +115,233 @@ mod locgpx {
 
 mod locjson {
     use super::*;
-    use geojson::{GeoJson, Geometry, Value};
+    use geojson::{Feature, GeoJson, Geometry, Value};
 
-    pub fn read(content: &str) -> Vec<Polygon> {
+    pub fn read(content: &str) -> Vec<Vec<Part>> {
         // Parse the GeoJSON content
         let geojson: GeoJson = content.parse().expect("Failed to parse GeoJSON content");
 
-        // Extract polygons from the GeoJSON
-        match geojson {
-            GeoJson::FeatureCollection(collection) => collection
-                .features
+        // Extract the boundaries from the GeoJSON
+        let boundaries = match geojson {
+            GeoJson::FeatureCollection(collection) => {
+                collection.features.iter().map(feature_parts).collect()
+            }
+            GeoJson::Feature(feature) => vec![feature_parts(&feature)],
+            GeoJson::Geometry(geometry) => vec![parts(geometry_to_polygons(&geometry), &[])],
+        };
+        boundaries.into_iter().filter(|b| !b.is_empty()).collect()
+    }
+
+    fn feature_parts(feature: &Feature) -> Vec<Part> {
+        let Some(geometry) = &feature.geometry else {
+            return Vec::new();
+        };
+        let names: Vec<String> = match feature.property("part_names") {
+            Some(serde_json::Value::Array(names)) => names
                 .iter()
-                .filter_map(|feature| {
-                    if let Some(geometry) = &feature.geometry {
-                        geometry_to_polygon(geometry)
-                    } else {
-                        None
-                    }
-                })
+                .map(|n| n.as_str().unwrap_or_default().to_string())
                 .collect(),
-            GeoJson::Feature(feature) => {
-                if let Some(geometry) = feature.geometry {
-                    geometry_to_polygon(&geometry).into_iter().collect()
-                } else {
-                    vec![]
-                }
-            }
-            GeoJson::Geometry(geometry) => geometry_to_polygon(&geometry).into_iter().collect(),
-        }
+            _ => Vec::new(),
+        };
+        parts(geometry_to_polygons(geometry), &names)
     }
 
-    fn geometry_to_polygon(geometry: &Geometry) -> Option<Polygon> {
+    // every polygon, the rings after the first are its holes
+    fn geometry_to_polygons(geometry: &Geometry) -> Vec<Polygon> {
+        let ring = |coords: &Vec<Vec<f64>>| -> Vec<WGS84Point> {
+            coords
+                .iter()
+                .map(|p| WGS84Point {
+                    lon: p[0],
+                    lat: p[1],
+                    // the altitude, if the position has one
+                    ele: p.get(2).copied(),
+                })
+                .collect()
+        };
+        let polygon = |coords: &Vec<Vec<Vec<f64>>>| {
+            Polygon::new(ring(&coords[0])).with_holes(coords[1..].iter().map(ring).collect())
+        };
         match &geometry.value {
-            Value::Polygon(coords) => {
-                let wgs: Vec<WGS84Point> = coords[0]
-                    .iter()
-                    .map(|p| WGS84Point {
-                        lon: p[0],
-                        lat: p[1],
-                        ele: None,
-                    })
-                    .collect();
-                Some(Polygon { wgs })
-            }
-            Value::MultiPolygon(multi_coords) => {
-                // Flatten the first polygon in the MultiPolygon
-                if let Some(coords) = multi_coords.first() {
-                    let wgs: Vec<WGS84Point> = coords[0]
-                        .iter()
-                        .map(|p| WGS84Point {
-                            lon: p[0],
-                            lat: p[1],
-                            ele: None,
-                        })
-                        .collect();
-                    Some(Polygon { wgs })
-                } else {
-                    None
-                }
-            }
-            _ => None,
+            Value::Polygon(coords) => vec![polygon(coords)],
+            Value::MultiPolygon(multi_coords) => multi_coords.iter().map(polygon).collect(),
+            _ => Vec::new(),
         }
     }
 }
 
-pub fn read_polyline(filename: &str) -> Vec<Polygon> {
+// One part of a boundary, with its position (from 1) in the multi-part
+// geometry and its name: from the "part_names" property of the GeoJSON
+// feature, "part <index>" otherwise.
+pub struct Part {
+    pub index: usize,
+    pub name: String,
+    pub polygon: Polygon,
+}
+
+fn parts(polygons: Vec<Polygon>, names: &[String]) -> Vec<Part> {
+    polygons
+        .into_iter()
+        .enumerate()
+        .map(|(k, polygon)| Part {
+            index: k + 1,
+            name: match names.get(k) {
+                Some(name) if !name.is_empty() => name.clone(),
+                _ => format!("part {}", k + 1),
+            },
+            polygon,
+        })
+        .collect()
+}
+
+// The boundaries of the file, each in one or several parts.
+pub fn read_boundaries(filename: &str) -> Vec<Vec<Part>> {
     // 1. Read file content
     let mut file = File::open(filename).unwrap();
     let mut content = String::new();
     file.read_to_string(&mut content).unwrap();
+    let single = |polygons: Vec<Polygon>| -> Vec<Vec<Part>> {
+        polygons.into_iter().map(|p| parts(vec![p], &[])).collect()
+    };
     if filename.ends_with("kml") {
         return lockml::read(&content);
     } else if filename.ends_with("gpx") {
-        return locgpx::read(&content);
+        return single(locgpx::read(&content));
     } else if filename.ends_with("geojson") {
         return locjson::read(&content);
     }
     Vec::new()
 }
+
+// every part of every boundary
+pub fn read_polyline(filename: &str) -> Vec<Polygon> {
+    read_boundaries(filename)
+        .into_iter()
+        .flatten()
+        .map(|part| part.polygon)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_multipolygon_parts() {
+        let square = |lon: f64| {
+            format!(
+                "[[[{0}, 46.0], [{1}, 46.0], [{1}, 46.1], [{0}, 46.1], [{0}, 46.0]]]",
+                lon,
+                lon + 0.1
+            )
+        };
+        let content = format!(
+            r#"{{"type": "FeatureCollection", "features": [
+                {{"type": "Feature", "properties": {{"part_names": ["north", ""]}},
+                  "geometry": {{"type": "MultiPolygon", "coordinates": [{}, {}]}}}},
+                {{"type": "Feature", "properties": null,
+                  "geometry": {{"type": "Polygon", "coordinates": {}}}}}
+            ]}}"#,
+            square(7.0),
+            square(7.2),
+            square(7.4)
+        );
+        let boundaries = locjson::read(&content);
+        assert_eq!(boundaries.len(), 2);
+        let names: Vec<_> = boundaries[0].iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["north", "part 2"]);
+        assert_eq!(boundaries[0][1].index, 2);
+        assert_eq!(boundaries[0][1].polygon.wgs[0].lon, 7.2);
+        assert_eq!(boundaries[1].len(), 1);
+        assert_eq!(boundaries[1][0].name, "part 1");
+    }
+
+    #[test]
+    fn test_holes() {
+        let content = r#"{"type": "Polygon", "coordinates": [
+            [[7.0, 46.0], [7.1, 46.0], [7.1, 46.1], [7.0, 46.1], [7.0, 46.0]],
+            [[7.02, 46.02], [7.02, 46.04], [7.04, 46.04], [7.04, 46.02], [7.02, 46.02]]
+        ]}"#;
+        let boundaries = locjson::read(content);
+        let polygon = &boundaries[0][0].polygon;
+        assert_eq!(polygon.wgs.len(), 5);
+        assert_eq!(polygon.holes.len(), 1);
+        assert_eq!(polygon.holes[0][2].lon, 7.04);
+
+        let content = r#"<kml xmlns="http://www.opengis.net/kml/2.2"><Placemark><Polygon>
+            <outerBoundaryIs><LinearRing><coordinates>
+                7.0,46.0 7.1,46.0 7.1,46.1 7.0,46.1 7.0,46.0
+            </coordinates></LinearRing></outerBoundaryIs>
+            <innerBoundaryIs><LinearRing><coordinates>
+                7.02,46.02 7.02,46.04 7.04,46.04 7.04,46.02 7.02,46.02
+            </coordinates></LinearRing></innerBoundaryIs>
+        </Polygon></Placemark></kml>"#;
+        let polygon = &lockml::read(content)[0][0].polygon;
+        assert_eq!(polygon.wgs.len(), 5);
+        assert_eq!(polygon.holes.len(), 1);
+        assert_eq!(polygon.holes[0][1].lat, 46.04);
+    }
+
+    #[test]
+    fn test_kml_placemarks() {
+        let placemark = |name: &str, lon: f64| {
+            format!(
+                "<Placemark><name>{0}</name><Polygon><outerBoundaryIs><LinearRing><coordinates>
+                    {1},46.0 {2},46.0 {2},46.1 {1},46.1 {1},46.0
+                </coordinates></LinearRing></outerBoundaryIs></Polygon></Placemark>",
+                name,
+                lon,
+                lon + 0.1
+            )
+        };
+        let content = format!(
+            r#"<kml xmlns="http://www.opengis.net/kml/2.2"><Document>{}<Folder>{}</Folder>
+            <Placemark><name>path</name><LineString><coordinates>7.0,46.0 7.1,46.1</coordinates></LineString></Placemark>
+            </Document></kml>"#,
+            placemark("west", 7.0),
+            placemark("", 7.2)
+        );
+        let boundaries = lockml::read(&content);
+        assert_eq!(boundaries.len(), 1);
+        let names: Vec<_> = boundaries[0].iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, ["west", "part 2"]);
+        assert_eq!(boundaries[0][1].polygon.wgs[0].lon, 7.2);
+    }
+
+    #[test]
+    fn test_elevations() {
+        let content = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <gpx version="1.1" creator="test" xmlns="http://www.topografix.com/GPX/1/1">
+            <trk><trkseg>
+                <trkpt lat="46.0" lon="7.0"><ele>1510.5</ele></trkpt>
+                <trkpt lat="46.0" lon="7.1"><ele>1522.0</ele></trkpt>
+                <trkpt lat="46.1" lon="7.1"></trkpt>
+                <trkpt lat="46.0" lon="7.0"><ele>1510.5</ele></trkpt>
+            </trkseg></trk></gpx>"#;
+        let polygons = locgpx::read(content);
+        let elevations: Vec<Option<f64>> = polygons[0].wgs.iter().map(|p| p.ele).collect();
+        assert_eq!(elevations, [Some(1510.5), Some(1522.0), None, Some(1510.5)]);
+
+        let content = r#"{"type": "Polygon", "coordinates": [
+            [[7.0, 46.0, 1510.5], [7.1, 46.0, 1522.0], [7.1, 46.1], [7.0, 46.0, 1510.5]]
+        ]}"#;
+        let polygon = &locjson::read(content)[0][0].polygon;
+        assert_eq!(polygon.wgs[1].ele, Some(1522.0));
+        assert_eq!(polygon.wgs[2].ele, None);
+
+        // on the ground, the altitudes are ignored
+        let kml = |mode: &str| {
+            format!(
+                r#"<kml xmlns="http://www.opengis.net/kml/2.2"><Placemark><Polygon>{}
+                <outerBoundaryIs><LinearRing><coordinates>
+                    7.0,46.0,1510.5 7.1,46.0,1522 7.1,46.1,1530 7.0,46.0,1510.5
+                </coordinates></LinearRing></outerBoundaryIs></Polygon></Placemark></kml>"#,
+                mode
+            )
+        };
+        let polygon = &lockml::read(&kml("<altitudeMode>absolute</altitudeMode>"))[0][0].polygon;
+        assert_eq!(polygon.wgs[1].ele, Some(1522.0));
+        let polygon = &lockml::read(&kml(""))[0][0].polygon;
+        assert!(polygon.wgs.iter().all(|p| p.ele.is_none()));
+    }
+}
@@ -1,38 +1,293 @@
+use core::fmt;
+use std::f64::consts::{FRAC_PI_2, PI};
+
 use geo::algorithm::geodesic_area::GeodesicArea;
 use geo::orient::Direction;
-use geo::{Area, Coord, Orient, Polygon};
+use geo::{Area, BooleanOps, Coord, MultiPolygon, Orient, Polygon, Validation};
+
+use crate::mercator::ProjectionError;
+use crate::point::{self, MercatorPoint, WGS84Point};
+
+// Relative difference between the geodesic area and the area in a local
+// equal-area (LAEA) projection above which something is off: the projection
+// preserves areas, what is left comes from straight versus geodesic edges.
+pub const CONSISTENCY_TOLERANCE: f64 = 0.01;
+
+// Relative difference between the spherical and the geodesic area above
+// which the ring itself is suspect (winding, closure, self-intersections):
+// the shape of the Earth alone accounts for far less.
+pub const SPHERICAL_TOLERANCE: f64 = 0.005;
+
+// WGS84
+const SEMI_MAJOR_AXIS: f64 = 6_378_137.0;
+const FLATTENING: f64 = 1.0 / 298.257_223_563;
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum GeometryError {
+    Invalid { reason: String },
+}
+
+impl fmt::Display for GeometryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeometryError::Invalid { reason } => write!(f, "invalid polygon: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for GeometryError {}
+
+impl From<ProjectionError> for GeometryError {
+    fn from(e: ProjectionError) -> Self {
+        GeometryError::Invalid {
+            reason: e.to_string(),
+        }
+    }
+}
 
-use crate::point::{MercatorPoint, WGS84Point};
+// Self-intersecting rings (bowties, loops) are resolved into their lobes
+// with the even-odd rule, so that every area below sums the parts and
+// subtracts the holes instead of adding or cancelling signed lobes.
+pub fn repair(coords: Vec<Coord<f64>>) -> Result<MultiPolygon<f64>, GeometryError> {
+    let polygon = Polygon::new(coords.into(), vec![]);
+    if polygon.is_valid() {
+        return Ok(MultiPolygon::new(vec![polygon.orient(Direction::Default)]));
+    }
+    let repaired = polygon.union(&MultiPolygon::<f64>::new(vec![]));
+    if repaired.0.is_empty() {
+        return Err(GeometryError::Invalid {
+            reason: "the ring encloses no area".to_string(),
+        });
+    }
+    if let Err(e) = repaired.check_validation() {
+        return Err(GeometryError::Invalid {
+            reason: e.to_string(),
+        });
+    }
+    log::warn!(
+        "self-intersecting polygon repaired into {} parts",
+        repaired.0.len()
+    );
+    Ok(repaired.orient(Direction::Default))
+}
 
-pub fn geodesic_area(polygon: &Vec<WGS84Point>) -> f64 {
+pub fn geodesic_area(polygon: &[WGS84Point]) -> Result<f64, GeometryError> {
     if polygon.len() < 3 {
-        return 0.0;
+        return Ok(0.0);
     }
-    // Convert WGS84Point to geo::Coord
+    // the repair is planar, in longitudes that do not jump at 180°
+    let across = point::crosses_antimeridian(polygon);
     let coords: Vec<Coord<f64>> = polygon
         .iter()
-        .map(|p| Coord { x: p.lon, y: p.lat })
+        .map(|p| Coord {
+            x: p.unwrapped_lon(across),
+            y: p.lat,
+        })
         .collect();
+    Ok(repair(coords)?.geodesic_area_unsigned())
+}
 
-    // Create a geo::Polygon (exterior ring, no holes)
-    let mut geo_polygon = Polygon::new(coords.into(), vec![]);
-    geo_polygon = geo_polygon.orient(Direction::Default);
+pub fn planar_area(polygon: &[MercatorPoint]) -> Result<f64, GeometryError> {
+    if polygon.len() < 3 {
+        return Ok(0.0);
+    }
+    let coords: Vec<Coord<f64>> = polygon.iter().map(|p| Coord { x: p.x, y: p.y }).collect();
+    Ok(repair(coords)?.unsigned_area())
+}
+
+// q(phi) of the authalic latitude, see Snyder, Map Projections (3-12)
+fn authalic_q(phi: f64) -> f64 {
+    let e2 = FLATTENING * (2.0 - FLATTENING);
+    let e = e2.sqrt();
+    let s = phi.sin();
+    (1.0 - e2) * (s / (1.0 - e2 * s * s) - 0.5 / e * ((1.0 - e * s) / (1.0 + e * s)).ln())
+}
 
-    // Compute geodesic area in square meters
-    geo_polygon.geodesic_area_unsigned()
+// the radius of the sphere with the area of the ellipsoid
+pub fn authalic_radius() -> f64 {
+    SEMI_MAJOR_AXIS * (0.5 * authalic_q(FRAC_PI_2)).sqrt()
 }
 
-pub fn planar_area(polygon: &Vec<MercatorPoint>) -> f64 {
+pub fn authalic_latitude(lat: f64) -> f64 {
+    (authalic_q(lat.to_radians()) / authalic_q(FRAC_PI_2))
+        .clamp(-1.0, 1.0)
+        .asin()
+}
+
+// The area on the authalic sphere, from the spherical excess of the ring:
+// every edge is a great circle and adds the signed excess of the triangle
+// it forms with the pole. Nothing is repaired, a ring that winds twice or
+// crosses itself gives the signed sum of its lobes, unlike geodesic_area.
+pub fn spherical_area(polygon: &[WGS84Point]) -> f64 {
     if polygon.len() < 3 {
         return 0.0;
     }
-    // Convert WGS84Point to geo::Coord
-    let coords: Vec<Coord<f64>> = polygon.iter().map(|p| Coord { x: p.x, y: p.y }).collect();
+    let mut excess = 0.0;
+    for (k, a) in polygon.iter().enumerate() {
+        let b = &polygon[(k + 1) % polygon.len()];
+        let mut dlon = (b.lon - a.lon).to_radians();
+        if dlon > PI {
+            dlon -= 2.0 * PI;
+        } else if dlon < -PI {
+            dlon += 2.0 * PI;
+        }
+        let ta = (0.5 * authalic_latitude(a.lat)).tan();
+        let tb = (0.5 * authalic_latitude(b.lat)).tan();
+        excess += 2.0 * ((0.5 * dlon).tan() * (ta + tb)).atan2(1.0 + ta * tb);
+    }
+    excess.abs() * authalic_radius().powi(2)
+}
+
+// Relative difference of the spherical and the geodesic area, if it exceeds
+// SPHERICAL_TOLERANCE.
+pub fn spherical_deviation(geodesic: f64, spherical: f64) -> Option<f64> {
+    if geodesic <= 0.0 {
+        return None;
+    }
+    let difference = (spherical - geodesic) / geodesic;
+    if difference.abs() > SPHERICAL_TOLERANCE {
+        Some(difference)
+    } else {
+        None
+    }
+}
+
+// Relative difference of the geodesic and the equal-area planar area, if it
+// exceeds CONSISTENCY_TOLERANCE.
+pub fn inconsistency(geodesic: f64, laea: f64) -> Option<f64> {
+    if geodesic <= 0.0 {
+        return None;
+    }
+    let difference = (laea - geodesic) / geodesic;
+    if difference.abs() > CONSISTENCY_TOLERANCE {
+        Some(difference)
+    } else {
+        None
+    }
+}
 
-    // Create a geo::Polygon (exterior ring, no holes)
-    let mut geo_polygon = Polygon::new(coords.into(), vec![]);
-    geo_polygon = geo_polygon.orient(Direction::Default);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::polygon::Polygon;
 
-    // Compute geodesic area in square meters
-    geo_polygon.unsigned_area()
+    fn wgs(lon: f64, lat: f64) -> WGS84Point {
+        WGS84Point {
+            lon,
+            lat,
+            ele: None,
+        }
+    }
+
+    // two triangles touching in (lon + 0.5 size, lat + 0.5 size)
+    fn bowtie(lon: f64, lat: f64, size: f64) -> Vec<WGS84Point> {
+        vec![
+            wgs(lon, lat),
+            wgs(lon + size, lat + size),
+            wgs(lon + size, lat),
+            wgs(lon, lat + size),
+        ]
+    }
+
+    #[test]
+    fn test_bowtie() {
+        let square = vec![
+            wgs(6.0, 46.0),
+            wgs(6.01, 46.0),
+            wgs(6.01, 46.01),
+            wgs(6.0, 46.01),
+        ];
+        let square = geodesic_area(&square).unwrap();
+        let geodesic = geodesic_area(&bowtie(6.0, 46.0, 0.01)).unwrap();
+        assert!(
+            (geodesic / square - 0.5).abs() < 1e-3,
+            "{}",
+            geodesic / square
+        );
+
+        let mut polygon = Polygon::new(bowtie(6.0, 46.0, 0.01));
+        polygon.proj4 = Some(polygon.wgsbbox().center().to_laea_proj4());
+        let laea = planar_area(&polygon.mercator().unwrap()).unwrap();
+        assert!(
+            inconsistency(geodesic, laea).is_none(),
+            "{} {}",
+            geodesic,
+            laea
+        );
+        // the signed lobes cancel out without the repair
+        assert!(inconsistency(geodesic, 0.0).is_some());
+    }
+
+    #[test]
+    fn test_spherical_quadrangles() {
+        assert!((authalic_radius() - 6_371_007.181).abs() < 1e-3);
+        // 1° × 1° quadrangles of the WGS84 ellipsoid, bounded by parallels,
+        // in km²; the great circle edges of the ring bulge towards the pole
+        // by a few 1e-5
+        for (lat, published) in [
+            (0.0, 12_308.5),
+            (30.0, 10_642.4),
+            (45.0, 8_686.5),
+            (60.0, 6_123.1),
+        ] {
+            let ring = vec![
+                wgs(6.0, lat),
+                wgs(7.0, lat),
+                wgs(7.0, lat + 1.0),
+                wgs(6.0, lat + 1.0),
+            ];
+            let spherical = spherical_area(&ring) / 1e6;
+            assert!(
+                (spherical / published - 1.0).abs() < 1e-4,
+                "{}: {}",
+                lat,
+                spherical
+            );
+            let reversed: Vec<_> = ring.iter().rev().cloned().collect();
+            assert!((spherical_area(&reversed) / 1e6 / spherical - 1.0).abs() < 1e-12);
+            let geodesic = geodesic_area(&ring).unwrap();
+            assert!(spherical_deviation(geodesic, spherical * 1e6).is_none());
+        }
+        // across the antimeridian
+        let ring = vec![
+            wgs(179.5, 0.0),
+            wgs(-179.5, 0.0),
+            wgs(-179.5, 1.0),
+            wgs(179.5, 1.0),
+        ];
+        assert!((spherical_area(&ring) / 1e6 / 12_308.5 - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_spherical_bowtie() {
+        // the repair keeps both lobes, the signed excess cancels them out
+        let ring = bowtie(6.0, 46.0, 0.01);
+        let geodesic = geodesic_area(&ring).unwrap();
+        let spherical = spherical_area(&ring);
+        assert!(spherical < 1e-3 * geodesic, "{}", spherical);
+        assert!(spherical_deviation(geodesic, spherical).is_some());
+    }
+
+    #[test]
+    fn test_invalid_ring() {
+        // the square traced twice cancels out under the even-odd rule
+        let square = [(0.0, 0.0), (10.0, 0.0), (10.0, 10.0), (0.0, 10.0)];
+        let twice: Vec<WGS84Point> = square
+            .iter()
+            .chain(&square)
+            .map(|(x, y)| wgs(6.0 + x / 1000.0, 46.0 + y / 1000.0))
+            .collect();
+        assert!(geodesic_area(&twice).is_err());
+        let clockwise: Vec<MercatorPoint> = square
+            .iter()
+            .rev()
+            .map(|(x, y)| MercatorPoint {
+                x: *x,
+                y: *y,
+                ele: None,
+            })
+            .collect();
+        assert_eq!(planar_area(&clockwise).unwrap(), 100.0);
+    }
 }
@@ -0,0 +1,322 @@
+use std::collections::HashMap;
+
+use crate::point::{MercatorBoundingBox, MercatorPoint};
+use crate::triangulation::{Mesh, Triangle};
+
+// Below this relative change of its 3D area, a split triangle is not split
+// further.
+pub const REFINE_TOLERANCE: f64 = 1e-3;
+
+pub struct RefineOptions {
+    // in percent
+    pub slope: f64,
+    pub depth: usize,
+}
+
+// The triangles of a refinement and the vertices it added, the midpoints of
+// the edges shared by the triangles on both sides.
+struct Refiner<'a> {
+    options: &'a RefineOptions,
+    elevation: &'a dyn Fn(&MercatorPoint) -> Option<f64>,
+    vertices: Vec<MercatorPoint>,
+    midpoints: HashMap<(u32, u32), u32>,
+    triangles: Vec<[u32; 3]>,
+}
+
+impl Refiner<'_> {
+    fn triangle(&self, [a, b, c]: [u32; 3]) -> Triangle {
+        let v = |i: u32| self.vertices[i as usize].clone();
+        Triangle(v(a), v(b), v(c))
+    }
+
+    // the vertex in the middle of the edge, at the elevation of the DEM there,
+    // or halfway between its ends outside of it
+    fn midpoint(&mut self, a: u32, b: u32) -> u32 {
+        if let Some(m) = self.midpoints.get(&(a.min(b), a.max(b))) {
+            return *m;
+        }
+        let (p, q) = (&self.vertices[a as usize], &self.vertices[b as usize]);
+        let mut m = MercatorPoint {
+            x: 0.5 * (p.x + q.x),
+            y: 0.5 * (p.y + q.y),
+            ele: None,
+        };
+        m.ele = (self.elevation)(&m).or(Some(0.5 * (p.ele.unwrap() + q.ele.unwrap())));
+        self.vertices.push(m);
+        let index = (self.vertices.len() - 1) as u32;
+        self.midpoints.insert((a.min(b), a.max(b)), index);
+        index
+    }
+
+    // Splits the triangle into four at the midpoints of its edges while it
+    // is steep, down to the depth or until its area no longer changes.
+    // Returns whether it was split.
+    fn add(&mut self, t: [u32; 3], depth: usize) -> bool {
+        let triangle = self.triangle(t);
+        if depth >= self.options.depth
+            || triangle
+                .slope_percent()
+                .is_none_or(|slope| slope <= self.options.slope)
+        {
+            self.triangles.push(t);
+            return false;
+        }
+        let [a, b, c] = t;
+        let (ab, bc, ca) = (
+            self.midpoint(a, b),
+            self.midpoint(b, c),
+            self.midpoint(c, a),
+        );
+        let children = [[a, ab, ca], [ab, b, bc], [ca, bc, c], [ab, bc, ca]];
+        let area: f64 = children.iter().map(|c| self.triangle(*c).area()).sum();
+        if (area / triangle.area() - 1.0).abs() < REFINE_TOLERANCE {
+            self.triangles.extend(children);
+        } else {
+            for child in children {
+                self.add(child, depth + 1);
+            }
+        }
+        true
+    }
+
+    // the vertices added inside of the edge, from a to b: the midpoint of
+    // the triangle split on the other side of it, and those of its children
+    fn hanging(&self, a: u32, b: u32, ret: &mut Vec<u32>) {
+        if let Some(&m) = self.midpoints.get(&(a.min(b), a.max(b))) {
+            self.hanging(a, m, ret);
+            ret.push(m);
+            self.hanging(m, b, ret);
+        }
+    }
+
+    // Splits the triangles left with vertices of their split neighbors on
+    // their edges, so that the mesh has no T-junctions, cracks in 3D: from
+    // the opposite corner when they are all on one edge, around the centroid
+    // at the elevation of the plane of the triangle otherwise.
+    fn conform(&mut self) {
+        for t in std::mem::take(&mut self.triangles) {
+            let [a, b, c] = t;
+            let edges = [(a, b), (b, c), (c, a)];
+            let inner: Vec<Vec<u32>> = edges
+                .iter()
+                .map(|(u, v)| {
+                    let mut ret = Vec::new();
+                    self.hanging(*u, *v, &mut ret);
+                    ret
+                })
+                .collect();
+            let split: Vec<usize> = (0..3).filter(|e| !inner[*e].is_empty()).collect();
+            match split[..] {
+                [] => self.triangles.push(t),
+                [e] => {
+                    let (u, v) = edges[e];
+                    let opposite = t[(e + 2) % 3];
+                    let chain: Vec<u32> = std::iter::once(u)
+                        .chain(inner[e].iter().copied())
+                        .chain(std::iter::once(v))
+                        .collect();
+                    for w in chain.windows(2) {
+                        self.triangles.push([w[0], w[1], opposite]);
+                    }
+                }
+                _ => {
+                    let corners = self.triangle(t).as_vector();
+                    let mean = |f: &dyn Fn(&MercatorPoint) -> f64| {
+                        corners.iter().map(f).sum::<f64>() / 3.0
+                    };
+                    self.vertices.push(MercatorPoint {
+                        x: mean(&|p| p.x),
+                        y: mean(&|p| p.y),
+                        ele: Some(mean(&|p| p.ele.unwrap())),
+                    });
+                    let center = (self.vertices.len() - 1) as u32;
+                    let ring: Vec<u32> = (0..3)
+                        .flat_map(|e| std::iter::once(edges[e].0).chain(inner[e].iter().copied()))
+                        .collect();
+                    for k in 0..ring.len() {
+                        self.triangles
+                            .push([ring[k], ring[(k + 1) % ring.len()], center]);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Refines the steep triangles of the mesh within the box: each triangle
+// steeper than the slope of the options is split into four at the midpoints
+// of its edges, sampled with the elevation function (the DEM interpolated
+// between its pixels), and so on for its steep parts. Their neighbors are
+// split along the shared edges, see Refiner::conform. Returns the refined
+// mesh and the number of triangles of the mesh that were split.
+pub fn refine(
+    mesh: &Mesh,
+    within: &MercatorBoundingBox,
+    options: &RefineOptions,
+    elevation: &dyn Fn(&MercatorPoint) -> Option<f64>,
+) -> (Mesh, usize) {
+    let mut refiner = Refiner {
+        options,
+        elevation,
+        vertices: mesh.vertices.clone(),
+        midpoints: HashMap::new(),
+        triangles: Vec::with_capacity(mesh.len()),
+    };
+    let mut refined = 0;
+    for (k, t) in mesh.triangles.iter().enumerate() {
+        let vertices = mesh.triangle(k).as_vector();
+        let outside = vertices.iter().all(|p| p.x < within.min.x)
+            || vertices.iter().all(|p| p.x > within.max.x)
+            || vertices.iter().all(|p| p.y < within.min.y)
+            || vertices.iter().all(|p| p.y > within.max.y);
+        if outside {
+            refiner.triangles.push(*t);
+        } else if refiner.add(*t, 0) {
+            refined += 1;
+        }
+    }
+    refiner.conform();
+    (Mesh::new(refiner.vertices, refiner.triangles), refined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triangulation;
+
+    // a cliff 50 m high and 10 m wide along x = 300
+    fn cliff(x: f64) -> f64 {
+        50.0 * ((x - 300.0) / 10.0).clamp(0.0, 1.0)
+    }
+
+    #[test]
+    fn test_refine_cliff() {
+        let mut points = Vec::new();
+        for i in 0..20 {
+            for j in 0..20 {
+                let (x, y) = (i as f64 * 30.0, j as f64 * 30.0);
+                points.push(MercatorPoint {
+                    x,
+                    y,
+                    ele: Some(cliff(x)),
+                });
+            }
+        }
+        let mesh = triangulation::grid::triangulate(&points);
+        let within = MercatorBoundingBox {
+            min: points[0].clone(),
+            max: points.last().unwrap().clone(),
+        };
+        let elevation = |p: &MercatorPoint| Some(cliff(p.x));
+        let expected = 570.0 * (560.0 + 10f64.hypot(50.0));
+        let mut errors = Vec::new();
+        for depth in 0..6 {
+            let options = RefineOptions { slope: 50.0, depth };
+            let (refined, count) = refine(&mesh, &within, &options, &elevation);
+            let flat: f64 = refined.iter().map(|t| t.flat().area()).sum();
+            assert!((flat - 570.0 * 570.0).abs() < 1e-6);
+            // no T-junction: the only free edges are those of the outline
+            let outline: f64 = refined
+                .boundary_edges()
+                .iter()
+                .map(|[a, b]| {
+                    let v = |i: &u32| &refined.vertices[*i as usize];
+                    v(a).distance_2d(v(b))
+                })
+                .sum();
+            assert!((outline - 4.0 * 570.0).abs() < 1e-6, "{}", outline);
+            let surface: f64 = refined.iter().map(|t| t.area()).sum();
+            errors.push((surface / expected - 1.0).abs());
+            // the column of cells across the cliff
+            assert_eq!(count, if depth == 0 { 0 } else { 2 * 19 });
+        }
+        assert!(errors.windows(2).all(|w| w[1] < w[0]), "{:?}", errors);
+        assert!(errors[0] > 0.01, "{:?}", errors);
+        assert!(errors[5] < 0.002, "{:?}", errors);
+
+        // gentle slopes are left alone
+        let options = RefineOptions {
+            slope: 1000.0,
+            depth: 3,
+        };
+        let (refined, count) = refine(&mesh, &within, &options, &elevation);
+        assert_eq!(count, 0);
+        assert_eq!(refined.triangles, mesh.triangles);
+    }
+
+    #[test]
+    fn test_conform() {
+        // a triangle with the midpoints of its split neighbors on two edges,
+        // two levels deep on the first one
+        let point = |x, y, ele| MercatorPoint {
+            x,
+            y,
+            ele: Some(ele),
+        };
+        let options = RefineOptions {
+            slope: 0.0,
+            depth: 0,
+        };
+        let mut refiner = Refiner {
+            options: &options,
+            elevation: &|_| None,
+            vertices: vec![
+                point(0.0, 0.0, 0.0),
+                point(60.0, 0.0, 6.0),
+                point(0.0, 60.0, 12.0),
+                point(30.0, 0.0, 5.0),
+                point(30.0, 30.0, 11.0),
+                point(15.0, 0.0, 1.0),
+            ],
+            midpoints: HashMap::from([((0, 1), 3), ((1, 2), 4), ((0, 3), 5)]),
+            triangles: vec![[0, 1, 2]],
+        };
+        refiner.conform();
+        let mesh = Mesh::new(refiner.vertices, refiner.triangles);
+        // around the centroid, at the elevation of the plane
+        assert_eq!(mesh.len(), 6);
+        assert_eq!(mesh.vertices[6].ele, Some(6.0));
+        let flat: f64 = mesh.iter().map(|t| t.flat().area()).sum();
+        assert!((flat - 1800.0).abs() < 1e-9, "{}", flat);
+        assert_eq!(mesh.boundary_edges().len(), 6);
+    }
+
+    // A cliff 80 m high across 4 pixels east of the meridian of the center,
+    // from every 4th sample of the DEM: the coarse triangles cut the foot and
+    // the top of the cliff, the refinement samples the DEM between them and
+    // the surface approaches the analytic one as the depth grows.
+    #[test]
+    fn test_refine_pipeline() {
+        use crate::fixtures::{self, STEP};
+        use crate::options::ComputeOptions;
+
+        let pixel = fixtures::meters_per_degree().0 * STEP;
+        let datasets = [fixtures::synthetic(
+            "cliff",
+            (-250.0, -250.0, 250.0, 250.0),
+            move |x, _| Some(600.0 + 20.0 * (x / pixel - 1.0).clamp(0.0, 4.0)),
+        )];
+        let expected = 400.0 * (400.0 - 4.0 * pixel + (4.0 * pixel).hypot(80.0));
+        let polygon = fixtures::polygon(&fixtures::square(200.0));
+        let mut options = ComputeOptions {
+            decimate: 4,
+            refine_slope: 50.0,
+            ..Default::default()
+        };
+        let mut errors = Vec::new();
+        for depth in 0..4 {
+            options.refine = depth > 0;
+            options.refine_depth = depth;
+            let data = crate::pipeline::compute("cliff", &polygon, &datasets, &options).unwrap();
+            errors.push((data.projected3d / expected - 1.0).abs());
+        }
+        assert!(errors[0] > 0.01, "{:?}", errors);
+        // once converged, the splits of the neighbors only move the rounding
+        assert!(
+            errors.windows(2).all(|w| w[1] <= w[0] + 1e-9),
+            "{:?}",
+            errors
+        );
+        assert!(errors[3] < 1e-4, "{:?}", errors);
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+
+use serde::Deserialize;
+
+use crate::{
+    dataset::Dataset,
+    hgtfile,
+    options::IoBackend,
+    point::{WGS84BoundingBox, WGS84Point},
+    polygon::Polygon,
+    rasterio,
+};
+
+// the DEM directories, separated as in PATH
+pub const DEM_DIRS: &str = "DEM_DIRS";
+
+const EXTENSIONS: [&str; 4] = ["hgt", "tif", "tiff", "asc"];
+
+const MANIFEST: &str = ".manifest.toml";
+
+// The DEM files under an ordered list of directories (or files, URLs,
+// manifests), found by their extension whatever the layout. The directories are walked once and
+// the box of every file opened is kept, so that the polygons of a run do not
+// walk them or open the files again.
+pub struct DatasetRegistry {
+    directories: Vec<PathBuf>,
+    files: OnceLock<Vec<String>>,
+    // None for the files that could not be opened
+    boxes: Mutex<BTreeMap<String, Option<WGS84BoundingBox>>>,
+    // the files stored after the directories were walked (downloads)
+    added: Mutex<Vec<String>>,
+}
+
+#[derive(Debug)]
+pub struct RegistryError(pub String);
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "DEM directories: {}", self.0)
+    }
+}
+
+impl std::error::Error for RegistryError {}
+
+// The config file, the relative directories are resolved against its own:
+//
+//   dem_dirs = ["/data/srtm", "swissalti3d"]
+#[derive(Deserialize)]
+struct Config {
+    dem_dirs: Vec<PathBuf>,
+}
+
+// Remote datasets (or local ones) listed with their box, so that they are
+// not opened to be selected. The relative paths are resolved against the
+// manifest's directory, the datasets without a box are opened.
+//
+//   [[datasets]]
+//   url = "https://example.com/dem/swissalti3d.tif"
+//   bbox = [5.9, 45.8, 10.5, 47.8]  # min lon, min lat, max lon, max lat
+#[derive(Deserialize)]
+struct Manifest {
+    datasets: Vec<ManifestEntry>,
+}
+
+#[derive(Deserialize)]
+struct ManifestEntry {
+    url: String,
+    bbox: Option<[f64; 4]>,
+}
+
+fn is_manifest(path: &Path) -> bool {
+    path.to_string_lossy().ends_with(MANIFEST)
+}
+
+// relative to the directory of a config file or manifest, URLs as they are
+fn resolve(base: &Path, path: &Path) -> PathBuf {
+    if rasterio::is_remote(&path.to_string_lossy()) {
+        path.to_path_buf()
+    } else {
+        base.join(path)
+    }
+}
+
+// ~/.config/surface-area/config.toml, or under $XDG_CONFIG_HOME
+pub fn config_path() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) => PathBuf::from(dir),
+        None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(base.join("surface-area").join("config.toml"))
+}
+
+impl DatasetRegistry {
+    pub fn new(directories: Vec<PathBuf>) -> Self {
+        DatasetRegistry {
+            directories,
+            files: OnceLock::new(),
+            boxes: Mutex::new(BTreeMap::new()),
+            added: Mutex::new(Vec::new()),
+        }
+    }
+
+    // The directories given on the command line, else those of DEM_DIRS,
+    // else those of the config file.
+    pub fn from_sources(dem_dirs: &[PathBuf]) -> Result<Self, RegistryError> {
+        if !dem_dirs.is_empty() {
+            return Ok(Self::new(dem_dirs.to_vec()));
+        }
+        if let Some(value) = std::env::var_os(DEM_DIRS) {
+            return Ok(Self::new(std::env::split_paths(&value).collect()));
+        }
+        match config_path() {
+            Some(path) if path.exists() => Self::from_config(&path),
+            path => Err(RegistryError(format!(
+                "none given: use --dem-dir, {} or {}",
+                DEM_DIRS,
+                path.map_or("a config file".to_string(), |p| p.display().to_string())
+            ))),
+        }
+    }
+
+    pub fn from_config(path: &Path) -> Result<Self, RegistryError> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| RegistryError(format!("{}: {}", path.display(), e)))?;
+        let config: Config = toml::from_str(&text)
+            .map_err(|e| RegistryError(format!("{}: {}", path.display(), e)))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        Ok(Self::new(
+            config.dem_dirs.iter().map(|d| resolve(base, d)).collect(),
+        ))
+    }
+
+    pub fn directories(&self) -> Vec<String> {
+        self.directories
+            .iter()
+            .map(|d| d.to_string_lossy().into_owned())
+            .collect()
+    }
+
+    // a file stored under a directory once it was walked, a candidate from
+    // now on
+    pub fn add(&self, filename: String) {
+        let mut added = self.added.lock().unwrap();
+        if !added.contains(&filename) && !self.files().contains(&filename) {
+            added.push(filename);
+        }
+    }
+
+    // the DEM files under the directories, in their order
+    fn files(&self) -> &[String] {
+        self.files.get_or_init(|| {
+            let mut ret = Vec::new();
+            for directory in &self.directories {
+                let before = ret.len();
+                let name = directory.to_string_lossy();
+                if rasterio::is_remote(&name) {
+                    ret.push(name.into_owned());
+                    continue;
+                }
+                for entry in walkdir::WalkDir::new(directory)
+                    .follow_links(true)
+                    .sort_by_file_name()
+                    .into_iter()
+                    .filter_map(Result::ok)
+                {
+                    let path = entry.path();
+                    let extension = path.extension().and_then(|ext| ext.to_str());
+                    if entry.file_type().is_file() && is_manifest(path) {
+                        self.read_manifest(path, &mut ret);
+                    } else if entry.file_type().is_file()
+                        && extension.is_some_and(|ext| {
+                            EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str())
+                        })
+                    {
+                        ret.push(path.to_string_lossy().into_owned());
+                    }
+                }
+                log::info!("{}: {} DEM files", directory.display(), ret.len() - before);
+            }
+            ret
+        })
+    }
+
+    fn read_manifest(&self, path: &Path, files: &mut Vec<String>) {
+        let manifest = std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|text| toml::from_str::<Manifest>(&text).map_err(|e| e.to_string()));
+        let manifest = match manifest {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                log::warn!("skip {}: {}", path.display(), e);
+                return;
+            }
+        };
+        let base = path.parent().unwrap_or(Path::new("."));
+        let mut boxes = self.boxes.lock().unwrap();
+        for entry in manifest.datasets {
+            let filename = resolve(base, Path::new(&entry.url))
+                .to_string_lossy()
+                .into_owned();
+            if let Some([minlon, minlat, maxlon, maxlat]) = entry.bbox {
+                let corner = |lon, lat| WGS84Point {
+                    lon,
+                    lat,
+                    ele: None,
+                };
+                let bbox = WGS84BoundingBox::from(&corner(minlon, minlat), &corner(maxlon, maxlat));
+                boxes.insert(filename.clone(), Some(bbox));
+            }
+            files.push(filename);
+        }
+    }
+
+    // the box of the file, opened the first time only
+    fn bbox(
+        &self,
+        filename: &str,
+        projection: &str,
+        backend: IoBackend,
+    ) -> Option<WGS84BoundingBox> {
+        if let Some(bbox) = self.boxes.lock().unwrap().get(filename) {
+            return bbox.clone();
+        }
+        let bbox = Dataset::open(&filename.to_string(), &projection.to_string(), backend)
+            .inspect_err(|e| log::warn!("skip {}", e))
+            .ok()
+            .map(|d| d.wgsbbox());
+        self.boxes
+            .lock()
+            .unwrap()
+            .insert(filename.to_string(), bbox.clone());
+        bbox
+    }
+
+    // The files that may cover a part of the polygon: the SRTM tiles it
+    // needs, by their name, and the other files whose box meets its box.
+    pub fn candidates(&self, polygon: &Polygon, backend: IoBackend) -> BTreeSet<String> {
+        let tiles = polygon.required_tiles();
+        let polybox = polygon.wgsbbox();
+        let projection = polygon.projection();
+        let mut ret = BTreeSet::new();
+        let added = self.added.lock().unwrap().clone();
+        for filename in self.files().iter().chain(&added) {
+            let path = Path::new(filename);
+            let hgt = path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("hgt"));
+            let needed = match hgtfile::tile(path) {
+                Some(tile) if hgt => tiles.contains(&tile.filename()),
+                _ => self
+                    .bbox(filename, &projection, backend)
+                    .is_some_and(|bbox| bbox.intersection(&polybox).is_some()),
+            };
+            if needed {
+                ret.insert(filename.clone());
+            } else {
+                log::trace!("discard {} (bbox)", filename);
+            }
+        }
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[test]
+    fn test_candidates() {
+        let dir = fixtures::temp_dir("surface-area-registry");
+        for sub in ["srtm/v3", "swiss/2024", "elsewhere", "remote"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        std::fs::write(dir.join("swiss/2024/tile.asc"), fixtures::grid(6.0)).unwrap();
+        std::fs::write(dir.join("elsewhere/far.asc"), fixtures::grid(10.0)).unwrap();
+        // tiles are selected by name, not opened
+        std::fs::write(dir.join("srtm/v3/N46E006.hgt"), []).unwrap();
+        std::fs::write(dir.join("srtm/v3/N47E006.hgt"), []).unwrap();
+        std::fs::write(dir.join("srtm/v3/README.txt"), "").unwrap();
+        // remote files are not opened either, with a box
+        std::fs::write(
+            dir.join("remote/cogs.manifest.toml"),
+            "[[datasets]]\n\
+             url = \"https://example.com/dem/near.tif\"\n\
+             bbox = [6.0, 46.0, 6.5, 46.5]\n\
+             [[datasets]]\n\
+             url = \"s3://dem/far.tif\"\n\
+             bbox = [10.0, 46.0, 10.5, 46.5]\n",
+        )
+        .unwrap();
+        let polygon = fixtures::triangle(6.005, 46.005, 0.01, 0.01);
+        let registry = DatasetRegistry::new(vec![
+            dir.join("srtm"),
+            dir.join("swiss"),
+            dir.join("elsewhere"),
+            dir.join("remote"),
+        ]);
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let expected: BTreeSet<String> = [
+            path("srtm/v3/N46E006.hgt"),
+            path("swiss/2024/tile.asc"),
+            "https://example.com/dem/near.tif".to_string(),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(registry.candidates(&polygon, IoBackend::Auto), expected);
+        assert_eq!(registry.boxes.lock().unwrap().len(), 4);
+        // the files are not looked for again
+        std::fs::remove_file(dir.join("swiss/2024/tile.asc")).unwrap();
+        assert_eq!(registry.candidates(&polygon, IoBackend::Auto), expected);
+
+        let config = dir.join("config.toml");
+        std::fs::write(
+            &config,
+            "dem_dirs = [\"srtm\", \"/data/dem\", \"https://example.com/dem.tif\"]\n",
+        )
+        .unwrap();
+        let registry = DatasetRegistry::from_config(&config).unwrap();
+        assert_eq!(
+            registry.directories(),
+            [
+                path("srtm"),
+                "/data/dem".to_string(),
+                "https://example.com/dem.tif".to_string()
+            ]
+        );
+        assert!(DatasetRegistry::from_config(&dir.join("missing.toml")).is_err());
+    }
+}
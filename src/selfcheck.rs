@@ -0,0 +1,126 @@
+use core::fmt;
+
+use serde::Serialize;
+
+use crate::area::{AreaUnit, AreaValue};
+
+// relative residual allowed for both identities
+pub const TOLERANCE: f64 = 1e-6;
+
+// On flattened terrain the 3D area of every plane is its flat area, and the
+// planes tile the polygon: the totals of the computation on the flattened
+// datasets, through the same path as the result, must give the planar area
+// back.
+#[derive(Clone, Debug, Serialize)]
+pub struct SelfCheck {
+    pub flat: f64,
+    pub surface: f64,
+    pub planar: f64,
+}
+
+impl SelfCheck {
+    pub fn new(flat: f64, surface: f64, planar: f64) -> Self {
+        SelfCheck {
+            flat,
+            surface,
+            planar,
+        }
+    }
+
+    // the checks of the tiles of a split polygon
+    pub fn add(&mut self, other: &SelfCheck) {
+        self.flat += other.flat;
+        self.surface += other.surface;
+        self.planar += other.planar;
+    }
+
+    // surface - flat, relative to the flat area
+    pub fn residual3d(&self) -> f64 {
+        (self.surface - self.flat) / self.flat
+    }
+
+    // flat - planar, relative to the planar area
+    pub fn residual2d(&self) -> f64 {
+        (self.flat - self.planar) / self.planar
+    }
+
+    pub fn passed(&self) -> bool {
+        self.residual3d().abs() <= TOLERANCE && self.residual2d().abs() <= TOLERANCE
+    }
+}
+
+impl fmt::Display for SelfCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: 3D - flat {} m² ({:+.1e}), flat - planar {} m² ({:+.1e}), tolerance {:.0e}",
+            if self.passed() { "PASS" } else { "FAIL" },
+            AreaValue::new(self.surface - self.flat).number(AreaUnit::M2),
+            self.residual3d(),
+            AreaValue::new(self.flat - self.planar).number(AreaUnit::M2),
+            self.residual2d(),
+            TOLERANCE
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compute::{self, Surface};
+    use crate::point::MercatorPoint;
+    use crate::triangulation;
+
+    fn point(x: f64, y: f64) -> MercatorPoint {
+        MercatorPoint {
+            x,
+            y,
+            ele: Some(0.0),
+        }
+    }
+
+    // a 100 m square on flat 10 m samples
+    fn surface() -> Surface {
+        let mut grid = Vec::new();
+        for row in 0..=12 {
+            for col in 0..=12 {
+                grid.push(point(col as f64 * 10.0 - 10.0, row as f64 * 10.0 - 10.0));
+            }
+        }
+        let square = vec![
+            point(0.0, 0.0),
+            point(100.0, 0.0),
+            point(100.0, 100.0),
+            point(0.0, 100.0),
+        ];
+        compute::clip(&square, &triangulation::grid::triangulate(&grid))
+    }
+
+    #[test]
+    fn test_flat_identity() {
+        let surface = surface();
+        let check = SelfCheck::new(surface.projected2d, surface.projected3d, 10_000.0);
+        assert!(check.passed(), "{}", check);
+        assert!(check.to_string().starts_with("PASS"));
+    }
+
+    #[test]
+    fn test_failures() {
+        let surface = surface();
+        // a plane tilted by the clip
+        let check = SelfCheck::new(surface.projected2d, surface.projected3d + 1.0, 10_000.0);
+        assert!(!check.passed());
+        assert!(check.residual3d() > 0.0);
+        assert!(check.to_string().starts_with("FAIL"), "{}", check);
+
+        // a leak: a plane of 50 m² is lost
+        let check = SelfCheck::new(
+            surface.projected2d - 50.0,
+            surface.projected3d - 50.0,
+            10_000.0,
+        );
+        assert!(check.residual3d().abs() <= TOLERANCE);
+        assert!(check.residual2d() < -TOLERANCE);
+        assert!(!check.passed());
+    }
+}
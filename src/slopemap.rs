@@ -0,0 +1,215 @@
+use std::path::Path;
+
+use geo::{Coord, Intersects};
+
+use crate::compute::Plane;
+use crate::intersection::to_geo_polygon;
+use crate::options::ExtraBand;
+use crate::point::MercatorBoundingBox;
+use crate::polygon;
+use crate::provenance::Provenance;
+use crate::rasterio::{self, RasterIoError};
+
+pub const NODATA: f64 = -9999.0;
+
+// The planes rasterized on a regular grid over a bounding box: every cell
+// gets the values of the plane containing its center, NODATA outside.
+pub struct SlopeMap {
+    pub geo_transform: [f64; 6],
+    pub size: (usize, usize),
+    pub bands: Vec<(String, Vec<f64>)>,
+}
+
+fn values(plane: &Plane, extra: &[ExtraBand]) -> Vec<f64> {
+    let mut ret = vec![plane.slope()];
+    for band in extra {
+        ret.push(match band {
+            ExtraBand::Aspect => polygon::normal_aspect(plane.normal()).unwrap_or(NODATA),
+            ExtraBand::Ratio => plane.area3d / plane.area2d,
+        });
+    }
+    ret
+}
+
+pub fn rasterize(
+    planes: &[Plane],
+    bbox: &MercatorBoundingBox,
+    step: f64,
+    extra: &[ExtraBand],
+) -> SlopeMap {
+    assert!(step > 0.0);
+    let (x0, y0) = (bbox.min.x, bbox.max.y);
+    let cols = ((bbox.max.x - bbox.min.x) / step).ceil().max(1.0) as usize;
+    let rows = ((bbox.max.y - bbox.min.y) / step).ceil().max(1.0) as usize;
+    let mut names = vec!["slope".to_string()];
+    for band in extra {
+        names.push(match band {
+            ExtraBand::Aspect => "aspect".to_string(),
+            ExtraBand::Ratio => "ratio".to_string(),
+        });
+    }
+    let mut bands: Vec<Vec<f64>> = vec![vec![NODATA; cols * rows]; names.len()];
+    let mut covered = vec![false; cols * rows];
+    // only the cells within the bounding box of a plane are tested against it
+    for plane in planes {
+        if plane.area2d <= 0.0 {
+            continue;
+        }
+        let shape = to_geo_polygon(&plane.points);
+        let min_x = plane
+            .points
+            .iter()
+            .map(|p| p.x)
+            .fold(f64::INFINITY, f64::min);
+        let max_x = plane
+            .points
+            .iter()
+            .map(|p| p.x)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let min_y = plane
+            .points
+            .iter()
+            .map(|p| p.y)
+            .fold(f64::INFINITY, f64::min);
+        let max_y = plane
+            .points
+            .iter()
+            .map(|p| p.y)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let c0 = ((min_x - x0) / step - 0.5).ceil().max(0.0) as usize;
+        let c1 = (((max_x - x0) / step - 0.5).floor() + 1.0).clamp(0.0, cols as f64) as usize;
+        let r0 = ((y0 - max_y) / step - 0.5).ceil().max(0.0) as usize;
+        let r1 = (((y0 - min_y) / step - 0.5).floor() + 1.0).clamp(0.0, rows as f64) as usize;
+        let v = values(plane, extra);
+        for r in r0..r1 {
+            for c in c0..c1 {
+                let k = r * cols + c;
+                let center = Coord {
+                    x: x0 + (c as f64 + 0.5) * step,
+                    y: y0 - (r as f64 + 0.5) * step,
+                };
+                // centers on a shared edge go to the first plane
+                if covered[k] || !shape.intersects(&center) {
+                    continue;
+                }
+                covered[k] = true;
+                for (band, value) in bands.iter_mut().zip(&v) {
+                    band[k] = *value;
+                }
+            }
+        }
+    }
+    SlopeMap {
+        geo_transform: [x0, step, 0.0, y0, 0.0, -step],
+        size: (cols, rows),
+        bands: names.into_iter().zip(bands).collect(),
+    }
+}
+
+impl SlopeMap {
+    pub fn write(&self, path: &Path, proj4: &str) -> Result<(), RasterIoError> {
+        let bands: Vec<(&str, &[f64])> = self
+            .bands
+            .iter()
+            .map(|(name, values)| (name.as_str(), values.as_slice()))
+            .collect();
+        rasterio::write_geotiff(path, self.size, self.geo_transform, proj4, NODATA, &bands)?;
+        Provenance::projected(proj4)
+            .write_sidecar(path)
+            .map_err(|e| RasterIoError(format!("{}: {}", Provenance::sidecar(path).display(), e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::point::MercatorPoint;
+
+    fn point(x: f64, y: f64, ele: f64) -> MercatorPoint {
+        MercatorPoint {
+            x,
+            y,
+            ele: Some(ele),
+        }
+    }
+
+    // a 100 m square, rising 10% to the east on the left half and 30% to
+    // the north on the right half
+    fn planes() -> Vec<Plane> {
+        let left = vec![
+            point(0.0, 0.0, 0.0),
+            point(50.0, 0.0, 5.0),
+            point(50.0, 100.0, 5.0),
+            point(0.0, 100.0, 0.0),
+        ];
+        let right = vec![
+            point(50.0, 0.0, 0.0),
+            point(100.0, 0.0, 0.0),
+            point(100.0, 100.0, 30.0),
+            point(50.0, 100.0, 30.0),
+        ];
+        vec![
+            Plane {
+                points: left,
+                area2d: 5000.0,
+                area3d: 5000.0 * 1.01f64.sqrt(),
+                inherited: None,
+            },
+            Plane {
+                points: right,
+                area2d: 5000.0,
+                area3d: 5000.0 * 1.09f64.sqrt(),
+                inherited: None,
+            },
+        ]
+    }
+
+    fn bbox() -> MercatorBoundingBox {
+        MercatorBoundingBox {
+            min: point(0.0, 0.0, 0.0),
+            max: point(120.0, 100.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn test_rasterize() {
+        let map = rasterize(&planes(), &bbox(), 10.0, &[ExtraBand::Aspect]);
+        assert_eq!(map.size, (12, 10));
+        assert_eq!(map.geo_transform, [0.0, 10.0, 0.0, 100.0, 0.0, -10.0]);
+        let (slope, aspect) = (&map.bands[0].1, &map.bands[1].1);
+        // row 2, column 1: left half; row 7, column 8: right half
+        assert!((slope[2 * 12 + 1] - 10.0).abs() < 1e-9);
+        assert!((aspect[2 * 12 + 1] - 270.0).abs() < 1e-9);
+        assert!((slope[7 * 12 + 8] - 30.0).abs() < 1e-9);
+        assert!((aspect[7 * 12 + 8] - 180.0).abs() < 1e-9);
+        // outside of the planes
+        assert_eq!(slope[5 * 12 + 11], NODATA);
+        assert_eq!(slope.iter().filter(|v| **v != NODATA).count(), 100);
+    }
+
+    #[cfg(feature = "gdal")]
+    #[test]
+    fn test_geotiff_round_trip() {
+        use crate::rasterio::{GdalRasterIo, RasterIo};
+        let path = std::env::temp_dir().join("surface-area-slope-test.tif");
+        let map = rasterize(&planes(), &bbox(), 10.0, &[ExtraBand::Ratio]);
+        let proj4 = "+proj=utm +zone=32 +datum=WGS84 +units=m +no_defs";
+        map.write(&path, proj4).unwrap();
+
+        let tif = GdalRasterIo::open(&path).unwrap();
+        assert_eq!(tif.raster_size(), (12, 10));
+        assert_eq!(tif.geo_transform().unwrap(), map.geo_transform);
+        assert_eq!(tif.no_data_value(1).unwrap(), Some(NODATA));
+        let slope = tif.read_window(1, (1, 2), (8, 1)).unwrap();
+        assert!((slope[0] - 10.0).abs() < 1e-9);
+        assert!((slope[7] - 30.0).abs() < 1e-9);
+        let ratio = tif.read_window(2, (9, 0), (2, 1)).unwrap();
+        assert!((ratio[0] - 1.09f64.sqrt()).abs() < 1e-9);
+        assert_eq!(ratio[1], NODATA);
+        let provenance = Provenance::read_sidecar(&path).unwrap();
+        assert_eq!(provenance.crs, proj4);
+        assert_eq!(provenance.offset, [0.0, 0.0]);
+        std::fs::remove_file(&path).ok();
+        std::fs::remove_file(Provenance::sidecar(&path)).ok();
+    }
+}
@@ -1,71 +1,172 @@
+use std::collections::BTreeMap;
+
 use crate::{
     point::{MercatorBoundingBox, MercatorPoint},
-    triangulation::Triangle,
+    reference::GeometryError,
 };
 
-pub struct SVG {
+// The canvas is 500 wide, its height follows the aspect ratio of the
+// bounding box within these bounds (the legends need 100); the figure is
+// centered in it.
+const MIN_CANVAS_HEIGHT: f64 = 200.0;
+const MAX_CANVAS_HEIGHT: f64 = 1000.0;
+// a side of the bounding box is at least this fraction of the other, a
+// fence line along a parallel would have no height
+const MIN_EXTENT: f64 = 1e-3;
+
+// A polygon of a layer, with its vertices as written in the figure, or any
+// other element.
+enum Item {
+    Ring { vertices: Vec<String>, fill: String },
+    Element(String),
+}
+
+struct Layer {
+    id: String,
+    visible: bool,
+    items: Vec<Item>,
+}
+
+pub struct Svg {
     mercator_bbox: MercatorBoundingBox,
     padding: f64,
     polygons: Vec<String>,
+    layers: Vec<Layer>,
+    markers: Vec<String>,
+    // the polygons of a layer are merged by color, see regions
+    regions: bool,
 }
 
-impl SVG {
+impl Svg {
     pub fn init(b: &MercatorBoundingBox) -> Self {
         Self {
             mercator_bbox: b.clone(),
             padding: 0.1,
             polygons: Vec::new(),
+            layers: Vec::new(),
+            markers: Vec::new(),
+            regions: true,
         }
     }
-    pub fn add_triangles_colors(&mut self, triangles: &Vec<Triangle>, colors: &Vec<String>) {
-        // Add each triangle as a polygon
-        for (i, triangle) in triangles.iter().enumerate() {
-            let (x1, y1) = self.transform(triangle.0.x, triangle.0.y);
-            let (x2, y2) = self.transform(triangle.1.x, triangle.1.y);
-            let (x3, y3) = self.transform(triangle.2.x, triangle.2.y);
-            let p = format!(
-                r#"  <polygon points="{:.2},{:.2} {:.2},{:.2} {:.2},{:.2}" fill="{}" stroke="black" stroke-width="1"/>"#,
-                x1, y1, x2, y2, x3, y3, colors[i]
-            );
-
+    // With regions off, every polygon of a layer is written as it was added.
+    pub fn set_regions(&mut self, regions: bool) {
+        self.regions = regions;
+    }
+    // Layers are rendered as <g id="layer-{id}"> groups after the plain
+    // polygons, hidden layers with display="none".
+    pub fn add_layer(&mut self, id: &str, visible: bool) {
+        self.layers.push(Layer {
+            id: id.to_string(),
+            visible,
+            items: Vec::new(),
+        });
+    }
+    // The holes are cut out with the even-odd rule.
+    pub fn add_polygon_with_holes(
+        &mut self,
+        points: &[MercatorPoint],
+        holes: &[Vec<MercatorPoint>],
+        fill: &str,
+    ) {
+        if holes.is_empty() {
+            let p = self.polygon(points, fill);
             self.polygons.push(p);
+            return;
         }
+        let d: Vec<String> = std::iter::once(points)
+            .chain(holes.iter().map(|h| h.as_slice()))
+            .map(|ring| format!("M{} Z", self.vertices(ring).join(" L")))
+            .collect();
+        self.polygons.push(format!(
+            r#"<path d="{}" fill="{}" fill-rule="evenodd" stroke="none" stroke-width="1"/>"#,
+            d.join(" "),
+            fill
+        ));
     }
-    pub fn add_triangles(&mut self, triangles: &Vec<Triangle>, altfill: bool) {
-        // Add each triangle as a polygon
-        let mut colors = Vec::with_capacity(triangles.len());
-        for i in 0..triangles.len() {
-            let mut fill = "none";
-            if altfill {
-                if i % 2 == 0 {
-                    fill = "blue";
-                }
-            }
-            colors.push(fill.to_string());
+    pub fn add_polygon_to_layer(&mut self, id: &str, points: &[MercatorPoint], fill: &str) {
+        let vertices = self.vertices(points);
+        self.layer(id).items.push(Item::Ring {
+            vertices,
+            fill: fill.to_string(),
+        });
+    }
+    fn layer(&mut self, id: &str) -> &mut Layer {
+        self.layers
+            .iter_mut()
+            .find(|l| l.id == id)
+            .expect("unknown layer")
+    }
+    pub fn add_polyline_to_layer(&mut self, id: &str, points: &[MercatorPoint], stroke: &str) {
+        let element = format!(
+            r#"<polyline points="{}" fill="none" stroke="{}" stroke-width="2"/>"#,
+            self.vertices(points).join(" "),
+            stroke
+        );
+        self.layer(id).items.push(Item::Element(element));
+    }
+    // A labeled circle, drawn on top of all layers.
+    pub fn add_marker(&mut self, point: &MercatorPoint, label: &str, color: &str) {
+        let (x, y) = self.transform(point.x, point.y);
+        let label = label
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        self.markers.push(format!(
+            r#"<g class="marker"><circle cx="{:.2}" cy="{:.2}" r="4" fill="{}" stroke="black" stroke-width="1"/><text x="{:.2}" y="{:.2}" font-size="10">{}</text></g>"#,
+            x,
+            y,
+            color,
+            x + 6.0,
+            y - 6.0,
+            label
+        ));
+    }
+    // Color stops with their labels, in the upper left corner of a layer.
+    pub fn add_legend_to_layer(&mut self, id: &str, title: &str, stops: &[(String, String)]) {
+        let mut legend = format!(
+            r#"<g class="legend"><text x="8" y="14" font-size="10">{}</text>"#,
+            title
+        );
+        for (i, (color, label)) in stops.iter().enumerate() {
+            let y = 20.0 + 14.0 * i as f64;
+            legend.push_str(&format!(
+                r#"<rect x="8" y="{:.0}" width="12" height="12" fill="{}" stroke="black" stroke-width="0.5"/><text x="24" y="{:.0}" font-size="10">{}</text>"#,
+                y,
+                color,
+                y + 10.0,
+                label
+            ));
         }
-        self.add_triangles_colors(triangles, &colors);
+        legend.push_str("</g>");
+        self.layer(id).items.push(Item::Element(legend));
     }
-
-    pub fn add_polygon(&mut self, points: &Vec<MercatorPoint>, fill: &str) {
-        // Add each triangle as a polygon
-        let s = points
+    fn vertices(&self, points: &[MercatorPoint]) -> Vec<String> {
+        points
             .iter()
             .map(|p| {
                 let (x, y) = self.transform(p.x, p.y);
                 format!("{:.2},{:.2}", x, y)
             })
-            .collect::<Vec<String>>()
-            .join(" ");
-        let p = format!(
-            r#"<polygon points="{}" fill="{}" stroke="none" stroke-width="1"/>"#,
-            s, fill
-        );
-        self.polygons.push(p);
+            .collect()
     }
-    pub fn render(&self) -> String {
+    fn polygon(&self, points: &[MercatorPoint], fill: &str) -> String {
+        polygon_element(&self.vertices(points), fill)
+    }
+    // Fails if the bounding box has no extent: every point would be NaN.
+    pub fn render(&self) -> Result<String, GeometryError> {
+        let extent = self.width().max(self.height());
+        if !(extent.is_finite() && extent > 0.0) {
+            return Err(GeometryError::Invalid {
+                reason: format!(
+                    "the figure has an empty bounding box {}",
+                    self.mercator_bbox
+                ),
+            });
+        }
         // Build SVG string
         let mut svg = format!(
-            r#"<svg width="500" height="{}" xmlns="http://www.w3.org/2000/svg">"#,
+            r#"<svg width="500" height="{}" viewBox="0 0 500 {}" xmlns="http://www.w3.org/2000/svg">"#,
+            self.svg_height(),
             self.svg_height()
         );
         svg.push('\n');
@@ -81,37 +182,337 @@ impl SVG {
             svg.push_str(&p.clone());
             svg.push('\n');
         }
+        for layer in &self.layers {
+            let display = if layer.visible { "inline" } else { "none" };
+            svg.push_str(&format!(
+                r#"<g id="layer-{}" display="{}">"#,
+                layer.id, display
+            ));
+            svg.push('\n');
+            for p in self.layer_elements(layer) {
+                svg.push_str(&p);
+                svg.push('\n');
+            }
+            svg.push_str("</g>\n");
+        }
+        for m in &self.markers {
+            svg.push_str(m);
+            svg.push('\n');
+        }
         svg.push_str("</svg>");
-        svg
+        Ok(svg)
     }
+    // The regions first, one path per color, then the other elements.
+    fn layer_elements(&self, layer: &Layer) -> Vec<String> {
+        if !self.regions {
+            return layer
+                .items
+                .iter()
+                .map(|item| match item {
+                    Item::Ring { vertices, fill } => polygon_element(vertices, fill),
+                    Item::Element(e) => e.clone(),
+                })
+                .collect();
+        }
+        let mut fills: Vec<(&str, Vec<&[String]>)> = Vec::new();
+        for item in &layer.items {
+            if let Item::Ring { vertices, fill } = item {
+                match fills.iter_mut().find(|(f, _)| f == fill) {
+                    Some((_, rings)) => rings.push(vertices),
+                    None => fills.push((fill, vec![vertices])),
+                }
+            }
+        }
+        let mut ret: Vec<String> = fills
+            .iter()
+            .map(|(fill, rings)| {
+                format!(
+                    r#"<path d="{}" fill="{}" fill-rule="evenodd" stroke="none"/>"#,
+                    region_path(rings),
+                    fill
+                )
+            })
+            .collect();
+        ret.extend(layer.items.iter().filter_map(|item| match item {
+            Item::Element(e) => Some(e.clone()),
+            Item::Ring { .. } => None,
+        }));
+        ret
+    }
+    // the extents of the bounding box, the smaller one clamped to MIN_EXTENT
     pub fn width(&self) -> f64 {
-        self.mercator_bbox.max.x - self.mercator_bbox.min.x
+        let width = self.mercator_bbox.width();
+        width.max(MIN_EXTENT * self.mercator_bbox.height())
     }
     pub fn height(&self) -> f64 {
-        self.mercator_bbox.max.y - self.mercator_bbox.min.y
+        let height = self.mercator_bbox.height();
+        height.max(MIN_EXTENT * self.mercator_bbox.width())
     }
     pub fn svg_width(&self) -> f64 {
         500.0
     }
     pub fn svg_height(&self) -> f64 {
-        self.svg_width() * self.height() / self.width()
+        (self.svg_width() * self.height() / self.width())
+            .clamp(MIN_CANVAS_HEIGHT, MAX_CANVAS_HEIGHT)
+            .round()
     }
+    // the same in both directions, the padded box fits the canvas
     pub fn scale(&self) -> f64 {
         let padded_width = self.width() * (1.0 + 2.0 * self.padding);
         let padded_height = self.height() * (1.0 + 2.0 * self.padding);
         (self.svg_width() / padded_width).min(self.svg_height() / padded_height)
     }
     fn transform(&self, x: f64, y: f64) -> (f64, f64) {
-        let svg_x = (x - self.mercator_bbox.min.x + self.width() * self.padding) * self.scale();
-        let svg_y = self.svg_height()
-            - (y - self.mercator_bbox.min.y + self.height() * self.padding) * self.scale();
+        let scale = self.scale();
+        // the center of the bounding box is the center of the canvas
+        let cx = 0.5 * (self.mercator_bbox.min.x + self.mercator_bbox.max.x);
+        let cy = 0.5 * (self.mercator_bbox.min.y + self.mercator_bbox.max.y);
+        let svg_x = 0.5 * self.svg_width() + (x - cx) * scale;
+        let svg_y = 0.5 * self.svg_height() - (y - cy) * scale;
         (svg_x, svg_y)
     }
 }
 
+fn polygon_element(vertices: &[String], fill: &str) -> String {
+    format!(
+        r#"<polygon points="{}" fill="{}" stroke="none" stroke-width="1"/>"#,
+        vertices.join(" "),
+        fill
+    )
+}
+
+// The outline of the union of non-overlapping rings, as the path data of
+// closed loops. An edge shared by two rings is interior and dropped, the
+// others are chained into loops from vertex to vertex. Filled with the
+// even-odd rule, the loops cover the rings whatever their orientation, and
+// adjacent rings show no seam.
+fn region_path(rings: &[&[String]]) -> String {
+    let mut count: BTreeMap<(&str, &str), usize> = BTreeMap::new();
+    for ring in rings {
+        for (k, a) in ring.iter().enumerate() {
+            let b = &ring[(k + 1) % ring.len()];
+            if a != b {
+                let key = if a < b { (a, b) } else { (b, a) };
+                *count.entry((key.0.as_str(), key.1.as_str())).or_default() += 1;
+            }
+        }
+    }
+    // every vertex of the remaining edges has an even degree
+    let edges: Vec<(&str, &str)> = count
+        .into_iter()
+        .filter(|(_, n)| n % 2 == 1)
+        .map(|(e, _)| e)
+        .collect();
+    let mut at: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (i, (a, b)) in edges.iter().enumerate() {
+        at.entry(a).or_default().push(i);
+        at.entry(b).or_default().push(i);
+    }
+    let mut used = vec![false; edges.len()];
+    let mut d = Vec::new();
+    for first in 0..edges.len() {
+        if used[first] {
+            continue;
+        }
+        let start = edges[first].0;
+        d.push(format!("M{}", start));
+        let mut vertex = start;
+        let mut next = Some(first);
+        while let Some(i) = next {
+            used[i] = true;
+            let (a, b) = edges[i];
+            vertex = if a == vertex { b } else { a };
+            if vertex == start {
+                break;
+            }
+            d.push(format!("L{}", vertex));
+            next = at[vertex].iter().copied().find(|j| !used[*j]);
+        }
+        d.push("Z".to_string());
+    }
+    d.join(" ")
+}
+
+// blue (low) to red (high) over the [min, max] elevation range
+pub fn color_for_elevation(elevation: f64, min: f64, max: f64) -> String {
+    let t = if max > min {
+        ((elevation - min) / (max - min)).clamp(0f64, 1f64)
+    } else {
+        0.5
+    };
+    let hue = 240f64 * (1f64 - t);
+    format!("hsl({:.0}, 70%, 50%)", hue)
+}
+
+pub fn color_for_incidence(cos: f64) -> String {
+    let l = 10f64 + 70f64 * cos.clamp(0f64, 1f64);
+    format!("hsl(45, 90%, {:.0}%)", l)
+}
+
 pub fn color_for_slope(percent: f64) -> String {
     let max = 50f64;
     let r = percent.abs().clamp(0f64, max);
     let l = (100f64 * (max - r) / max).clamp(0f64, 75f64);
     format!("rgb({:.0}%, {:.0}%, {:.0}%)", l, l, l)
 }
+
+// The plain triangles and polygons, for the figures of the tests.
+#[cfg(test)]
+impl Svg {
+    // the triangles of a slice, or of a mesh by Mesh::iter
+    pub fn add_triangles<T: std::borrow::Borrow<crate::triangulation::Triangle>>(
+        &mut self,
+        triangles: impl IntoIterator<Item = T>,
+        altfill: bool,
+    ) {
+        for (i, triangle) in triangles.into_iter().enumerate() {
+            let mut fill = "none";
+            if altfill {
+                if i % 2 == 0 {
+                    fill = "blue";
+                }
+            }
+            self.add_triangle(triangle.borrow(), fill);
+        }
+    }
+    fn add_triangle(&mut self, triangle: &crate::triangulation::Triangle, fill: &str) {
+        let (x1, y1) = self.transform(triangle.0.x, triangle.0.y);
+        let (x2, y2) = self.transform(triangle.1.x, triangle.1.y);
+        let (x3, y3) = self.transform(triangle.2.x, triangle.2.y);
+        let p = format!(
+            r#"  <polygon points="{:.2},{:.2} {:.2},{:.2} {:.2},{:.2}" fill="{}" stroke="black" stroke-width="1"/>"#,
+            x1, y1, x2, y2, x3, y3, fill
+        );
+        self.polygons.push(p);
+    }
+
+    pub fn add_polygon(&mut self, points: &Vec<MercatorPoint>, fill: &str) {
+        let p = self.polygon(points, fill);
+        self.polygons.push(p);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(x: f64, y: f64) -> MercatorPoint {
+        MercatorPoint { x, y, ele: None }
+    }
+
+    fn figure(points: &[MercatorPoint]) -> Svg {
+        let bbox = MercatorBoundingBox {
+            min: point(
+                points.iter().map(|p| p.x).fold(f64::INFINITY, f64::min),
+                points.iter().map(|p| p.y).fold(f64::INFINITY, f64::min),
+            ),
+            max: point(
+                points.iter().map(|p| p.x).fold(f64::NEG_INFINITY, f64::max),
+                points.iter().map(|p| p.y).fold(f64::NEG_INFINITY, f64::max),
+            ),
+        };
+        let mut svg = Svg::init(&bbox);
+        svg.add_polygon(&points.to_vec(), "gray");
+        svg
+    }
+
+    #[test]
+    fn test_wide_polygon() {
+        let svg = figure(&[
+            point(0.0, 0.0),
+            point(1000.0, 0.0),
+            point(1000.0, 100.0),
+            point(0.0, 100.0),
+        ]);
+        // 10:1 on the minimum canvas height, centered
+        let expected = r#"<svg width="500" height="200" viewBox="0 0 500 200" xmlns="http://www.w3.org/2000/svg">
+<polygon points="0,0 500,0 500,200 0,200 0,0" fill="none" stroke="black" stroke-width="1"/><polygon points="41.67,120.83 458.33,120.83 458.33,79.17 41.67,79.17" fill="gray" stroke="none" stroke-width="1"/>
+</svg>"#;
+        assert_eq!(svg.render().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_polygon_with_holes() {
+        let outer = [
+            point(0.0, 0.0),
+            point(1000.0, 0.0),
+            point(1000.0, 100.0),
+            point(0.0, 100.0),
+        ];
+        let hole = vec![
+            point(100.0, 25.0),
+            point(200.0, 25.0),
+            point(200.0, 75.0),
+            point(100.0, 75.0),
+        ];
+        let mut svg = figure(&outer);
+        svg.polygons.clear();
+        svg.add_polygon_with_holes(&outer, &[hole], "gray");
+        let rendered = svg.render().unwrap();
+        assert!(rendered.contains(
+            r#"<path d="M41.67,120.83 L458.33,120.83 L458.33,79.17 L41.67,79.17 Z M83.33,110.42 L125.00,110.42 L125.00,89.58 L83.33,89.58 Z" fill="gray" fill-rule="evenodd""#
+        ), "{}", rendered);
+        // without holes, a plain polygon
+        svg.polygons.clear();
+        svg.add_polygon_with_holes(&outer, &[], "gray");
+        assert_eq!(svg.render().unwrap(), figure(&outer).render().unwrap());
+    }
+
+    #[test]
+    fn test_regions() {
+        // two columns of 4 squares, the west one gray, the east one blue
+        // with a square of gray in it
+        let square = |col: f64, row: f64| {
+            vec![
+                point(col, row),
+                point(col + 1.0, row),
+                point(col + 1.0, row + 1.0),
+                point(col, row + 1.0),
+            ]
+        };
+        let mut svg = figure(&square(0.0, 0.0));
+        svg.mercator_bbox.max = point(2.0, 4.0);
+        svg.add_layer("slope", true);
+        for row in 0..4 {
+            let row = row as f64;
+            svg.add_polygon_to_layer("slope", &square(0.0, row), "gray");
+            let fill = if row == 2.0 { "gray" } else { "blue" };
+            // the rings of one column run the other way
+            let ring: Vec<_> = square(1.0, row).into_iter().rev().collect();
+            svg.add_polygon_to_layer("slope", &ring, fill);
+        }
+        svg.add_legend_to_layer("slope", "slope", &[]);
+        let regions = svg.render().unwrap();
+        svg.set_regions(false);
+        let polygons = svg.render().unwrap();
+        assert!(regions.len() < polygons.len());
+        assert_eq!(polygons.matches("<polygon").count(), 2 + 8);
+        assert_eq!(regions.matches("<polygon").count(), 2);
+        // the gray region: 5 squares, one outline of 12 edges; the blue
+        // one: outlines of 6 and 4 edges
+        let paths: Vec<&str> = regions.lines().filter(|l| l.starts_with("<path")).collect();
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].contains(r#"fill="gray""#));
+        assert_eq!(paths[0].matches('M').count(), 1);
+        assert_eq!(paths[0].matches('L').count(), 11);
+        assert!(paths[1].contains(r#"fill="blue""#));
+        assert_eq!(paths[1].matches('M').count(), 2);
+        assert_eq!(paths[1].matches('L').count(), 8);
+        // the legend follows
+        let legend = regions.find(r#"<g class="legend">"#).unwrap();
+        assert!(regions.find("<path").unwrap() < legend);
+    }
+
+    #[test]
+    fn test_degenerate_bbox() {
+        // a fence line along a parallel
+        let svg = figure(&[point(0.0, 0.0), point(1000.0, 0.0), point(500.0, 0.0)]);
+        let expected = r#"<svg width="500" height="200" viewBox="0 0 500 200" xmlns="http://www.w3.org/2000/svg">
+<polygon points="0,0 500,0 500,200 0,200 0,0" fill="none" stroke="black" stroke-width="1"/><polygon points="41.67,100.00 458.33,100.00 250.00,100.00" fill="gray" stroke="none" stroke-width="1"/>
+</svg>"#;
+        assert_eq!(svg.render().unwrap(), expected);
+        // a single point
+        let svg = figure(&[point(10.0, 20.0), point(10.0, 20.0), point(10.0, 20.0)]);
+        assert!(svg.render().is_err());
+    }
+}
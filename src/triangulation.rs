@@ -1,6 +1,9 @@
 use core::fmt;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::OnceLock;
 
 use crate::point::MercatorPoint;
+use crate::polygon;
 
 #[derive(Clone)]
 pub struct Triangle(pub MercatorPoint, pub MercatorPoint, pub MercatorPoint);
@@ -30,14 +33,32 @@ impl Triangle {
         0.5 * (n.0 * n.0 + n.1 * n.1 + n.2 * n.2).sqrt()
     }
 
+    // the smallest angle in the plane, in degrees
+    fn min_angle(&self) -> f64 {
+        let angle = |a: &MercatorPoint, b: &MercatorPoint, c: &MercatorPoint| {
+            let (u, v) = ((b.x - a.x, b.y - a.y), (c.x - a.x, c.y - a.y));
+            (u.0 * v.1 - u.1 * v.0).abs().atan2(u.0 * v.0 + u.1 * v.1)
+        };
+        let (a, b, c) = (&self.0, &self.1, &self.2);
+        angle(a, b, c)
+            .min(angle(b, c, a))
+            .min(angle(c, a, b))
+            .to_degrees()
+    }
+
     fn normal_vector(&self) -> (f64, f64, f64) {
+        self.cross_product().unwrap()
+    }
+
+    // None with a missing elevation
+    fn cross_product(&self) -> Option<(f64, f64, f64)> {
         let p1 = &self.0;
         let p2 = &self.1;
         let p3 = &self.2;
 
-        let e1 = p1.ele.unwrap();
-        let e2 = p2.ele.unwrap();
-        let e3 = p3.ele.unwrap();
+        let e1 = p1.ele?;
+        let e2 = p2.ele?;
+        let e3 = p3.ele?;
 
         // Two edge vectors
         let v1 = (p2.x - p1.x, p2.y - p1.y, e2 - e1);
@@ -48,91 +69,597 @@ impl Triangle {
         let ny = v1.2 * v2.0 - v1.0 * v2.2;
         let nz = v1.0 * v2.1 - v1.1 * v2.0;
 
-        (nx, ny, nz)
+        Some((nx, ny, nz))
+    }
+
+    // Unit normal, pointing up. None with a missing elevation or for
+    // collinear vertices.
+    pub fn normal(&self) -> Option<(f64, f64, f64)> {
+        let (nx, ny, nz) = self.cross_product()?;
+        let length = (nx * nx + ny * ny + nz * nz).sqrt();
+        if length == 0.0 {
+            return None;
+        }
+        let sign = if nz < 0.0 { -1.0 } else { 1.0 };
+        Some((sign * nx / length, sign * ny / length, sign * nz / length))
+    }
+
+    // in percent, infinite for a vertical triangle
+    pub fn slope_percent(&self) -> Option<f64> {
+        Some(polygon::normal_slope(self.normal()?))
+    }
+
+    // The direction the triangle faces (downhill), clockwise from north in
+    // degrees, see polygon::normal_aspect. None for a flat triangle.
+    pub fn aspect_degrees(&self) -> Option<f64> {
+        polygon::normal_aspect(self.normal()?)
+    }
+
+    // The centroid, at the elevation of the triangle there, none with a
+    // missing elevation.
+    pub fn centroid(&self) -> MercatorPoint {
+        let (a, b, c) = (&self.0, &self.1, &self.2);
+        let ele = match (a.ele, b.ele, c.ele) {
+            (Some(e0), Some(e1), Some(e2)) => Some((e0 + e1 + e2) / 3.0),
+            _ => None,
+        };
+        MercatorPoint {
+            x: (a.x + b.x + c.x) / 3.0,
+            y: (a.y + b.y + c.y) / 3.0,
+            ele,
+        }
+    }
+
+    // The barycentric coordinates of the position in the plane, relative to
+    // the three vertices. Not finite for collinear vertices.
+    pub fn barycentric_coords(&self, p: &MercatorPoint) -> (f64, f64, f64) {
+        let v0x = self.1.x - self.0.x;
+        let v0y = self.1.y - self.0.y;
+        let v1x = self.2.x - self.0.x;
+        let v1y = self.2.y - self.0.y;
+        let v2x = p.x - self.0.x;
+        let v2y = p.y - self.0.y;
+
+        let den = v0x * v1y - v1x * v0y;
+        let v = (v2x * v1y - v1x * v2y) / den;
+        let w = (v0x * v2y - v2x * v0y) / den;
+        let u = 1.0 - v - w;
+
+        (u, v, w)
+    }
+
+    // Whether the position is in the triangle or on its edges, in the plane.
+    pub fn contains_point(&self, p: &MercatorPoint) -> bool {
+        let (u, v, w) = self.barycentric_coords(p);
+        let eps = 1e-12;
+        u >= -eps && v >= -eps && w >= -eps
     }
 }
 
-pub mod grid {
-    use super::Triangle;
-    use crate::point::MercatorPoint;
-    use geo::Coord;
-    use spade::{DelaunayTriangulation, Point2, Triangulation};
+// Indexed triangle mesh: vertices are stored once, triangles refer to them by
+// index. The edge map (sorted vertex pair -> triangles) gives the adjacency,
+// it is built on the first query: the clip does not need it.
+pub struct Mesh {
+    pub vertices: Vec<MercatorPoint>,
+    pub triangles: Vec<[u32; 3]>,
+    adjacency: OnceLock<Adjacency>,
+}
 
-    pub fn triangulate(points: &[MercatorPoint]) -> Vec<Triangle> {
-        if points.len() < 3 {
-            return Vec::new();
+struct Adjacency {
+    edges: BTreeMap<(u32, u32), Vec<usize>>,
+    stars: Vec<Vec<usize>>,
+}
+
+impl Adjacency {
+    fn new(vertices: usize, triangles: &[[u32; 3]]) -> Self {
+        let mut edges: BTreeMap<(u32, u32), Vec<usize>> = BTreeMap::new();
+        let mut stars = vec![Vec::new(); vertices];
+        for (t, [a, b, c]) in triangles.iter().enumerate() {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                edges.entry(edge_key(*u, *v)).or_default().push(t);
+            }
+            for v in [a, b, c] {
+                stars[*v as usize].push(t);
+            }
+        }
+        Adjacency { edges, stars }
+    }
+}
+
+fn edge_key(a: u32, b: u32) -> (u32, u32) {
+    (a.min(b), a.max(b))
+}
+
+impl Mesh {
+    pub fn new(vertices: Vec<MercatorPoint>, triangles: Vec<[u32; 3]>) -> Self {
+        Mesh {
+            vertices,
+            triangles,
+            adjacency: OnceLock::new(),
+        }
+    }
+
+    fn adjacency(&self) -> &Adjacency {
+        self.adjacency
+            .get_or_init(|| Adjacency::new(self.vertices.len(), &self.triangles))
+    }
+
+    pub fn len(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.triangles.is_empty()
+    }
+
+    pub fn triangle(&self, index: usize) -> Triangle {
+        let [a, b, c] = self.triangles[index];
+        Triangle(
+            self.vertices[a as usize].clone(),
+            self.vertices[b as usize].clone(),
+            self.vertices[c as usize].clone(),
+        )
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = Triangle> + '_ {
+        (0..self.triangles.len()).map(|i| self.triangle(i))
+    }
+
+    // Triangles sharing an edge with the given triangle.
+    pub fn neighbors(&self, index: usize) -> Vec<usize> {
+        let [a, b, c] = self.triangles[index];
+        let edges = &self.adjacency().edges;
+        let mut ret = Vec::new();
+        for (u, v) in [(a, b), (b, c), (c, a)] {
+            for t in &edges[&edge_key(u, v)] {
+                if *t != index {
+                    ret.push(*t);
+                }
+            }
         }
+        ret
+    }
 
-        // Create Delaunay triangulation
-        let spade_points: Vec<Point2<f64>> = points.iter().map(|p| Point2::new(p.x, p.y)).collect();
+    // Edges that belong to a single triangle, oriented as in that triangle.
+    pub fn boundary_edges(&self) -> Vec<[u32; 2]> {
+        let edges = &self.adjacency().edges;
+        let mut ret = Vec::new();
+        for (t, [a, b, c]) in self.triangles.iter().enumerate() {
+            for (u, v) in [(a, b), (b, c), (c, a)] {
+                if edges[&edge_key(*u, *v)] == [t] {
+                    ret.push([*u, *v]);
+                }
+            }
+        }
+        ret
+    }
 
-        let mut triangulation = DelaunayTriangulation::<Point2<f64>>::new();
+    // The side of every triangle with respect to the cuts, edges of the mesh:
+    // crossing a cut changes it. The first triangle of each connected part of
+    // the mesh (one for a whole triangulation, more once triangles were
+    // dropped) is on the side given by seed.
+    pub fn sides(&self, cuts: &[[u32; 2]], seed: impl Fn(usize) -> bool) -> Vec<bool> {
+        let cuts: HashSet<(u32, u32)> = cuts.iter().map(|[a, b]| edge_key(*a, *b)).collect();
+        let edges = &self.adjacency().edges;
+        let mut sides = vec![None; self.len()];
+        let mut stack = Vec::new();
+        for first in 0..self.len() {
+            if sides[first].is_some() {
+                continue;
+            }
+            sides[first] = Some(seed(first));
+            stack.push(first);
+            while let Some(t) = stack.pop() {
+                let [a, b, c] = self.triangles[t];
+                for (u, v) in [(a, b), (b, c), (c, a)] {
+                    let key = edge_key(u, v);
+                    let side = sides[t].unwrap() != cuts.contains(&key);
+                    for n in &edges[&key] {
+                        if sides[*n].is_none() {
+                            sides[*n] = Some(side);
+                            stack.push(*n);
+                        }
+                    }
+                }
+            }
+        }
+        sides.into_iter().map(Option::unwrap).collect()
+    }
+
+    // Triangles incident to the given vertex.
+    pub fn vertex_star(&self, vertex: usize) -> &[usize] {
+        &self.adjacency().stars[vertex]
+    }
 
-        for point in spade_points {
-            triangulation.insert(point).ok();
+    // Reverses the triangles whose normal points down, so that all of them
+    // face up as terrain does; vertical ones are left alone. Returns the
+    // number of reversed triangles.
+    pub fn orient_up(&mut self) -> usize {
+        let mut reversed = 0;
+        for t in 0..self.triangles.len() {
+            if self.triangle(t).normal_vector().2 < 0.0 {
+                self.triangles[t].swap(1, 2);
+                reversed += 1;
+            }
         }
+        reversed
+    }
 
-        // Extract triangles
-        let mut triangles = Vec::new();
-        for face in triangulation.inner_faces() {
-            let [v1, v2, v3] = face.vertices();
-            let p1 = find_matching_point(
-                points,
-                &Coord {
-                    x: v1.position().x,
-                    y: v1.position().y,
-                },
-            );
-            let p2 = find_matching_point(
-                points,
-                &Coord {
-                    x: v2.position().x,
-                    y: v2.position().y,
-                },
-            );
-            let p3 = find_matching_point(
-                points,
-                &Coord {
-                    x: v3.position().x,
-                    y: v3.position().y,
-                },
-            );
+    // The median of the shortest edge of the triangles, in the plane: the
+    // grid spacing of the samples.
+    pub fn spacing(&self) -> f64 {
+        let mut shortest: Vec<f64> = self
+            .iter()
+            .map(|t| {
+                let (a, b, c) = (&t.0, &t.1, &t.2);
+                a.distance_2d(b).min(b.distance_2d(c)).min(c.distance_2d(a))
+            })
+            .collect();
+        if shortest.is_empty() {
+            return 0.0;
+        }
+        let middle = shortest.len() / 2;
+        *shortest.select_nth_unstable_by(middle, f64::total_cmp).1
+    }
 
-            triangles.push(Triangle(p1, p2, p3));
+    // Drops the triangles whose flat area is below min_area or whose smallest
+    // angle is below min_angle, in degrees: the slivers between duplicate or
+    // almost collinear samples, where the noise of the elevations counts as
+    // 3D area. The triangles with a vertex in `fixed` (the boundary of a
+    // constrained triangulation, which fans out to the samples in thin
+    // triangles that cover the polygon) are only dropped below min_area.
+    // Returns the dropped triangles, on the vertices of the mesh.
+    pub fn drop_degenerate(&mut self, min_area: f64, min_angle: f64, fixed: &HashSet<u32>) -> Mesh {
+        let (mut dropped, mut kept) = (Vec::new(), Vec::with_capacity(self.triangles.len()));
+        for (t, vertices) in self.triangles.iter().enumerate() {
+            let triangle = self.triangle(t).flat();
+            let thin =
+                !vertices.iter().any(|v| fixed.contains(v)) && triangle.min_angle() < min_angle;
+            if triangle.area() < min_area || thin {
+                dropped.push(*vertices);
+            } else {
+                kept.push(*vertices);
+            }
+        }
+        if dropped.is_empty() {
+            return Mesh::new(Vec::new(), dropped);
         }
+        self.triangles = kept;
+        self.adjacency = OnceLock::new();
+        Mesh::new(self.vertices.clone(), dropped)
+    }
 
-        triangles
+    // Unit normal of every vertex, for smooth shading: the mean of the
+    // normals of its triangles weighted by their area (the cross product is
+    // twice the area long). Straight up for a vertex without triangles.
+    pub fn vertex_normals(&self) -> Vec<(f64, f64, f64)> {
+        let faces: Vec<_> = self.iter().map(|t| t.normal_vector()).collect();
+        self.adjacency()
+            .stars
+            .iter()
+            .map(|star| {
+                let (mut x, mut y, mut z) = (0.0, 0.0, 0.0);
+                for t in star {
+                    x += faces[*t].0;
+                    y += faces[*t].1;
+                    z += faces[*t].2;
+                }
+                let length = (x * x + y * y + z * z).sqrt();
+                if length > 0.0 {
+                    (x / length, y / length, z / length)
+                } else {
+                    (0.0, 0.0, 1.0)
+                }
+            })
+            .collect()
     }
+}
+
+pub mod grid {
+    use std::collections::HashMap;
+
+    use super::Mesh;
+    use crate::intersection;
+    use crate::point::MercatorPoint;
+    use geo::BooleanOps;
+    use spade::{
+        ConstrainedDelaunayTriangulation, DelaunayTriangulation, HasPosition, Point2,
+        PositionInTriangulation, Triangulation,
+    };
+
+    // a sample in the triangulation, with its elevation
+    struct Vertex(MercatorPoint);
 
-    // Helper function to find the original MercatorPoint that matches a coordinate
-    fn find_matching_point(points: &[MercatorPoint], coord: &Coord<f64>) -> MercatorPoint {
-        let eps = 1e-10;
+    // Samples closer than this in both directions (in meters) are at the same
+    // position: the ones of adjacent tiles differ by the rounding of their
+    // projection.
+    pub const SNAP: f64 = 1e-6;
 
+    impl HasPosition for Vertex {
+        type Scalar = f64;
+        fn position(&self) -> Point2<f64> {
+            Point2::new(self.0.x, self.0.y)
+        }
+    }
+
+    pub fn triangulate(points: &[MercatorPoint]) -> Mesh {
+        if points.len() < 3 {
+            return Mesh::new(Vec::new(), Vec::new());
+        }
+        let mut triangulation = DelaunayTriangulation::<Vertex>::new();
+        insert_samples(&mut triangulation, points);
+        mesh(&triangulation)
+    }
+
+    fn insert_samples<T: Triangulation<Vertex = Vertex>>(
+        triangulation: &mut T,
+        points: &[MercatorPoint],
+    ) {
+        let (mut duplicates, mut rejected) = (0, Vec::new());
+        // the inserted positions by cell of SNAP, one per cell at most
+        let mut inserted: HashMap<(i64, i64), (f64, f64)> = HashMap::new();
+        let cell = |v: f64| (v / SNAP).floor() as i64;
         for point in points {
-            if (point.x - coord.x).abs() < eps && (point.y - coord.y).abs() < eps {
-                return point.clone();
+            let vertex = Vertex(point.clone());
+            if let Err(e) = spade::validate_vertex(&vertex) {
+                rejected.push((point, e));
+                continue;
+            }
+            // spade would replace the sample at the position, or keep both
+            // a rounding apart: the first one is kept
+            let (col, row) = (cell(point.x), cell(point.y));
+            let snapped = (col - 1..=col + 1)
+                .flat_map(|c| (row - 1..=row + 1).map(move |r| (c, r)))
+                .filter_map(|key| inserted.get(&key))
+                .any(|(x, y)| (point.x - x).abs() <= SNAP && (point.y - y).abs() <= SNAP);
+            if snapped {
+                duplicates += 1;
+                continue;
+            }
+            match triangulation.insert(vertex) {
+                Ok(_) => {
+                    inserted.insert((col, row), (point.x, point.y));
+                }
+                Err(e) => rejected.push((point, e)),
             }
         }
-        assert!(false);
+        if duplicates > 0 {
+            log::debug!("{} samples at the position of another one", duplicates);
+        }
+        if let Some((point, e)) = rejected.first() {
+            log::warn!(
+                "{} samples not triangulated, the first at {}: {}",
+                rejected.len(),
+                point,
+                e
+            );
+        }
+    }
 
-        // If no exact match found (shouldn't happen), create a new point without elevation
-        MercatorPoint {
-            x: coord.x,
-            y: coord.y,
-            ele: None,
+    fn mesh<T: Triangulation<Vertex = Vertex>>(triangulation: &T) -> Mesh {
+        // the vertices in the order of spade's handles
+        let vertices = triangulation
+            .vertices()
+            .map(|v| v.data().0.clone())
+            .collect();
+        let triangles = triangulation
+            .inner_faces()
+            .map(|face| face.vertices().map(|v| v.fix().index() as u32))
+            .collect();
+        Mesh::new(vertices, triangles)
+    }
+
+    // The elevation at the position, linear in the triangle around it. None
+    // outside of the samples or at a sample without elevation.
+    fn interpolate<T: Triangulation<Vertex = Vertex>>(
+        triangulation: &T,
+        p: &MercatorPoint,
+    ) -> Option<f64> {
+        let position = Point2::new(p.x, p.y);
+        match triangulation.locate(position) {
+            PositionInTriangulation::OnVertex(v) => triangulation.vertex(v).data().0.ele,
+            PositionInTriangulation::OnEdge(e) => {
+                let [a, b] = triangulation.directed_edge(e).vertices();
+                let (a, b) = (&a.data().0, &b.data().0);
+                let t = a.distance_2d(p) / a.distance_2d(b);
+                Some(a.ele? + t * (b.ele? - a.ele?))
+            }
+            PositionInTriangulation::OnFace(f) => {
+                let face = triangulation.face(f);
+                let weights = face.barycentric_interpolation(position);
+                let mut ele = 0.0;
+                for (v, w) in face.vertices().iter().zip(weights) {
+                    ele += w * v.data().0.ele?;
+                }
+                Some(ele)
+            }
+            _ => None,
         }
     }
+
+    // The rings of the polygon (the outer one, then its holes) within the
+    // convex hull of the samples, where the clip finds triangles: unchanged
+    // when they are inside of it, intersected with it otherwise. The vertices
+    // of the rings that are kept keep their elevation.
+    fn within_hull<T: Triangulation<Vertex = Vertex>>(
+        triangulation: &T,
+        rings: &[&[MercatorPoint]],
+    ) -> Vec<Vec<MercatorPoint>> {
+        let outside = |p: &MercatorPoint| {
+            matches!(
+                triangulation.locate(Point2::new(p.x, p.y)),
+                PositionInTriangulation::OutsideOfConvexHull(_)
+            )
+        };
+        let Some((outer, holes)) = rings.split_first() else {
+            return Vec::new();
+        };
+        if !rings.iter().flat_map(|ring| ring.iter()).any(outside) {
+            return rings.iter().map(|ring| ring.to_vec()).collect();
+        }
+        log::warn!("the boundary leaves the samples, it is cut to them");
+        // the position rounded to the millimeter
+        let key = |x: f64, y: f64| ((x * 1000.0).round() as i64, (y * 1000.0).round() as i64);
+        let kept: HashMap<(i64, i64), &MercatorPoint> = rings
+            .iter()
+            .flat_map(|ring| ring.iter())
+            .map(|p| (key(p.x, p.y), p))
+            .collect();
+        let hull: Vec<MercatorPoint> = triangulation
+            .convex_hull()
+            .map(|e| e.from().data().0.clone())
+            .collect();
+        let holes: Vec<Vec<MercatorPoint>> = holes.iter().map(|h| h.to_vec()).collect();
+        let polygon = intersection::to_geo_polygon_with_holes(outer, &holes);
+        polygon
+            .intersection(&intersection::to_geo_polygon(&hull))
+            .into_iter()
+            .flat_map(|p| {
+                let (exterior, interiors) = p.into_inner();
+                std::iter::once(exterior).chain(interiors)
+            })
+            .map(|ring| {
+                let mut coords = ring.into_inner();
+                // closed
+                coords.pop();
+                coords
+                    .into_iter()
+                    .map(|c| match kept.get(&key(c.x, c.y)) {
+                        Some(p) => (*p).clone(),
+                        None => MercatorPoint {
+                            x: c.x,
+                            y: c.y,
+                            ele: None,
+                        },
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // Triangulates the samples with the rings of the polygon (the outer one,
+    // then its holes) as constraints, so that no triangle crosses the
+    // boundary: the boundary vertices are vertices of the mesh, the edges of
+    // the rings are edges of it, split where they cross. The rings are cut
+    // to the samples first (see within_hull). The boundary vertices and the
+    // crossings without an elevation take the one of the elevation function
+    // (the DEM, interpolated bilinearly in its cell), or else the linear one
+    // of the samples around them. Returns the mesh and the edges of the
+    // rings in it.
+    pub fn triangulate_constrained(
+        points: &[MercatorPoint],
+        rings: &[&[MercatorPoint]],
+        elevation: &dyn Fn(&MercatorPoint) -> Option<f64>,
+    ) -> (Mesh, Vec<[u32; 2]>) {
+        if points.len() < 3 {
+            return (Mesh::new(Vec::new(), Vec::new()), Vec::new());
+        }
+        let mut triangulation = ConstrainedDelaunayTriangulation::<Vertex>::new();
+        insert_samples(&mut triangulation, points);
+        // on the samples alone, before any of them is inserted
+        let rings: Vec<Vec<MercatorPoint>> = within_hull(&triangulation, rings)
+            .into_iter()
+            .map(|ring| {
+                ring.into_iter()
+                    .map(|p| MercatorPoint {
+                        ele: p
+                            .ele
+                            .or_else(|| elevation(&p))
+                            .or_else(|| interpolate(&triangulation, &p)),
+                        ..p
+                    })
+                    .collect()
+            })
+            .collect();
+        let mut rejected = 0;
+        for ring in rings {
+            let count = ring.len();
+            let handles: Vec<_> = ring
+                .into_iter()
+                .filter_map(|p| triangulation.insert(Vertex(p)).ok())
+                .collect();
+            rejected += count - handles.len();
+            for k in 0..handles.len() {
+                let (from, to) = (handles[k], handles[(k + 1) % handles.len()]);
+                if from == to {
+                    continue;
+                }
+                // the crossings of the rings, linear along the edge where
+                // the elevation function has none
+                let a = triangulation.vertex(from).data().0.clone();
+                let b = triangulation.vertex(to).data().0.clone();
+                triangulation.add_constraint_and_split(from, to, |p| {
+                    let mut m = MercatorPoint {
+                        x: p.x,
+                        y: p.y,
+                        ele: None,
+                    };
+                    m.ele = elevation(&m).or_else(|| {
+                        let t = a.distance_2d(&m) / a.distance_2d(&b);
+                        Some(a.ele? + t * (b.ele? - a.ele?))
+                    });
+                    Vertex(m)
+                });
+            }
+        }
+        if rejected > 0 {
+            log::warn!("{} boundary vertices not triangulated", rejected);
+        }
+        let constraints = triangulation
+            .undirected_edges()
+            .filter(|e| e.is_constraint_edge())
+            .map(|e| e.vertices().map(|v| v.fix().index() as u32))
+            .collect();
+        (mesh(&triangulation), constraints)
+    }
+
+    // Triangulates the samples of a lattice, given with their (column, row)
+    // index: two triangles per cell whose four corners are there, without
+    // Delaunay. A cell with a missing corner (nodata) is skipped. None when
+    // two samples have the same index: the points are not a clean lattice,
+    // triangulate them.
+    pub fn triangulate_regular(
+        points: &[MercatorPoint],
+        indices: &[(isize, isize)],
+    ) -> Option<Mesh> {
+        assert_eq!(points.len(), indices.len());
+        let mut at = HashMap::with_capacity(indices.len());
+        for (k, index) in indices.iter().enumerate() {
+            if at.insert(*index, k as u32).is_some() {
+                return None;
+            }
+        }
+        // counterclockwise, as spade's
+        let oriented = |t: [u32; 3]| {
+            let [a, b, c] = t.map(|v| &points[v as usize]);
+            if (b.x - a.x) * (c.y - a.y) - (b.y - a.y) * (c.x - a.x) < 0.0 {
+                [t[0], t[2], t[1]]
+            } else {
+                t
+            }
+        };
+        let mut triangles = Vec::with_capacity(2 * points.len());
+        for (a, (col, row)) in indices.iter().enumerate() {
+            let corner = |dc: isize, dr: isize| at.get(&(col + dc, row + dr)).copied();
+            let (Some(b), Some(c), Some(d)) = (corner(1, 0), corner(1, 1), corner(0, 1)) else {
+                continue;
+            };
+            triangles.push(oriented([a as u32, b, c]));
+            triangles.push(oriented([a as u32, c, d]));
+        }
+        Some(Mesh::new(points.to_vec(), triangles))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::grid::triangulate;
+    use super::grid::{SNAP, triangulate};
     use super::*;
     use crate::point::MercatorPoint;
     use crate::svg;
 
     fn drawresult(triangles: &Vec<Triangle>, filename: &str) -> String {
-        let mut svg = svg::SVG::init(&crate::point::MercatorBoundingBox {
+        let mut svg = svg::Svg::init(&crate::point::MercatorBoundingBox {
             min: MercatorPoint {
                 x: 0.0,
                 y: 0.0,
@@ -144,8 +671,8 @@ mod tests {
                 ele: None,
             },
         });
-        svg.add_triangles(&triangles, true);
-        let ret = svg.render();
+        svg.add_triangles(triangles, true);
+        let ret = svg.render().unwrap();
         std::fs::write(filename, ret.clone()).unwrap();
         ret
     }
@@ -192,7 +719,7 @@ mod tests {
             },
         ];
 
-        let triangles = triangulate(&points);
+        let triangles: Vec<Triangle> = triangulate(&points).iter().collect();
         drawresult(&triangles, "/tmp/simple.svg");
         assert_eq!(triangles.len(), 1);
     }
@@ -222,7 +749,7 @@ mod tests {
             },
         ];
 
-        let triangles = triangulate(&points);
+        let triangles: Vec<Triangle> = triangulate(&points).iter().collect();
         drawresult(&triangles, "/tmp/square.svg");
         assert_eq!(triangles.len(), 2);
     }
@@ -252,7 +779,7 @@ mod tests {
             },
         ];
 
-        let triangles = triangulate(&points);
+        let triangles: Vec<Triangle> = triangulate(&points).iter().collect();
         drawresult(&triangles, "/tmp/convex.svg");
         assert_eq!(triangles.len(), 2);
     }
@@ -260,7 +787,363 @@ mod tests {
     #[test]
     fn test_triangulate_random_grid() {
         let points = generate_random_grid(20);
-        let triangles = triangulate(&points);
+        let triangles: Vec<Triangle> = triangulate(&points).iter().collect();
         drawresult(&triangles, "/tmp/random-grid.svg");
     }
+
+    fn grid3x3() -> Vec<MercatorPoint> {
+        let mut points = Vec::new();
+        for row in 0..3 {
+            for col in 0..3 {
+                points.push(MercatorPoint {
+                    x: 100.0 * col as f64,
+                    y: 100.0 * row as f64,
+                    ele: Some(10.0 * (row + col) as f64),
+                });
+            }
+        }
+        points
+    }
+
+    #[test]
+    fn test_mesh_adjacency() {
+        // 6 7 8
+        // 3 4 5
+        // 0 1 2   every cell split along its 0-4 style diagonal
+        let triangles = vec![
+            [0, 1, 4],
+            [0, 4, 3],
+            [1, 2, 5],
+            [1, 5, 4],
+            [3, 4, 7],
+            [3, 7, 6],
+            [4, 5, 8],
+            [4, 8, 7],
+        ];
+        let mesh = Mesh::new(grid3x3(), triangles);
+        assert_eq!(mesh.len(), 8);
+        // built by the first query
+        assert!(mesh.adjacency.get().is_none());
+        let sorted = |mut v: Vec<usize>| {
+            v.sort();
+            v
+        };
+        assert_eq!(sorted(mesh.neighbors(0)), vec![1, 3]);
+        assert_eq!(sorted(mesh.neighbors(3)), vec![0, 2, 6]);
+        assert_eq!(sorted(mesh.neighbors(5)), vec![4]);
+        assert_eq!(mesh.vertex_star(4), &[0, 1, 3, 4, 6, 7]);
+        assert_eq!(mesh.vertex_star(2), &[2]);
+        let boundary = mesh.boundary_edges();
+        assert_eq!(boundary.len(), 8);
+        assert!(boundary.contains(&[0, 1]));
+        assert!(boundary.contains(&[7, 6]));
+        assert!(!boundary.iter().any(|e| e.contains(&4)));
+        assert_eq!(mesh.triangle(6).2.ele, Some(40.0));
+    }
+
+    #[test]
+    fn test_triangulate_grid_mesh() {
+        let mut points = grid3x3();
+        // duplicates are stored once
+        points.push(points[4].clone());
+        let mesh = triangulate(&points);
+        assert_eq!(mesh.vertices.len(), 9);
+        assert_eq!(mesh.len(), 8);
+        assert_eq!(mesh.boundary_edges().len(), 8);
+        for t in mesh.iter() {
+            assert!(t.area() > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_orient_up_and_vertex_normals() {
+        // the cells of test_mesh_adjacency, every other triangle reversed
+        let triangles = vec![
+            [0, 1, 4],
+            [0, 3, 4],
+            [1, 2, 5],
+            [1, 4, 5],
+            [3, 4, 7],
+            [3, 6, 7],
+            [4, 5, 8],
+            [4, 7, 8],
+        ];
+        let mut mesh = Mesh::new(grid3x3(), triangles);
+        assert_eq!(mesh.orient_up(), 4);
+        assert_eq!(mesh.orient_up(), 0);
+        assert!(mesh.iter().all(|t| t.normal_vector().2 > 0.0));
+        assert_eq!(mesh.boundary_edges().len(), 8);
+
+        // z = 0.1 (x + y): every normal is the normal of the plane
+        let plane = {
+            let n = (-0.1f64, -0.1f64, 1.0f64);
+            let length = (n.0 * n.0 + n.1 * n.1 + n.2 * n.2).sqrt();
+            (n.0 / length, n.1 / length, n.2 / length)
+        };
+        for n in mesh.vertex_normals() {
+            assert!(((n.0 * n.0 + n.1 * n.1 + n.2 * n.2).sqrt() - 1.0).abs() < 1e-12);
+            assert!((n.0 - plane.0).abs() < 1e-12);
+            assert!((n.1 - plane.1).abs() < 1e-12);
+            assert!((n.2 - plane.2).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_snapped_points() {
+        let point = |x: f64, y: f64, ele: f64| MercatorPoint {
+            x,
+            y,
+            ele: Some(ele),
+        };
+        let points = vec![
+            point(0.0, 0.0, 0.0),
+            point(100.0, 0.0, 10.0),
+            point(100.0, 100.0, 20.0),
+            point(0.0, 100.0, 30.0),
+            // closer than SNAP: the same position
+            point(100.0 + 0.5 * SNAP, 100.0 - 0.5 * SNAP, 99.0),
+            point(f64::NAN, 50.0, 99.0),
+        ];
+        assert_ne!(points[4].x, points[2].x);
+        let mesh = triangulate(&points);
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.len(), 2);
+        // the first sample at the position
+        let corner = mesh.vertices.iter().find(|v| v.x == 100.0 && v.y == 100.0);
+        assert_eq!(corner.unwrap().ele, Some(20.0));
+        for triangle in mesh.iter() {
+            for v in triangle.as_vector() {
+                assert!(points[..4].contains(&v), "{}", v);
+            }
+        }
+    }
+
+    #[test]
+    fn test_triangulate_regular() {
+        // a sheared lattice, as the pixels of a DEM in a projection
+        let (cols, rows) = (20isize, 15isize);
+        let mut points = Vec::new();
+        let mut indices = Vec::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                points.push(MercatorPoint {
+                    x: 30.0 * col as f64 + 0.5 * row as f64,
+                    y: -30.0 * row as f64 + 0.25 * col as f64,
+                    ele: Some(((col * 7 + row * 13) % 11) as f64),
+                });
+                indices.push((col, row));
+            }
+        }
+        let flat = |mesh: &Mesh| mesh.iter().map(|t| t.flat().area()).sum::<f64>();
+        let regular = grid::triangulate_regular(&points, &indices).unwrap();
+        let delaunay = triangulate(&points);
+        assert_eq!(regular.len(), 2 * 19 * 14);
+        assert_eq!(regular.len(), delaunay.len());
+        assert!((flat(&regular) - flat(&delaunay)).abs() < 1e-6 * flat(&delaunay));
+        // all counterclockwise
+        assert!(regular.iter().all(|t| t.flat().normal_vector().2 > 0.0));
+
+        // the cells around a nodata sample are skipped
+        let k = (5 * cols + 5) as usize;
+        let (mut holed, mut holed_indices) = (points.clone(), indices.clone());
+        holed.remove(k);
+        holed_indices.remove(k);
+        let mesh = grid::triangulate_regular(&holed, &holed_indices).unwrap();
+        assert_eq!(mesh.len(), regular.len() - 8);
+
+        // not a lattice
+        indices[1] = indices[0];
+        assert!(grid::triangulate_regular(&points, &indices).is_none());
+    }
+
+    #[test]
+    fn test_drop_degenerate() {
+        // a plane rising 10% to the east, its column at x = 300 sampled a
+        // second time 1 mm away with 50 cm of noise, as on a tile seam
+        let mut points = Vec::new();
+        for i in 0..20 {
+            for j in 0..20 {
+                let (x, y) = (i as f64 * 30.0, j as f64 * 30.0);
+                points.push(MercatorPoint {
+                    x,
+                    y,
+                    ele: Some(0.1 * x),
+                });
+                if i == 10 {
+                    let noise = if j % 2 == 0 { 0.5 } else { -0.5 };
+                    points.push(MercatorPoint {
+                        x: x + 0.001,
+                        y,
+                        ele: Some(0.1 * x + noise),
+                    });
+                }
+            }
+        }
+        let ratio = |mesh: &Mesh| {
+            let a3d: f64 = mesh.iter().map(|t| t.area()).sum();
+            let a2d: f64 = mesh.iter().map(|t| t.flat().area()).sum();
+            a3d / a2d
+        };
+        let plane = 1.01f64.sqrt();
+        let mut mesh = triangulate(&points);
+        assert!(ratio(&mesh) - plane > 5e-4, "{}", ratio(&mesh));
+        assert!((mesh.spacing() - 30.0).abs() < 1e-6);
+        let count = mesh.len();
+        let dropped = mesh.drop_degenerate(1e-3 * 30.0 * 30.0, 1.0, &HashSet::new());
+        assert_eq!(mesh.len(), count - dropped.len());
+        assert!(dropped.len() >= 2 * 19, "{}", dropped.len());
+        let area: f64 = dropped.iter().map(|t| t.flat().area()).sum();
+        assert!(area < 1.0, "{}", area);
+        assert!((ratio(&mesh) - plane).abs() < 1e-4, "{}", ratio(&mesh));
+        // nothing left to drop
+        assert!(
+            mesh.drop_degenerate(1e-3 * 30.0 * 30.0, 1.0, &HashSet::new())
+                .is_empty()
+        );
+        assert!(mesh.boundary_edges().len() > 4 * 19);
+    }
+
+    // the boundary inserted into the triangulation gives the areas of the
+    // clip, on a cone and a wavy boundary of many vertices
+    #[test]
+    fn test_constrained_pipeline() {
+        use crate::fixtures::{self, assert_close};
+        use crate::options::ComputeOptions;
+        use crate::pipeline;
+        use std::f64::consts::PI;
+
+        let ring: Vec<(f64, f64)> = (0..240)
+            .map(|k| {
+                let a = k as f64 * PI / 120.0;
+                let r = 250.0 + 20.0 * (9.0 * a).sin();
+                (r * a.cos(), r * a.sin())
+            })
+            .collect();
+        let polygon = fixtures::polygon(&ring);
+        let cone = || {
+            fixtures::synthetic("constrained", (-350.0, -350.0, 350.0, 350.0), |x, y| {
+                Some(400.0 + 100.0 * (1.0 - (x * x + y * y).sqrt() / 200.0).max(0.0))
+            })
+        };
+        let mut options = ComputeOptions::default();
+        let clipped = pipeline::compute("clipped", &polygon, &[cone()], &options).unwrap();
+        options.constrained = true;
+        let data = pipeline::compute("constrained", &polygon, &[cone()], &options).unwrap();
+        assert_eq!(data.clip_paths.intersected, 0);
+        assert_close("flat", data.projected2d, clipped.projected2d, 1e-6);
+        assert_close("surface", data.projected3d, clipped.projected3d, 1e-3);
+    }
+
+    #[test]
+    fn test_constrained_elevations() {
+        // bilinear in every cell: the linear interpolation in a triangle is
+        // not exact, the elevation function is
+        let z = |x: f64, y: f64| x * y / 100.0;
+        let mut points = Vec::new();
+        for i in 0..20 {
+            for j in 0..20 {
+                let (x, y) = (i as f64 * 30.0, j as f64 * 30.0);
+                points.push(MercatorPoint {
+                    x,
+                    y,
+                    ele: Some(z(x, y)),
+                });
+            }
+        }
+        let ring = |x0: f64, y0: f64, x1: f64, y1: f64| -> Vec<MercatorPoint> {
+            [(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+                .iter()
+                .map(|&(x, y)| MercatorPoint { x, y, ele: None })
+                .collect()
+        };
+        let elevation = |p: &MercatorPoint| Some(z(p.x, p.y));
+        let check = |mesh: &Mesh| {
+            for p in &mesh.vertices {
+                let ele = p.ele.unwrap();
+                assert!((ele - z(p.x, p.y)).abs() < 1e-9, "{} {}", p, ele);
+            }
+        };
+
+        // two crossing rings: their crossings are vertices too
+        let (a, b) = (
+            ring(40.0, 50.0, 200.0, 170.0),
+            ring(100.0, 100.0, 400.0, 310.0),
+        );
+        let (mesh, constraints) = grid::triangulate_constrained(&points, &[&a, &b], &elevation);
+        assert_eq!(mesh.vertices.len(), points.len() + 8 + 2);
+        assert_eq!(constraints.len(), 8 + 4);
+        check(&mesh);
+
+        // overhanging the samples to the east and the south: cut to them
+        let overhang = ring(300.0, -100.0, 700.0, 400.0);
+        let (mesh, _) = grid::triangulate_constrained(&points, &[&overhang], &elevation);
+        check(&mesh);
+        let flat: f64 = mesh.iter().map(|t| t.flat().area()).sum();
+        assert!((flat - 570.0 * 570.0).abs() < 1e-6, "{}", flat);
+        assert!(mesh.vertices.iter().all(|p| p.x <= 570.0 && p.y >= 0.0));
+    }
+
+    #[test]
+    fn test_triangle_geometry() {
+        let point = |x: f64, y: f64, ele: Option<f64>| MercatorPoint { x, y, ele };
+        // rising 3 m every 4 m to the north: a 3-4-5 slope
+        let triangle = Triangle(
+            point(0.0, 0.0, Some(10.0)),
+            point(12.0, 0.0, Some(10.0)),
+            point(0.0, 8.0, Some(16.0)),
+        );
+        let (nx, ny, nz) = triangle.normal().unwrap();
+        assert!(nx.abs() < 1e-12);
+        assert!((ny + 0.6).abs() < 1e-12);
+        assert!((nz - 0.8).abs() < 1e-12);
+        assert!((triangle.slope_percent().unwrap() - 75.0).abs() < 1e-9);
+        // facing downhill, to the south
+        assert!((triangle.aspect_degrees().unwrap() - 180.0).abs() < 1e-9);
+        // the other way around, the normal still points up
+        let reversed = Triangle(triangle.0.clone(), triangle.2.clone(), triangle.1.clone());
+        assert_eq!(reversed.normal(), triangle.normal());
+        assert!((triangle.area() - 0.5 * 12.0 * 10.0).abs() < 1e-9);
+
+        let centroid = triangle.centroid();
+        assert!((centroid.x - 4.0).abs() < 1e-12);
+        assert!((centroid.y - 8.0 / 3.0).abs() < 1e-12);
+        assert!((centroid.ele.unwrap() - 12.0).abs() < 1e-12);
+        let (u, v, w) = triangle.barycentric_coords(&centroid);
+        for c in [u, v, w] {
+            assert!((c - 1.0 / 3.0).abs() < 1e-12);
+        }
+
+        assert!(triangle.contains_point(&centroid));
+        // on an edge, at a vertex, outside
+        assert!(triangle.contains_point(&point(6.0, 4.0, None)));
+        assert!(triangle.contains_point(&point(12.0, 0.0, None)));
+        assert!(!triangle.contains_point(&point(6.1, 4.1, None)));
+        assert!(!triangle.contains_point(&point(-0.1, 1.0, None)));
+
+        // flat: no aspect
+        let flat = triangle.flat();
+        assert_eq!(flat.slope_percent(), Some(0.0));
+        assert_eq!(flat.aspect_degrees(), None);
+
+        // a missing elevation
+        let missing = Triangle(
+            triangle.0.clone(),
+            triangle.1.clone(),
+            point(0.0, 8.0, None),
+        );
+        assert_eq!(missing.normal(), None);
+        assert_eq!(missing.slope_percent(), None);
+        assert_eq!(missing.aspect_degrees(), None);
+        assert_eq!(missing.centroid().ele, None);
+        assert!(missing.contains_point(&centroid));
+
+        // collinear
+        let line = Triangle(
+            point(0.0, 0.0, Some(0.0)),
+            point(1.0, 1.0, Some(1.0)),
+            point(2.0, 2.0, Some(2.0)),
+        );
+        assert_eq!(line.normal(), None);
+        assert!(!line.contains_point(&point(1.0, 1.0, None)));
+    }
 }
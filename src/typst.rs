@@ -1,50 +1,381 @@
-#[derive(Clone)]
+use serde::Serialize;
+
+use crate::area::{AreaValue, ReferenceComparison, Units};
+use crate::breakdown::Breakdown;
+use crate::compute::ClipPaths;
+use crate::estimate::Estimates;
+use crate::extremes::Extremes;
+use crate::format::{self, Quantity};
+use crate::insolation::InsolationSummary;
+use crate::labels::{Labels, Lang};
+use crate::pipeline::Phase;
+use crate::rasterio::IoStats;
+use crate::selfcheck::SelfCheck;
+
+#[derive(Clone, Serialize)]
+pub struct PlaneSummary {
+    pub slope: f64,
+    pub elevation: f64,
+    pub area: f64,
+    // a sliver: the slope of its grid triangle
+    pub inherited: bool,
+}
+
+#[derive(Clone, Serialize)]
 pub struct Data {
     pub name: String,
     pub geodesic2d: f64,
     pub planar2d: f64,
+    // on the authalic sphere, a check of the geodesic area
+    pub spherical2d: f64,
     pub projected2d: f64,
     pub projected3d: f64,
+    // the flat area within the polygon left out of projected2d: the planes
+    // across DEM voids excluded or without elevations, the degenerate
+    // triangles dropped
+    pub discarded2d: f64,
     pub geodesic3d: f64,
+    // geodesic3d by every strategy, and the one it was taken from
+    pub estimates: Estimates,
+    // in meters, along the geodesics and with the elevations of the vertices
+    pub perimeter2d: f64,
+    pub perimeter3d: Option<f64>,
+    #[serde(skip)]
     pub svg: String,
     pub nplanes: usize,
+    pub low_confidence: bool,
+    // fraction of the boundary length within a cell of a dataset edge
+    pub edge_fraction: f64,
+    // the proj4 string of the planar stage
+    pub projection: String,
+    pub notes: Vec<String>,
+    pub planes: Vec<PlaneSummary>,
+    pub insolation: Option<InsolationSummary>,
+    pub breakdown: Option<Breakdown>,
+    pub extremes: Extremes,
+    pub reference: Option<ReferenceComparison>,
+    pub self_check: Option<SelfCheck>,
+    // the raster reads, per dataset
+    pub io_stats: Vec<IoStats>,
+    // the time of the stages of the computation
+    pub phases: Vec<Phase>,
+    // the triangles taken whole or intersected with the polygon
+    pub clip_paths: ClipPaths,
+    // the parts of a multi-part boundary, the fields above are their sums
+    pub parts: Vec<Data>,
+    // the areas are in m², this is the unit they are printed in
+    #[serde(skip)]
+    pub units: Units,
+    // the language of the reports
+    #[serde(skip)]
+    pub lang: Lang,
+}
+
+impl Data {
+    pub fn area(&self, m2: f64) -> String {
+        self.labels().group(&AreaValue::new(m2).render(self.units))
+    }
+
+    pub fn labels(&self) -> &'static Labels {
+        self.lang.labels()
+    }
 }
 
 pub fn make_typst_document(data: &Vec<Data>) -> String {
     let mut doc = String::from("#set page(paper: \"a4\")\n#set text(size: 11pt)\n\n");
 
     for item in data {
-        let ratio = item.geodesic3d / item.geodesic2d - 1f64;
-        // 1. Main line with name in bold
-        let name = item.name.clone().replace("_", "\\_");
-        doc.push_str(&format!("== #strong[{}]\n\n", name));
+        push_section(&mut doc, item);
+        // a multi-part boundary: its parts, then a section for each
+        if !item.parts.is_empty() {
+            push_parts(&mut doc, item);
+            for part in &item.parts {
+                push_section(&mut doc, part);
+            }
+        }
+    }
+
+    doc
+}
+
+// The areas of every part, and their sums in the last row.
+fn push_parts(doc: &mut String, item: &Data) {
+    let labels = item.labels();
+    doc.push_str("#table(\n");
+    doc.push_str("  columns: (2fr, 1fr, 1fr, 1fr),\n");
+    doc.push_str("  inset: 10pt,\n");
+    doc.push_str("  align: horizon,\n");
+    doc.push_str(&format!(
+        "  [*{}*], [*{}*], [*{}*], [*{}*],\n",
+        labels.part, labels.value2d, labels.value3d, labels.ratio
+    ));
+    let row = |name: &str, data: &Data| {
+        format!(
+            "  [{}], [{}], [{}], [{}],\n",
+            name.replace("_", "\\_"),
+            data.area(data.geodesic2d),
+            data.area(data.geodesic3d),
+            format::signed(
+                100f64 * (data.geodesic3d / data.geodesic2d - 1f64),
+                Quantity::Percent
+            )
+        )
+    };
+    for part in &item.parts {
+        doc.push_str(&row(&part.name, part));
+    }
+    doc.push_str(&row(&format!("*{}*", labels.total), item));
+    doc.push_str(")\n\n");
+    doc.push_str("#pagebreak()\n\n");
+}
 
-        doc.push_str(&format!("== #strong[ratio: +{:.2}%]\n", 100f64 * ratio));
+fn push_section(doc: &mut String, item: &Data) {
+    let labels = item.labels();
+    let ratio = item.geodesic3d / item.geodesic2d - 1f64;
+    // 1. Main line with name in bold
+    let name = item.name.clone().replace("_", "\\_");
+    doc.push_str(&format!("== #strong[{}]\n\n", name));
 
-        doc.push_str("#table(\n");
-        doc.push_str("  columns: (1fr, 1fr, 1fr),\n");
-        doc.push_str("  inset: 10pt,\n");
-        doc.push_str("  align: horizon,\n");
-        doc.push_str("  [*Field*], [*2D Value*], [*3D Value*],\n");
+    doc.push_str(&format!(
+        "== #strong[{}: {}]\n",
+        labels.ratio,
+        format::signed(100f64 * ratio, Quantity::Percent)
+    ));
+    if item.low_confidence {
         doc.push_str(&format!(
-            "  [Geodesic], [{:.0} $m^2$], [{:.0} $m^2$],\n",
-            item.geodesic2d, item.geodesic3d,
+            "#text(fill: red)[*{}:* {}]\n\n",
+            labels.low_confidence, labels.low_confidence_reason
         ));
+    }
+    for note in &item.notes {
+        doc.push_str(&format!("_{}_\n\n", note));
+    }
+    doc.push_str(&format!("{}: `{}`\n\n", labels.projection, item.projection));
+
+    doc.push_str("#table(\n");
+    doc.push_str("  columns: (1fr, 1fr, 1fr),\n");
+    doc.push_str("  inset: 10pt,\n");
+    doc.push_str("  align: horizon,\n");
+    doc.push_str(&format!(
+        "  [*{}*], [*{}*], [*{}*],\n",
+        labels.field, labels.value2d, labels.value3d
+    ));
+    doc.push_str(&format!(
+        "  [{}], [{}], [{}],\n",
+        labels.geodesic,
+        item.area(item.geodesic2d),
+        item.area(item.geodesic3d),
+    ));
+    doc.push_str(&format!(
+        "  [{}], [{}], [{}],\n",
+        labels.mercator,
+        item.area(item.planar2d),
+        item.area(item.projected3d)
+    ));
+    doc.push_str(")\n\n");
+
+    if let Some(reference) = &item.reference {
+        let fill = if reference.within_tolerance {
+            "black"
+        } else {
+            "red"
+        };
+        doc.push_str(&format!("#text(fill: {})[{}]\n\n", fill, reference));
+    }
+
+    for (label, e) in [
+        (labels.highest_point, &item.extremes.highest),
+        (labels.lowest_point, &item.extremes.lowest),
+    ] {
+        if let Some(e) = e {
+            doc.push_str(&format!(
+                "{}: {} {} {}, {}\n\n",
+                label,
+                labels.group(&format::with_unit(e.elevation, Quantity::Elevation)),
+                labels.at,
+                format::with_unit(e.lat, Quantity::Coordinate),
+                format::with_unit(e.lon, Quantity::Coordinate)
+            ));
+        }
+    }
+
+    if let Some(insolation) = &item.insolation {
+        doc.push_str(&format!(
+            "{} ({} {} {}, {} {}): {} {}, {} {}\n\n",
+            labels.insolation,
+            labels.sun_at,
+            labels.azimuth,
+            format::with_unit(insolation.azimuth, Quantity::Angle),
+            labels.altitude,
+            format::with_unit(insolation.altitude, Quantity::Angle),
+            labels.weighted_area,
+            item.area(insolation.weighted),
+            labels.south_equivalent,
+            item.area(insolation.equivalent)
+        ));
+        doc.push_str("#table(\n");
+        doc.push_str("  columns: (1fr, 1fr, 1fr, 1fr),\n");
         doc.push_str(&format!(
-            "  [Mercator (UTM)], [{:.0} $m^2$], [{:.0} $m^2$],\n",
-            item.planar2d, item.projected3d
+            "  [*{}*], [*{}*], [*{}*], [*{}*],\n",
+            labels.aspect, labels.area, labels.weighted, labels.mean_cosine
         ));
+        for class in &insolation.classes {
+            if class.area == 0f64 {
+                continue;
+            }
+            doc.push_str(&format!(
+                "  [{}], [{}], [{}], [{}],\n",
+                labels.aspect_name(&class.name),
+                item.area(class.area),
+                item.area(class.weighted),
+                format::number(class.weighted / class.area, Quantity::Fraction)
+            ));
+        }
         doc.push_str(")\n\n");
+    }
+
+    if let Some(breakdown) = &item.breakdown {
+        for (label, unit, bins) in [
+            (labels.elevation, " m", &breakdown.elevation),
+            (labels.slope_class, "%", &breakdown.slope),
+        ] {
+            doc.push_str("#table(\n");
+            doc.push_str("  columns: (1fr, 1fr, 1fr),\n");
+            doc.push_str(&format!(
+                "  [*{}*], [*{}*], [*{}*],\n",
+                label, labels.value2d, labels.value3d
+            ));
+            for bin in bins.iter().filter(|b| b.area2d > 0.0) {
+                doc.push_str(&format!(
+                    "  [{}], [{}], [{}],\n",
+                    bin.range(unit),
+                    item.area(bin.area2d),
+                    item.area(bin.area3d)
+                ));
+            }
+            doc.push_str(")\n\n");
+        }
+        doc.push_str(&format!("_{}_\n\n", labels.breakdown_note));
+    }
+
+    // 3. SVG Image
+    // We use triple backticks ``` to wrap the SVG content safely
+    doc.push_str("#align(center, image(\n");
+    doc.push_str(&format!("  bytes(```{}```.text),\n", item.svg));
+    doc.push_str("  height: 15cm\n");
+    doc.push_str("))\n\n");
+
+    doc.push_str("#pagebreak()\n\n");
+}
 
-        // 3. SVG Image
-        // We use triple backticks ``` to wrap the SVG content safely
-        doc.push_str("#align(center, image(\n");
-        doc.push_str(&format!("  bytes(```{}```.text),\n", item.svg));
-        doc.push_str("  height: 15cm\n");
-        doc.push_str("))\n\n");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extremes::Extreme;
+    use crate::point::MercatorPoint;
 
-        doc.push_str("#pagebreak()\n\n");
+    fn data(lang: Lang) -> Data {
+        let highest = Extreme {
+            lon: 6.5,
+            lat: 46.25,
+            elevation: 1234.4,
+            mercator: MercatorPoint {
+                x: 0.0,
+                y: 0.0,
+                ele: Some(1234.4),
+            },
+        };
+        Data {
+            name: "parcel_12.kml".to_string(),
+            geodesic2d: 21420.0,
+            planar2d: 21425.0,
+            spherical2d: 21421.0,
+            projected2d: 21425.0,
+            projected3d: 22950.0,
+            discarded2d: 0.0,
+            geodesic3d: 22945.0,
+            estimates: Default::default(),
+            perimeter2d: 600.0,
+            perimeter3d: Some(612.0),
+            svg: "<svg/>".to_string(),
+            nplanes: 2,
+            low_confidence: true,
+            edge_fraction: 0.0,
+            projection: "+proj=utm +zone=32 +datum=WGS84".to_string(),
+            notes: vec![],
+            planes: vec![],
+            insolation: None,
+            breakdown: None,
+            extremes: Extremes {
+                highest: Some(highest),
+                lowest: None,
+            },
+            reference: None,
+            self_check: None,
+            io_stats: Vec::new(),
+            phases: Vec::new(),
+            clip_paths: Default::default(),
+            parts: Vec::new(),
+            units: Units::M2,
+            lang,
+        }
     }
 
-    doc
+    #[test]
+    fn test_french_document() {
+        let doc = make_typst_document(&vec![data(Lang::Fr)]);
+        assert_eq!(doc, FRENCH);
+        // the JSON does not depend on the language
+        assert_eq!(
+            serde_json::to_string(&data(Lang::Fr)).unwrap(),
+            serde_json::to_string(&data(Lang::En)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parts_table() {
+        let mut part = data(Lang::De);
+        part.name = "insel_1".to_string();
+        let mut total = data(Lang::De);
+        total.geodesic2d = 2.0 * part.geodesic2d;
+        total.geodesic3d = 2.0 * part.geodesic3d;
+        total.parts = vec![part.clone(), part];
+        let doc = make_typst_document(&vec![total]);
+        assert!(doc.contains("[*Teil*], [*2D-Wert*]"), "{}", doc);
+        assert_eq!(doc.matches("  [insel\\_1], ").count(), 2);
+        assert!(doc.contains("  [*Summe*], [42\u{202f}840 m²], [45\u{202f}890 m²], [+7.12%],\n"));
+        // the boundary, then each part
+        assert_eq!(doc.matches("#strong[insel\\_1]").count(), 2);
+        assert_eq!(doc.matches("#strong[parcel\\_12.kml]").count(), 1);
+    }
+
+    const FRENCH: &str = "#set page(paper: \"a4\")
+#set text(size: 11pt)
+
+== #strong[parcel\\_12.kml]
+
+== #strong[rapport: +7.12%]
+#text(fill: red)[*Fiabilité faible:* le polygone est plus petit que quelques cellules du MNT.]
+
+projection: `+proj=utm +zone=32 +datum=WGS84`
+
+#table(
+  columns: (1fr, 1fr, 1fr),
+  inset: 10pt,
+  align: horizon,
+  [*Champ*], [*Valeur 2D*], [*Valeur 3D*],
+  [Géodésique], [21\u{202f}420 m²], [22\u{202f}945 m²],
+  [Mercator (UTM)], [21\u{202f}425 m²], [22\u{202f}950 m²],
+)
+
+Point le plus haut: 1\u{202f}234 m à 46.25000°, 6.50000°
+
+#align(center, image(
+  bytes(```<svg/>```.text),
+  height: 15cm
+))
+
+#pagebreak()
+
+";
 }
@@ -0,0 +1,109 @@
+// Void filling on row-major rasters with a validity flag per pixel: the
+// missing pixels get the inverse distance weighted mean of the valid pixels
+// around them.
+
+// Fills the pixels flagged in `missing` from the valid pixels within `radius`
+// pixels, weighted by the inverse of their squared distance. Only the pixels
+// valid before the call are used, the filled ones become valid. The missing
+// pixels without a valid one in the radius stay invalid. Returns the number
+// of filled pixels.
+pub fn fill(
+    values: &mut [f64],
+    valid: &mut [bool],
+    missing: &[bool],
+    xsize: usize,
+    radius: usize,
+) -> usize {
+    assert_eq!(values.len(), valid.len());
+    assert_eq!(values.len(), missing.len());
+    if radius == 0 || xsize == 0 {
+        return 0;
+    }
+    let ysize = values.len() / xsize;
+    let r = radius as isize;
+    let mut filled = Vec::new();
+    for row in 0..ysize {
+        for col in 0..xsize {
+            let k = row * xsize + col;
+            if valid[k] || !missing[k] {
+                continue;
+            }
+            let (mut sum, mut weights) = (0.0, 0.0);
+            for dy in -r..=r {
+                for dx in -r..=r {
+                    let d2 = (dx * dx + dy * dy) as f64;
+                    let (c, l) = (col as isize + dx, row as isize + dy);
+                    if d2 > (r * r) as f64
+                        || c < 0
+                        || l < 0
+                        || c >= xsize as isize
+                        || l >= ysize as isize
+                    {
+                        continue;
+                    }
+                    let n = l as usize * xsize + c as usize;
+                    if valid[n] {
+                        sum += values[n] / d2;
+                        weights += 1.0 / d2;
+                    }
+                }
+            }
+            if weights > 0.0 {
+                filled.push((k, sum / weights));
+            }
+        }
+    }
+    for (k, value) in &filled {
+        values[*k] = *value;
+        valid[*k] = true;
+    }
+    filled.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: usize = 20;
+
+    #[test]
+    fn test_fill_hole() {
+        // a plane with a 4 × 4 hole
+        let mut values: Vec<f64> = (0..SIZE * SIZE)
+            .map(|k| 100.0 + (k % SIZE) as f64 + 2.0 * (k / SIZE) as f64)
+            .collect();
+        let mut valid = vec![true; values.len()];
+        for row in 8..12 {
+            for col in 8..12 {
+                values[row * SIZE + col] = -9999.0;
+                valid[row * SIZE + col] = false;
+            }
+        }
+        let missing: Vec<bool> = valid.iter().map(|v| !v).collect();
+        // too small a radius reaches the rim of the hole only
+        let (mut small, mut small_valid) = (values.clone(), valid.clone());
+        assert_eq!(fill(&mut small, &mut small_valid, &missing, SIZE, 1), 12);
+        assert!(!small_valid[9 * SIZE + 9]);
+        assert_eq!(fill(&mut values, &mut valid, &missing, SIZE, 4), 16);
+        assert!(valid.iter().all(|v| *v));
+        // pulled towards the nearest rim, by less than the rise of a pixel
+        for row in 8..12 {
+            for col in 8..12 {
+                let expected = 100.0 + col as f64 + 2.0 * row as f64;
+                assert!((values[row * SIZE + col] - expected).abs() < 2.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_masked_pixels_stay() {
+        let mut values = vec![100.0; SIZE * SIZE];
+        let mut valid = vec![true; values.len()];
+        valid[5 * SIZE + 5] = false;
+        values[5 * SIZE + 5] = 0.0;
+        let missing = vec![false; values.len()];
+        assert_eq!(fill(&mut values, &mut valid, &missing, SIZE, 3), 0);
+        assert!(!valid[5 * SIZE + 5]);
+        assert_eq!(values[5 * SIZE + 5], 0.0);
+    }
+}